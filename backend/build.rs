@@ -0,0 +1,22 @@
+//! Records the git commit the backend is built from, reported by `GET /health`.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=MC_GIT_HASH");
+    if std::env::var_os("MC_GIT_HASH").is_some() {
+        // Set by the environment, e.g. when building outside a checkout
+        return;
+    }
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=MC_GIT_HASH={}", hash.trim());
+    }
+}
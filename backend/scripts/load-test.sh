@@ -0,0 +1,109 @@
+#!/usr/bin/env bash
+# Measures how /status holds up while the server floods its console.
+#
+# Starts the backend with a fake server that prints LINES lines as fast as it
+# can, connects CLIENTS log streams (/events), and polls /status throughout.
+# Prints the /status latency percentiles once every line has been broadcast.
+#
+# Usage: scripts/load-test.sh [LINES] [CLIENTS]
+#
+# Environment:
+#   BACKEND - backend binary, default target/release/backend (built if missing)
+#   PORT    - port the backend listens on, default 18080
+#
+# Needs bash, curl, awk and sort. Run from the backend directory.
+
+set -euo pipefail
+
+LINES=${1:-50000}
+CLIENTS=${2:-3}
+PORT=${PORT:-18080}
+BACKEND=${BACKEND:-target/release/backend}
+BASE="http://127.0.0.1:$PORT"
+
+if [[ ! -x $BACKEND ]]; then
+  cargo build --release
+fi
+BACKEND=$(realpath "$BACKEND")
+
+WORK=$(mktemp -d)
+PIDS=()
+cleanup() {
+  for pid in "${PIDS[@]}"; do
+    kill "$pid" 2>/dev/null || true
+  done
+  wait 2>/dev/null || true
+  rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+# The fake server waits for a "flood" command so the clients can connect first
+cat > "$WORK/server.sh" <<'EOF'
+#!/usr/bin/env bash
+echo "[12:00:00] [Server thread/INFO]: Done (0.001s)! For help, type \"help\""
+while IFS= read -r line; do
+  case "$line" in
+    "flood "*)
+      seq 1 "${line#flood }" |
+        sed 's|^|[12:00:01] [Server thread/INFO]: load test line |; s|$| xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx|'
+      echo "[12:00:02] [Server thread/INFO]: load test done"
+      ;;
+    stop) exit 0 ;;
+  esac
+done
+EOF
+chmod +x "$WORK/server.sh"
+
+# A working directory without a config.toml, so only these settings apply
+(
+  cd "$WORK"
+  MC_PORT=$PORT MC_BIND_ADDRESS=127.0.0.1 MC_SERVER_PATH="$WORK/server.sh" \
+    MC_AUDIT_ENABLED=false MC_LOG_FILE_ENABLED=false \
+    exec "$BACKEND" > "$WORK/backend.log" 2>&1
+) &
+PIDS+=($!)
+
+for _ in $(seq 1 50); do
+  curl -sf "$BASE/health" > /dev/null && break
+  sleep 0.1
+done
+curl -sf -X POST "$BASE/start" > /dev/null
+
+for _ in $(seq 1 "$CLIENTS"); do
+  curl -sN "$BASE/events" > /dev/null &
+  PIDS+=($!)
+done
+sleep 1
+
+# One more stream tells when the last line has been broadcast
+curl -sN "$BASE/events" | grep --line-buffered -m1 "load test done" > "$WORK/done" &
+PIDS+=($!)
+
+echo "Flooding $LINES lines to $CLIENTS clients while polling /status"
+# /command waits for the output to go quiet, so it runs alongside the polling
+curl -s -X POST "$BASE/command" -H 'Content-Type: application/json' \
+  -d "{\"command\": \"flood $LINES\"}" > /dev/null &
+PIDS+=($!)
+
+: > "$WORK/latencies"
+START=$(date +%s)
+until [[ -s $WORK/done ]]; do
+  curl -s -o /dev/null -w '%{time_total}\n' "$BASE/status" >> "$WORK/latencies"
+  if (( $(date +%s) - START > 600 )); then
+    echo "Gave up waiting for the flood to reach the clients" >&2
+    exit 1
+  fi
+done
+ELAPSED=$(( $(date +%s) - START ))
+
+curl -sf -X POST "$BASE/stop" > /dev/null || true
+
+sort -n "$WORK/latencies" | awk -v lines="$LINES" -v elapsed="$ELAPSED" '
+  function at(p,  i) { i = int(NR * p) + 1; return ms[i > NR ? NR : i] }
+  { ms[NR] = $1 * 1000 }
+  END {
+    if (NR == 0) { print "No /status requests finished"; exit 1 }
+    printf "%d lines in about %ds, %d /status requests\n", lines, elapsed, NR
+    printf "/status latency: p50 %.1f ms, p95 %.1f ms, p99 %.1f ms, max %.1f ms\n",
+      at(0.50), at(0.95), at(0.99), ms[NR]
+  }'
@@ -0,0 +1,351 @@
+//! Audit log of administrative actions.
+//!
+//! Starting, stopping and restarting a server, disconnecting a client,
+//! every command sent through `/command`, `/rcon`, `/moderation` or a
+//! WebSocket console, every macro run and every mod upload is recorded with
+//! who asked for it, the address the request came from and what came of it.
+//! Entries are appended to a JSONL file from their own task, so recording
+//! never waits for the disk, and read back by `GET /audit`.
+//!
+//! Once the file grows past `max_size_mb` it is renamed to `<path>.1`, older
+//! files move up by one, and the oldest beyond `max_files` is deleted.
+
+use crate::auth::{Principal, Scope};
+use crate::config;
+use crate::error::ServerError;
+use crate::server::ServerId;
+use actix_web::{HttpMessage, HttpRequest};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::error;
+
+/// Settings for the audit log.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Whether actions are recorded.
+    pub enabled: bool,
+    /// Path of the audit file, relative to the backend's working directory.
+    pub path: PathBuf,
+    /// Size in MiB after which the file is rotated; 0 never rotates.
+    pub max_size_mb: u64,
+    /// Rotated files kept besides the current one.
+    pub max_files: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            enabled: true,
+            path: PathBuf::from("audit.jsonl"),
+            max_size_mb: 10,
+            max_files: 5,
+        }
+    }
+}
+
+impl AuditConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_AUDIT_ENABLED` - `true` or `false`
+    /// * `MC_AUDIT_FILE` - path of the audit file
+    /// * `MC_AUDIT_MAX_SIZE_MB` - size after which the file is rotated
+    /// * `MC_AUDIT_MAX_FILES` - rotated files kept
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(enabled) = config::env_parse("MC_AUDIT_ENABLED")? {
+            self.enabled = enabled;
+        }
+        if let Some(path) = config::env_path("MC_AUDIT_FILE") {
+            self.path = path;
+        }
+        if let Some(size) = config::env_parse("MC_AUDIT_MAX_SIZE_MB")? {
+            self.max_size_mb = size;
+        }
+        if let Some(files) = config::env_parse("MC_AUDIT_MAX_FILES")? {
+            self.max_files = files;
+        }
+        Ok(())
+    }
+}
+
+/// An action that is audited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Start,
+    Stop,
+    Restart,
+    /// A console command, from `/command`, `/moderation` or a WebSocket console.
+    Command,
+    /// A command sent over RCON with `/rcon`.
+    Rcon,
+    /// A macro run, with its commands as the arguments.
+    Macro,
+    /// A WebSocket or SSE client disconnected with `DELETE /clients/{id}`.
+    DisconnectClient,
+    /// A mod jar uploaded with `POST /mods/upload`, with its name as the argument.
+    UploadMod,
+}
+
+/// What came of an audited action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditResult {
+    Ok,
+    /// The command policy refused the command, or the caller's scope doesn't allow it.
+    Denied,
+    Failed,
+}
+
+/// Who asked for an action.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Caller {
+    /// WebSocket client, absent for HTTP requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<usize>,
+    /// IP address the request or WebSocket connection came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_ip: Option<String>,
+    /// User the request was authenticated as, when logins are enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The user's scope, which decides what commands they may send.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Scope>,
+}
+
+impl Caller {
+    /// Identifies the sender of an HTTP request.
+    pub fn http(req: &HttpRequest) -> Self {
+        let extensions = req.extensions();
+        let principal = extensions.get::<Principal>();
+        Caller {
+            client_id: None,
+            remote_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+            user: principal.map(|principal| principal.name.clone()),
+            scope: principal.map(|principal| principal.scope),
+        }
+    }
+}
+
+/// One line of the audit file, as returned by `GET /audit`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Local>,
+    #[serde(flatten)]
+    pub caller: Caller,
+    /// Server the action was for.
+    pub server: ServerId,
+    pub action: AuditAction,
+    /// The command, the script path of a start, the id of a disconnected
+    /// client, or a macro's name and commands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+    pub result: AuditResult,
+    /// Why the action failed or was denied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Records audit entries to the audit file.
+pub struct AuditLog {
+    config: AuditConfig,
+    /// Channel to the writer task, None if auditing is disabled.
+    queue: Option<UnboundedSender<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// Creates the audit log, starting its writer task if auditing is enabled.
+    pub fn new(config: AuditConfig) -> Self {
+        let queue = config.enabled.then(|| {
+            let (queue, rx) = unbounded_channel();
+            tokio::spawn(write_entries(rx, config.clone()));
+            queue
+        });
+        AuditLog { config, queue }
+    }
+
+    /// Returns the settings the audit log was created with.
+    pub fn config(&self) -> &AuditConfig {
+        &self.config
+    }
+
+    /// Records an action and its result. Never waits for the write.
+    ///
+    /// # Arguments
+    /// * `caller` - Who asked for the action
+    /// * `server` - Id of the server the action was for
+    /// * `action` - What was done
+    /// * `arguments` - The command, or the script path of a start
+    /// * `result` - What came of it
+    pub fn record<T>(
+        &self,
+        caller: &Caller,
+        server: &str,
+        action: AuditAction,
+        arguments: Option<&str>,
+        result: &Result<T, ServerError>,
+    ) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        let (result, error) = match result {
+            Ok(_) => (AuditResult::Ok, None),
+            Err(e @ (ServerError::CommandDenied { .. } | ServerError::Forbidden { .. })) => {
+                (AuditResult::Denied, Some(e.to_string()))
+            }
+            Err(e) => (AuditResult::Failed, Some(e.to_string())),
+        };
+        // The writer task only ends when the audit log is gone
+        let _ = queue.send(AuditEntry {
+            timestamp: Local::now(),
+            caller: caller.clone(),
+            server: server.to_string(),
+            action,
+            arguments: arguments.map(|arguments| arguments.trim().to_string()),
+            result,
+            error,
+        });
+    }
+}
+
+/// Appends queued entries to the audit file, rotating it when it grows too large.
+///
+/// If the file can't be opened or written, an error is printed and the entry
+/// is dropped.
+async fn write_entries(mut rx: UnboundedReceiver<AuditEntry>, config: AuditConfig) {
+    let max_size = config.max_size_mb.saturating_mul(1024 * 1024);
+    let mut file = None;
+    while let Some(entry) = rx.recv().await {
+        let line = match serde_json::to_string(&entry) {
+            Ok(json) => format!("{}\n", json),
+            Err(e) => {
+                error!("Error serializing entry: {}", e);
+                continue;
+            }
+        };
+        if file.is_none() {
+            file = open(&config.path).await;
+        }
+        if let Some((_, size)) = &file {
+            if max_size != 0 && *size != 0 && size + line.len() as u64 > max_size {
+                // Close the file before it is renamed
+                drop(file.take());
+                if let Err(e) = rotate(&config.path, config.max_files).await {
+                    error!("Error rotating {}: {}", config.path.display(), e);
+                }
+                file = open(&config.path).await;
+            }
+        }
+        let Some((opened, size)) = &mut file else {
+            continue;
+        };
+        match opened.write_all(line.as_bytes()).await {
+            Ok(()) => *size += line.len() as u64,
+            Err(e) => error!("Error writing {}: {}", config.path.display(), e),
+        }
+    }
+}
+
+/// Opens the audit file for appending.
+///
+/// # Returns
+/// * `Option<(tokio::fs::File, u64)>` - The file and its size, None if it couldn't be opened
+async fn open(path: &Path) -> Option<(fs::File, u64)> {
+    match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+    {
+        Ok(file) => {
+            let size = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+            Some((file, size))
+        }
+        Err(e) => {
+            error!("Could not open {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Returns the path of a rotated audit file, `<path>.<index>`.
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Moves the audit file to `<path>.1`, shifting older files up and deleting
+/// the one beyond `max_files`.
+async fn rotate(path: &Path, max_files: usize) -> std::io::Result<()> {
+    if max_files == 0 {
+        return fs::remove_file(path).await;
+    }
+    match fs::remove_file(rotated_path(path, max_files)).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for index in (1..max_files).rev() {
+        match fs::rename(rotated_path(path, index), rotated_path(path, index + 1)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::rename(path, rotated_path(path, 1)).await
+}
+
+/// Reads the most recent entries from the audit file and its rotated files.
+///
+/// This reads the files synchronously, so call it from a blocking context
+/// (e.g. `web::block`). Lines that aren't valid entries are skipped, and
+/// missing files are treated as empty.
+///
+/// # Arguments
+/// * `config` - Where the audit files are
+/// * `limit` - Most entries to return
+/// * `since` - Only return entries recorded at or after this time
+///
+/// # Returns
+/// * `std::io::Result<Vec<AuditEntry>>` - The matching entries, newest first
+pub fn read_entries(
+    config: &AuditConfig,
+    limit: usize,
+    since: Option<DateTime<Local>>,
+) -> std::io::Result<Vec<AuditEntry>> {
+    let paths = (1..=config.max_files)
+        .rev()
+        .map(|index| rotated_path(&config.path, index))
+        .chain(std::iter::once(config.path.clone()));
+
+    let mut entries = VecDeque::new();
+    for path in paths {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) else {
+                continue;
+            };
+            if since.is_some_and(|since| entry.timestamp < since) {
+                continue;
+            }
+            if entries.len() == limit {
+                entries.pop_front();
+            }
+            if limit != 0 {
+                entries.push_back(entry);
+            }
+        }
+    }
+    Ok(entries.into_iter().rev().collect())
+}
@@ -0,0 +1,483 @@
+//! Logins and scopes.
+//!
+//! With users configured in `[auth]`, every API route except `/login`,
+//! `/health` and `/ready` needs a token from `POST /login`, sent as
+//! `Authorization: Bearer <token>` or, for WebSocket and SSE connections
+//! where browsers can't set headers, as `?token=<token>`. Tokens are JWTs
+//! signed with HS256 that carry the user's scope and expire after
+//! `token_ttl_secs`.
+//!
+//! Scopes are tiers, each allowed what the ones below it are:
+//! - Viewers may watch: status, versions, the MOTD, logs, stats, pings, queries, the server
+//!   list, events and the WebSocket console, where they can't send commands.
+//! - Operators may also send commands, through the console, `/command`,
+//!   `/rcon`, `/save`, the moderation and message routes, except the admin
+//!   commands of the command policy, e.g. `op` or `stop`.
+//! - Admins may do everything else, e.g. start and stop servers.
+//!
+//! A request without the scope it needs is answered with 403 naming the
+//! scope. Without users, nothing is checked and the backend behaves as it
+//! always has.
+//!
+//! Passwords are stored as argon2 PHC strings, created with
+//! `backend --hash-password`.
+
+use crate::config;
+use crate::error::ServerError;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, ResponseError};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rustls::crypto::ring;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Routes anyone may call, so clients can log in and probes keep working.
+const PUBLIC_ROUTES: &[&str] = &["/login", "/health", "/ready"];
+
+/// Routes viewers may GET, also under `/servers/{id}`.
+const VIEWER_ROUTES: &[&str] = &[
+    "/status",
+    "/logs",
+    "/logs/download",
+    "/logs/stream",
+    "/ws",
+    "/events",
+    "/stats",
+    "/ping",
+    "/query",
+    "/version",
+    "/motd",
+    "/servers",
+];
+
+/// Routes operators may GET besides the viewer routes, also under `/servers/{id}`.
+const OPERATOR_READ_ROUTES: &[&str] = &["/commands/history", "/macros"];
+
+/// Routes operators may POST to, also under `/servers/{id}`. The commands
+/// they send are checked against the operator's scope as well.
+const OPERATOR_ROUTES: &[&str] = &[
+    "/command",
+    "/rcon",
+    "/save",
+    "/moderation/{action}",
+    "/players/{name}/message",
+    "/broadcast",
+    "/macros/{name}/run",
+];
+
+/// Shortest `jwt_secret` accepted, in bytes.
+const MIN_SECRET_LEN: usize = 32;
+
+/// Hash checked when logging in as a user that doesn't exist, made by
+/// [`hash_password`] with the same parameters as real users' hashes.
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$87FxBNZ7Jufh00t361+H+w$hcW6jj0pMw0koAQip4M6QQeWXew86Xc0on2dX29sy3s";
+
+/// Settings for logins.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Users who may log in; none disables authentication.
+    pub users: Vec<UserConfig>,
+    /// Secret signing the tokens. Without one a random secret is generated at
+    /// startup, so tokens don't survive a restart.
+    pub jwt_secret: Option<String>,
+    /// How long a token is valid.
+    #[serde(rename = "token_ttl_secs", deserialize_with = "config::secs")]
+    pub token_ttl: Duration,
+}
+
+/// A user who may log in.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    pub name: String,
+    /// Argon2 hash of the password in PHC format (`$argon2id$...`).
+    pub password_hash: String,
+    /// Called `role` before operators were added, which is still accepted.
+    #[serde(alias = "role")]
+    pub scope: Scope,
+}
+
+/// What a user may do. Each scope may do everything the ones before it may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Watching the console and the server's state.
+    Viewer,
+    /// Also sending commands, except the command policy's admin commands.
+    Operator,
+    /// Everything, including starting and stopping servers.
+    Admin,
+}
+
+impl Scope {
+    /// Returns the name used in the config and in tokens, e.g. `operator`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Viewer => "viewer",
+            Scope::Operator => "operator",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            users: Vec::new(),
+            jwt_secret: None,
+            token_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_JWT_SECRET` - secret signing the tokens
+    /// * `MC_AUTH_TOKEN_TTL_SECS` - seconds a token is valid
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(secret) = config::env_string("MC_JWT_SECRET") {
+            self.jwt_secret = Some(secret);
+        }
+        if let Some(ttl) = config::env_secs("MC_AUTH_TOKEN_TTL_SECS")? {
+            self.token_ttl = ttl;
+        }
+        Ok(())
+    }
+
+    /// Checks that the values make sense.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.token_ttl.is_zero() {
+            return Err("auth token_ttl_secs must be greater than zero".to_string());
+        }
+        if let Some(secret) = &self.jwt_secret {
+            if secret.len() < MIN_SECRET_LEN {
+                return Err(format!(
+                    "auth jwt_secret must be at least {} characters",
+                    MIN_SECRET_LEN
+                ));
+            }
+        }
+        for (i, user) in self.users.iter().enumerate() {
+            if user.name.is_empty() {
+                return Err("auth user names must not be empty".to_string());
+            }
+            if self.users[..i].iter().any(|other| other.name == user.name) {
+                return Err(format!("auth user '{}' is defined twice", user.name));
+            }
+            PasswordHash::new(&user.password_hash).map_err(|e| {
+                format!(
+                    "auth user '{}' has an invalid password_hash ({}), create one with --hash-password",
+                    user.name, e
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Claims of the tokens issued by `POST /login`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    /// User name.
+    sub: String,
+    /// Tokens issued before operators were added call it `role`.
+    #[serde(alias = "role")]
+    scope: Scope,
+    /// Unix time the token was issued.
+    iat: u64,
+    /// Unix time the token expires.
+    exp: u64,
+}
+
+/// The user a request was authenticated as, stored in the request's extensions.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub scope: Scope,
+    /// Unix time the user's token expires.
+    pub expires_at: u64,
+}
+
+impl Principal {
+    /// Returns the time left until the token expires, zero once it has.
+    pub fn expires_in(&self) -> Duration {
+        Duration::from_secs(self.expires_at.saturating_sub(unix_now()))
+    }
+}
+
+/// Checks passwords and issues and verifies tokens.
+pub struct Auth {
+    users: Vec<UserConfig>,
+    token_ttl: Duration,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    validation: Validation,
+}
+
+impl Auth {
+    /// Creates the authenticator, generating a signing secret if none is configured.
+    pub fn new(config: &AuthConfig) -> Arc<Self> {
+        let secret = match &config.jwt_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                if !config.users.is_empty() {
+                    warn!("No jwt_secret configured, tokens won't survive a restart");
+                }
+                random_bytes::<32>().to_vec()
+            }
+        };
+        let mut validation = Validation::new(Algorithm::HS256);
+        // Expiry is enforced exactly, matching when WebSocket sessions are closed
+        validation.leeway = 0;
+        Arc::new(Auth {
+            users: config.users.clone(),
+            token_ttl: config.token_ttl,
+            encoding: EncodingKey::from_secret(&secret),
+            decoding: DecodingKey::from_secret(&secret),
+            validation,
+        })
+    }
+
+    /// Returns whether requests need a token, i.e. whether any users are configured.
+    pub fn enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Checks a user's password and issues a token.
+    ///
+    /// Hashing is deliberately slow, so call this from a blocking thread.
+    ///
+    /// # Arguments
+    /// * `name` - User name
+    /// * `password` - The password to check
+    ///
+    /// # Returns
+    /// * `Result<(String, Principal), ServerError>` - The token and who it is for, or Unauthorized
+    pub fn login(&self, name: &str, password: &str) -> Result<(String, Principal), ServerError> {
+        let invalid = || ServerError::Unauthorized {
+            reason: "invalid user name or password".to_string(),
+        };
+        let user = self.users.iter().find(|user| user.name == name);
+        // An unknown name is checked against a dummy hash, so it takes as long
+        // as a wrong password and the response time doesn't tell who has an account
+        let hash = user.map_or(DUMMY_HASH, |user| user.password_hash.as_str());
+        let hash = PasswordHash::new(hash).map_err(|_| invalid())?;
+        let verified = Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok();
+        let user = user.filter(|_| verified).ok_or_else(invalid)?;
+
+        let now = unix_now();
+        let claims = Claims {
+            sub: user.name.clone(),
+            scope: user.scope,
+            iat: now,
+            exp: now + self.token_ttl.as_secs(),
+        };
+        let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &self.encoding)
+            .map_err(|e| ServerError::Io(std::io::Error::other(e)))?;
+        Ok((
+            token,
+            Principal {
+                name: claims.sub,
+                scope: claims.scope,
+                expires_at: claims.exp,
+            },
+        ))
+    }
+
+    /// Checks a token's signature and expiry.
+    ///
+    /// # Returns
+    /// * `Result<Principal, ServerError>` - Who the token is for, or Unauthorized
+    pub fn verify(&self, token: &str) -> Result<Principal, ServerError> {
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.decoding, &self.validation)
+            .map_err(|e| ServerError::Unauthorized {
+                reason: match e.kind() {
+                    jsonwebtoken::errors::ErrorKind::ExpiredSignature => "token expired",
+                    _ => "invalid token",
+                }
+                .to_string(),
+            })?
+            .claims;
+        // A token for a user removed from the config is no longer accepted, and
+        // one for a user whose scope was lowered only gets the lower scope
+        let user = self.users.iter().find(|user| user.name == claims.sub);
+        let Some(user) = user else {
+            return Err(ServerError::Unauthorized {
+                reason: "unknown user".to_string(),
+            });
+        };
+        Ok(Principal {
+            name: claims.sub,
+            scope: claims.scope.min(user.scope),
+            expires_at: claims.exp,
+        })
+    }
+}
+
+/// Hashes a password for the `password_hash` of a user.
+///
+/// # Returns
+/// * `Result<String, String>` - The PHC string, or why hashing failed
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::encode_b64(&random_bytes::<16>()).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Middleware checking the token of every API request when authentication is enabled.
+///
+/// Paths that aren't API routes, i.e. the frontend's files, are passed
+/// through. The authenticated [`Principal`] is stored in the request's
+/// extensions for handlers and the audit log.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let auth = req.app_data::<web::Data<Arc<Auth>>>().cloned();
+    // The frontend is mounted at the root, so its files match the empty pattern
+    let pattern = req.match_pattern().filter(|pattern| !pattern.is_empty());
+    let (Some(auth), Some(pattern)) = (auth, pattern) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if !auth.enabled() || PUBLIC_ROUTES.contains(&pattern.as_str()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let checked = bearer_token(&req)
+        .ok_or_else(|| ServerError::Unauthorized {
+            reason: "missing bearer token".to_string(),
+        })
+        .and_then(|token| auth.verify(&token))
+        .and_then(|principal| {
+            let required = required_scope(req.method(), &pattern);
+            if principal.scope >= required {
+                Ok(principal)
+            } else {
+                Err(ServerError::Forbidden { required })
+            }
+        });
+    match checked {
+        Ok(principal) => {
+            req.extensions_mut().insert(principal);
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        // Answer here rather than failing, so CORS headers are still added
+        Err(e) => Ok(req.into_response(e.error_response()).map_into_right_body()),
+    }
+}
+
+/// Returns the scope a route needs.
+fn required_scope(method: &Method, pattern: &str) -> Scope {
+    let route = pattern.strip_prefix("/servers/{id}").unwrap_or(pattern);
+    if method == Method::GET && VIEWER_ROUTES.contains(&route) {
+        Scope::Viewer
+    } else if (method == Method::GET && OPERATOR_READ_ROUTES.contains(&route))
+        || (method == Method::POST && OPERATOR_ROUTES.contains(&route))
+    {
+        Scope::Operator
+    } else {
+        Scope::Admin
+    }
+}
+
+/// Query parameter carrying the token for WebSocket and SSE connections.
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+/// Takes the token from the Authorization header, or the `token` query parameter.
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let header = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match header {
+        Some(token) => Some(token.trim().to_string()),
+        None => web::Query::<TokenQuery>::from_query(req.query_string())
+            .ok()
+            .map(|query| query.into_inner().token),
+    }
+}
+
+/// Returns the current Unix time in seconds.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+/// Returns random bytes from the operating system.
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    ring::default_provider()
+        .secure_random
+        .fill(&mut bytes)
+        .expect("the operating system's random number generator failed");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth_with(name: &str, password: &str) -> Arc<Auth> {
+        Auth::new(&AuthConfig {
+            users: vec![UserConfig {
+                name: name.to_string(),
+                password_hash: hash_password(password).unwrap(),
+                scope: Scope::Operator,
+            }],
+            ..AuthConfig::default()
+        })
+    }
+
+    #[test]
+    fn dummy_hash_costs_as_much_as_a_real_one() {
+        let dummy = PasswordHash::new(DUMMY_HASH).unwrap();
+        let real = hash_password("password").unwrap();
+        let real = PasswordHash::new(&real).unwrap();
+        assert_eq!(dummy.algorithm, real.algorithm);
+        assert_eq!(dummy.version, real.version);
+        assert_eq!(dummy.params, real.params);
+    }
+
+    #[test]
+    fn login_with_the_right_password() {
+        let auth = auth_with("alex", "hunter2");
+        let (token, principal) = auth.login("alex", "hunter2").unwrap();
+        assert_eq!(principal.name, "alex");
+        assert_eq!(principal.scope, Scope::Operator);
+        assert_eq!(auth.verify(&token).unwrap().name, "alex");
+    }
+
+    #[test]
+    fn unknown_user_and_wrong_password_fail_alike() {
+        let auth = auth_with("alex", "hunter2");
+        let wrong_password = auth.login("alex", "hunter3").unwrap_err().to_string();
+        let unknown_user = auth.login("steve", "hunter2").unwrap_err().to_string();
+        assert_eq!(wrong_password, unknown_user);
+    }
+}
@@ -0,0 +1,719 @@
+//! Fan-out of a server's console output and events to its clients.
+//!
+//! Each server has a [`Broadcaster`] with its connected WebSocket and SSE
+//! clients and the replay buffer behind a lock of its own. The log
+//! broadcaster task sends output through it without taking the app state
+//! lock, so heavy console output doesn't hold up HTTP handlers waiting on
+//! that lock; only events, which change the server's state, still need it.
+
+use crate::auth::Principal;
+use crate::metrics::Metrics;
+use crate::server::log_entry::{LogEntry, LogFormat};
+use crate::server::ServerId;
+use crate::state::ServerEvent;
+use crate::websocket::{OverflowPolicy, WebSocketConfig};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot};
+use tracing::{debug, info, warn};
+
+// Unique ID counter for WebSocket and SSE clients, across all servers
+static NEXT_CLIENT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// How a subscriber wants broadcast frames encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientEncoding {
+    /// A WebSocket client: log entries in its chosen format, events as JSON
+    /// or, with [`LogFormat::Text`], as a console line.
+    WebSocket(LogFormat),
+    /// A Server-Sent Events stream: every frame is a complete SSE message
+    /// whose id is the frame's sequence number. With [`LogFormat::Json`] log
+    /// entries are `log` events carrying JSON, with [`LogFormat::Text`] they
+    /// are unnamed events carrying the plain line. Events are JSON either way.
+    Sse(LogFormat),
+}
+
+/// Something broadcast to clients, kept for replaying to clients that connect later.
+struct ReplayFrame {
+    /// Position in the stream of everything broadcast, starting at 1.
+    seq: u64,
+    payload: Payload,
+}
+
+/// Contents of a [`ReplayFrame`].
+enum Payload {
+    /// A log entry, rendered in each client's format.
+    Log(LogEntry),
+    /// An event, rendered as JSON except for text WebSocket clients.
+    Event(ServerEvent),
+}
+
+/// A JSON frame with its sequence number, e.g. `{"seq":42,"type":"log",...}`.
+#[derive(Serialize)]
+struct Sequenced<'a, T> {
+    seq: u64,
+    #[serde(flatten)]
+    frame: &'a T,
+}
+
+/// Sent before the replay to a client resuming after `since` when some of
+/// the frames it missed are no longer buffered, so it knows to reload the
+/// full log instead of appending.
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "gap")]
+struct Gap {
+    /// Sequence number the client asked to resume after.
+    since: u64,
+    /// Sequence number of the first frame it gets.
+    resumed_at: u64,
+}
+
+impl Gap {
+    /// Renders the marker for a client.
+    fn render(&self, encoding: ClientEncoding) -> String {
+        // Only numbers, so this can't fail
+        let json = serde_json::to_string(self).unwrap_or_default();
+        match encoding {
+            ClientEncoding::WebSocket(LogFormat::Text) => format!(
+                "--- Output after {} is no longer available, resuming at {}; reload for the full log ---",
+                self.since, self.resumed_at
+            ),
+            ClientEncoding::WebSocket(LogFormat::Json) => json,
+            // No id, so the client's Last-Event-ID is left alone
+            ClientEncoding::Sse(_) => format!("event: gap\ndata: {}\n\n", json),
+        }
+    }
+}
+
+impl ReplayFrame {
+    /// Renders the frame as JSON with its sequence number.
+    fn to_json(&self) -> String {
+        let seq = self.seq;
+        // Only strings, numbers and enums, so this can't fail
+        match &self.payload {
+            Payload::Log(entry) => serde_json::to_string(&Sequenced { seq, frame: entry }),
+            Payload::Event(event) => serde_json::to_string(&Sequenced { seq, frame: event }),
+        }
+        .unwrap_or_default()
+    }
+
+    /// Renders the frame for a client.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The message, None for an event a text WebSocket
+    ///   client sees in the server's output anyway
+    fn render(&self, encoding: ClientEncoding) -> Option<String> {
+        let message = match (&self.payload, encoding) {
+            (Payload::Log(entry), ClientEncoding::WebSocket(LogFormat::Text)) => entry.to_text(),
+            (Payload::Event(event), ClientEncoding::WebSocket(LogFormat::Text)) => {
+                event.to_text()?
+            }
+            (_, ClientEncoding::WebSocket(LogFormat::Json)) => self.to_json(),
+            // An unnamed event reaches a browser's `onmessage`
+            (Payload::Log(entry), ClientEncoding::Sse(LogFormat::Text)) => {
+                format!("id: {}\ndata: {}\n\n", self.seq, entry.to_text())
+            }
+            (payload, ClientEncoding::Sse(_)) => {
+                let name = match payload {
+                    Payload::Log(_) => "log",
+                    Payload::Event(event) => event.sse_name(),
+                };
+                format!(
+                    "id: {}\nevent: {}\ndata: {}\n\n",
+                    self.seq,
+                    name,
+                    self.to_json()
+                )
+            }
+        };
+        Some(message)
+    }
+}
+
+/// A connected WebSocket or SSE client.
+struct Subscriber {
+    /// Channel to the client's WebSocket actor or event stream, holding at most
+    /// `client_buffer` messages the client hasn't taken yet.
+    sender: broadcast::Sender<String>,
+    /// How frames are rendered for this client.
+    encoding: ClientEncoding,
+    /// Address the client connected from.
+    remote_ip: Option<String>,
+    /// User the client logged in as, None when logins are disabled.
+    user: Option<String>,
+    /// Whether the client can't send commands.
+    read_only: bool,
+    connected_at: DateTime<Utc>,
+    /// Messages the client took and dropped, counted by its [`ClientReceiver`].
+    counters: Arc<ClientCounters>,
+    /// Console commands the client sent, including refused ones.
+    commands: u64,
+    /// When the client last sent a message, None if it never did.
+    last_activity: Option<DateTime<Utc>>,
+    /// Tells the client it is being disconnected by an admin.
+    kick: Option<oneshot::Sender<()>>,
+}
+
+/// Messages a client took from its channel and dropped for lagging behind.
+#[derive(Debug, Default)]
+struct ClientCounters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// A connected client, as listed by `GET /clients`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub id: usize,
+    /// Server whose console the client is attached to.
+    pub server: ServerId,
+    /// `websocket` or `sse`.
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_ip: Option<String>,
+    /// User the client logged in as; absent when logins are disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Whether the client can't send commands: SSE clients, viewers and
+    /// WebSocket clients connected with `?mode=viewer`.
+    pub read_only: bool,
+    pub connected_at: DateTime<Utc>,
+    /// Messages forwarded to the client.
+    pub messages_sent: u64,
+    /// Messages dropped because the client fell behind.
+    pub messages_dropped: u64,
+    /// Console commands the client sent, including refused ones. Always 0 for SSE clients.
+    pub commands: u64,
+    /// When the client last sent a message; absent if it never did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+/// Receiving end of a client's channel.
+pub struct ClientReceiver {
+    client_id: usize,
+    receiver: broadcast::Receiver<String>,
+    counters: Arc<ClientCounters>,
+    kicked: oneshot::Receiver<()>,
+}
+
+impl ClientReceiver {
+    /// Waits for the next message, skipping any that were dropped while the
+    /// client lagged behind.
+    ///
+    /// # Returns
+    /// * `Option<String>` - None once the client has been unregistered
+    pub async fn recv(&mut self) -> Option<String> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(message) => {
+                    self.counters.sent.fetch_add(1, Ordering::Relaxed);
+                    return Some(message);
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    self.counters.dropped.fetch_add(skipped, Ordering::Relaxed);
+                    debug!(
+                        client_id = self.client_id,
+                        "Client fell behind, dropped {} messages", skipped
+                    )
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns true if the client was unregistered by
+    /// [`Broadcaster::disconnect_client`] rather than for lagging behind.
+    pub fn was_kicked(&mut self) -> bool {
+        self.kicked.try_recv().is_ok()
+    }
+}
+
+/// A server's connected clients and replay buffer.
+pub struct Broadcaster {
+    /// Id of the server, for log messages.
+    server_id: ServerId,
+    /// Clients and replay buffer. Never held across an await, and only for
+    /// as long as one frame takes to be handed to every client.
+    channels: Mutex<Channels>,
+    /// Counters exported at `/metrics`, shared by all servers.
+    metrics: Arc<Metrics>,
+    /// Number of messages buffered for each client.
+    client_buffer: usize,
+    /// What happens to a client whose buffer is full.
+    overflow: OverflowPolicy,
+    /// Number of frames kept for replaying to new clients.
+    replay_lines: usize,
+}
+
+/// What [`Broadcaster`] keeps behind its lock.
+struct Channels {
+    /// Map of connected WebSocket and SSE clients
+    subscribers: HashMap<usize, Subscriber>,
+    /// Most recent log entries and events, replayed to clients when they connect.
+    replay: VecDeque<ReplayFrame>,
+    /// Sequence number of the next broadcast frame.
+    next_seq: u64,
+}
+
+impl Broadcaster {
+    /// Creates the broadcaster of a server without any clients.
+    pub fn new(server_id: ServerId, config: &WebSocketConfig, metrics: Arc<Metrics>) -> Self {
+        Broadcaster {
+            server_id,
+            channels: Mutex::new(Channels {
+                subscribers: HashMap::new(),
+                replay: VecDeque::new(),
+                next_seq: 1,
+            }),
+            metrics,
+            client_buffer: config.client_buffer,
+            overflow: config.overflow,
+            replay_lines: config.replay_lines,
+        }
+    }
+
+    /// Locks the clients and replay buffer.
+    fn channels(&self) -> MutexGuard<'_, Channels> {
+        // Nothing is left half-updated if a holder panicked
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Registers a new client and returns a channel for receiving logs
+    ///
+    /// The channel starts out with the most recent log entries and events, as
+    /// many as fit in the client's buffer. A client resuming after `after`
+    /// whose missed frames aren't all buffered any more gets a `gap` marker
+    /// first.
+    ///
+    /// # Arguments
+    /// * `encoding` - How frames are rendered for this client
+    /// * `after` - Only replay frames with a sequence number above this, e.g.
+    ///   from an SSE client's `Last-Event-ID` or a WebSocket's `?since=`
+    /// * `remote_ip` - Address the client connected from
+    /// * `principal` - User the client logged in as, if logins are enabled
+    /// * `read_only` - Whether the client can't send commands; SSE clients never can
+    pub fn register_client(
+        &self,
+        encoding: ClientEncoding,
+        after: Option<u64>,
+        remote_ip: Option<String>,
+        principal: Option<&Principal>,
+        read_only: bool,
+    ) -> (usize, ClientReceiver) {
+        let client_id = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = broadcast::channel(self.client_buffer);
+        let mut channels = self.channels();
+        // Catch the client up on recent output; the receiver can't be closed yet
+        let (frames, gap) = channels.replay_for(after, self.client_buffer);
+        if let Some(gap) = gap {
+            let _ = sender.send(gap.render(encoding));
+        }
+        for message in frames.iter().filter_map(|frame| frame.render(encoding)) {
+            let _ = sender.send(message);
+        }
+        let counters = Arc::new(ClientCounters::default());
+        let (kick, kicked) = oneshot::channel();
+        channels.subscribers.insert(
+            client_id,
+            Subscriber {
+                sender,
+                encoding,
+                remote_ip,
+                user: principal.map(|principal| principal.name.clone()),
+                read_only: read_only || matches!(encoding, ClientEncoding::Sse(_)),
+                connected_at: Utc::now(),
+                counters: counters.clone(),
+                commands: 0,
+                last_activity: None,
+                kick: Some(kick),
+            },
+        );
+        self.metrics
+            .connected_clients
+            .fetch_add(1, Ordering::Relaxed);
+        info!(
+            client_id,
+            server = %self.server_id,
+            clients = channels.subscribers.len(),
+            "Client connected"
+        );
+        (
+            client_id,
+            ClientReceiver {
+                client_id,
+                receiver,
+                counters,
+                kicked,
+            },
+        )
+    }
+
+    /// Returns the number of frames replayed to a client when it connects.
+    ///
+    /// # Arguments
+    /// * `after` - Sequence number the client resumes after, if any
+    pub fn replay_len(&self, after: Option<u64>) -> usize {
+        self.channels()
+            .replay_for(after, self.client_buffer)
+            .0
+            .len()
+    }
+
+    /// Returns the number of connected WebSocket clients, leaving out SSE streams.
+    pub fn websocket_clients(&self) -> usize {
+        self.channels()
+            .subscribers
+            .values()
+            .filter(|subscriber| matches!(subscriber.encoding, ClientEncoding::WebSocket(_)))
+            .count()
+    }
+
+    /// Describes the connected WebSocket and SSE clients.
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        self.channels()
+            .subscribers
+            .iter()
+            .map(|(&id, subscriber)| ClientInfo {
+                id,
+                server: self.server_id.clone(),
+                kind: match subscriber.encoding {
+                    ClientEncoding::WebSocket(_) => "websocket",
+                    ClientEncoding::Sse(_) => "sse",
+                },
+                remote_ip: subscriber.remote_ip.clone(),
+                user: subscriber.user.clone(),
+                read_only: subscriber.read_only,
+                connected_at: subscriber.connected_at,
+                messages_sent: subscriber.counters.sent.load(Ordering::Relaxed),
+                messages_dropped: subscriber.counters.dropped.load(Ordering::Relaxed),
+                commands: subscriber.commands,
+                last_activity: subscriber.last_activity,
+            })
+            .collect()
+    }
+
+    /// Notes that a client sent a message, for `GET /clients`.
+    ///
+    /// # Arguments
+    /// * `client_id` - The client
+    /// * `command` - Whether the message was a console command
+    pub fn record_activity(&self, client_id: usize, command: bool) {
+        if let Some(subscriber) = self.channels().subscribers.get_mut(&client_id) {
+            subscriber.last_activity = Some(Utc::now());
+            if command {
+                subscriber.commands += 1;
+            }
+        }
+    }
+
+    /// Disconnects a client on an admin's request. The client's
+    /// [`ClientReceiver`] reports it as kicked once its channel is closed, so
+    /// a WebSocket client is closed with a reason saying so.
+    ///
+    /// # Returns
+    /// * `bool` - False if the client isn't connected to this server
+    pub fn disconnect_client(&self, client_id: usize) -> bool {
+        let mut channels = self.channels();
+        let Some(subscriber) = channels.subscribers.get_mut(&client_id) else {
+            return false;
+        };
+        if let Some(kick) = subscriber.kick.take() {
+            // The receiver is gone if the client is already going away
+            let _ = kick.send(());
+        }
+        self.remove_subscriber(&mut channels, client_id);
+        true
+    }
+
+    /// Unregisters a WebSocket client when they disconnect
+    pub fn unregister_client(&self, client_id: usize) {
+        self.remove_subscriber(&mut self.channels(), client_id);
+    }
+
+    /// Removes a client with the lock already held.
+    fn remove_subscriber(&self, channels: &mut Channels, client_id: usize) {
+        if channels.subscribers.remove(&client_id).is_some() {
+            self.metrics
+                .connected_clients
+                .fetch_sub(1, Ordering::Relaxed);
+            info!(
+                client_id,
+                server = %self.server_id,
+                clients = channels.subscribers.len(),
+                "Client disconnected"
+            );
+        }
+    }
+
+    /// Broadcast a log entry to all connected clients, each in the format it asked for.
+    pub fn broadcast_log(&self, entry: LogEntry) {
+        Metrics::increment(&self.metrics.log_lines_broadcast);
+        self.broadcast_frame(Payload::Log(entry));
+    }
+
+    /// Broadcast a structured event to all connected clients, as JSON or a console line.
+    pub fn broadcast_event(&self, event: &ServerEvent) {
+        self.broadcast_frame(Payload::Event(event.clone()));
+    }
+
+    /// Numbers a frame, sends it to every subscriber and keeps it for replay.
+    fn broadcast_frame(&self, payload: Payload) {
+        let mut channels = self.channels();
+        let frame = ReplayFrame {
+            seq: channels.next_seq,
+            payload,
+        };
+        channels.next_seq += 1;
+        self.send_to_subscribers(&mut channels, |encoding| frame.render(encoding));
+        if self.replay_lines == 0 {
+            return;
+        }
+        // Drop the oldest frames once the replay buffer is full
+        while channels.replay.len() >= self.replay_lines {
+            channels.replay.pop_front();
+        }
+        channels.replay.push_back(frame);
+    }
+
+    /// Sends a message to every subscriber, dropping clients whose channel is
+    /// closed, and with [`OverflowPolicy::Disconnect`] those whose buffer is full
+    ///
+    /// # Arguments
+    /// * `channels` - The clients, with the lock held
+    /// * `render` - Produces the message for a client's encoding, None to send it nothing
+    fn send_to_subscribers(
+        &self,
+        channels: &mut Channels,
+        render: impl Fn(ClientEncoding) -> Option<String>,
+    ) {
+        // Track any clients that need to be disconnected
+        let mut disconnected_clients = Vec::new();
+
+        // For all the clients in the subscribers map
+        // we send the message
+        // If the send fails, we log the error and mark the client for disconnection
+        // This is to avoid sending messages to clients that are no longer connected
+        for (&client_id, subscriber) in &channels.subscribers {
+            let Some(message) = render(subscriber.encoding) else {
+                continue;
+            };
+            if self.overflow == OverflowPolicy::Disconnect
+                && subscriber.sender.len() >= self.client_buffer
+            {
+                warn!(
+                    client_id,
+                    "Client has {} messages waiting, disconnecting it for lagging",
+                    subscriber.sender.len()
+                );
+                disconnected_clients.push(client_id);
+                continue;
+            }
+            match subscriber.sender.send(message) {
+                Ok(_) => {} // Success case - no need to log every message
+                Err(e) => {
+                    Metrics::increment(&self.metrics.broadcast_failures);
+                    warn!(client_id, "Error sending log to client: {:?}", e);
+                    disconnected_clients.push(client_id);
+                }
+            }
+        }
+
+        // Clean up disconnected clients; dropping the sender ends the client's stream
+        for client_id in disconnected_clients {
+            debug!(client_id, "Dropping client");
+            self.remove_subscriber(channels, client_id);
+        }
+    }
+}
+
+impl Channels {
+    /// Works out what a connecting client is replayed.
+    ///
+    /// That is the buffered frames after `after`, as many of the newest as
+    /// fit in the client's buffer. If the client is resuming and doesn't get
+    /// every frame since `after`, one slot is kept for a [`Gap`] marker.
+    ///
+    /// # Returns
+    /// * `(Vec<&ReplayFrame>, Option<Gap>)` - Frames to replay, oldest first, and the marker if any
+    fn replay_for(
+        &self,
+        after: Option<u64>,
+        client_buffer: usize,
+    ) -> (Vec<&ReplayFrame>, Option<Gap>) {
+        let pending: Vec<_> = self
+            .replay
+            .iter()
+            .filter(|frame| frame.seq > after.unwrap_or(0))
+            .collect();
+        let newest = |room: usize| pending[pending.len().saturating_sub(room)..].to_vec();
+        let resumed_at = |frames: &[&ReplayFrame]| frames.first().map_or(self.next_seq, |f| f.seq);
+
+        let frames = newest(client_buffer);
+        match after {
+            Some(since) if resumed_at(&frames) != since.saturating_add(1) => {
+                let frames = newest(client_buffer - 1);
+                let gap = Gap {
+                    since,
+                    resumed_at: resumed_at(&frames),
+                };
+                (frames, Some(gap))
+            }
+            _ => (frames, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// A broadcaster keeping the last 5 frames, after broadcasting 10 lines.
+    fn broadcaster_after_ten_lines() -> Broadcaster {
+        let config = WebSocketConfig {
+            replay_lines: 5,
+            client_buffer: 100,
+            ..WebSocketConfig::default()
+        };
+        let broadcaster = Broadcaster::new("test".to_string(), &config, Arc::default());
+        for n in 1..=10 {
+            broadcaster.broadcast_log(LogEntry::console(format!("line {}", n)));
+        }
+        broadcaster
+    }
+
+    /// Registers a JSON WebSocket client resuming after `after` and returns
+    /// the frames it is replayed.
+    fn replayed(broadcaster: &Broadcaster, after: Option<u64>) -> Vec<Value> {
+        let encoding = ClientEncoding::WebSocket(LogFormat::Json);
+        let (_, mut client) = broadcaster.register_client(encoding, after, None, None, false);
+        let mut frames = Vec::new();
+        while let Ok(frame) = client.receiver.try_recv() {
+            frames.push(serde_json::from_str(&frame).unwrap());
+        }
+        frames
+    }
+
+    fn seqs(frames: &[Value]) -> Vec<u64> {
+        frames.iter().filter_map(|f| f["seq"].as_u64()).collect()
+    }
+
+    #[test]
+    fn new_client_gets_the_buffer() {
+        let frames = replayed(&broadcaster_after_ten_lines(), None);
+        assert_eq!(seqs(&frames), [6, 7, 8, 9, 10]);
+        assert_eq!(frames[0]["type"], "log");
+    }
+
+    #[test]
+    fn resume_at_the_buffer_boundary_has_no_gap() {
+        // 6 is the oldest frame kept, so resuming after 5 misses nothing
+        let frames = replayed(&broadcaster_after_ten_lines(), Some(5));
+        assert_eq!(seqs(&frames), [6, 7, 8, 9, 10]);
+        assert!(frames.iter().all(|frame| frame["type"] != "gap"));
+    }
+
+    #[test]
+    fn resume_past_the_buffer_gets_a_gap_marker() {
+        // Frame 5 has been evicted
+        let frames = replayed(&broadcaster_after_ten_lines(), Some(4));
+        assert_eq!(frames[0]["type"], "gap");
+        assert_eq!(frames[0]["since"], 4);
+        assert_eq!(frames[0]["resumed_at"], 6);
+        assert_eq!(seqs(&frames[1..]), [6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn resume_at_the_latest_frame_replays_nothing() {
+        assert!(replayed(&broadcaster_after_ten_lines(), Some(10)).is_empty());
+    }
+
+    #[test]
+    fn resume_after_an_unknown_seq_gets_a_gap_marker() {
+        // E.g. a client that connected before the backend restarted
+        let frames = replayed(&broadcaster_after_ten_lines(), Some(500));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0]["type"], "gap");
+        assert_eq!(frames[0]["resumed_at"], 11);
+    }
+
+    #[test]
+    fn resume_beyond_the_client_buffer_gets_a_gap_marker() {
+        let config = WebSocketConfig {
+            replay_lines: 5,
+            client_buffer: 3,
+            ..WebSocketConfig::default()
+        };
+        let broadcaster = Broadcaster::new("test".to_string(), &config, Arc::default());
+        for n in 1..=10 {
+            broadcaster.broadcast_log(LogEntry::console(format!("line {}", n)));
+        }
+        // All five missed frames are buffered, but only three fit the client's
+        // buffer, one of them taken by the marker
+        let frames = replayed(&broadcaster, Some(5));
+        assert_eq!(frames[0]["type"], "gap");
+        assert_eq!(frames[0]["resumed_at"], 9);
+        assert_eq!(seqs(&frames[1..]), [9, 10]);
+    }
+
+    #[test]
+    fn live_frames_continue_the_sequence() {
+        let broadcaster = broadcaster_after_ten_lines();
+        let encoding = ClientEncoding::WebSocket(LogFormat::Json);
+        let (_, mut client) = broadcaster.register_client(encoding, Some(10), None, None, false);
+        broadcaster.broadcast_log(LogEntry::console("line 11".to_string()));
+        let frame: Value = serde_json::from_str(&client.receiver.try_recv().unwrap()).unwrap();
+        assert_eq!(frame["seq"], 11);
+    }
+
+    #[test]
+    fn text_clients_get_events_as_console_lines() {
+        let broadcaster = Broadcaster::new(
+            "test".to_string(),
+            &WebSocketConfig::default(),
+            Arc::default(),
+        );
+        let encoding = ClientEncoding::WebSocket(LogFormat::Text);
+        let (_, mut client) = broadcaster.register_client(encoding, None, None, None, false);
+        broadcaster.broadcast_event(&ServerEvent::PlayerJoin {
+            name: "Steve".to_string(),
+        });
+        broadcaster.broadcast_event(&ServerEvent::Stopped { exit_code: Some(0) });
+        // The join is in the server's own output, so only the stop is sent
+        assert_eq!(
+            client.receiver.try_recv().unwrap(),
+            "--- Server stopped (exit code 0) ---"
+        );
+        assert!(client.receiver.try_recv().is_err());
+
+        // A client connecting later is replayed the same
+        let (_, mut late) = broadcaster.register_client(encoding, None, None, None, false);
+        assert_eq!(
+            late.receiver.try_recv().unwrap(),
+            "--- Server stopped (exit code 0) ---"
+        );
+        assert!(late.receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn json_clients_get_every_event() {
+        let broadcaster = Broadcaster::new(
+            "test".to_string(),
+            &WebSocketConfig::default(),
+            Arc::default(),
+        );
+        let encoding = ClientEncoding::WebSocket(LogFormat::Json);
+        let (_, mut client) = broadcaster.register_client(encoding, None, None, None, false);
+        broadcaster.broadcast_event(&ServerEvent::PlayerJoin {
+            name: "Steve".to_string(),
+        });
+        let frame: Value = serde_json::from_str(&client.receiver.try_recv().unwrap()).unwrap();
+        assert_eq!(frame["type"], "player_join");
+        assert_eq!(frame["name"], "Steve");
+    }
+}
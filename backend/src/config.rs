@@ -0,0 +1,778 @@
+//! Backend configuration.
+//!
+//! Settings are read from `minecraft-console.toml` in the working directory
+//! (or the file named by `MC_CONSOLE_CONFIG`) when it exists, then individual
+//! values can be overridden with environment variables. Anything left unset
+//! falls back to a default, so the precedence is env, then file, then default.
+//! Invalid values are reported at startup instead of being silently replaced
+//! by defaults.
+//!
+//! Each subsystem owns its section: [`ServerConfig`], [`BackupConfig`],
+//! [`PerformanceConfig`], [`RconConfig`], [`WebSocketConfig`], [`LogFileConfig`],
+//! [`LoggingConfig`], [`AuditConfig`], [`LifecycleConfig`], [`NotificationConfig`], [`AutoRestartConfig`],
+//! [`RestartScheduleConfig`], [`CountdownConfig`], [`AuthConfig`] and [`CommandPolicy`] live next to the code they configure and know their own
+//! environment variables.
+
+use crate::audit::AuditConfig;
+use crate::auth::AuthConfig;
+use crate::history::HistoryConfig;
+use crate::lifecycle::LifecycleConfig;
+use crate::logging::LoggingConfig;
+use crate::macros::{self, MacroConfig};
+use crate::notifications::NotificationConfig;
+use crate::policy::CommandPolicy;
+use crate::scheduler::{CountdownConfig, RestartScheduleConfig};
+use crate::server::backup::BackupConfig;
+use crate::server::log_file::LogFileConfig;
+use crate::server::mods::ModsConfig;
+use crate::server::motd::MotdConfig;
+use crate::server::performance::PerformanceConfig;
+use crate::server::rcon::RconConfig;
+use crate::server::{
+    is_valid_env_var, is_valid_server_id, ServerConfig, ServerDefinition, DEFAULT_SERVER_ID,
+};
+use crate::supervisor::AutoRestartConfig;
+use crate::webhooks::WebhookTarget;
+use crate::websocket::WebSocketConfig;
+use serde::{Deserialize, Deserializer};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Name of the configuration file looked up in the working directory.
+pub const CONFIG_FILE: &str = "minecraft-console.toml";
+
+/// Commented configuration file with every setting at its default, printed by
+/// `--print-default-config`.
+pub const DEFAULT_CONFIG: &str = r#"# minecraft-console configuration
+#
+# Every setting can also be overridden with the environment variable named
+# in its comment. Environment variables take precedence over this file.
+
+[http]
+# Address to listen on; 127.0.0.1 only accepts local connections (MC_BIND_ADDRESS,
+# or MC_BIND_ADDR for address and port together, e.g. 127.0.0.1:8080)
+bind_address = "0.0.0.0"
+# Port to listen on (MC_PORT)
+port = 8080
+# Origins allowed to call the API from a browser; an empty list allows any, or none
+# when frontend_dir is set (MC_CORS_ORIGINS, comma separated)
+allowed_origins = ["http://localhost:3000"]
+# How long browsers may cache a CORS preflight response, in seconds (MC_CORS_MAX_AGE)
+cors_max_age = 3600
+# PEM certificate chain and private key; with both set the API and WebSocket are
+# only served over HTTPS and wss://; both are read again on SIGHUP or POST /admin/reload-tls
+# (MC_TLS_CERT, MC_TLS_KEY)
+# tls_cert = "/etc/letsencrypt/live/example.com/fullchain.pem"
+# tls_key = "/etc/letsencrypt/live/example.com/privkey.pem"
+# Frontend build (frontend/build after npm run build) to serve at / next to the API;
+# with it, an empty allowed_origins means same-origin only instead of any origin (MC_FRONTEND_DIR)
+# frontend_dir = "../frontend/build"
+
+[server]
+# Script, executable or jar that starts the server, used when /start is called without one;
+# jars are run with java -jar, and server.jar in the working directory is tried last (MC_SERVER_PATH)
+# server_path = "/srv/minecraft/run.sh"
+# Arguments passed to java before -jar, e.g. ["-Xmx4G"]; only used for jars (MC_JVM_ARGS, separated by spaces)
+jvm_args = []
+# java that runs jars; defaults to $JAVA_HOME/bin/java ([server.env] first), then java on PATH (MC_JAVA_PATH)
+# java_path = "/usr/lib/jvm/java-21/bin/java"
+# Lowest Java major version a jar is started with, checked with java -version before
+# starting; e.g. 21 for Minecraft 1.20.5 and newer, 0 accepts any (MC_MIN_JAVA_VERSION)
+min_java_version = 0
+# Write eula=true to eula.txt before starting, accepting the Minecraft EULA
+# (https://aka.ms/MinecraftEULA) for fresh servers; also per start with
+# {"accept_eula": true} (MC_ACCEPT_EULA)
+accept_eula = false
+# Arguments passed to the server (MC_SERVER_ARGS, separated by spaces)
+args = []
+# Directory the server runs in; defaults to the directory of server_path (MC_WORKING_DIR)
+# working_dir = "/srv/minecraft"
+# Console command that shuts the server down (MC_STOP_COMMAND)
+stop_command = "stop"
+# Commands sent before the stop command (MC_PRE_STOP_COMMANDS, separated by ;)
+pre_stop_commands = []
+# Delay after each pre-stop command, in milliseconds (MC_STOP_COMMAND_DELAY_MS)
+stop_command_delay_ms = 500
+# Seconds to wait for the server to exit after the stop command before sending it
+# SIGTERM (MC_STOP_TIMEOUT_SECS)
+stop_timeout_secs = 60
+# Seconds to wait for the server to exit after SIGTERM before killing it; on Windows the
+# server is killed straight away (MC_TERMINATE_TIMEOUT_SECS)
+terminate_timeout_secs = 30
+# Seconds to wait for "Saved the game" after save-all (MC_SAVE_TIMEOUT_SECS)
+save_timeout_secs = 10
+# Seconds to wait for a command's output, e.g. for /command or a WebSocket command with an id,
+# before answering with a timeout error; per request with timeout_secs (MC_RESPONSE_TIMEOUT_SECS)
+response_timeout_secs = 5
+# Console lines buffered for listeners waiting on a command's output (MC_OUTPUT_BUFFER_LINES)
+output_buffer_lines = 1024
+# Commands waiting to be written to the server's console, in the order they were sent;
+# once full, senders wait for a free place (MC_COMMAND_QUEUE_LEN)
+command_queue_len = 64
+# Simulate the server instead of starting it, to try the backend and its clients without
+# Minecraft or Java: commands get canned responses and a fake player joins, chats and
+# leaves. Its console lines come from the "Dry run" thread (MC_DRY_RUN)
+dry_run = false
+
+# Start the server with only the variables from [server.env] instead of also passing on
+# the backend's environment, for reproducible starts; PATH then has to be set there too.
+# Also per start with {"env_clear": true} (MC_ENV_CLEAR)
+env_clear = false
+
+[server.env]
+# Extra environment variables for the server process, added to by {"env": {...}} in a
+# /start request; their values are never shown by /status or in the logs
+# JAVA_HOME = "/usr/lib/jvm/java-21"
+
+# More servers managed alongside the one above, at /servers/{id}/start, /servers/{id}/ws
+# and so on; the routes without the prefix use the server above, whose id is "default".
+# The stop command, timeouts, buffer sizes and accept_eula are taken from [server], and java_path and
+# min_java_version unless set here.
+# [[servers]]
+# id = "creative"
+# server_path = "/srv/creative/server.jar"
+# working_dir = "/srv/creative"
+# jvm_args = ["-Xmx2G"]
+# java_path = "/usr/lib/jvm/java-17/bin/java"
+# min_java_version = 17
+# args = ["nogui"]
+
+[backup]
+# Directory archives are written to; defaults to "backups" in the server directory (MC_BACKUP_DIR)
+# backup_dir = "/srv/minecraft/backups"
+# World directory to back up; defaults to level-name from server.properties (MC_WORLD_DIR)
+# world_dir = "world"
+# Interval between automatic backups such as "6h" or "1h30m"; unset disables them (MC_BACKUP_INTERVAL)
+# interval = "6h"
+# In-game warning before an automatic backup; {seconds} is replaced by warning_secs (MC_BACKUP_WARNING)
+warning_message = "Backup starting in {seconds}s"
+# Seconds between the warning and the backup (MC_BACKUP_WARNING_SECS)
+warning_secs = 60
+# Seconds to wait for "Saved the game" before archiving; the backup fails without it
+# (MC_BACKUP_SAVE_TIMEOUT_SECS)
+save_timeout_secs = 60
+
+[restart_schedule]
+# Local time of a daily restart such as "04:00"; unset disables it. See GET /schedule,
+# and skip the next restart with POST /schedule/restart/skip (MC_RESTART_TIME)
+# time = "04:00"
+# More restarts, each "HH:MM" or a cron expression "minute hour day month weekday"
+# (MC_RESTART_TIMES, separated by ;)
+times = []
+# Minutes before a restart at which players are warned (MC_RESTART_WARNING_MINUTES, comma separated)
+warning_minutes = [15, 10, 5, 1]
+# Restart right away, without the countdown, when nobody is online (MC_RESTART_SKIP_IF_EMPTY)
+skip_countdown_if_empty = true
+# In-game warning during the countdown; {minutes} is replaced by the minutes left (MC_RESTART_WARNING)
+warning_message = "Server restarting in {minutes} minute(s)"
+
+[countdown]
+# Warnings before a stop or restart requested with /stop?countdown=<seconds> or
+# /restart?countdown=<seconds>: players are warned when it starts and this long before
+# the end (MC_COUNTDOWN_WARNINGS, comma separated)
+warnings = ["5m", "1m", "30s", "10s"]
+# In-game warning; {action} is replaced by stopping or restarting, {time} by the time
+# left, e.g. "5 minutes" (MC_COUNTDOWN_MESSAGE)
+message = "Server {action} in {time}"
+
+[performance]
+# Command reporting the tick rate; unset tries neoforge tps, forge tps, tps and tick query (MC_TPS_COMMAND)
+# tps_command = "tps"
+# Command reporting memory usage, e.g. "gc" with EssentialsX; unset skips memory (MC_MEMORY_COMMAND)
+# memory_command = "gc"
+# Seconds between polls (MC_PERFORMANCE_INTERVAL_SECS)
+interval_secs = 30
+# Seconds between readings of the server process's memory and CPU usage from /proc, shown
+# in /status, /stats and /metrics (MC_RESOURCE_INTERVAL_SECS)
+resource_interval_secs = 5
+
+[rcon]
+# Run commands that need a response over RCON when server.properties has enable-rcon=true;
+# otherwise their output is collected from the console (MC_RCON_ENABLED)
+enabled = true
+# Host RCON listens on (MC_RCON_HOST)
+host = "127.0.0.1"
+# Port and password; default to rcon.port and rcon.password from server.properties (MC_RCON_PORT, MC_RCON_PASSWORD)
+# port = 25575
+# password = "secret"
+# Seconds to wait for a connection or a response (MC_RCON_TIMEOUT_SECS)
+timeout_secs = 5
+
+[motd]
+# Command sent to the running server after PUT /motd, over RCON if enabled, for plugins that
+# change the MOTD live; {motd} is replaced by the MOTD with line breaks as \n. Unset, a new
+# MOTD shows after the next start (MC_MOTD_APPLY_COMMAND)
+# apply_command = "motd set {motd}"
+
+[mods]
+# Largest jar accepted by POST /mods/upload, in MiB (MC_MODS_MAX_UPLOAD_MB)
+max_upload_mb = 256
+
+[command_policy]
+# Only commands starting with one of these may be sent by clients; empty allows all
+# (MC_ALLOWED_COMMANDS, comma separated). Changeable at runtime with PUT /policy.
+allowed_prefixes = []
+# Commands starting with one of these are refused, e.g. ["op", "deop", "stop", "whitelist off", "execute"]
+# (MC_DENIED_COMMANDS, comma separated)
+denied_prefixes = []
+# Commands only users with the admin scope may send; operators may send the rest.
+# Only applies when [auth] users are configured (MC_ADMIN_COMMANDS, comma separated)
+admin_prefixes = ["op", "deop", "stop", "ban", "ban-ip", "pardon", "pardon-ip", "whitelist", "save-off", "reload", "execute"]
+
+[websocket]
+# Seconds between pings to WebSocket clients (MC_WS_HEARTBEAT_SECS)
+heartbeat_interval_secs = 5
+# Seconds without a pong before a client is disconnected with a "Heartbeat timeout"
+# close frame; must be longer than the heartbeat interval (MC_WS_CLIENT_TIMEOUT_SECS)
+client_timeout_secs = 10
+# Recent log lines and events replayed to a client when it connects; 0 disables (MC_WS_REPLAY_LINES)
+replay_lines = 200
+# Most WebSocket clients connected at once, across all servers; 0 for no limit (MC_WS_MAX_CLIENTS)
+max_clients = 100
+# Messages buffered for a WebSocket or SSE client that can't keep up (MC_WS_CLIENT_BUFFER)
+client_buffer = 1024
+# Once a client's buffer is full: "drop_oldest" messages or "disconnect" the client (MC_WS_OVERFLOW)
+overflow = "drop_oldest"
+# Commands a client may send per second on average, 0 for no limit (MC_WS_COMMAND_RATE);
+# more are answered with a rate_limited frame
+command_rate = 5.0
+# Commands a client may send in a burst before the rate applies (MC_WS_COMMAND_BURST)
+command_burst = 10
+# Refused commands in a row after which the client is disconnected; 0 never disconnects
+# (MC_WS_COMMAND_DISCONNECT_AFTER)
+command_disconnect_after = 50
+# Line sent to text clients when they connect, with {server}, {client_id} and {timestamp}
+# filled in; "" sends none. Clients can skip it with /ws?banner=false (MC_WS_BANNER)
+banner = "--- Connected to the '{server}' console as client {client_id} ---"
+# Send clients hints for debugging their connection, e.g. about duplicate connections (MC_WS_DEBUG)
+debug = false
+# Announce each command a client sends to every client of the server as
+# "<client N> ran: <command>" (MC_WS_AUDIT_COMMANDS)
+audit_commands = false
+
+[auto_restart]
+# Whether servers that exit without /stop are started again; toggled per server with
+# PUT /autorestart, history at GET /restarts (MC_AUTO_RESTART)
+enabled = false
+# Most restarts within the window before the server is left stopped (MC_AUTO_RESTART_MAX_ATTEMPTS)
+max_attempts = 3
+# Seconds over which restarts are counted (MC_AUTO_RESTART_WINDOW_SECS)
+window_secs = 600
+# Seconds before the first restart, doubled for each further one (MC_AUTO_RESTART_DELAY_SECS)
+initial_delay_secs = 5
+# Longest wait before a restart, in seconds (MC_AUTO_RESTART_MAX_DELAY_SECS)
+max_delay_secs = 300
+
+[logging]
+# What the backend prints about itself, as an env-filter directive such as "info" or
+# "info,backend::websocket=debug"; RUST_LOG takes precedence (MC_LOG_LEVEL)
+level = "info"
+# "text" for readable lines or "json" for one object per line (MC_LOG_FORMAT)
+format = "text"
+
+[log_file]
+# Whether console output is also written to a file, read back by /logs (MC_LOG_FILE_ENABLED)
+enabled = true
+# Path of the log file, one JSON entry per line (MC_LOG_FILE)
+path = "minecraft-console.log"
+
+[audit]
+# Whether starts, stops, restarts and client commands are recorded with who sent them,
+# read back by GET /audit (MC_AUDIT_ENABLED)
+enabled = true
+# Path of the audit file, one JSON entry per line (MC_AUDIT_FILE)
+path = "audit.jsonl"
+# Size in MiB after which the file is moved to audit.jsonl.1; 0 never rotates (MC_AUDIT_MAX_SIZE_MB)
+max_size_mb = 10
+# Rotated files kept besides the current one (MC_AUDIT_MAX_FILES)
+max_files = 5
+
+[lifecycle]
+# Runs of the server processes kept for GET /lifecycle, with start, stop, exit code
+# and duration (MC_LIFECYCLE_MAX_ENTRIES)
+max_entries = 100
+# JSON file keeping the runs across backend restarts; unset keeps them in memory (MC_LIFECYCLE_FILE)
+# path = "lifecycle.json"
+
+[history]
+# Commands clients sent kept for GET /history and GET /commands/history; a command sent
+# again replaces its earlier entry (MC_HISTORY_MAX_ENTRIES)
+max_entries = 500
+# JSON file keeping the commands across backend restarts; unset keeps them in memory
+# (MC_HISTORY_FILE)
+# path = "command_history.json"
+# Commands containing any of these, ignoring case, are kept as their first word and
+# [redacted] (MC_HISTORY_REDACT, comma separated)
+redact_patterns = ["password"]
+
+[macros]
+# Delay between a macro's commands, in milliseconds (MC_MACRO_DELAY_MS)
+delay_ms = 500
+# JSON file macros changed with PUT /macros are kept in, replacing the definitions below
+# once it exists; unset keeps changes until the backend restarts (MC_MACROS_FILE)
+# path = "macros.json"
+
+[macros.definitions]
+# Console commands run in order by POST /macros/{name}/run or a WebSocket
+# {"type": "macro", "name": ...} message; names are letters, digits, '-' and '_'
+# event = ["weather clear", "time set day", "gamerule keepInventory true"]
+
+[notifications]
+# Discord webhook URLs notified of the events below; empty disables notifications
+# (MC_NOTIFY_WEBHOOKS, comma separated). Test them with POST /notifications/test.
+webhooks = []
+# Events to send: started, ready, stopped, crashed, player_join, player_leave,
+# backup_completed and backup_failed; crashed includes servers that need the EULA
+# accepted (MC_NOTIFY_EVENTS, comma separated)
+events = ["started", "stopped", "crashed", "backup_failed"]
+# Identical notifications within this many seconds are sent once, e.g. during a crash loop (MC_NOTIFY_REPEAT_SECS)
+repeat_secs = 300
+
+[auth]
+# Secret signing login tokens, at least 32 characters; unset generates one at startup,
+# so tokens don't survive a restart (MC_JWT_SECRET)
+# jwt_secret = "change-me-to-a-long-random-string"
+# Seconds a token from POST /login is valid (MC_AUTH_TOKEN_TTL_SECS)
+token_ttl_secs = 3600
+
+# Users who may log in; without any, the API is open to anyone who can reach it.
+# Scopes: viewers only watch the console and the server's state, operators also send
+# commands except those in [command_policy] admin_prefixes, and admins may do everything.
+# Create password_hash with `echo -n 'password' | backend --hash-password`.
+# [[auth.users]]
+# name = "admin"
+# password_hash = "$argon2id$v=19$m=19456,t=2,p=1$..."
+# scope = "admin"
+
+# Services that receive every server event as JSON: {"event", "timestamp", "data"}.
+# With a secret, the X-Signature-256 header holds "sha256=" and the hex HMAC-SHA256
+# of the body. Failed deliveries are retried, then appended to webhook-dead-letters.log;
+# recent deliveries are listed at GET /webhooks/deliveries.
+# [[webhooks]]
+# url = "https://example.com/minecraft-events"
+# secret = "change-me"
+# Events to send: player_join, player_leave, started, ready, stopping, stopped, crashed,
+# eula_required, restarting, restart_abandoned and restart_warning; empty sends all
+# events = ["started", "stopped", "crashed"]
+"#;
+
+/// Top-level configuration.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Settings for the HTTP and WebSocket listener.
+    pub http: HttpConfig,
+    /// Settings for launching and stopping the server process.
+    pub server: ServerConfig,
+    /// Settings for world backups.
+    pub backup: BackupConfig,
+    /// Settings for daily restarts.
+    pub restart_schedule: RestartScheduleConfig,
+    /// Warnings before a stop or restart requested with `?countdown=`.
+    pub countdown: CountdownConfig,
+    /// Settings for polling the server's tick rate and memory usage.
+    pub performance: PerformanceConfig,
+    /// Settings for running commands over RCON.
+    pub rcon: RconConfig,
+    /// Settings for changing the MOTD.
+    pub motd: MotdConfig,
+    /// Settings for managing mods.
+    pub mods: ModsConfig,
+    /// Commands clients may send.
+    pub command_policy: CommandPolicy,
+    /// Settings for WebSocket connections.
+    pub websocket: WebSocketConfig,
+    /// Settings for restarting crashed servers.
+    pub auto_restart: AutoRestartConfig,
+    /// Settings for the backend's own diagnostic output.
+    pub logging: LoggingConfig,
+    /// Settings for the console log file.
+    pub log_file: LogFileConfig,
+    /// Settings for the audit log.
+    pub audit: AuditConfig,
+    /// Settings for the history of the server's runs.
+    pub lifecycle: LifecycleConfig,
+    /// Settings for the history of the commands clients sent.
+    pub history: HistoryConfig,
+    /// Named lists of commands run as one action.
+    pub macros: MacroConfig,
+    /// Settings for webhook notifications.
+    pub notifications: NotificationConfig,
+    /// Users who may log in.
+    pub auth: AuthConfig,
+    /// Outbound webhooks receiving server events.
+    pub webhooks: Vec<WebhookTarget>,
+    /// Servers managed in addition to the default one.
+    pub servers: Vec<ServerDefinition>,
+}
+
+/// Settings for the HTTP and WebSocket listener.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address to listen on, e.g. `0.0.0.0` for all interfaces or `127.0.0.1` for local only.
+    pub bind_address: String,
+    /// Port to listen on.
+    pub port: u16,
+    /// Origins allowed to call the API from a browser. An empty list allows
+    /// any origin, or only the same origin when serving the frontend.
+    pub allowed_origins: Vec<String>,
+    /// How long browsers may cache a CORS preflight response, in seconds.
+    pub cors_max_age: usize,
+    /// PEM file with the TLS certificate chain. With [`HttpConfig::tls_key`]
+    /// set too the API and WebSocket are served over HTTPS and `wss://`
+    /// only; without them over plain HTTP.
+    pub tls_cert: Option<PathBuf>,
+    /// PEM file with the private key for [`HttpConfig::tls_cert`].
+    pub tls_key: Option<PathBuf>,
+    /// Directory with the frontend's production build (`npm run build`),
+    /// served at `/` behind the API when set.
+    pub frontend_dir: Option<PathBuf>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            bind_address: "0.0.0.0".to_string(),
+            port: 8080,
+            allowed_origins: vec!["http://localhost:3000".to_string()],
+            cors_max_age: 3600,
+            tls_cert: None,
+            tls_key: None,
+            frontend_dir: None,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_BIND_ADDR` - address and port together, e.g. `127.0.0.1:8080` or `[::1]:8080`
+    /// * `MC_BIND_ADDRESS` - address to listen on, overriding `MC_BIND_ADDR`'s
+    /// * `MC_PORT` - port to listen on, overriding `MC_BIND_ADDR`'s
+    /// * `MC_CORS_ORIGINS` - allowed origins separated by `,` (empty allows any)
+    /// * `MC_CORS_MAX_AGE` - preflight cache time in seconds
+    /// * `MC_TLS_CERT` - PEM certificate chain, enables HTTPS with `MC_TLS_KEY`
+    /// * `MC_TLS_KEY` - PEM private key
+    /// * `MC_FRONTEND_DIR` - frontend build to serve at `/`
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(address) = env_string("MC_BIND_ADDR") {
+            let address: SocketAddr = address.parse().map_err(|_| {
+                format!(
+                    "MC_BIND_ADDR must be an address and port like 127.0.0.1:8080, got '{}'",
+                    address
+                )
+            })?;
+            self.bind_address = address.ip().to_string();
+            self.port = address.port();
+        }
+        if let Some(address) = env_string("MC_BIND_ADDRESS") {
+            self.bind_address = address;
+        }
+        if let Some(port) = env_parse("MC_PORT")? {
+            self.port = port;
+        }
+        if let Ok(origins) = std::env::var("MC_CORS_ORIGINS") {
+            self.allowed_origins = env_list(&origins, ',');
+        }
+        if let Some(max_age) = env_parse("MC_CORS_MAX_AGE")? {
+            self.cors_max_age = max_age;
+        }
+        if let Some(cert) = env_path("MC_TLS_CERT") {
+            self.tls_cert = Some(cert);
+        }
+        if let Some(key) = env_path("MC_TLS_KEY") {
+            self.tls_key = Some(key);
+        }
+        if let Some(dir) = env_path("MC_FRONTEND_DIR") {
+            self.frontend_dir = Some(dir);
+        }
+        Ok(())
+    }
+
+    /// Returns the address and port to listen on.
+    ///
+    /// Only call this after [`HttpConfig::validate`], which checks that the address parses.
+    pub fn socket_addr(&self) -> SocketAddr {
+        let ip = self
+            .bind_address
+            .parse()
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        SocketAddr::new(ip, self.port)
+    }
+
+    /// Checks that the values make sense.
+    fn validate(&self) -> Result<(), String> {
+        if self.bind_address.parse::<IpAddr>().is_err() {
+            return Err(format!(
+                "bind_address must be an IP address, got '{}'",
+                self.bind_address
+            ));
+        }
+        if self.port == 0 {
+            return Err("port must not be 0".to_string());
+        }
+        for origin in &self.allowed_origins {
+            if !(origin.starts_with("http://") || origin.starts_with("https://"))
+                || origin.ends_with('/')
+            {
+                return Err(format!(
+                    "allowed origin '{}' must look like http://host:port, without a trailing slash",
+                    origin
+                ));
+            }
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("tls_cert and tls_key must be set together".to_string());
+        }
+        if let Some(dir) = &self.frontend_dir {
+            if !dir.join("index.html").is_file() {
+                return Err(format!(
+                    "frontend_dir '{}' must contain the frontend build's index.html",
+                    dir.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the config file and environment.
+    ///
+    /// The file is `MC_CONSOLE_CONFIG` if set, otherwise [`CONFIG_FILE`] in
+    /// the working directory. A missing default file is fine; a missing file
+    /// named explicitly is an error.
+    ///
+    /// # Returns
+    /// * `Result<Config, String>` - Error message if the file or a value is invalid
+    pub fn load() -> Result<Self, String> {
+        let mut config = match env_string("MC_CONSOLE_CONFIG") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None if Path::new(CONFIG_FILE).exists() => Self::from_file(Path::new(CONFIG_FILE))?,
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads and parses a config file.
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("Error parsing {}: {}", path.display(), e))
+    }
+
+    /// Applies the environment variable overrides of every section.
+    fn apply_env(&mut self) -> Result<(), String> {
+        self.http.apply_env()?;
+        self.server.apply_env()?;
+        self.backup.apply_env()?;
+        self.restart_schedule.apply_env()?;
+        self.countdown.apply_env()?;
+        self.performance.apply_env()?;
+        self.rcon.apply_env()?;
+        self.motd.apply_env()?;
+        self.mods.apply_env()?;
+        self.command_policy.apply_env()?;
+        self.websocket.apply_env()?;
+        self.auto_restart.apply_env()?;
+        self.logging.apply_env()?;
+        self.log_file.apply_env()?;
+        self.audit.apply_env()?;
+        self.lifecycle.apply_env()?;
+        self.history.apply_env()?;
+        self.macros.apply_env()?;
+        self.auth.apply_env()?;
+        self.notifications.apply_env()
+    }
+
+    /// Checks that the values make sense.
+    fn validate(&self) -> Result<(), String> {
+        self.http.validate()?;
+        self.notifications.validate()?;
+        self.auto_restart.validate()?;
+        self.command_policy.validate()?;
+        self.auth.validate()?;
+        self.logging.validate()?;
+        for webhook in &self.webhooks {
+            webhook.validate()?;
+        }
+        if let Some(interval) = &self.backup.interval {
+            crate::scheduler::parse_duration(interval)
+                .map_err(|e| format!("backup interval: {}", e))?;
+        }
+        self.restart_schedule.validate()?;
+        self.countdown.validate()?;
+        for (name, value) in &self.server.env {
+            if !is_valid_env_var(name, value) {
+                return Err(format!(
+                    "[server.env] variable '{}' must have a name without '=' and no NUL bytes",
+                    name
+                ));
+            }
+        }
+        if self.server.output_buffer_lines == 0 {
+            return Err("output_buffer_lines must be at least 1".to_string());
+        }
+        if self.server.command_queue_len == 0 {
+            return Err("command_queue_len must be at least 1".to_string());
+        }
+        macros::validate(&self.macros.definitions)?;
+        let mut ids = vec![DEFAULT_SERVER_ID];
+        for definition in &self.servers {
+            if !is_valid_server_id(&definition.id) {
+                return Err(format!(
+                    "server id '{}' must be 1 to 32 letters, digits, '-' or '_'",
+                    definition.id
+                ));
+            }
+            if ids.contains(&definition.id.as_str()) {
+                return Err(format!("server id '{}' is used twice", definition.id));
+            }
+            ids.push(&definition.id);
+        }
+        Ok(())
+    }
+}
+
+/// Reads an environment variable, treating an empty value as unset.
+pub fn env_string(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Reads and parses an environment variable.
+///
+/// # Returns
+/// * `Result<Option<T>, String>` - None if unset, an error naming the variable if it doesn't parse
+pub fn env_parse<T: FromStr>(name: &str) -> Result<Option<T>, String> {
+    match env_string(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{} has an invalid value '{}'", name, value)),
+        None => Ok(None),
+    }
+}
+
+/// Reads an environment variable holding a number of seconds.
+pub fn env_secs(name: &str) -> Result<Option<Duration>, String> {
+    Ok(env_parse(name)?.map(Duration::from_secs))
+}
+
+/// Reads an environment variable holding a path.
+pub fn env_path(name: &str) -> Option<PathBuf> {
+    env_string(name).map(PathBuf::from)
+}
+
+/// Splits a list given in an environment variable, dropping empty items.
+pub fn env_list(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Deserializes a whole number of seconds into a Duration.
+pub fn secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+/// Deserializes a whole number of milliseconds into a Duration.
+pub fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Held by the tests that set environment variables, which are shared by the whole process.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Loads the config from a file with `contents` and the given environment variables.
+    fn load(test: &str, contents: &str, env: &[(&str, &str)]) -> Result<Config, String> {
+        let _guard = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let path = std::env::temp_dir().join(format!(
+            "minecraft-console-config-{}-{}.toml",
+            std::process::id(),
+            test
+        ));
+        std::fs::write(&path, contents).unwrap();
+        std::env::set_var("MC_CONSOLE_CONFIG", &path);
+        for (name, value) in env {
+            std::env::set_var(name, value);
+        }
+        let config = Config::load();
+        for (name, _) in env {
+            std::env::remove_var(name);
+        }
+        std::env::remove_var("MC_CONSOLE_CONFIG");
+        let _ = std::fs::remove_file(&path);
+        config
+    }
+
+    #[test]
+    fn env_overrides_file_overrides_default() {
+        let file = "[http]\nport = 9000\ncors_max_age = 120\n\n[server]\nstop_timeout_secs = 45\n";
+        let config = load(
+            "precedence",
+            file,
+            &[("MC_PORT", "9100"), ("MC_STOP_TIMEOUT_SECS", "5")],
+        )
+        .unwrap();
+        // Set in both: the environment wins
+        assert_eq!(config.http.port, 9100);
+        assert_eq!(config.server.stop_timeout, Duration::from_secs(5));
+        // Only in the file
+        assert_eq!(config.http.cors_max_age, 120);
+        // In neither
+        assert_eq!(config.http.bind_address, HttpConfig::default().bind_address);
+        assert_eq!(
+            config.server.command_queue_len,
+            ServerConfig::default().command_queue_len
+        );
+    }
+
+    #[test]
+    fn empty_env_value_keeps_the_file_value() {
+        let config = load("empty", "[http]\nport = 9000\n", &[("MC_PORT", " ")]).unwrap();
+        assert_eq!(config.http.port, 9000);
+    }
+
+    #[test]
+    fn invalid_env_value_is_an_error() {
+        for value in ["http", "70000", "-1"] {
+            let err = load("invalid", "", &[("MC_PORT", value)]).unwrap_err();
+            assert_eq!(err, format!("MC_PORT has an invalid value '{}'", value));
+        }
+        let err = load("invalid-bind", "", &[("MC_BIND_ADDR", "localhost")]).unwrap_err();
+        assert!(err.starts_with("MC_BIND_ADDR must be an address and port"));
+    }
+
+    #[test]
+    fn invalid_file_is_an_error() {
+        let err = load("unknown-key", "[http]\nprot = 9000\n", &[]).unwrap_err();
+        assert!(err.starts_with("Error parsing "), "{}", err);
+        assert!(err.contains("prot"), "{}", err);
+    }
+
+    #[test]
+    fn default_config_file_matches_the_defaults() {
+        let config: Config = toml::from_str(DEFAULT_CONFIG).unwrap();
+        assert_eq!(config.http.port, Config::default().http.port);
+        assert_eq!(
+            config.server.stop_timeout,
+            Config::default().server.stop_timeout
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! Errors returned when managing the server process.
+//!
+//! Starting, stopping and talking to the server can fail in ways clients need
+//! to tell apart: a server that isn't running is a conflict, a bad path is the
+//! caller's mistake, and a broken pipe is our problem. [`ServerError`] keeps
+//! those apart and maps each to an HTTP status and a JSON body, and the
+//! WebSocket sends the same information as an error frame.
+//!
+//! Code that only deals in IO errors (backups, the scheduler) converts back
+//! with `From<ServerError> for std::io::Error`.
+
+use crate::auth::Scope;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Error from starting, stopping or talking to the server process.
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    /// A server is already running, so nothing was started.
+    #[error("Minecraft server is already running from {}", path.display())]
+    AlreadyRunning {
+        /// Script, executable or jar the running server was started from.
+        path: PathBuf,
+        /// Seconds since the running server started.
+        uptime_seconds: Option<u64>,
+    },
+    /// No server is running.
+    #[error("Minecraft server is not running")]
+    NotRunning,
+    /// The server is being stopped, so it can't be started or stopped again yet.
+    #[error("Minecraft server is stopping")]
+    Stopping,
+    /// No server with this id is managed by the backend.
+    #[error("No server with id '{id}'")]
+    UnknownServer { id: String },
+    /// No macro with this name is defined.
+    #[error("No macro named '{name}'")]
+    UnknownMacro { name: String },
+    /// The server directory has no world with this name.
+    #[error("No world named '{name}'")]
+    UnknownWorld { name: String },
+    /// A world's `level.dat` can't be parsed.
+    #[error("The level.dat of world '{name}' can't be read: {reason}")]
+    InvalidLevelDat { name: String, reason: String },
+    /// No WebSocket or SSE client with this id is connected.
+    #[error("No client with id {id} is connected")]
+    UnknownClient { id: usize },
+    /// A server with this id already exists.
+    #[error("A server with id '{id}' already exists")]
+    ServerExists { id: String },
+    /// The id can't be used for a server.
+    #[error("Invalid server id '{id}': use 1 to 32 letters, digits, '-' or '_'")]
+    InvalidServerId { id: String },
+    /// The launch path doesn't exist or isn't a file.
+    #[error("Server path '{}' can't be used: {reason}", path.display())]
+    InvalidPath { path: PathBuf, reason: String },
+    /// An environment variable for the server has an empty name, or a name or
+    /// value that can't be passed to a process.
+    #[error("Environment variable '{name}' can't be set: names must not be empty or contain '=', and neither names nor values may contain NUL bytes")]
+    InvalidEnvVar { name: String },
+    /// The `java` that would run a jar can't be run, or its version can't be read.
+    #[error("Java runtime '{}' can't be used: {reason}", java.display())]
+    JavaUnavailable { java: PathBuf, reason: String },
+    /// The `java` that would run a jar is older than `[server] min_java_version`.
+    #[error("Java {version} is too old, this server needs Java {minimum} or newer")]
+    JavaTooOld {
+        /// Version reported by `java -version`.
+        version: String,
+        /// Its major version.
+        major: u32,
+        /// The configured minimum.
+        minimum: u32,
+    },
+    /// A port the server would listen on is taken, e.g. by a server that is
+    /// still shutting down.
+    #[error("Port {port} ({purpose}) is already in use{}", pid.map(|pid| format!(" by process {}", pid)).unwrap_or_default())]
+    PortInUse {
+        port: u16,
+        /// What the port is for: `server`, `query` or `rcon`.
+        purpose: &'static str,
+        /// Process holding the port, if it could be found.
+        pid: Option<u32>,
+    },
+    /// The server process could not be spawned.
+    #[error("Could not start the server: {source}")]
+    SpawnFailed { source: std::io::Error },
+    /// A command contained a line break or other control character, which
+    /// would let it smuggle a second command onto the console.
+    #[error("Commands must be a single line without control characters")]
+    InvalidCommand,
+    /// The command policy doesn't let clients send this command.
+    #[error("Command denied by policy: {rule}")]
+    CommandDenied {
+        /// The rule that denied the command, e.g. `denied prefix 'op'`.
+        rule: String,
+    },
+    /// The server is running but its console input is closed, either from
+    /// the start or because writing to it broke the pipe.
+    #[error("The server's console input is closed")]
+    StdinUnavailable,
+    /// Writing a command to the server's console failed.
+    #[error("Could not send the command to the server: {source}")]
+    CommandFailed { source: std::io::Error },
+    /// RCON isn't enabled for the server, so commands go through the console.
+    #[error("RCON is not enabled for this server")]
+    RconDisabled,
+    /// Connecting to RCON or running a command over it failed.
+    #[error("RCON command failed: {source}")]
+    RconFailed { source: std::io::Error },
+    /// The server didn't answer a status ping, or answered it with garbage.
+    #[error("Server list ping failed: {source}")]
+    PingFailed { source: std::io::Error },
+    /// The server is older than Minecraft 1.7 and doesn't speak the modern ping protocol.
+    #[error("Unsupported protocol: the server predates Minecraft 1.7")]
+    UnsupportedProtocol,
+    /// The server doesn't have `enable-query=true` in `server.properties`.
+    #[error("Query is not enabled for this server")]
+    QueryDisabled,
+    /// The server didn't answer a query, or answered it with garbage.
+    #[error("Query failed: {source}")]
+    QueryFailed { source: std::io::Error },
+    /// The server didn't answer over RCON, a status ping or a query in time, or
+    /// printed nothing in response to a console command.
+    #[error("{operation} timed out: {source}")]
+    Timeout {
+        /// What timed out, e.g. `RCON`.
+        operation: &'static str,
+        source: std::io::Error,
+    },
+    /// The request has no valid token, or the user name or password was wrong.
+    #[error("Unauthorized: {reason}")]
+    Unauthorized { reason: String },
+    /// The WebSocket client connected with `?mode=viewer` and can't send commands.
+    #[error("This connection is read-only")]
+    ReadOnly,
+    /// The user's scope doesn't allow this.
+    #[error("This requires the {required} scope")]
+    Forbidden { required: Scope },
+    /// Any other IO failure.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// JSON body sent to clients for a [`ServerError`].
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    /// Human-readable description of the error.
+    pub error: String,
+    /// Stable identifier of the kind of error, e.g. `not_running`.
+    pub code: &'static str,
+    /// Path of the running server, for [`ServerError::AlreadyRunning`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Uptime of the running server, for [`ServerError::AlreadyRunning`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_seconds: Option<u64>,
+    /// The policy rule that denied a command, for [`ServerError::CommandDenied`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    /// The detected Java version, for [`ServerError::JavaTooOld`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub java_version: Option<String>,
+    /// The port that is taken, for [`ServerError::PortInUse`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// The process holding the port, for [`ServerError::PortInUse`] if it was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// The scope that was missing, for [`ServerError::Forbidden`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_scope: Option<Scope>,
+}
+
+impl ServerError {
+    /// Wraps an error from talking to the server over the network: Timeout if
+    /// it timed out, otherwise the protocol's own variant.
+    ///
+    /// # Arguments
+    /// * `operation` - What was attempted, e.g. `RCON`
+    /// * `source` - The error
+    /// * `failed` - Builds the protocol's variant, e.g. RconFailed
+    pub fn network(
+        operation: &'static str,
+        source: std::io::Error,
+        failed: fn(std::io::Error) -> Self,
+    ) -> Self {
+        if source.kind() == ErrorKind::TimedOut {
+            ServerError::Timeout { operation, source }
+        } else {
+            failed(source)
+        }
+    }
+
+    /// Timeout for a console command the server printed no response to.
+    ///
+    /// # Arguments
+    /// * `operation` - What was waited for, e.g. `Command`
+    /// * `timeout` - How long was waited
+    pub fn no_response(operation: &'static str, timeout: Duration) -> Self {
+        ServerError::Timeout {
+            operation,
+            source: std::io::Error::new(
+                ErrorKind::TimedOut,
+                format!("no response within {:?}", timeout),
+            ),
+        }
+    }
+
+    /// Returns a stable identifier for the kind of error, used by clients to
+    /// react to specific errors without matching on messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServerError::AlreadyRunning { .. } => "already_running",
+            ServerError::NotRunning => "not_running",
+            ServerError::Stopping => "stopping",
+            ServerError::UnknownServer { .. } => "unknown_server",
+            ServerError::UnknownClient { .. } => "unknown_client",
+            ServerError::UnknownMacro { .. } => "unknown_macro",
+            ServerError::UnknownWorld { .. } => "unknown_world",
+            ServerError::InvalidLevelDat { .. } => "invalid_level_dat",
+            ServerError::ServerExists { .. } => "server_exists",
+            ServerError::InvalidServerId { .. } => "invalid_server_id",
+            ServerError::InvalidPath { .. } => "invalid_path",
+            ServerError::InvalidEnvVar { .. } => "invalid_env_var",
+            ServerError::JavaUnavailable { .. } => "java_unavailable",
+            ServerError::JavaTooOld { .. } => "java_too_old",
+            ServerError::PortInUse { .. } => "port_in_use",
+            ServerError::InvalidCommand => "invalid_command",
+            ServerError::CommandDenied { .. } => "command_denied",
+            ServerError::SpawnFailed { .. } => "spawn_failed",
+            ServerError::StdinUnavailable => "stdin_unavailable",
+            ServerError::CommandFailed { .. } => "command_failed",
+            ServerError::RconDisabled => "rcon_disabled",
+            ServerError::RconFailed { .. } => "rcon_failed",
+            ServerError::PingFailed { .. } => "ping_failed",
+            ServerError::UnsupportedProtocol => "unsupported_protocol",
+            ServerError::QueryDisabled => "query_disabled",
+            ServerError::QueryFailed { .. } => "query_failed",
+            ServerError::Timeout { .. } => "timeout",
+            ServerError::Unauthorized { .. } => "unauthorized",
+            ServerError::ReadOnly => "read_only",
+            ServerError::Forbidden { .. } => "forbidden",
+            ServerError::Io(_) => "io",
+        }
+    }
+
+    /// Builds the JSON body describing this error.
+    pub fn to_body(&self) -> ErrorBody {
+        let (path, uptime_seconds) = match self {
+            ServerError::AlreadyRunning {
+                path,
+                uptime_seconds,
+            } => (Some(path.clone()), *uptime_seconds),
+            _ => (None, None),
+        };
+        let rule = match self {
+            ServerError::CommandDenied { rule } => Some(rule.clone()),
+            _ => None,
+        };
+        let java_version = match self {
+            ServerError::JavaTooOld { version, .. } => Some(version.clone()),
+            _ => None,
+        };
+        let (port, pid) = match self {
+            ServerError::PortInUse { port, pid, .. } => (Some(*port), *pid),
+            _ => (None, None),
+        };
+        ErrorBody {
+            error: self.to_string(),
+            code: self.code(),
+            path,
+            uptime_seconds,
+            rule,
+            java_version,
+            port,
+            pid,
+            required_scope: match self {
+                ServerError::Forbidden { required } => Some(*required),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl ResponseError for ServerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServerError::AlreadyRunning { .. }
+            | ServerError::NotRunning
+            | ServerError::Stopping
+            | ServerError::PortInUse { .. }
+            | ServerError::ServerExists { .. }
+            | ServerError::RconDisabled
+            | ServerError::QueryDisabled => StatusCode::CONFLICT,
+            ServerError::RconFailed { .. }
+            | ServerError::PingFailed { .. }
+            | ServerError::UnsupportedProtocol
+            | ServerError::QueryFailed { .. } => StatusCode::BAD_GATEWAY,
+            ServerError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            ServerError::UnknownServer { .. }
+            | ServerError::UnknownClient { .. }
+            | ServerError::UnknownMacro { .. }
+            | ServerError::UnknownWorld { .. } => StatusCode::NOT_FOUND,
+            ServerError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            ServerError::CommandDenied { .. }
+            | ServerError::ReadOnly
+            | ServerError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            ServerError::InvalidPath { .. }
+            | ServerError::InvalidEnvVar { .. }
+            | ServerError::JavaUnavailable { .. }
+            | ServerError::JavaTooOld { .. }
+            | ServerError::InvalidCommand
+            | ServerError::InvalidServerId { .. } => StatusCode::BAD_REQUEST,
+            ServerError::StdinUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ServerError::SpawnFailed { .. }
+            | ServerError::CommandFailed { .. }
+            | ServerError::InvalidLevelDat { .. }
+            | ServerError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let ServerError::Unauthorized { .. } = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.json(self.to_body())
+    }
+}
+
+impl From<ServerError> for std::io::Error {
+    fn from(e: ServerError) -> Self {
+        match e {
+            ServerError::Io(source) | ServerError::SpawnFailed { source } => source,
+            e => {
+                let kind = match &e {
+                    ServerError::CommandFailed { source }
+                    | ServerError::RconFailed { source }
+                    | ServerError::PingFailed { source }
+                    | ServerError::QueryFailed { source }
+                    | ServerError::Timeout { source, .. } => source.kind(),
+                    ServerError::UnsupportedProtocol => ErrorKind::Unsupported,
+                    ServerError::PortInUse { .. } => ErrorKind::AddrInUse,
+                    ServerError::AlreadyRunning { .. } | ServerError::ServerExists { .. } => {
+                        ErrorKind::AlreadyExists
+                    }
+                    ServerError::UnknownServer { .. }
+                    | ServerError::UnknownClient { .. }
+                    | ServerError::UnknownMacro { .. }
+                    | ServerError::UnknownWorld { .. } => ErrorKind::NotFound,
+                    ServerError::InvalidLevelDat { .. } => ErrorKind::InvalidData,
+                    ServerError::CommandDenied { .. }
+                    | ServerError::Unauthorized { .. }
+                    | ServerError::ReadOnly
+                    | ServerError::Forbidden { .. } => ErrorKind::PermissionDenied,
+                    ServerError::NotRunning
+                    | ServerError::StdinUnavailable
+                    | ServerError::RconDisabled
+                    | ServerError::QueryDisabled => ErrorKind::NotConnected,
+                    ServerError::InvalidPath { .. }
+                    | ServerError::InvalidEnvVar { .. }
+                    | ServerError::JavaUnavailable { .. }
+                    | ServerError::JavaTooOld { .. }
+                    | ServerError::InvalidCommand
+                    | ServerError::InvalidServerId { .. } => ErrorKind::InvalidInput,
+                    _ => ErrorKind::Other,
+                };
+                std::io::Error::new(kind, e.to_string())
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Liveness of the backend itself, reported by `GET /health` and `GET /ready`.
+//!
+//! Kept outside AppState so the liveness check never waits for the state
+//! lock and says nothing about the game servers.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Crate version the backend was built from.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Git commit the backend was built from, if the build could tell.
+pub const GIT_HASH: Option<&str> = option_env!("MC_GIT_HASH");
+
+/// State of the backend's own background tasks.
+#[derive(Debug)]
+pub struct Health {
+    started: Instant,
+    /// True while the log broadcaster task is running.
+    broadcaster: AtomicBool,
+}
+
+impl Health {
+    /// Creates the health state, counting uptime from now.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Health {
+            started: Instant::now(),
+            broadcaster: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns how long the backend has been running.
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Returns whether the log broadcaster task is running.
+    pub fn broadcaster_alive(&self) -> bool {
+        self.broadcaster.load(Ordering::Relaxed)
+    }
+
+    /// Marks the log broadcaster as running until the returned guard is
+    /// dropped, which also happens if the task panics.
+    pub fn broadcaster_running(self: &Arc<Self>) -> TaskGuard {
+        self.broadcaster.store(true, Ordering::Relaxed);
+        TaskGuard(self.clone())
+    }
+}
+
+/// Marks the log broadcaster as stopped when dropped.
+pub struct TaskGuard(Arc<Health>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.0.broadcaster.store(false, Ordering::Relaxed);
+    }
+}
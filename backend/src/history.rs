@@ -0,0 +1,303 @@
+//! History of the console commands clients sent.
+//!
+//! Commands from the web console and from `/command`, `/rcon` and
+//! `/moderation` are recorded once they reached the server, and commands the
+//! command policy refused are recorded as denied, so `GET /history` shows what
+//! was tried as well as what ran. Commands the backend sends itself are not
+//! recorded. WebSocket clients can recall their own recent commands with a
+//! `history` message, and `GET /commands/history` lists a server's executed
+//! commands for a console's up-arrow recall.
+//!
+//! A command a client sends again replaces that client's earlier record of
+//! it, so each command appears once per server, client and status; the
+//! server-wide recall lists each command once, from whichever client sent it
+//! last. Commands containing a redact pattern (e.g.
+//! `password`) are kept as their first word followed by `[redacted]`. With
+//! `[history] path` set, the history is also written to that JSON file and
+//! read back at startup, so it survives restarts of the backend.
+
+use crate::config;
+use crate::server::ServerId;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{error, warn};
+
+/// Settings for the command history.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Commands kept, across all servers.
+    pub max_entries: usize,
+    /// JSON file the history is kept in across restarts; None keeps it in memory only.
+    pub path: Option<PathBuf>,
+    /// Commands containing any of these, ignoring case, are stored redacted.
+    pub redact_patterns: Vec<String>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            max_entries: 500,
+            path: None,
+            redact_patterns: vec!["password".to_string()],
+        }
+    }
+}
+
+impl HistoryConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_HISTORY_MAX_ENTRIES` - commands kept
+    /// * `MC_HISTORY_FILE` - JSON file the history is kept in
+    /// * `MC_HISTORY_REDACT` - redact patterns separated by commas
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(max_entries) = config::env_parse("MC_HISTORY_MAX_ENTRIES")? {
+            self.max_entries = max_entries;
+        }
+        if let Some(path) = config::env_path("MC_HISTORY_FILE") {
+            self.path = Some(path);
+        }
+        if let Ok(patterns) = std::env::var("MC_HISTORY_REDACT") {
+            self.redact_patterns = config::env_list(&patterns, ',');
+        }
+        Ok(())
+    }
+}
+
+/// Whether a recorded command was sent to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    /// The command reached the server.
+    Executed,
+    /// The command policy refused the command.
+    Denied,
+}
+
+/// A command a client sent, as listed by `GET /history`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    /// Server the command was for.
+    pub server: ServerId,
+    /// WebSocket client that sent the command; absent for HTTP requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<usize>,
+    pub command: String,
+    pub status: CommandStatus,
+    pub timestamp: DateTime<Local>,
+}
+
+/// Recent commands, oldest first.
+#[derive(Debug)]
+pub struct CommandHistory {
+    entries: VecDeque<CommandRecord>,
+    max_entries: usize,
+    /// Lowercase redact patterns.
+    redact_patterns: Vec<String>,
+    /// Channel to the task writing the history file, None if it isn't persisted.
+    writer: Option<UnboundedSender<Vec<CommandRecord>>>,
+}
+
+impl CommandHistory {
+    /// Creates the history, reading the history file and starting its writer
+    /// task if one is configured.
+    pub fn new(config: &HistoryConfig) -> Self {
+        let mut entries = VecDeque::new();
+        let writer = config.path.as_ref().map(|path| {
+            match std::fs::read_to_string(path) {
+                Ok(contents) => match serde_json::from_str::<Vec<CommandRecord>>(&contents) {
+                    Ok(records) => entries.extend(records),
+                    Err(e) => warn!("Ignoring command history {}: {}", path.display(), e),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => error!("Could not read command history {}: {}", path.display(), e),
+            }
+            let (writer, rx) = unbounded_channel();
+            tokio::spawn(write_history(rx, path.clone()));
+            writer
+        });
+        while entries.len() > config.max_entries {
+            entries.pop_front();
+        }
+        CommandHistory {
+            entries,
+            max_entries: config.max_entries,
+            redact_patterns: config
+                .redact_patterns
+                .iter()
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| pattern.to_lowercase())
+                .collect(),
+            writer,
+        }
+    }
+
+    /// Records a command, replacing the same client's earlier record of the
+    /// same command and dropping the oldest once the history is full.
+    pub fn record(
+        &mut self,
+        server: &str,
+        client_id: Option<usize>,
+        command: &str,
+        status: CommandStatus,
+    ) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let command = self.redact(command.trim());
+        self.entries.retain(|record| {
+            record.server != server
+                || record.client_id != client_id
+                || record.status != status
+                || record.command != command
+        });
+        while self.entries.len() >= self.max_entries {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(CommandRecord {
+            server: server.to_string(),
+            client_id,
+            command,
+            status,
+            timestamp: Local::now(),
+        });
+        if let Some(writer) = &self.writer {
+            // The writer task only ends when the history is gone
+            let _ = writer.send(self.entries.iter().cloned().collect());
+        }
+    }
+
+    /// Returns the command as it is stored: its first word followed by
+    /// `[redacted]` if it contains a redact pattern, otherwise unchanged.
+    fn redact(&self, command: &str) -> String {
+        let lowercase = command.to_lowercase();
+        if !self
+            .redact_patterns
+            .iter()
+            .any(|pattern| lowercase.contains(pattern.as_str()))
+        {
+            return command.to_string();
+        }
+        match command.split_whitespace().next() {
+            Some(name) if name.len() < command.len() => format!("{} [redacted]", name),
+            _ => "[redacted]".to_string(),
+        }
+    }
+
+    /// Returns the most recent commands that match a filter, newest first.
+    ///
+    /// # Arguments
+    /// * `limit` - Most commands to return
+    /// * `filter` - Selects the commands to return
+    pub fn recent(
+        &self,
+        limit: usize,
+        filter: impl Fn(&CommandRecord) -> bool,
+    ) -> Vec<CommandRecord> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|record| filter(record))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the most recent commands that match a filter, newest first,
+    /// each command only once however many clients sent it.
+    ///
+    /// # Arguments
+    /// * `limit` - Most commands to return
+    /// * `filter` - Selects the commands to return
+    pub fn recent_unique(
+        &self,
+        limit: usize,
+        filter: impl Fn(&CommandRecord) -> bool,
+    ) -> Vec<CommandRecord> {
+        let mut seen = HashSet::new();
+        self.entries
+            .iter()
+            .rev()
+            .filter(|record| filter(record) && seen.insert(record.command.as_str()))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Writes the history file whenever the history changes.
+///
+/// The file is replaced through a temporary file, so a crash mid-write
+/// leaves the previous history intact.
+async fn write_history(mut rx: UnboundedReceiver<Vec<CommandRecord>>, path: PathBuf) {
+    while let Some(mut records) = rx.recv().await {
+        // Only the latest snapshot matters
+        while let Ok(newer) = rx.try_recv() {
+            records = newer;
+        }
+        let json = match serde_json::to_vec(&records) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Could not serialize the command history: {}", e);
+                continue;
+            }
+        };
+        let temporary = path.with_extension("json.tmp");
+        let result = match tokio::fs::write(&temporary, json).await {
+            Ok(()) => tokio::fs::rename(&temporary, &path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Could not write command history {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> CommandHistory {
+        CommandHistory::new(&HistoryConfig::default())
+    }
+
+    fn commands_of(history: &CommandHistory, client_id: usize) -> Vec<String> {
+        history
+            .recent(10, |record| record.client_id == Some(client_id))
+            .into_iter()
+            .map(|record| record.command)
+            .collect()
+    }
+
+    #[test]
+    fn sending_a_command_again_replaces_only_that_clients_record() {
+        let mut history = history();
+        history.record("default", Some(1), "list", CommandStatus::Executed);
+        history.record("default", Some(1), "say hi", CommandStatus::Executed);
+        history.record("default", Some(2), "list", CommandStatus::Executed);
+        history.record("default", Some(1), "say hi", CommandStatus::Executed);
+
+        assert_eq!(commands_of(&history, 1), ["say hi", "list"]);
+        assert_eq!(commands_of(&history, 2), ["list"]);
+    }
+
+    #[test]
+    fn recent_unique_lists_each_command_once() {
+        let mut history = history();
+        history.record("default", Some(1), "list", CommandStatus::Executed);
+        history.record("default", Some(2), "say hi", CommandStatus::Executed);
+        history.record("default", Some(2), "list", CommandStatus::Executed);
+
+        let recent = history.recent_unique(10, |_| true);
+        let commands: Vec<_> = recent
+            .iter()
+            .map(|record| record.command.as_str())
+            .collect();
+        assert_eq!(commands, ["list", "say hi"]);
+        // Credited to whoever sent it last
+        assert_eq!(recent[0].client_id, Some(2));
+    }
+}
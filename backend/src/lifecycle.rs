@@ -0,0 +1,220 @@
+//! History of the server processes' runs.
+//!
+//! Every time a server process ends, whether stopped through the backend,
+//! exiting on its own or crashing, a [`RunRecord`] with its start and end,
+//! exit code and how long it ran is kept, listed by `GET /lifecycle`. The
+//! duration comes from a monotonic clock, so a clock change while the server
+//! runs doesn't distort it.
+//!
+//! With `[lifecycle] path` set, the history is also written to that JSON
+//! file and read back at startup, so it survives restarts of the backend.
+
+use crate::config;
+use crate::server::{ServerId, StopStage};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{error, warn};
+
+/// Settings for the run history.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LifecycleConfig {
+    /// Runs kept, across all servers.
+    pub max_entries: usize,
+    /// JSON file the history is kept in across restarts; None keeps it in memory only.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        LifecycleConfig {
+            max_entries: 100,
+            path: None,
+        }
+    }
+}
+
+impl LifecycleConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_LIFECYCLE_MAX_ENTRIES` - runs kept
+    /// * `MC_LIFECYCLE_FILE` - JSON file the history is kept in
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(max_entries) = config::env_parse("MC_LIFECYCLE_MAX_ENTRIES")? {
+            self.max_entries = max_entries;
+        }
+        if let Some(path) = config::env_path("MC_LIFECYCLE_FILE") {
+            self.path = Some(path);
+        }
+        Ok(())
+    }
+}
+
+/// How a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    /// Stopped through the backend, e.g. `/stop` or a restart.
+    Stopped,
+    /// Exited on its own with exit code 0, e.g. `stop` typed into the console.
+    Exited,
+    /// Exited with an error or was killed by a signal.
+    Crashed,
+}
+
+/// One run of a server process, as listed by `GET /lifecycle`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub server: ServerId,
+    pub started_at: DateTime<Local>,
+    pub stopped_at: DateTime<Local>,
+    /// How long the process ran, measured with a monotonic clock.
+    pub duration_secs: u64,
+    /// None if the process was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub outcome: RunOutcome,
+    /// How far a stop through the backend had to escalate before the process exited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_stage: Option<StopStage>,
+    /// Crash report the game wrote before a crash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash_report: Option<PathBuf>,
+}
+
+/// What is known about how a process ended, besides its outcome.
+#[derive(Debug)]
+pub struct RunEnd {
+    /// Its exit code, None if it was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// How far stopping it had to escalate, for a stop through the backend.
+    pub stop_stage: Option<StopStage>,
+    /// Crash report the run left behind, if any.
+    pub crash_report: Option<PathBuf>,
+}
+
+/// The run history, shared by all servers.
+#[derive(Clone)]
+pub struct Lifecycle {
+    entries: Arc<Mutex<VecDeque<RunRecord>>>,
+    max_entries: usize,
+    /// Channel to the task writing the history file, None if it isn't persisted.
+    writer: Option<UnboundedSender<Vec<RunRecord>>>,
+}
+
+impl Lifecycle {
+    /// Creates the history, reading the history file and starting its writer
+    /// task if one is configured.
+    pub fn new(config: &LifecycleConfig) -> Self {
+        let mut entries = VecDeque::new();
+        let writer = config.path.as_ref().map(|path| {
+            match std::fs::read_to_string(path) {
+                Ok(contents) => match serde_json::from_str::<Vec<RunRecord>>(&contents) {
+                    Ok(records) => entries.extend(records),
+                    Err(e) => warn!("Ignoring run history {}: {}", path.display(), e),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => error!("Could not read run history {}: {}", path.display(), e),
+            }
+            let (writer, rx) = unbounded_channel();
+            tokio::spawn(write_history(rx, path.clone()));
+            writer
+        });
+        while entries.len() > config.max_entries {
+            entries.pop_front();
+        }
+        Lifecycle {
+            entries: Arc::new(Mutex::new(entries)),
+            max_entries: config.max_entries,
+            writer,
+        }
+    }
+
+    /// Records a run that just ended, dropping the oldest once the history is full.
+    ///
+    /// # Arguments
+    /// * `server` - Server the process belonged to
+    /// * `started_at` - When the process was started
+    /// * `duration` - How long it ran
+    /// * `outcome` - How it ended
+    /// * `end` - Exit code, stop stage and crash report of the run
+    pub fn record(
+        &self,
+        server: &str,
+        started_at: DateTime<Local>,
+        duration: Duration,
+        outcome: RunOutcome,
+        end: RunEnd,
+    ) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if self.max_entries == 0 {
+            return;
+        }
+        while entries.len() >= self.max_entries {
+            entries.pop_front();
+        }
+        entries.push_back(RunRecord {
+            server: server.to_string(),
+            started_at,
+            stopped_at: Local::now(),
+            duration_secs: duration.as_secs(),
+            exit_code: end.exit_code,
+            outcome,
+            stop_stage: end.stop_stage,
+            crash_report: end.crash_report,
+        });
+        if let Some(writer) = &self.writer {
+            // The writer task only ends when the history is gone
+            let _ = writer.send(entries.iter().cloned().collect());
+        }
+    }
+
+    /// Returns the most recent runs that match a filter, newest first.
+    ///
+    /// # Arguments
+    /// * `limit` - Most runs to return
+    /// * `filter` - Selects the runs to return
+    pub fn recent(&self, limit: usize, filter: impl Fn(&RunRecord) -> bool) -> Vec<RunRecord> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .rev()
+            .filter(|record| filter(record))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Writes the history file whenever the history changes.
+///
+/// The file is replaced through a temporary file, so a crash mid-write
+/// leaves the previous history intact.
+async fn write_history(mut rx: UnboundedReceiver<Vec<RunRecord>>, path: PathBuf) {
+    while let Some(mut records) = rx.recv().await {
+        // Only the latest snapshot matters
+        while let Ok(newer) = rx.try_recv() {
+            records = newer;
+        }
+        let json = match serde_json::to_vec_pretty(&records) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Could not serialize the run history: {}", e);
+                continue;
+            }
+        };
+        let temporary = path.with_extension("json.tmp");
+        let result = match tokio::fs::write(&temporary, json).await {
+            Ok(()) => tokio::fs::rename(&temporary, &path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Could not write run history {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! The backend's own diagnostic output.
+//!
+//! Messages are emitted with `tracing` and printed to stdout with a timestamp
+//! and level, or as one JSON object per line for log ingestion. Which
+//! messages are printed is an env-filter directive such as `info` or
+//! `info,backend::websocket=debug`; `RUST_LOG` takes precedence over the
+//! configured level. Per-line console output is only logged at `trace`.
+//!
+//! Each request is logged in a span built by [`RequestSpan`], which leaves
+//! the login token of WebSocket and SSE URLs out.
+//!
+//! This is separate from the Minecraft server's console output, which is
+//! kept by [`crate::server::log_file`].
+
+use crate::config;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Uri;
+use actix_web::{web, Error, HttpMessage};
+use serde::Deserialize;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RequestId, RootSpanBuilder};
+use tracing_subscriber::EnvFilter;
+
+/// How diagnostic messages are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown format {}, expected text or json", s)),
+        }
+    }
+}
+
+/// Settings for the backend's diagnostic output.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Env-filter directive selecting what is printed, e.g. `info` or `warn,backend::auth=debug`.
+    pub level: String,
+    /// Whether messages are printed as text or JSON.
+    pub format: OutputFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            format: OutputFormat::Text,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_LOG_LEVEL` - env-filter directive
+    /// * `MC_LOG_FORMAT` - `text` or `json`
+    ///
+    /// `RUST_LOG` is read when the output is set up, see [`init`].
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(level) = config::env_string("MC_LOG_LEVEL") {
+            self.level = level;
+        }
+        if let Some(format) = config::env_parse("MC_LOG_FORMAT")? {
+            self.format = format;
+        }
+        Ok(())
+    }
+
+    /// Checks that the level is a valid filter directive.
+    pub fn validate(&self) -> Result<(), String> {
+        EnvFilter::try_new(&self.level)
+            .map(|_| ())
+            .map_err(|e| format!("Invalid [logging] level {}: {}", self.level, e))
+    }
+}
+
+/// Sets up the diagnostic output. Call once, before anything is logged.
+pub fn init(config: &LoggingConfig) {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&config.level))
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+    // No colors when the output goes to a file or journal
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(std::io::stdout().is_terminal());
+    match config.format {
+        OutputFormat::Text => builder.init(),
+        OutputFormat::Json => builder.json().init(),
+    }
+}
+
+/// Builds the span each request is logged in, with the fields of
+/// tracing-actix-web's default span but the `token` query parameter redacted
+/// from `http.target`, so the tokens of WebSocket and SSE clients don't end up
+/// in the log.
+pub struct RequestSpan;
+
+impl RootSpanBuilder for RequestSpan {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let user_agent = request
+            .headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let request_id = request.extensions().get::<RequestId>().copied();
+        tracing::info_span!(
+            "HTTP request",
+            http.method = %request.method(),
+            http.route = %request.match_pattern().unwrap_or_default(),
+            http.client_ip = %request.connection_info().realip_remote_addr().unwrap_or(""),
+            http.user_agent = %user_agent,
+            http.target = %redacted_target(request.uri()),
+            http.status_code = tracing::field::Empty,
+            otel.status_code = tracing::field::Empty,
+            request_id = request_id.map(tracing::field::display),
+            exception.message = tracing::field::Empty,
+            exception.details = tracing::field::Empty,
+        )
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+/// Returns the path and query of a URI with the value of `token` replaced.
+///
+/// Parameter names are decoded first, so `%74oken` is redacted as well.
+fn redacted_target(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.path().to_string();
+    };
+    let query: Vec<&str> = query
+        .split('&')
+        .map(|pair| {
+            let name = web::Query::<Vec<(String, String)>>::from_query(pair)
+                .ok()
+                .and_then(|pairs| pairs.into_inner().into_iter().next())
+                .map(|(name, _)| name);
+            if name.as_deref() == Some("token") {
+                "token=redacted"
+            } else {
+                pair
+            }
+        })
+        .collect();
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(uri: &str) -> String {
+        redacted_target(&uri.parse().unwrap())
+    }
+
+    #[test]
+    fn token_is_redacted() {
+        assert_eq!(
+            target("/ws?format=json&token=eyJhbGciOiJIUzI1NiJ9.e30.sig&since=4"),
+            "/ws?format=json&token=redacted&since=4"
+        );
+        assert_eq!(target("/events?token=abc"), "/events?token=redacted");
+    }
+
+    #[test]
+    fn encoded_token_name_is_redacted() {
+        assert_eq!(target("/events?%74oken=abc"), "/events?token=redacted");
+    }
+
+    #[test]
+    fn other_parameters_are_kept() {
+        assert_eq!(target("/status"), "/status");
+        assert_eq!(target("/logs?limit=10&tokens=5"), "/logs?limit=10&tokens=5");
+    }
+}
@@ -0,0 +1,210 @@
+//! Named lists of console commands run as one action.
+//!
+//! Macros are defined in `[macros.definitions]` and listed by `GET /macros`;
+//! `PUT /macros` replaces them without restarting the backend. `POST
+//! /macros/{name}/run` or a `macro` WebSocket message sends a macro's
+//! commands in order, a short delay apart, each one checked against the
+//! command policy like a command sent on its own.
+//!
+//! With `[macros] path` set, macros changed at runtime are written to that
+//! JSON file and read back at startup, replacing the ones in the config file.
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{error, warn};
+
+/// Macros by name, each a list of console commands.
+pub type MacroDefinitions = BTreeMap<String, Vec<String>>;
+
+/// Settings for macros.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MacroConfig {
+    /// Delay between a macro's commands.
+    #[serde(rename = "delay_ms", deserialize_with = "config::millis")]
+    pub delay: Duration,
+    /// JSON file macros changed at runtime are kept in; None keeps the changes
+    /// until the backend restarts.
+    pub path: Option<PathBuf>,
+    /// Macros available when there is no macros file yet.
+    pub definitions: MacroDefinitions,
+}
+
+impl Default for MacroConfig {
+    fn default() -> Self {
+        MacroConfig {
+            delay: Duration::from_millis(500),
+            path: None,
+            definitions: MacroDefinitions::new(),
+        }
+    }
+}
+
+impl MacroConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_MACRO_DELAY_MS` - milliseconds between a macro's commands
+    /// * `MC_MACROS_FILE` - JSON file macros changed at runtime are kept in
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(ms) = config::env_parse("MC_MACRO_DELAY_MS")? {
+            self.delay = Duration::from_millis(ms);
+        }
+        if let Some(path) = config::env_path("MC_MACROS_FILE") {
+            self.path = Some(path);
+        }
+        Ok(())
+    }
+}
+
+/// Checks that macro names can be used in a URL and that every macro has
+/// commands that can be written to the console.
+///
+/// # Returns
+/// * `Result<(), String>` - Why the macros can't be used
+pub fn validate(definitions: &MacroDefinitions) -> Result<(), String> {
+    for (name, commands) in definitions {
+        let valid_name = (1..=32).contains(&name.len())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "macro name '{}' must be 1 to 32 letters, digits, '-' or '_'",
+                name
+            ));
+        }
+        if commands.is_empty() {
+            return Err(format!("macro '{}' has no commands", name));
+        }
+        if commands
+            .iter()
+            .any(|command| command.trim().is_empty() || command.contains(char::is_control))
+        {
+            return Err(format!(
+                "macro '{}' has an empty command or one with line breaks",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The macros, adjustable at runtime.
+pub struct Macros {
+    definitions: MacroDefinitions,
+    delay: Duration,
+    /// Channel to the task writing the macros file, None if changes aren't persisted.
+    writer: Option<UnboundedSender<MacroDefinitions>>,
+}
+
+impl Macros {
+    /// Creates the macros from the config, or from the macros file if one is
+    /// configured and exists, and starts the file's writer task.
+    pub fn new(config: &MacroConfig) -> Self {
+        let mut definitions = config.definitions.clone();
+        let writer = config.path.as_ref().map(|path| {
+            match std::fs::read_to_string(path) {
+                Ok(contents) => match serde_json::from_str::<MacroDefinitions>(&contents)
+                    .map_err(|e| e.to_string())
+                    .and_then(|saved| validate(&saved).map(|()| saved))
+                {
+                    Ok(saved) => definitions = saved,
+                    Err(e) => warn!("Ignoring macros file {}: {}", path.display(), e),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => error!("Could not read macros file {}: {}", path.display(), e),
+            }
+            let (writer, rx) = unbounded_channel();
+            tokio::spawn(write_macros(rx, path.clone()));
+            writer
+        });
+        Macros {
+            definitions,
+            delay: config.delay,
+            writer,
+        }
+    }
+
+    /// Returns all macros.
+    pub fn all(&self) -> &MacroDefinitions {
+        &self.definitions
+    }
+
+    /// Returns the commands of a macro, None if there is none by that name.
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.definitions.get(name).map(Vec::as_slice)
+    }
+
+    /// Returns the delay between a macro's commands.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Replaces all macros, writing them to the macros file if there is one.
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Why the macros can't be used, leaving the old ones
+    pub fn replace(&mut self, definitions: MacroDefinitions) -> Result<(), String> {
+        validate(&definitions)?;
+        self.definitions = definitions;
+        if let Some(writer) = &self.writer {
+            // The writer task only ends when the macros are gone
+            let _ = writer.send(self.definitions.clone());
+        }
+        Ok(())
+    }
+}
+
+/// What came of one of a macro's commands.
+#[derive(Debug, Clone, Serialize)]
+pub struct MacroStep {
+    pub command: String,
+    /// Whether the command was written to the console.
+    pub sent: bool,
+    /// Why it wasn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What came of running a macro, as returned by `POST /macros/{name}/run`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MacroRun {
+    pub name: String,
+    /// The commands tried, in order; after a failure without
+    /// `continue_on_error` the rest are left out.
+    pub steps: Vec<MacroStep>,
+    /// Whether every command was sent.
+    pub completed: bool,
+}
+
+/// Writes the macros file whenever the macros change.
+///
+/// The file is replaced through a temporary file, so a crash mid-write
+/// leaves the previous macros intact.
+async fn write_macros(mut rx: UnboundedReceiver<MacroDefinitions>, path: PathBuf) {
+    while let Some(mut definitions) = rx.recv().await {
+        // Only the latest snapshot matters
+        while let Ok(newer) = rx.try_recv() {
+            definitions = newer;
+        }
+        let json = match serde_json::to_vec_pretty(&definitions) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Could not serialize the macros: {}", e);
+                continue;
+            }
+        };
+        let temporary = path.with_extension("json.tmp");
+        let result = match tokio::fs::write(&temporary, json).await {
+            Ok(()) => tokio::fs::rename(&temporary, &path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("Could not write macros file {}: {}", path.display(), e);
+        }
+    }
+}
@@ -6,84 +6,249 @@
 //! - Log forwarding from the Minecraft server to clients
 
 use actix_cors::Cors;
+use actix_web::middleware::{from_fn, Condition};
 use actix_web::{http, web, App, HttpServer};
-use std::sync::{Arc, Mutex};
+use broadcaster::Broadcaster;
+use server::log_parser;
+use server::{ServerId, DEFAULT_SERVER_ID};
+use state::{Broadcast, ServerEvent};
+use std::collections::HashMap;
+use std::sync::Arc;
 use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+use tracing_actix_web::TracingLogger;
 
+mod audit;
+mod auth;
+mod broadcaster;
+mod config;
+mod error;
+mod health;
+mod history;
+mod lifecycle;
+mod logging;
+mod macros;
+mod metrics;
+mod notifications;
+mod policy;
 mod routes;
+mod scheduler;
 mod server;
 mod state;
+mod stats;
+mod supervisor;
+#[cfg(test)]
+mod tests;
+mod tls;
+mod webhooks;
 mod websocket;
 
 /// Main entry point for the application.
 ///
 /// This function:
-/// 1. Sets up communication channels for log messages
+/// 1. Loads the configuration and sets up communication channels for log messages
 /// 2. Initializes shared application state
 /// 3. Creates a log broadcaster task
-/// 4. Configures and starts the Actix-web server
+/// 4. Starts the scheduler for automatic backups, TPS polling and the resource sampler
+/// 5. Configures and starts the Actix-web server
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Create a channel for log messages.
-    let (log_sender, mut log_receiver) = unbounded_channel::<String>();
+    if std::env::args().any(|arg| arg == "--print-default-config") {
+        print!("{}", config::DEFAULT_CONFIG);
+        return Ok(());
+    }
+    if std::env::args().any(|arg| arg == "--hash-password") {
+        // Read the password from stdin so it doesn't end up in the shell history
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        match auth::hash_password(password.trim_end_matches(['\r', '\n'])) {
+            Ok(hash) => println!("{}", hash),
+            Err(e) => {
+                eprintln!("Could not hash the password: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    logging::init(&config.logging);
+    let http_config = config.http.clone();
+    let log_file_config = config.log_file.clone();
+    let auth = auth::Auth::new(&config.auth);
+    if !auth.enabled() {
+        warn!("No users configured in [auth], the API is open to anyone who can reach it");
+    }
+    if config.server.dry_run {
+        warn!("Dry run: servers are simulated, no Minecraft server will be started");
+    }
+    let certificates = match (&http_config.tls_cert, &http_config.tls_key) {
+        (Some(cert), Some(key)) => match tls::Certificates::load(cert, key) {
+            Ok(certificates) => Some(certificates),
+            Err(e) => {
+                error!("Invalid TLS configuration: {}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    // Serving the frontend makes it same-origin, so without configured
+    // origins no cross-origin requests need to be allowed at all
+    let cors_enabled =
+        http_config.frontend_dir.is_none() || !http_config.allowed_origins.is_empty();
+    if cors_enabled && http_config.allowed_origins.is_empty() {
+        warn!("No CORS origins configured, allowing requests from any origin");
+    }
+    if let Some(dir) = &http_config.frontend_dir {
+        info!("Serving the frontend from {}", dir.display());
+    }
 
-    // Initialize the shared state.
-    let state = Arc::new(Mutex::new(state::AppState::new(log_sender)));
+    // Create a channel for log messages of all servers.
+    let (log_sender, mut log_receiver) = unbounded_channel::<(ServerId, Broadcast)>();
+
+    // Track the backend's own tasks for /health, outside the state lock
+    let health = health::Health::new();
+
+    // Initialize the shared state. Tokio's lock doesn't poison: if a handler
+    // panics while holding it, the guard is released and later requests carry on.
+    let state = Arc::new(RwLock::new(state::AppState::new(log_sender, config)));
+
+    // Persist the default server's console output so /logs can serve history
+    let log_file = log_file_config
+        .enabled
+        .then(|| server::log_file::spawn_writer(log_file_config.path.clone()));
 
     // Create a log broadcaster task to forward logs to connected clients
     let state_clone = state.clone();
+    let broadcaster_health = health.clone();
     tokio::spawn(async move {
-        println!("Log broadcaster started");
+        info!("Log broadcaster started");
+        let _running = broadcaster_health.broadcaster_running();
+
+        // Servers are never removed, so each one's broadcaster is looked up
+        // under the state lock only once
+        let mut broadcasters: HashMap<ServerId, Arc<Broadcaster>> = HashMap::new();
 
-        // Process incoming log messages
-        while let Some(log) = log_receiver.recv().await {
-            // Forward logs to all connected WebSocket clients
-            match state_clone.lock() {
-                Ok(mut app_state) => {
+        // Process incoming log messages and events in the order they were sent
+        while let Some((server_id, item)) = log_receiver.recv().await {
+            let broadcaster = match broadcasters.get(&server_id) {
+                Some(broadcaster) => broadcaster.clone(),
+                None => {
+                    let Ok(broadcaster) = state_clone
+                        .read()
+                        .await
+                        .server(Some(&server_id))
+                        .map(|server| server.broadcaster())
+                    else {
+                        continue;
+                    };
+                    broadcasters.insert(server_id.clone(), broadcaster.clone());
+                    broadcaster
+                }
+            };
+            let event = match item {
+                Broadcast::Log(log) => {
                     // Skip empty logs and just newlines to reduce noise
-                    let trimmed = log.trim();
-                    if !trimmed.is_empty() {
-                        // Broadcast the log to the subscribers
-                        app_state.broadcast_log(log);
-                    } else {
-                        // Skip empty messages silently
+                    if log.line.trim().is_empty() {
+                        continue;
+                    }
+                    if let Some(log_file) = &log_file {
+                        if server_id == DEFAULT_SERVER_ID {
+                            let _ = log_file.send(log.clone());
+                        }
                     }
+                    // Lines that match a known console pattern (e.g. a player
+                    // joining) are followed by a structured event
+                    let event = log_parser::parse_line(&log.line).map(ServerEvent::from);
+                    // Forward the log to the clients of the server it came from
+                    broadcaster.broadcast_log(log);
+                    event
                 }
-                Err(e) => {
-                    println!("Error: Could not lock app_state for broadcasting: {:?}", e);
+                Broadcast::Event(event) => Some(event),
+            };
+            // Events change the server's state (players online, readiness), so
+            // only they need the state lock
+            if let Some(event) = event {
+                if let Ok(server) = state_clone.write().await.server_mut(Some(&server_id)) {
+                    server.broadcast_event(&event);
                 }
             }
         }
 
-        println!("Log broadcaster terminated - channel closed");
+        error!("Log broadcaster terminated - channel closed");
     });
 
+    // Run scheduled tasks (automatic backups, TPS polling)
+    tokio::spawn(scheduler::run_backup_scheduler(state.clone()));
+    tokio::spawn(scheduler::run_restart_scheduler(state.clone()));
+    tokio::spawn(scheduler::run_performance_poller(state.clone()));
+
+    // Sample the server process's memory and CPU usage
+    tokio::spawn(stats::run_sampler(state.clone()));
+    tokio::spawn(scheduler::run_exit_watcher(state.clone()));
+
     // Print server startup message
-    println!("Starting server on http://0.0.0.0:8080");
+    let bind_address = http_config.socket_addr();
+    let scheme = if certificates.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    info!("Starting server on {}://{}", scheme, bind_address);
 
     // Configure and run the Actix-web server
-    HttpServer::new(move || {
-        // Configure CORS for frontend communication - allow localhost and 192.168.x.x network
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:3000")
-            .allowed_origin_fn(|origin, _req_head| {
-                let origin_str = origin.as_str();
-                // Allow 192.168.x.x IPs on port 3000
-                origin_str.starts_with("http://192.168.") && origin_str.ends_with(":3000")
-            })
-            .allowed_methods(vec!["GET", "POST"])
+    let app_certificates = certificates.clone();
+    let server = HttpServer::new(move || {
+        // Configure CORS for frontend communication from the configured origins
+        let mut cors = if http_config.allowed_origins.is_empty() {
+            Cors::default().allow_any_origin()
+        } else {
+            http_config
+                .allowed_origins
+                .iter()
+                .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        };
+        cors = cors
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
             .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
             .allowed_header(http::header::CONTENT_TYPE)
             .supports_credentials()
-            .max_age(3600);
+            .max_age(http_config.cors_max_age);
 
-        // Create and configure the application
+        // Create and configure the application, with the frontend (if any)
+        // after the API routes so they take precedence
         App::new()
-            .wrap(cors)
+            .wrap(from_fn(auth::authenticate))
+            .wrap(TracingLogger::<logging::RequestSpan>::new())
+            .wrap(Condition::new(cors_enabled, cors))
             .app_data(web::Data::new(state.clone()))
+            .app_data(web::Data::new(health.clone()))
+            .app_data(web::Data::new(auth.clone()))
+            .app_data(web::Data::new(app_certificates.clone()))
             .configure(routes::init_routes)
-    })
-    .bind("0.0.0.0:8080")?
+            .configure(|cfg| {
+                if let Some(dir) = &http_config.frontend_dir {
+                    routes::init_frontend(cfg, dir);
+                }
+            })
+    });
+    // Serve HTTPS when a certificate is configured, plain HTTP otherwise
+    match &certificates {
+        Some(certificates) => {
+            // Pick up renewed certificates without a restart
+            #[cfg(unix)]
+            tokio::spawn(tls::reload_on_sighup(certificates.clone()));
+            server.bind_rustls_0_23(bind_address, certificates.server_config())?
+        }
+        None => server.bind(bind_address)?,
+    }
     .run()
     .await
 }
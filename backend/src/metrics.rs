@@ -0,0 +1,201 @@
+//! Counters and process statistics exported at `GET /metrics`.
+//!
+//! The counters are plain atomics shared between AppState and the running
+//! MinecraftServer, so they can be bumped without taking the state lock and
+//! keep counting across server restarts. Rendering uses the Prometheus text
+//! exposition format, written by hand to avoid pulling in a client library.
+
+use std::fmt::Write;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Clock ticks per second used by `/proc/<pid>/stat` (USER_HZ, 100 on Linux).
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// Counters updated as the backend runs.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Log lines broadcast to WebSocket clients.
+    pub log_lines_broadcast: AtomicU64,
+    /// Commands written to the server console.
+    pub commands_sent: AtomicU64,
+    /// Messages that could not be delivered to a WebSocket client.
+    pub broadcast_failures: AtomicU64,
+    /// WebSocket clients currently connected.
+    pub connected_clients: AtomicU64,
+}
+
+impl Metrics {
+    /// Adds one to a counter.
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Resource usage of the server process.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessStats {
+    /// Resident set size in bytes.
+    pub resident_memory_bytes: u64,
+    /// User plus system CPU time in seconds.
+    pub cpu_seconds: f64,
+}
+
+impl ProcessStats {
+    /// Reads the statistics of a process from `/proc`.
+    ///
+    /// # Returns
+    /// * `Option<ProcessStats>` - None if `/proc` isn't available (e.g. not on Linux)
+    ///   or the process has exited
+    pub fn read(pid: u32) -> Option<Self> {
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let rss_kb: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+
+        // The command name (field 2) may contain spaces, so split after its closing parenthesis
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+        // utime and stime are fields 14 and 15, i.e. 11 and 12 after the command name
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+
+        Some(ProcessStats {
+            resident_memory_bytes: rss_kb * 1024,
+            cpu_seconds: (utime + stime) as f64 / CLOCK_TICKS_PER_SECOND,
+        })
+    }
+}
+
+/// Builder for a Prometheus text exposition document.
+#[derive(Default)]
+pub struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// Appends a metric with a single unlabelled sample.
+    ///
+    /// # Arguments
+    /// * `name` - Metric name, e.g. `minecraft_server_running`
+    /// * `kind` - Prometheus metric type (`counter` or `gauge`)
+    /// * `help` - One-line description
+    /// * `value` - The sample value
+    pub fn metric(&mut self, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+        self.header(name, kind, help);
+        // Writing to a String can't fail
+        let _ = writeln!(self.text, "{} {}", name, value);
+    }
+
+    /// Appends a metric with a sample for each value of one label.
+    ///
+    /// # Arguments
+    /// * `name` - Metric name, e.g. `minecraft_console_server_running`
+    /// * `kind` - Prometheus metric type (`counter` or `gauge`)
+    /// * `help` - One-line description
+    /// * `label` - Label name, e.g. `server`
+    /// * `samples` - Label value and sample value pairs
+    pub fn labelled<L: AsRef<str>, V: std::fmt::Display>(
+        &mut self,
+        name: &str,
+        kind: &str,
+        help: &str,
+        label: &str,
+        samples: impl IntoIterator<Item = (L, V)>,
+    ) {
+        self.header(name, kind, help);
+        for (label_value, value) in samples {
+            let _ = writeln!(
+                self.text,
+                "{}{{{}=\"{}\"}} {}",
+                name,
+                label,
+                escape_label_value(label_value.as_ref()),
+                value
+            );
+        }
+    }
+
+    /// Appends the HELP and TYPE lines of a metric.
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, escape_help(help));
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    /// Returns the finished document.
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+/// Escapes HELP text: backslashes and line breaks.
+fn escape_help(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Escapes a label value: backslashes, double quotes and line breaks.
+fn escape_label_value(value: &str) -> String {
+    escape_help(value).replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metric_has_help_and_type_lines() {
+        let mut out = Exposition::default();
+        out.metric("a_total", "counter", "Things counted.", 3);
+        out.metric("b", "gauge", "A level.", 1.5);
+        assert_eq!(
+            out.finish(),
+            "# HELP a_total Things counted.\n\
+             # TYPE a_total counter\n\
+             a_total 3\n\
+             # HELP b A level.\n\
+             # TYPE b gauge\n\
+             b 1.5\n"
+        );
+    }
+
+    #[test]
+    fn labelled_metric_has_a_sample_per_label_value() {
+        let mut out = Exposition::default();
+        out.labelled(
+            "up",
+            "gauge",
+            "Up.",
+            "server",
+            [("lobby", 1), ("survival", 0)],
+        );
+        assert_eq!(
+            out.finish(),
+            "# HELP up Up.\n\
+             # TYPE up gauge\n\
+             up{server=\"lobby\"} 1\n\
+             up{server=\"survival\"} 0\n"
+        );
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut out = Exposition::default();
+        out.labelled("up", "gauge", "Up.", "server", [("a\"b\\c\nd", 1)]);
+        assert!(out.finish().ends_with("up{server=\"a\\\"b\\\\c\\nd\"} 1\n"));
+    }
+
+    #[test]
+    fn help_is_escaped() {
+        let mut out = Exposition::default();
+        out.metric("x", "gauge", "C:\\server \"quoted\"\nsecond line", 0);
+        // Quotes only need escaping in label values
+        assert!(out
+            .finish()
+            .starts_with("# HELP x C:\\\\server \"quoted\"\\nsecond line\n"));
+    }
+}
@@ -0,0 +1,423 @@
+//! Discord webhook notifications for server events.
+//!
+//! Selected events, such as a crash or a failed backup, are posted as embeds
+//! to every configured webhook. Notifications are queued and delivered by a
+//! task of their own, so a slow or unreachable Discord never holds up the log
+//! broadcaster. An identical notification within `repeat_secs` of the last one
+//! is dropped, so a crash loop doesn't flood the channel.
+
+use crate::config::{self, env_list};
+use crate::server::ServerId;
+use crate::state::ServerEvent;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{debug, warn};
+
+/// How long a single webhook request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attempts per webhook before a notification is given up.
+const MAX_ATTEMPTS: u32 = 4;
+/// Delay before the first retry, doubled after each further failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest wait honoured from a rate-limited response's `Retry-After`.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Embed colors by outcome.
+const COLOR_GOOD: u32 = 0x2ECC71;
+const COLOR_NEUTRAL: u32 = 0x95A5A6;
+const COLOR_BAD: u32 = 0xE74C3C;
+const COLOR_PLAYER: u32 = 0x3498DB;
+
+/// Events that can be sent as notifications, named as in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Started,
+    Ready,
+    Stopped,
+    Crashed,
+    PlayerJoin,
+    PlayerLeave,
+    BackupCompleted,
+    BackupFailed,
+}
+
+impl NotificationKind {
+    /// Parses a name as used in the config file, e.g. `backup_failed`.
+    fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(name.to_string())).ok()
+    }
+}
+
+/// Settings for webhook notifications.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Discord webhook URLs every notification is posted to.
+    pub webhooks: Vec<String>,
+    /// Events that are sent.
+    pub events: Vec<NotificationKind>,
+    /// Identical notifications within this long of each other are sent once.
+    #[serde(rename = "repeat_secs", deserialize_with = "config::secs")]
+    pub repeat_interval: Duration,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            webhooks: Vec::new(),
+            events: vec![
+                NotificationKind::Started,
+                NotificationKind::Stopped,
+                NotificationKind::Crashed,
+                NotificationKind::BackupFailed,
+            ],
+            repeat_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+impl NotificationConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_NOTIFY_WEBHOOKS` - webhook URLs, comma separated
+    /// * `MC_NOTIFY_EVENTS` - event names, comma separated
+    /// * `MC_NOTIFY_REPEAT_SECS` - seconds within which identical notifications are sent once
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Ok(webhooks) = std::env::var("MC_NOTIFY_WEBHOOKS") {
+            self.webhooks = env_list(&webhooks, ',');
+        }
+        if let Ok(events) = std::env::var("MC_NOTIFY_EVENTS") {
+            self.events = env_list(&events, ',')
+                .iter()
+                .map(|name| {
+                    NotificationKind::parse(name)
+                        .ok_or_else(|| format!("MC_NOTIFY_EVENTS has an unknown event '{}'", name))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(repeat) = config::env_secs("MC_NOTIFY_REPEAT_SECS")? {
+            self.repeat_interval = repeat;
+        }
+        Ok(())
+    }
+
+    /// Checks that the webhook URLs are HTTP(S) URLs.
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, webhook) in self.webhooks.iter().enumerate() {
+            if !(webhook.starts_with("https://") || webhook.starts_with("http://")) {
+                return Err(format!(
+                    "notification webhook {} must be an http:// or https:// URL",
+                    index
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A notification waiting to be delivered.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    kind: NotificationKind,
+    server_id: ServerId,
+    title: String,
+    description: Option<String>,
+}
+
+impl Notification {
+    /// Creates a notification about a server.
+    pub fn new(
+        kind: NotificationKind,
+        server_id: &str,
+        title: String,
+        description: Option<String>,
+    ) -> Self {
+        Notification {
+            kind,
+            server_id: server_id.to_string(),
+            title,
+            description,
+        }
+    }
+
+    /// Describes a server event, None for events that are never notified (`stopping`).
+    pub fn from_event(server_id: &str, event: &ServerEvent) -> Option<Self> {
+        let (kind, title, description) = match event {
+            ServerEvent::Started { path } => (
+                NotificationKind::Started,
+                format!("Server {} started", server_id),
+                Some(path.display().to_string()),
+            ),
+            ServerEvent::Ready => (
+                NotificationKind::Ready,
+                format!("Server {} is ready", server_id),
+                None,
+            ),
+            ServerEvent::Stopping
+            | ServerEvent::Version(_)
+            | ServerEvent::Overloaded { .. }
+            | ServerEvent::Restarting { .. }
+            | ServerEvent::RestartAbandoned { .. }
+            | ServerEvent::RestartWarning { .. } => return None,
+            ServerEvent::Stopped { exit_code } => (
+                NotificationKind::Stopped,
+                format!("Server {} stopped", server_id),
+                exit_code.map(|code| format!("Exit code {}", code)),
+            ),
+            ServerEvent::Crashed {
+                exit_code,
+                crash_report,
+            } => {
+                let exit = match exit_code {
+                    Some(code) => format!("Exit code {}", code),
+                    None => "Killed by a signal".to_string(),
+                };
+                (
+                    NotificationKind::Crashed,
+                    format!("Server {} crashed", server_id),
+                    Some(
+                        match crash_report.as_ref().and_then(|path| path.file_name()) {
+                            Some(name) => {
+                                format!("{}, crash report {}", exit, name.to_string_lossy())
+                            }
+                            None => exit,
+                        },
+                    ),
+                )
+            }
+            ServerEvent::EulaRequired { eula_file } => (
+                NotificationKind::Crashed,
+                format!("Server {} needs the EULA accepted", server_id),
+                Some(format!("Set eula=true in {}", eula_file.display())),
+            ),
+            ServerEvent::ConsoleUnavailable => (
+                NotificationKind::Crashed,
+                format!("Server {} closed its console input", server_id),
+                Some("Commands only work over RCON until it is restarted".to_string()),
+            ),
+            ServerEvent::PlayerJoin { name } => (
+                NotificationKind::PlayerJoin,
+                format!("{} joined the game", name),
+                None,
+            ),
+            ServerEvent::PlayerLeave { name } => (
+                NotificationKind::PlayerLeave,
+                format!("{} left the game", name),
+                None,
+            ),
+        };
+        Some(Notification::new(kind, server_id, title, description))
+    }
+
+    /// Builds the webhook request body with the notification as an embed.
+    fn payload(&self) -> Value {
+        let color = match self.kind {
+            NotificationKind::Started
+            | NotificationKind::Ready
+            | NotificationKind::BackupCompleted => COLOR_GOOD,
+            NotificationKind::Stopped => COLOR_NEUTRAL,
+            NotificationKind::Crashed | NotificationKind::BackupFailed => COLOR_BAD,
+            NotificationKind::PlayerJoin | NotificationKind::PlayerLeave => COLOR_PLAYER,
+        };
+        let mut embed = json!({
+            "title": self.title,
+            "color": color,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "footer": { "text": format!("minecraft-console · {}", self.server_id) },
+        });
+        if let Some(description) = &self.description {
+            embed["description"] = json!(description);
+        }
+        json!({ "embeds": [embed] })
+    }
+
+    /// Identifies notifications that are the same for rate limiting.
+    fn key(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.server_id,
+            self.title,
+            self.description.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// Outcome of posting to one webhook, as reported by `POST /notifications/test`.
+#[derive(Debug, Serialize)]
+pub struct WebhookResult {
+    /// Position of the webhook in the config; the URL itself is a secret.
+    pub webhook: usize,
+    pub delivered: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Handle for sending notifications, cheap to clone.
+#[derive(Clone)]
+pub struct Notifier {
+    config: Arc<NotificationConfig>,
+    client: reqwest::Client,
+    /// Queue to the delivery task, None if no webhooks are configured.
+    queue: Option<UnboundedSender<Notification>>,
+}
+
+impl Notifier {
+    /// Creates the notifier and, if any webhooks are configured, starts the
+    /// task that delivers notifications. Must be called within the Tokio runtime.
+    pub fn new(config: NotificationConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        let config = Arc::new(config);
+        let queue = (!config.webhooks.is_empty()).then(|| {
+            let (queue, rx) = unbounded_channel();
+            tokio::spawn(deliver(rx, config.clone(), client.clone()));
+            queue
+        });
+        Notifier {
+            config,
+            client,
+            queue,
+        }
+    }
+
+    /// Queues a notification if its event is selected. Never waits for delivery.
+    pub fn notify(&self, notification: Notification) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        if self.config.events.contains(&notification.kind) {
+            // The delivery task only ends when every notifier is gone
+            let _ = queue.send(notification);
+        }
+    }
+
+    /// Returns true if at least one webhook is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    /// Posts a test message to every webhook once, bypassing the event
+    /// selection and rate limit.
+    ///
+    /// # Returns
+    /// * `Vec<WebhookResult>` - Whether each webhook accepted the message
+    pub async fn send_test(&self) -> Vec<WebhookResult> {
+        let payload = Notification::new(
+            NotificationKind::Ready,
+            "test",
+            "Test notification".to_string(),
+            Some("Webhook notifications from minecraft-console are working.".to_string()),
+        )
+        .payload();
+        let mut results = Vec::new();
+        for (webhook, url) in self.config.webhooks.iter().enumerate() {
+            let error = post(&self.client, url, &payload).await.err();
+            results.push(WebhookResult {
+                webhook,
+                delivered: error.is_none(),
+                error: error.map(|e| e.message),
+            });
+        }
+        results
+    }
+}
+
+/// Delivers queued notifications until every notifier is gone.
+///
+/// Each webhook request runs in a task of its own, so retrying a failing
+/// webhook doesn't delay the others or the next notification.
+async fn deliver(
+    mut rx: UnboundedReceiver<Notification>,
+    config: Arc<NotificationConfig>,
+    client: reqwest::Client,
+) {
+    let mut last_sent: HashMap<String, Instant> = HashMap::new();
+    while let Some(notification) = rx.recv().await {
+        let now = Instant::now();
+        last_sent.retain(|_, sent| now.duration_since(*sent) < config.repeat_interval);
+        let key = notification.key();
+        if last_sent.contains_key(&key) {
+            debug!("Skipping repeated notification '{}'", notification.title);
+            continue;
+        }
+        last_sent.insert(key, now);
+
+        let payload = Arc::new(notification.payload());
+        for (webhook, url) in config.webhooks.iter().enumerate() {
+            let client = client.clone();
+            let url = url.clone();
+            let payload = payload.clone();
+            tokio::spawn(async move {
+                if let Err(e) = post_with_retry(&client, &url, &payload).await {
+                    warn!("Giving up on webhook {}: {}", webhook, e.message);
+                }
+            });
+        }
+    }
+}
+
+/// Why a webhook request failed.
+struct PostError {
+    message: String,
+    /// Whether trying again might work, e.g. not for an invalid webhook URL.
+    retryable: bool,
+    /// How long Discord asked us to wait before the next request.
+    retry_after: Option<Duration>,
+}
+
+/// Posts to a webhook, retrying failures with exponential backoff.
+async fn post_with_retry(
+    client: &reqwest::Client,
+    url: &str,
+    payload: &Value,
+) -> Result<(), PostError> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match post(client, url, payload).await {
+            Ok(()) => return Ok(()),
+            Err(e) if !e.retryable || attempt == MAX_ATTEMPTS => return Err(e),
+            Err(e) => {
+                tokio::time::sleep(e.retry_after.unwrap_or(backoff)).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Posts to a webhook once.
+async fn post(client: &reqwest::Client, url: &str, payload: &Value) -> Result<(), PostError> {
+    let response = client
+        .post(url)
+        .json(payload)
+        .send()
+        .await
+        .map_err(|e| PostError {
+            message: e.to_string(),
+            retryable: true,
+            retry_after: None,
+        })?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<f64>().ok())
+        .map(|secs| Duration::from_secs_f64(secs.max(0.0)).min(MAX_RETRY_AFTER));
+    Err(PostError {
+        message: format!("webhook answered {}", status),
+        retryable: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+        retry_after,
+    })
+}
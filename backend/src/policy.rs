@@ -0,0 +1,145 @@
+//! Allow and deny lists for console commands sent by clients.
+//!
+//! Commands typed into the web console or sent to `/command`, `/rcon` and
+//! `/moderation` are checked against the policy before they reach the server;
+//! commands the backend sends itself (backup and restart announcements, TPS
+//! polling) are not. A command matches a prefix when its first words are the
+//! prefix's words, so `op` matches `op Steve` and `/OP Steve` but not
+//! `opengui`, and a `minecraft:` style namespace on the command is ignored.
+//!
+//! Deny rules win over allow rules. With no allow rules every command that
+//! isn't denied is allowed. Note that `execute ... run <command>` runs another
+//! command, so a deny list should include `execute` as well.
+//!
+//! When logins are enabled, viewers can't send commands at all and operators
+//! can't send the admin commands, e.g. `op` or `stop`; see [`crate::auth`].
+
+use crate::auth::Scope;
+use crate::config::env_list;
+use crate::error::ServerError;
+use serde::{Deserialize, Serialize};
+
+/// Commands only admins may send unless `admin_prefixes` is configured.
+const DEFAULT_ADMIN_PREFIXES: &[&str] = &[
+    "op",
+    "deop",
+    "stop",
+    "ban",
+    "ban-ip",
+    "pardon",
+    "pardon-ip",
+    "whitelist",
+    "save-off",
+    "reload",
+    "execute",
+];
+
+/// Which commands clients may send.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandPolicy {
+    /// If not empty, only commands starting with one of these are allowed.
+    pub allowed_prefixes: Vec<String>,
+    /// Commands starting with one of these are denied.
+    pub denied_prefixes: Vec<String>,
+    /// Commands starting with one of these need the admin scope; operators
+    /// may send the rest.
+    pub admin_prefixes: Vec<String>,
+}
+
+impl Default for CommandPolicy {
+    fn default() -> Self {
+        CommandPolicy {
+            allowed_prefixes: Vec::new(),
+            denied_prefixes: Vec::new(),
+            admin_prefixes: DEFAULT_ADMIN_PREFIXES
+                .iter()
+                .map(|prefix| prefix.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl CommandPolicy {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_ALLOWED_COMMANDS` - allowed prefixes, comma separated
+    /// * `MC_DENIED_COMMANDS` - denied prefixes, comma separated
+    /// * `MC_ADMIN_COMMANDS` - prefixes of commands that need the admin scope, comma separated
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Ok(allowed) = std::env::var("MC_ALLOWED_COMMANDS") {
+            self.allowed_prefixes = env_list(&allowed, ',');
+        }
+        if let Ok(denied) = std::env::var("MC_DENIED_COMMANDS") {
+            self.denied_prefixes = env_list(&denied, ',');
+        }
+        if let Ok(admin) = std::env::var("MC_ADMIN_COMMANDS") {
+            self.admin_prefixes = env_list(&admin, ',');
+        }
+        Ok(())
+    }
+
+    /// Checks that no prefix is empty, which would match every command.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut prefixes = self
+            .allowed_prefixes
+            .iter()
+            .chain(&self.denied_prefixes)
+            .chain(&self.admin_prefixes);
+        if prefixes.any(|prefix| words(prefix).is_empty()) {
+            return Err("command policy prefixes must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks whether a client may send a command.
+    ///
+    /// # Arguments
+    /// * `command` - The console command
+    /// * `scope` - Scope of the client's user, None when logins are disabled
+    ///
+    /// # Returns
+    /// * `Result<(), ServerError>` - CommandDenied naming the rule that denied it, or
+    ///   Forbidden naming the scope the command needs
+    pub fn check(&self, command: &str, scope: Option<Scope>) -> Result<(), ServerError> {
+        let command = words(command);
+        let matches = |prefix: &String| command.starts_with(&words(prefix));
+
+        if let Some(prefix) = self.denied_prefixes.iter().find(|prefix| matches(prefix)) {
+            return Err(ServerError::CommandDenied {
+                rule: format!("denied prefix '{}'", prefix),
+            });
+        }
+        if !self.allowed_prefixes.is_empty() && !self.allowed_prefixes.iter().any(matches) {
+            return Err(ServerError::CommandDenied {
+                rule: "not in the allowed prefixes".to_string(),
+            });
+        }
+        let required = if self.admin_prefixes.iter().any(matches) {
+            Scope::Admin
+        } else {
+            Scope::Operator
+        };
+        match scope {
+            Some(scope) if scope < required => Err(ServerError::Forbidden { required }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Splits a command or prefix into lowercase words, without a leading `/`
+/// or a namespace on the command name.
+fn words(command: &str) -> Vec<String> {
+    let mut words: Vec<String> = command
+        .trim()
+        .trim_start_matches('/')
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect();
+    if let Some(name) = words.first_mut() {
+        if let Some((_, unqualified)) = name.split_once(':') {
+            *name = unqualified.to_string();
+        }
+    }
+    words
+}
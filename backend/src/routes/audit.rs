@@ -0,0 +1,54 @@
+//! HTTP handler for reading the audit log.
+
+use crate::audit;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Number of entries returned when the request doesn't say.
+const DEFAULT_LIMIT: usize = 100;
+/// Largest page a client may request.
+const MAX_LIMIT: usize = 1000;
+
+/// Query parameters for `GET /audit`.
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// Maximum number of entries to return, at most 1000.
+    pub limit: Option<usize>,
+    /// RFC 3339 timestamp; only entries recorded at or after it are returned.
+    pub since: Option<DateTime<Local>>,
+}
+
+/// HTTP handler returning the most recent audit entries.
+///
+/// The audit files are read on the blocking thread pool after the state lock
+/// has been released. Entries are written from their own task, so an action
+/// may take a moment to show up.
+///
+/// # Returns
+/// * JSON array of audit entries, newest first
+/// * Not found if auditing is disabled
+pub async fn audit_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    let config = state.read().await.audit.config().clone();
+    if !config.enabled {
+        return HttpResponse::NotFound().body("Auditing is disabled");
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let since = query.since;
+    match web::block(move || audit::read_entries(&config, limit, since)).await {
+        Ok(Ok(entries)) => HttpResponse::Ok().json(entries),
+        Ok(Err(e)) => {
+            HttpResponse::InternalServerError().body(format!("Error reading audit log: {}", e))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Error reading audit log: {}", e))
+        }
+    }
+}
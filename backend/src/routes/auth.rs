@@ -0,0 +1,68 @@
+//! HTTP handler for logging in.
+//!
+//! `POST /login` exchanges a user name and password from `[auth]` for a token
+//! to send with later requests; see [`crate::auth`].
+
+use crate::auth::{Auth, Scope};
+use crate::error::ServerError;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Request body for `POST /login`.
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// Response body for a successful login.
+#[derive(Serialize)]
+struct LoginResponse {
+    /// Token to send as `Authorization: Bearer <token>`.
+    token: String,
+    scope: Scope,
+    /// Unix time the token expires; log in again before then.
+    expires_at: u64,
+}
+
+/// HTTP handler checking a user's password and issuing a token.
+///
+/// # Returns
+/// * The token, the user's scope and when the token expires
+/// * Unauthorized if the user name or password is wrong
+/// * Conflict if no users are configured
+pub async fn login_handler(
+    req: HttpRequest,
+    auth: web::Data<Arc<Auth>>,
+    body: web::Json<LoginRequest>,
+) -> HttpResponse {
+    if !auth.enabled() {
+        return HttpResponse::Conflict().body("Authentication is not enabled");
+    }
+    let LoginRequest { username, password } = body.into_inner();
+    let auth = auth.get_ref().clone();
+    let name = username.clone();
+    // Verifying an argon2 hash takes a while, keep it off the worker thread
+    match web::block(move || auth.login(&name, &password)).await {
+        Ok(Ok((token, principal))) => {
+            info!(user = %principal.name, scope = %principal.scope, "Logged in");
+            HttpResponse::Ok().json(LoginResponse {
+                token,
+                scope: principal.scope,
+                expires_at: principal.expires_at,
+            })
+        }
+        Ok(Err(e)) => {
+            let remote_ip = req.peer_addr().map(|addr| addr.ip().to_string());
+            warn!(
+                user = %username,
+                remote_ip = remote_ip.as_deref().unwrap_or("unknown"),
+                "Failed login"
+            );
+            e.error_response()
+        }
+        Err(e) => ServerError::Io(std::io::Error::other(e.to_string())).error_response(),
+    }
+}
@@ -0,0 +1,111 @@
+//! HTTP handlers for world backups.
+
+use crate::server::backup;
+use crate::state::AppState;
+use actix_files::NamedFile;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Query parameters for `POST /backups/{name}/restore`.
+#[derive(Deserialize)]
+pub struct RestoreQuery {
+    /// Only inspect the archive without touching the world.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// HTTP handler to back up the active world.
+///
+/// Progress lines are streamed to WebSocket clients while the backup runs.
+///
+/// # Returns
+/// * JSON response with the archive's file name and size
+/// * Conflict if another backup is already running
+/// * Error response with details if the backup failed
+pub async fn backup_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    match backup::run_backup(state.get_ref()).await {
+        Ok(info) => HttpResponse::Ok().json(info),
+        Err(e) if e.kind() == ErrorKind::ResourceBusy => backup_error_response(e),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error creating backup: {}", e)),
+    }
+}
+
+/// HTTP handler listing the archives in the backups directory.
+///
+/// # Returns
+/// * JSON array with name, size and creation time of each archive, newest first
+pub async fn list_backups_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let backup_dir = {
+        let app_state = state.read().await;
+        app_state
+            .backup_config
+            .backup_dir(&app_state.default_server().server_dir())
+    };
+    match web::block(move || backup::list_backups(&backup_dir)).await {
+        Ok(Ok(backups)) => HttpResponse::Ok().json(backups),
+        Ok(Err(e)) => {
+            HttpResponse::InternalServerError().body(format!("Error listing backups: {}", e))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error listing backups: {}", e)),
+    }
+}
+
+/// HTTP handler streaming an archive to the client.
+///
+/// # Returns
+/// * The archive as an attachment
+/// * Bad request for names that aren't plain archive file names, not found for unknown ones
+pub async fn download_backup_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    name: web::Path<String>,
+) -> HttpResponse {
+    let backup_dir = {
+        let app_state = state.read().await;
+        app_state
+            .backup_config
+            .backup_dir(&app_state.default_server().server_dir())
+    };
+    let file = backup::resolve_backup(&backup_dir, &name).and_then(NamedFile::open);
+    match file {
+        Ok(file) => file
+            .use_last_modified(true)
+            .set_content_disposition(actix_web::http::header::ContentDisposition::attachment(
+                name.as_str(),
+            ))
+            .into_response(&http_req),
+        Err(e) => backup_error_response(e),
+    }
+}
+
+/// HTTP handler restoring an archive over the active world.
+///
+/// Pass `?dry_run=true` to only check the archive. Progress is streamed to
+/// WebSocket clients.
+///
+/// # Returns
+/// * JSON report of what was restored
+/// * Conflict if the server is running
+pub async fn restore_backup_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    name: web::Path<String>,
+    query: web::Query<RestoreQuery>,
+) -> impl Responder {
+    match backup::restore_backup(state.get_ref(), &name, query.dry_run).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => backup_error_response(e),
+    }
+}
+
+/// Maps a backup error to an HTTP response.
+fn backup_error_response(e: std::io::Error) -> HttpResponse {
+    match e.kind() {
+        ErrorKind::InvalidInput => HttpResponse::BadRequest().body(e.to_string()),
+        ErrorKind::NotFound => HttpResponse::NotFound().body(e.to_string()),
+        ErrorKind::ResourceBusy => HttpResponse::Conflict().body(e.to_string()),
+        _ => HttpResponse::InternalServerError().body(format!("Backup error: {}", e)),
+    }
+}
@@ -0,0 +1,168 @@
+//! HTTP handlers for the ban lists.
+//!
+//! While the Minecraft server is running, bans and pardons are issued as
+//! console commands. When it is stopped, the JSON files in the server
+//! directory are edited directly instead.
+
+use crate::server::bans;
+use crate::state::{self, AppState};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Request body for `POST /bans`.
+#[derive(Deserialize)]
+pub struct BanRequest {
+    /// Player name, or IP address when `ip` is set.
+    pub target: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Ban an IP address instead of a player.
+    #[serde(default)]
+    pub ip: bool,
+}
+
+/// Returns true if `name` is a valid Minecraft player name.
+pub fn is_valid_player_name(name: &str) -> bool {
+    (1..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// HTTP handler listing the player and IP ban lists.
+///
+/// # Returns
+/// * JSON object with `players` and `ips` arrays
+/// * Error response if a ban list could not be read
+pub async fn list_bans_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let server_dir = state.read().await.default_server().server_dir();
+
+    let players = match bans::load_banned_players(&server_dir) {
+        Ok(players) => players,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!(
+                "Error reading {}: {}",
+                bans::BANNED_PLAYERS_FILE,
+                e
+            ))
+        }
+    };
+    let ips = match bans::load_banned_ips(&server_dir) {
+        Ok(ips) => ips,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!(
+                "Error reading {}: {}",
+                bans::BANNED_IPS_FILE,
+                e
+            ))
+        }
+    };
+
+    let players: Vec<_> = players
+        .iter()
+        .map(|b| json!({ "expired": b.details.is_expired(), "entry": b }))
+        .collect();
+    let ips: Vec<_> = ips
+        .iter()
+        .map(|b| json!({ "expired": b.details.is_expired(), "entry": b }))
+        .collect();
+    HttpResponse::Ok().json(json!({ "players": players, "ips": ips }))
+}
+
+/// HTTP handler to ban a player or IP address.
+///
+/// # Returns
+/// * Success response once the ban was issued or written
+/// * Bad request if the target or reason is malformed
+/// * Not found if an offline ban targets a player the server has never seen
+pub async fn ban_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<BanRequest>,
+) -> impl Responder {
+    let target = req.target.trim();
+    if req.ip && target.parse::<IpAddr>().is_err() {
+        return HttpResponse::BadRequest().body(format!("Invalid IP address: {}", target));
+    }
+    if !req.ip && !is_valid_player_name(target) {
+        return HttpResponse::BadRequest().body(format!("Invalid player name: {}", target));
+    }
+    let reason = req.reason.as_deref().unwrap_or("").trim();
+    if reason.contains(['\n', '\r']) {
+        return HttpResponse::BadRequest().body("Ban reason must be a single line");
+    }
+
+    let app_state = state.read().await;
+    if app_state.default_server().is_running() {
+        drop(app_state);
+        let verb = if req.ip { "ban-ip" } else { "ban" };
+        let command = format!("{} {} {}", verb, target, reason);
+        return match state::send_command(&state, None, command.trim_end()).await {
+            Ok(_) => HttpResponse::Ok().body(format!("Ban issued for {}.", target)),
+            Err(e) => e.error_response(),
+        };
+    }
+
+    let server_dir = app_state.default_server().server_dir();
+    drop(app_state);
+    let reason = if reason.is_empty() {
+        "Banned by an operator."
+    } else {
+        reason
+    };
+    let result = if req.ip {
+        bans::ban_ip(&server_dir, target, reason)
+    } else {
+        bans::ban_player(&server_dir, target, reason)
+    };
+    match result {
+        Ok(true) => HttpResponse::Ok().body(format!("{} banned.", target)),
+        Ok(false) => HttpResponse::Ok().body(format!("{} is already banned.", target)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            HttpResponse::NotFound().body(e.to_string())
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error writing ban: {}", e)),
+    }
+}
+
+/// HTTP handler to lift a ban. IP addresses are pardoned from the IP list,
+/// anything else is treated as a player name.
+///
+/// # Returns
+/// * Success response once the pardon was issued or written
+/// * Not found if an offline pardon targets something that isn't banned
+pub async fn pardon_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    target: web::Path<String>,
+) -> impl Responder {
+    let target = target.trim();
+    let is_ip = target.parse::<IpAddr>().is_ok();
+    if !is_ip && !is_valid_player_name(target) {
+        return HttpResponse::BadRequest().body(format!("Invalid player name: {}", target));
+    }
+
+    let app_state = state.read().await;
+    if app_state.default_server().is_running() {
+        drop(app_state);
+        let verb = if is_ip { "pardon-ip" } else { "pardon" };
+        return match state::send_command(&state, None, &format!("{} {}", verb, target)).await {
+            Ok(_) => HttpResponse::Ok().body(format!("Pardon issued for {}.", target)),
+            Err(e) => e.error_response(),
+        };
+    }
+
+    let server_dir = app_state.default_server().server_dir();
+    drop(app_state);
+    let result = if is_ip {
+        bans::pardon_ip(&server_dir, target)
+    } else {
+        bans::pardon_player(&server_dir, target)
+    };
+    match result {
+        Ok(true) => HttpResponse::Ok().body(format!("{} pardoned.", target)),
+        Ok(false) => HttpResponse::NotFound().body(format!("{} is not banned.", target)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Error writing ban list: {}", e))
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! HTTP handlers for the clients attached to the consoles.
+//!
+//! `GET /clients` lists the WebSocket and SSE clients of every server, and
+//! `DELETE /clients/{id}` disconnects one, e.g. a stale session.
+
+use crate::audit::{AuditAction, Caller};
+use crate::error::ServerError;
+use crate::server::DEFAULT_SERVER_ID;
+use crate::state::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// HTTP handler listing the connected clients.
+///
+/// # Returns
+/// * JSON array of clients with their server, kind, address, connect time,
+///   message counts, commands sent and last activity, ordered by id
+pub async fn list_clients_handler(state: web::Data<Arc<RwLock<AppState>>>) -> HttpResponse {
+    HttpResponse::Ok().json(state.read().await.clients())
+}
+
+/// HTTP handler disconnecting a client.
+///
+/// A WebSocket client is closed with the reason "Disconnected by admin"; an
+/// SSE stream just ends.
+///
+/// # Returns
+/// * No content once the client was unregistered
+/// * Not found if no client with the id is connected
+pub async fn disconnect_client_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    client_id: web::Path<usize>,
+) -> Result<HttpResponse, ServerError> {
+    let client_id = client_id.into_inner();
+    let app_state = state.read().await;
+    let result = app_state.disconnect_client(client_id);
+    let server = result.as_deref().unwrap_or(DEFAULT_SERVER_ID).to_string();
+    app_state.audit.record(
+        &Caller::http(&req),
+        &server,
+        AuditAction::DisconnectClient,
+        Some(&client_id.to_string()),
+        &result,
+    );
+    result?;
+    Ok(HttpResponse::NoContent().finish())
+}
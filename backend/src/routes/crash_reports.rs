@@ -0,0 +1,65 @@
+//! HTTP handlers for the crash reports the Minecraft server wrote.
+//!
+//! `GET /crash-reports` lists the reports in the server's `crash-reports`
+//! directory with a preview of each, and `GET /crash-reports/{name}` returns
+//! a whole report. Both also serve `/servers/{id}/...`.
+
+use crate::error::ServerError;
+use crate::routes::handlers::server_id;
+use crate::server::crash_reports;
+use crate::state::AppState;
+use actix_files::NamedFile;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// HTTP handler listing the crash reports.
+///
+/// # Returns
+/// * JSON array with name, size, modification time, description and the
+///   first KiB of each report, newest first
+/// * Not found if there is no server with the id in the path
+pub async fn list_crash_reports_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    let server_dir = state.read().await.server(server_id(&req))?.server_dir();
+    Ok(
+        match web::block(move || crash_reports::list_reports(&server_dir)).await {
+            Ok(Ok(reports)) => HttpResponse::Ok().json(reports),
+            Ok(Err(e)) => HttpResponse::InternalServerError()
+                .body(format!("Error listing crash reports: {}", e)),
+            Err(e) => HttpResponse::InternalServerError()
+                .body(format!("Error listing crash reports: {}", e)),
+        },
+    )
+}
+
+/// HTTP handler streaming a whole crash report to the client.
+///
+/// # Returns
+/// * The report as plain text
+/// * Bad request for names that aren't plain report file names, not found
+///   for unknown ones or if there is no server with the id in the path
+pub async fn crash_report_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    let server_dir = state.read().await.server(server_id(&req))?.server_dir();
+    let name = req.match_info().get("name").unwrap_or_default();
+    let file = crash_reports::resolve_report(&server_dir, name).and_then(NamedFile::open);
+    Ok(match file {
+        Ok(file) => file
+            .use_last_modified(true)
+            .set_content_type(ContentType::plaintext().0)
+            .into_response(&req),
+        Err(e) => match e.kind() {
+            ErrorKind::InvalidInput => HttpResponse::BadRequest().body(e.to_string()),
+            ErrorKind::NotFound => HttpResponse::NotFound().body(e.to_string()),
+            _ => HttpResponse::InternalServerError()
+                .body(format!("Error reading crash report: {}", e)),
+        },
+    })
+}
@@ -0,0 +1,117 @@
+//! Server-Sent Events stream of the console.
+//!
+//! `GET /events` is a lighter alternative to the WebSocket for clients that
+//! only want to watch: log lines arrive as `event: log`, lifecycle changes as
+//! `event: status` and players joining or leaving as `event: player`. Every
+//! message carries its sequence number as the SSE id, so a reconnecting client
+//! sending `Last-Event-ID` only gets what it missed from the replay buffer,
+//! preceded by an `event: gap` if some of it has aged out.
+//!
+//! `GET /logs/stream` is the same stream for tools that only want the log:
+//! each line is an unnamed event whose data is the plain line, so it reaches
+//! a browser `EventSource`'s `onmessage` while the named events don't.
+//!
+//! `/servers/{id}/events` and `/servers/{id}/logs/stream` stream that server's
+//! console, `/events` and `/logs/stream` the default server's.
+
+use crate::auth::Principal;
+use crate::broadcaster::{Broadcaster, ClientEncoding, ClientReceiver};
+use crate::error::ServerError;
+use crate::routes::handlers::server_id;
+use crate::server::log_entry::LogFormat;
+use crate::state::AppState;
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::{interval_at, Instant, Interval};
+
+/// How often a comment is sent on an idle stream to keep proxies from closing it.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Unregisters an SSE client when its stream is dropped, i.e. when the
+/// connection closes.
+struct SubscriberGuard {
+    broadcaster: Arc<Broadcaster>,
+    client_id: usize,
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        self.broadcaster.unregister_client(self.client_id);
+    }
+}
+
+/// State of one open event stream.
+struct EventStream {
+    receiver: ClientReceiver,
+    heartbeat: Interval,
+    _guard: SubscriberGuard,
+}
+
+/// HTTP handler streaming console output and events as Server-Sent Events.
+///
+/// # Returns
+/// * A `text/event-stream` response that stays open until the client disconnects
+/// * Not found if there is no server with the id in the path
+pub async fn events_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    event_stream(req, state, ClientEncoding::Sse(LogFormat::Json)).await
+}
+
+/// HTTP handler streaming the console log as Server-Sent Events, one plain
+/// line per `data:` field, starting with the replay buffer.
+///
+/// # Returns
+/// * A `text/event-stream` response that stays open until the client disconnects
+/// * Not found if there is no server with the id in the path
+pub async fn log_stream_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    event_stream(req, state, ClientEncoding::Sse(LogFormat::Text)).await
+}
+
+/// Registers an SSE client and streams its frames until it disconnects.
+async fn event_stream(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    encoding: ClientEncoding,
+) -> Result<HttpResponse, ServerError> {
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    let broadcaster = state.read().await.server(server_id(&req))?.broadcaster();
+    let remote_ip = req.peer_addr().map(|addr| addr.ip().to_string());
+    let principal = req.extensions().get::<Principal>().cloned();
+    let (client_id, receiver) =
+        broadcaster.register_client(encoding, last_event_id, remote_ip, principal.as_ref(), true);
+
+    let stream = EventStream {
+        receiver,
+        heartbeat: interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL),
+        _guard: SubscriberGuard {
+            broadcaster,
+            client_id,
+        },
+    };
+    let body = futures_util::stream::unfold(stream, |mut stream| async move {
+        let chunk = tokio::select! {
+            message = stream.receiver.recv() => Bytes::from(message?),
+            _ = stream.heartbeat.tick() => Bytes::from_static(b": keepalive\n\n"),
+        };
+        Some((Ok::<_, actix_web::Error>(chunk), stream))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body))
+}
@@ -0,0 +1,93 @@
+//! Static hosting of the React frontend.
+//!
+//! With `frontend_dir` set, the production build is served at `/` after the
+//! API routes, so an API path always wins over a file of the same name.
+//! Browser navigations to paths that aren't files (client-side routes) get
+//! `index.html`; other unknown paths still get a plain 404.
+//!
+//! Files under `/static/` have a content hash in their name and are cached
+//! for a year, while `index.html` is always revalidated so a new build is
+//! picked up on the next load.
+
+use actix_files::{Files, NamedFile};
+use actix_web::dev::{fn_service, Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpResponse};
+use std::path::{Path, PathBuf};
+
+/// Cache-Control for build assets whose names change with their content.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Cache-Control for `index.html` and other pages.
+const NO_CACHE: &str = "no-cache";
+
+/// Serves the frontend build from `dir`, falling back to its `index.html`.
+///
+/// Configure this after every API route: it matches any path.
+pub fn init_frontend(cfg: &mut web::ServiceConfig, dir: &Path) {
+    let index = dir.join("index.html");
+    let files = Files::new("/", dir)
+        .index_file("index.html")
+        .default_handler(fn_service(move |req: ServiceRequest| {
+            let index = index.clone();
+            async move { spa_fallback(req, index).await }
+        }));
+
+    cfg.service(
+        web::scope("")
+            .wrap_fn(|req, srv| {
+                let hashed = req.path().starts_with("/static/");
+                let response = srv.call(req);
+                async move {
+                    let mut response = response.await?;
+                    set_cache_control(&mut response, hashed);
+                    Ok(response)
+                }
+            })
+            .service(files),
+    );
+}
+
+/// Answers a path that isn't a file: `index.html` for a browser navigation,
+/// so client-side routes survive a reload, and 404 for anything else.
+async fn spa_fallback(
+    req: ServiceRequest,
+    index: PathBuf,
+) -> Result<ServiceResponse, actix_web::Error> {
+    let (req, _) = req.into_parts();
+    let navigation = req.method() == actix_web::http::Method::GET
+        && req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+    let response = if navigation {
+        NamedFile::open_async(&index).await?.into_response(&req)
+    } else {
+        HttpResponse::NotFound().finish()
+    };
+    Ok(ServiceResponse::new(req, response))
+}
+
+/// Sets Cache-Control on a successful response: immutable for hashed build
+/// assets, no-cache for HTML pages.
+fn set_cache_control(response: &mut ServiceResponse, hashed: bool) {
+    if !response.status().is_success() {
+        return;
+    }
+    let html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/html"));
+    let value = if hashed {
+        IMMUTABLE
+    } else if html {
+        NO_CACHE
+    } else {
+        return;
+    };
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+}
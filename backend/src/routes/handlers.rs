@@ -2,58 +2,416 @@
 //!
 //! This file contains the implementation of HTTP handlers for various
 //! endpoints like starting/stopping the server and checking status.
+//!
+//! The lifecycle handlers serve both `/servers/{id}/...` and the unprefixed
+//! routes, which act on the default server.
 
-use crate::state::AppState;
+use crate::audit::{AuditAction, Caller};
+use crate::error::ServerError;
+use crate::routes::{
+    audit, auth, backup, bans, clients, crash_reports, events, health, history, lifecycle, logs,
+    macros, metrics, moderation, mods, motd, notifications, ping, players, policy, properties,
+    query, rcon, restarts, schedule, servers, stats, tls, version, webhooks, whitelist, worlds,
+};
+use crate::scheduler;
+use crate::server::java::JavaVersion;
+use crate::server::performance::{OverloadSummary, PerformanceStats};
+use crate::server::ping::{self as server_ping, PingResponse};
+use crate::server::{StopOutcome, StopStage, DEFAULT_SERVER_ID};
+use crate::state::{self, AppState, ManagedServer, StartOptions};
+use crate::stats::ResourceSample;
 use crate::websocket::ws_index;
-use actix_web::{web, HttpResponse, Responder};
-use serde::Deserialize;
-use std::sync::{Arc, Mutex};
+use actix_web::middleware::Compress;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// How long `/status` waits for the running server to answer a status ping.
+const STATUS_PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns the id in a `/servers/{id}/...` route, or None for the unprefixed
+/// routes that act on the default server.
+pub fn server_id(req: &HttpRequest) -> Option<&str> {
+    req.match_info().get("id")
+}
+
+/// Request body for `/start`.
+#[derive(Deserialize)]
+pub struct StartRequest {
+    /// Script or executable that starts the server. Defaults to the configured server path.
+    #[serde(default)]
+    pub file_path: Option<String>,
+    /// Write `eula=true` to `eula.txt` before starting, accepting the Minecraft EULA.
+    #[serde(default)]
+    pub accept_eula: bool,
+    /// Start even if a port from `server.properties` is in use.
+    #[serde(default)]
+    pub force: bool,
+    /// Environment variables for the server, added to `[server.env]`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Start with only the configured and given variables instead of also the
+    /// backend's environment; defaults to `[server] env_clear`.
+    #[serde(default)]
+    pub env_clear: Option<bool>,
+}
+
+impl From<StartRequest> for StartOptions {
+    fn from(req: StartRequest) -> Self {
+        StartOptions {
+            file_path: req.file_path,
+            accept_eula: req.accept_eula,
+            force: req.force,
+            env: req.env,
+            env_clear: req.env_clear,
+        }
+    }
+}
 
 /// HTTP handler to start the Minecraft server.
 ///
+/// The request body is optional; without one the configured server path is used.
+/// Errors are answered with a JSON [`ErrorBody`](crate::error::ErrorBody).
+///
 /// # Returns
 /// * Success response if the server was started successfully
-/// * Error response with details if the server failed to start
+/// * Bad request if the path doesn't exist or isn't a file, an environment variable can't be set,
+///   or a jar's Java is missing or too old
+/// * Conflict with the running server's path and uptime if one is already running, if
+///   one is being stopped, or
+///   with the port and owning process if a port it listens on is taken (unless `force`)
+/// * Not found if there is no server with the id in the path
+/// * Internal server error if the process could not be spawned
+pub async fn start_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: Option<web::Json<StartRequest>>,
+) -> Result<HttpResponse, ServerError> {
+    let options: StartOptions = req.map(|req| req.0.into()).unwrap_or_default();
+    let file_path = options.file_path.clone();
+    let mut app_state = state.write().await;
+    let server = app_state.server_mut(server_id(&http_req))?;
+    let id = server.id().to_string();
+    let result = server.start_minecraft(options).await;
+    app_state.audit.record(
+        &Caller::http(&http_req),
+        &id,
+        AuditAction::Start,
+        file_path.as_deref(),
+        &result,
+    );
+    result?;
+    Ok(HttpResponse::Ok().body("Minecraft server started."))
+}
+
+/// Query parameters for `/stop` and `/restart`.
 #[derive(Deserialize)]
-pub struct StartRequest {
-    pub file_path: String,
+pub struct CountdownQuery {
+    /// Seconds during which players are warned in game before the server is
+    /// stopped or restarted; absent or 0 acts at once.
+    #[serde(default)]
+    pub countdown: u64,
 }
 
-pub async fn start_handler(
-    state: web::Data<Arc<Mutex<AppState>>>,
-    req: web::Json<StartRequest>,
-) -> impl Responder {
-    let mut app_state = state.lock().unwrap();
-    match app_state.start_minecraft(Some(req.file_path.clone())).await {
-        Ok(_) => HttpResponse::Ok().body("Minecraft server started."),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Error starting server: {}", e)),
+/// HTTP handler to stop the Minecraft server.
+///
+/// With `?countdown=<seconds>` players are warned first and the server is
+/// stopped in the background once the countdown is over.
+///
+/// The stop escalates from the stop command to SIGTERM to killing the
+/// process; the response says which of them the server finally exited on.
+///
+/// # Returns
+/// * Success response if the server was stopped, or its pending automatic restart cancelled
+/// * Accepted if a countdown was started
+/// * Conflict if no server is running or it is already being stopped, or a
+///   countdown is already running
+/// * Not found if there is no server with the id in the path
+/// * Internal server error if the server failed to stop
+pub async fn stop_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<CountdownQuery>,
+) -> Result<HttpResponse, ServerError> {
+    if query.countdown > 0 {
+        return start_countdown(&req, &state, query.countdown, false).await;
     }
+    let message = match stop_server(&state, server_id(&req), &Caller::http(&req)).await? {
+        Some(outcome) => match outcome.stage {
+            StopStage::Command => "Minecraft server stopped.",
+            StopStage::Terminate => "Minecraft server stopped after SIGTERM.",
+            StopStage::Kill => "Minecraft server killed after it did not stop.",
+        },
+        None => "Cancelled the pending automatic restart.",
+    };
+    Ok(HttpResponse::Ok().body(message))
 }
 
-/// HTTP handler to stop the Minecraft server.
+/// HTTP handler to stop the Minecraft server and start it again the way it
+/// was last started.
+///
+/// With `?countdown=<seconds>` players are warned first and the server is
+/// restarted in the background once the countdown is over.
 ///
 /// # Returns
-/// * Success response if the server was stopped successfully
-/// * Error response with details if the server failed to stop
-pub async fn stop_handler(state: web::Data<Arc<Mutex<AppState>>>) -> impl Responder {
-    let mut app_state = state.lock().unwrap();
-    match app_state.stop_minecraft().await {
-        Ok(_) => HttpResponse::Ok().body("Minecraft server stopped."),
-        Err(e) => HttpResponse::InternalServerError().body(format!("Error stopping server: {}", e)),
+/// * Success response once the server was started again
+/// * Accepted if a countdown was started
+/// * Conflict if no server is running, or a countdown is already running
+/// * Not found if there is no server with the id in the path
+/// * Internal server error if the server failed to stop or start
+pub async fn restart_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<CountdownQuery>,
+) -> Result<HttpResponse, ServerError> {
+    if query.countdown > 0 {
+        return start_countdown(&req, &state, query.countdown, true).await;
     }
+    restart_server(&state, server_id(&req), &Caller::http(&req)).await?;
+    Ok(HttpResponse::Ok().body("Minecraft server restarted."))
 }
 
-/// HTTP handler to check the server status.
+/// Stops a server and records it in the audit log.
 ///
 /// # Returns
-/// * Response indicating whether the server is running or not
-pub async fn status_handler(state: web::Data<Arc<Mutex<AppState>>>) -> impl Responder {
-    let app_state = state.lock().unwrap();
-    if app_state.is_running() {
-        HttpResponse::Ok().body("Minecraft server is running.")
+/// * `Result<Option<StopOutcome>, ServerError>` - As [`state::stop_server`]
+async fn stop_server(
+    state: &Arc<RwLock<AppState>>,
+    id: Option<&str>,
+    caller: &Caller,
+) -> Result<Option<StopOutcome>, ServerError> {
+    let result = state::stop_server(state, id).await;
+    state::audit(state, caller, id, AuditAction::Stop, None, &result).await;
+    result
+}
+
+/// Restarts a running server and records it in the audit log.
+///
+/// # Returns
+/// * `Result<(), ServerError>` - NotRunning, or the error of the stop or the start
+async fn restart_server(
+    state: &Arc<RwLock<AppState>>,
+    id: Option<&str>,
+    caller: &Caller,
+) -> Result<(), ServerError> {
+    let running = state.read().await.server(id)?.is_running();
+    let result = if running {
+        state::restart_server(state, id, || Ok(())).await
     } else {
-        HttpResponse::Ok().body("Minecraft server is not running.")
+        Err(ServerError::NotRunning)
+    };
+    state::audit(state, caller, id, AuditAction::Restart, None, &result).await;
+    result
+}
+
+/// Warns a server's players and stops or restarts it once the countdown is
+/// over, in the background.
+///
+/// # Arguments
+/// * `req` - The request, for the server id and the audit log
+/// * `state` - Shared application state
+/// * `seconds` - Length of the countdown
+/// * `restart` - Whether to restart rather than stop
+///
+/// # Returns
+/// * Accepted once the countdown has started
+/// * Conflict if a countdown is already running for the server
+/// * NotRunning or UnknownServer
+async fn start_countdown(
+    req: &HttpRequest,
+    state: &Arc<RwLock<AppState>>,
+    seconds: u64,
+    restart: bool,
+) -> Result<HttpResponse, ServerError> {
+    let length = Duration::from_secs(seconds);
+    let id = {
+        let mut app_state = state.write().await;
+        let server = app_state.server_mut(server_id(req))?;
+        if !server.is_running() {
+            return Err(ServerError::NotRunning);
+        }
+        if server.countdown_until.is_some() {
+            return Ok(HttpResponse::Conflict().body("A countdown is already running"));
+        }
+        server.countdown_until = Some(Local::now() + length);
+        server.id().to_string()
+    };
+    let action = if restart { "restarting" } else { "stopping" };
+    info!(server = %id, "Minecraft server {} in {}s", action, seconds);
+
+    let state = state.clone();
+    let caller = Caller::http(req);
+    let server = id.clone();
+    tokio::spawn(async move {
+        scheduler::countdown_warnings(&state, &server, length, action).await;
+        let result = if restart {
+            restart_server(&state, Some(&server), &caller).await
+        } else {
+            stop_server(&state, Some(&server), &caller)
+                .await
+                .map(|_| ())
+        };
+        if let Ok(managed) = state.write().await.server_mut(Some(&server)) {
+            managed.countdown_until = None;
+        }
+        if let Err(e) = result {
+            warn!(server = %server, "Countdown ended without {}: {}", action, e);
+        }
+    });
+    Ok(HttpResponse::Accepted().body(format!("Minecraft server {} in {}s.", action, seconds)))
+}
+
+/// HTTP handler to save the world (`save-all flush`).
+///
+/// # Returns
+/// * Success response once the server confirmed the save
+/// * Conflict if the server is not running
+/// * Not found if there is no server with the id in the path
+/// * Gateway timeout if the save wasn't confirmed within the save timeout
+pub async fn save_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    state::save_world(&state, server_id(&req), None).await?;
+    Ok(HttpResponse::Ok().body("World saved."))
+}
+
+/// Where console commands for a running server go, for `GET /status`.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsoleInput {
+    /// Written to the server's stdin.
+    Stdin,
+    /// Sent over RCON, because the server closed its console input.
+    Rcon,
+    /// Nowhere: the server closed its console input and has no RCON.
+    Unavailable,
+}
+
+/// Response body for `GET /status`.
+#[derive(Serialize)]
+pub struct StatusResponse {
+    /// Id of the server, `default` for the one configured in `[server]`.
+    pub id: String,
+    pub running: bool,
+    /// Whether the server is saving its world and shutting down after a stop
+    /// or restart; `running` is already false meanwhile.
+    pub stopping: bool,
+    /// When the current server process started; absent while stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Local>>,
+    /// Seconds since the current server process started; absent while stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_seconds: Option<u64>,
+    /// When a stop or restart requested with `?countdown=` happens; absent without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub countdown_until: Option<DateTime<Local>>,
+    pub restart_count: usize,
+    /// Whether the server last exited because the EULA isn't accepted.
+    pub eula_required: bool,
+    /// Java runtime the server was last started with; absent for servers that aren't jars.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub java: Option<JavaVersion>,
+    /// Whether the server has finished starting and accepts players.
+    pub ready: bool,
+    /// Where commands go while the server runs, e.g. `unavailable` so a UI can
+    /// disable its command box; absent while stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub console: Option<ConsoleInput>,
+    /// Players online, from the join and leave messages since the server started.
+    pub players_online: usize,
+    /// Latest memory and CPU sample of the server process; absent while stopped.
+    /// Only sampled for the default server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceSample>,
+    /// Tick rate and memory usage reported by the server; absent until known.
+    /// Only polled for the default server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub performance: Option<PerformanceStats>,
+    /// "Can't keep up!" warnings the server printed since the backend started.
+    pub overload: OverloadSummary,
+    /// What the server reports to the multiplayer screen; absent while stopped,
+    /// still starting, or not answering pings. Only filled in by `/status`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ping: Option<PingResponse>,
+    /// WebSocket clients connected to any server.
+    pub websocket_clients: usize,
+    /// Most WebSocket clients allowed at once; absent if there is no limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_websocket_clients: Option<usize>,
+}
+
+impl StatusResponse {
+    /// Describes a server's current state.
+    pub fn new(app_state: &AppState, server: &ManagedServer) -> Self {
+        let running = server.is_running();
+        let monitored = running && server.id() == DEFAULT_SERVER_ID;
+        StatusResponse {
+            id: server.id().to_string(),
+            running,
+            stopping: server.is_stopping(),
+            started_at: server.start_time(),
+            uptime_seconds: server.uptime_seconds(),
+            countdown_until: server.countdown_until,
+            restart_count: server.restart_count,
+            eula_required: server.eula_required(),
+            java: server.java_version().cloned(),
+            ready: server.is_ready(),
+            console: running.then(|| {
+                if server.console_available() {
+                    ConsoleInput::Stdin
+                } else if server.rcon().is_available(&server.server_dir()) {
+                    ConsoleInput::Rcon
+                } else {
+                    ConsoleInput::Unavailable
+                }
+            }),
+            players_online: server.online_players().len(),
+            resources: monitored
+                .then(|| app_state.resource_history.latest().cloned())
+                .flatten(),
+            performance: (monitored && !app_state.performance.is_empty())
+                .then(|| app_state.performance.clone()),
+            overload: server.overload().summary(),
+            ping: None,
+            websocket_clients: app_state.websocket_clients(),
+            max_websocket_clients: Some(app_state.websocket_config.max_clients)
+                .filter(|&max| max != 0),
+        }
+    }
+}
+
+/// HTTP handler to check the server status.
+///
+/// A running server is also pinged on localhost at the port from its
+/// `server.properties`, after the state lock has been released.
+///
+/// # Returns
+/// * JSON response with the running state, uptime, restart count, resource usage, TPS,
+///   the server's ping response and the number of WebSocket clients
+/// * Not found if there is no server with the id in the path
+pub async fn status_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    let (mut status, server_dir) = {
+        let app_state = state.read().await;
+        let server = app_state.server(server_id(&req))?;
+        let status = StatusResponse::new(&app_state, server);
+        (status, server.is_running().then(|| server.server_dir()))
+    };
+    if let Some(server_dir) = server_dir {
+        let port = server_ping::server_port(&server_dir);
+        status.ping = server_ping::ping("127.0.0.1", port, STATUS_PING_TIMEOUT)
+            .await
+            .ok();
     }
+    Ok(HttpResponse::Ok().json(status))
 }
 
 /// Configures the application routes.
@@ -61,8 +419,199 @@ pub async fn status_handler(state: web::Data<Arc<Mutex<AppState>>>) -> impl Resp
 /// # Arguments
 /// * `cfg` - Service config to register routes with
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/login").route(web::post().to(auth::login_handler)));
     cfg.service(web::resource("/start").route(web::post().to(start_handler)));
     cfg.service(web::resource("/stop").route(web::post().to(stop_handler)));
+    cfg.service(web::resource("/restart").route(web::post().to(restart_handler)));
     cfg.service(web::resource("/status").route(web::get().to(status_handler)));
+    cfg.service(web::resource("/save").route(web::post().to(save_handler)));
     cfg.service(web::resource("/ws").route(web::get().to(ws_index)));
+    cfg.service(web::resource("/events").route(web::get().to(events::events_handler)));
+    cfg.service(web::resource("/command").route(web::post().to(servers::command_handler)));
+    cfg.service(web::resource("/rcon").route(web::post().to(rcon::rcon_handler)));
+    cfg.service(web::resource("/clients").route(web::get().to(clients::list_clients_handler)));
+    cfg.service(
+        web::resource("/clients/{client_id}")
+            .route(web::delete().to(clients::disconnect_client_handler)),
+    );
+    cfg.service(web::resource("/history").route(web::get().to(history::history_handler)));
+    cfg.service(
+        web::resource("/commands/history").route(web::get().to(history::recent_commands_handler)),
+    );
+    cfg.service(web::resource("/lifecycle").route(web::get().to(lifecycle::lifecycle_handler)));
+    cfg.service(
+        web::resource("/crash-reports")
+            .route(web::get().to(crash_reports::list_crash_reports_handler)),
+    );
+    cfg.service(
+        web::resource("/crash-reports/{name}")
+            .wrap(Compress::default())
+            .route(web::get().to(crash_reports::crash_report_handler)),
+    );
+    cfg.service(web::resource("/health").route(web::get().to(health::health_handler)));
+    cfg.service(web::resource("/ready").route(web::get().to(health::ready_handler)));
+    cfg.service(web::resource("/ping").route(web::get().to(ping::ping_handler)));
+    cfg.service(web::resource("/version").route(web::get().to(version::version_handler)));
+    cfg.service(web::resource("/query").route(web::get().to(query::query_handler)));
+    cfg.service(
+        web::resource("/autorestart").route(web::put().to(restarts::set_auto_restart_handler)),
+    );
+    cfg.service(web::resource("/restarts").route(web::get().to(restarts::list_restarts_handler)));
+    cfg.service(
+        web::resource("/servers")
+            .route(web::get().to(servers::list_servers_handler))
+            .route(web::post().to(servers::add_server_handler)),
+    );
+    cfg.service(
+        web::scope("/servers/{id}")
+            .route("/start", web::post().to(start_handler))
+            .route("/stop", web::post().to(stop_handler))
+            .route("/restart", web::post().to(restart_handler))
+            .route("/status", web::get().to(status_handler))
+            .route("/save", web::post().to(save_handler))
+            .route("/command", web::post().to(servers::command_handler))
+            .route("/rcon", web::post().to(rcon::rcon_handler))
+            .route("/query", web::get().to(query::query_handler))
+            .route("/version", web::get().to(version::version_handler))
+            .route(
+                "/autorestart",
+                web::put().to(restarts::set_auto_restart_handler),
+            )
+            .route("/restarts", web::get().to(restarts::list_restarts_handler))
+            .route("/history", web::get().to(history::history_handler))
+            .route(
+                "/commands/history",
+                web::get().to(history::recent_commands_handler),
+            )
+            .route("/lifecycle", web::get().to(lifecycle::lifecycle_handler))
+            .route(
+                "/players/{name}/message",
+                web::post().to(players::message_handler),
+            )
+            .route("/broadcast", web::post().to(players::broadcast_handler))
+            .route(
+                "/macros/{name}/run",
+                web::post().to(macros::run_macro_handler),
+            )
+            .route(
+                "/crash-reports",
+                web::get().to(crash_reports::list_crash_reports_handler),
+            )
+            .service(
+                web::resource("/crash-reports/{name}")
+                    .wrap(Compress::default())
+                    .route(web::get().to(crash_reports::crash_report_handler)),
+            )
+            .route("/ws", web::get().to(ws_index))
+            .route("/events", web::get().to(events::events_handler))
+            .route("/logs/stream", web::get().to(events::log_stream_handler)),
+    );
+    cfg.service(
+        web::resource("/bans")
+            .route(web::get().to(bans::list_bans_handler))
+            .route(web::post().to(bans::ban_handler)),
+    );
+    cfg.service(web::resource("/bans/{target}").route(web::delete().to(bans::pardon_handler)));
+    cfg.service(
+        web::resource("/whitelist")
+            .route(web::get().to(whitelist::list_whitelist_handler))
+            .route(web::post().to(whitelist::add_whitelist_handler)),
+    );
+    cfg.service(
+        web::resource("/whitelist/{name}")
+            .route(web::delete().to(whitelist::remove_whitelist_handler)),
+    );
+    cfg.service(
+        web::resource("/moderation/{action}").route(web::post().to(moderation::moderation_handler)),
+    );
+    cfg.service(
+        web::resource("/players/{name}/message").route(web::post().to(players::message_handler)),
+    );
+    cfg.service(web::resource("/broadcast").route(web::post().to(players::broadcast_handler)));
+    cfg.service(web::resource("/backup").route(web::post().to(backup::backup_handler)));
+    cfg.service(web::resource("/backups").route(web::get().to(backup::list_backups_handler)));
+    cfg.service(
+        web::resource("/backups/{name}/download")
+            .route(web::get().to(backup::download_backup_handler)),
+    );
+    cfg.service(
+        web::resource("/backups/{name}/restore")
+            .route(web::post().to(backup::restore_backup_handler)),
+    );
+    // Logs and crash reports are text and can be large; backups are compressed already
+    cfg.service(
+        web::resource("/logs")
+            .wrap(Compress::default())
+            .route(web::get().to(logs::logs_handler)),
+    );
+    cfg.service(
+        web::resource("/logs/download")
+            .wrap(Compress::default())
+            .route(web::get().to(logs::download_logs_handler)),
+    );
+    cfg.service(web::resource("/logs/stream").route(web::get().to(events::log_stream_handler)));
+    cfg.service(web::resource("/audit").route(web::get().to(audit::audit_handler)));
+    cfg.service(web::resource("/stats").route(web::get().to(stats::stats_handler)));
+    cfg.service(web::resource("/admin/reload-tls").route(web::post().to(tls::reload_tls_handler)));
+    cfg.service(web::resource("/metrics").route(web::get().to(metrics::metrics_handler)));
+    cfg.service(
+        web::resource("/webhooks/deliveries")
+            .route(web::get().to(webhooks::list_deliveries_handler)),
+    );
+    cfg.service(
+        web::resource("/notifications/test")
+            .route(web::post().to(notifications::test_notifications_handler)),
+    );
+    cfg.service(
+        web::resource("/schedule")
+            .route(web::get().to(schedule::get_schedule_handler))
+            .route(web::put().to(schedule::update_schedule_handler)),
+    );
+    cfg.service(
+        web::resource("/policy")
+            .route(web::get().to(policy::get_policy_handler))
+            .route(web::put().to(policy::update_policy_handler)),
+    );
+    cfg.service(
+        web::resource("/macros")
+            .route(web::get().to(macros::get_macros_handler))
+            .route(web::put().to(macros::update_macros_handler)),
+    );
+    cfg.service(
+        web::resource("/macros/{name}/run").route(web::post().to(macros::run_macro_handler)),
+    );
+    cfg.service(
+        web::resource("/schedule/restart")
+            .route(web::put().to(schedule::update_restart_schedule_handler)),
+    );
+    cfg.service(
+        web::resource("/schedule/restart/skip")
+            .route(web::post().to(schedule::skip_restart_handler)),
+    );
+    cfg.service(
+        web::resource("/properties")
+            .route(web::get().to(properties::get_properties_handler))
+            .route(web::put().to(properties::update_properties_handler))
+            .route(web::post().to(properties::update_properties_handler)),
+    );
+    cfg.service(web::resource("/worlds").route(web::get().to(worlds::list_worlds_handler)));
+    cfg.service(
+        web::resource("/worlds/active").route(web::post().to(worlds::set_active_world_handler)),
+    );
+    cfg.service(
+        web::resource("/worlds/{name}/info").route(web::get().to(worlds::world_info_handler)),
+    );
+    cfg.service(web::resource("/mods").route(web::get().to(mods::list_mods_handler)));
+    cfg.service(web::resource("/mods/upload").route(web::post().to(mods::upload_mod_handler)));
+    cfg.service(
+        web::resource("/mods/{file}/enable").route(web::post().to(mods::enable_mod_handler)),
+    );
+    cfg.service(
+        web::resource("/mods/{file}/disable").route(web::post().to(mods::disable_mod_handler)),
+    );
+    cfg.service(
+        web::resource("/motd")
+            .route(web::get().to(motd::get_motd_handler))
+            .route(web::put().to(motd::update_motd_handler)),
+    );
 }
@@ -0,0 +1,83 @@
+//! HTTP handlers for liveness and readiness probes.
+//!
+//! `GET /health` answers without touching the shared state, so it stays
+//! cheap and only fails if the backend itself is gone. `GET /ready` also
+//! checks that the state lock can be taken and that log output still
+//! reaches clients. Neither says anything about the Minecraft server; use
+//! `/status` for that.
+
+use crate::health::{Health, GIT_HASH, VERSION};
+use crate::state::AppState;
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// How long `/ready` waits for the state lock before reporting not ready.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Response body of `GET /health`.
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git_hash: Option<&'static str>,
+    /// Seconds since the backend started.
+    uptime_seconds: u64,
+    /// Whether the log broadcaster task is running.
+    broadcaster: bool,
+}
+
+/// Response body of `GET /ready`.
+#[derive(Serialize)]
+struct ReadyResponse {
+    ready: bool,
+    /// Whether the state lock could be taken within [`LOCK_TIMEOUT`].
+    state_lock: bool,
+    /// Whether the log broadcaster is running and its channel is open.
+    broadcaster: bool,
+}
+
+/// HTTP handler reporting that the backend is alive.
+///
+/// # Returns
+/// * JSON with the build's version and git hash, the backend's uptime and
+///   whether the log broadcaster is running
+pub async fn health_handler(health: web::Data<Arc<Health>>) -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse {
+        status: "ok",
+        version: VERSION,
+        git_hash: GIT_HASH,
+        uptime_seconds: health.uptime().as_secs(),
+        broadcaster: health.broadcaster_alive(),
+    })
+}
+
+/// HTTP handler reporting whether the backend can serve requests.
+///
+/// # Returns
+/// * JSON with the result of each check
+/// * Service unavailable if the state lock is stuck or logs can't be broadcast
+pub async fn ready_handler(
+    health: web::Data<Arc<Health>>,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> HttpResponse {
+    let channel_open = tokio::time::timeout(LOCK_TIMEOUT, state.read())
+        .await
+        .ok()
+        .map(|app_state| app_state.broadcaster_open());
+    let state_lock = channel_open.is_some();
+    let broadcaster = health.broadcaster_alive() && channel_open.unwrap_or(false);
+    let body = ReadyResponse {
+        ready: state_lock && broadcaster,
+        state_lock,
+        broadcaster,
+    };
+    if body.ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
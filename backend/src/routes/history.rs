@@ -0,0 +1,81 @@
+//! HTTP handlers listing the commands clients sent.
+//!
+//! `GET /history` lists the commands for all servers, and
+//! `GET /servers/{id}/history` only those for one server. `GET /commands/history`
+//! lists the commands that ran on a server, for a console's up-arrow recall.
+
+use crate::error::ServerError;
+use crate::history::CommandStatus;
+use crate::routes::handlers::server_id;
+use crate::state::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Number of commands returned when the request doesn't say.
+const DEFAULT_LIMIT: usize = 50;
+
+/// Query parameters for `GET /history`.
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// Maximum number of commands to return.
+    pub limit: Option<usize>,
+    /// Only return the commands of this WebSocket client.
+    pub client_id: Option<usize>,
+}
+
+/// HTTP handler returning the recent commands, executed and denied.
+///
+/// # Returns
+/// * JSON array of commands with their server, client id, status and time, newest first
+/// * Not found if there is no server with the id in the path
+pub async fn history_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse, ServerError> {
+    let app_state = state.read().await;
+    let server = match server_id(&req) {
+        Some(id) => Some(app_state.server(Some(id))?.id()),
+        None => None,
+    };
+    let commands =
+        app_state
+            .command_history
+            .recent(query.limit.unwrap_or(DEFAULT_LIMIT), |record| {
+                server.is_none_or(|server| record.server == server)
+                    && query
+                        .client_id
+                        .is_none_or(|id| record.client_id == Some(id))
+            });
+    Ok(HttpResponse::Ok().json(commands))
+}
+
+/// Query parameters for `GET /commands/history`.
+#[derive(Deserialize)]
+pub struct RecentCommandsQuery {
+    /// Maximum number of commands to return.
+    pub limit: Option<usize>,
+}
+
+/// HTTP handler returning the commands that ran on a server, from any client.
+///
+/// # Returns
+/// * JSON array of executed commands with their client id and time, newest first,
+///   each command once
+/// * Not found if there is no server with the id in the path
+pub async fn recent_commands_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<RecentCommandsQuery>,
+) -> Result<HttpResponse, ServerError> {
+    let app_state = state.read().await;
+    let server = app_state.server(server_id(&req))?.id();
+    let commands = app_state
+        .command_history
+        .recent_unique(query.limit.unwrap_or(DEFAULT_LIMIT), |record| {
+            record.server == server && record.status == CommandStatus::Executed
+        });
+    Ok(HttpResponse::Ok().json(commands))
+}
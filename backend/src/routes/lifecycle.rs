@@ -0,0 +1,46 @@
+//! HTTP handler listing the runs of the server processes.
+//!
+//! `GET /lifecycle` lists the runs of all servers, and
+//! `GET /servers/{id}/lifecycle` only those of one server.
+
+use crate::error::ServerError;
+use crate::routes::handlers::server_id;
+use crate::state::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Number of runs returned when the request doesn't say.
+const DEFAULT_LIMIT: usize = 20;
+
+/// Query parameters for `GET /lifecycle`.
+#[derive(Deserialize)]
+pub struct LifecycleQuery {
+    /// Maximum number of runs to return.
+    pub limit: Option<usize>,
+}
+
+/// HTTP handler returning the recent runs of the server processes.
+///
+/// # Returns
+/// * JSON array of runs with their start, stop, duration, exit code and
+///   whether they were stopped, exited or crashed, newest first
+/// * Not found if there is no server with the id in the path
+pub async fn lifecycle_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<LifecycleQuery>,
+) -> Result<HttpResponse, ServerError> {
+    let app_state = state.read().await;
+    let server = match server_id(&req) {
+        Some(id) => Some(app_state.server(Some(id))?.id()),
+        None => None,
+    };
+    let runs = app_state
+        .lifecycle
+        .recent(query.limit.unwrap_or(DEFAULT_LIMIT), |record| {
+            server.is_none_or(|server| record.server == server)
+        });
+    Ok(HttpResponse::Ok().json(runs))
+}
@@ -0,0 +1,111 @@
+//! HTTP handlers for reading console history from the log file.
+//!
+//! `GET /logs` returns a page of entries and `GET /logs/download` the whole
+//! file. Both are compressed when the client sends `Accept-Encoding: gzip`;
+//! the file is compressed as it is streamed, never held in memory as a whole.
+
+use crate::server::log_entry::LogLevel;
+use crate::server::log_file::{self, LogQuery};
+use crate::state::AppState;
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentDisposition, ContentType};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Number of lines returned when the request doesn't say.
+const DEFAULT_LIMIT: usize = 100;
+/// Largest page a client may request.
+const MAX_LIMIT: usize = 1000;
+
+/// Query parameters for `GET /logs`.
+#[derive(Deserialize)]
+pub struct LogsQuery {
+    /// Number of matching lines to skip.
+    #[serde(default)]
+    pub offset: usize,
+    /// Maximum number of lines to return, at most 1000.
+    pub limit: Option<usize>,
+    /// RFC 3339 timestamp; only entries logged at or after it are returned.
+    pub since: Option<DateTime<Local>>,
+    /// Minimum level, e.g. `WARN` returns warnings, errors and fatal errors.
+    pub level: Option<String>,
+}
+
+/// HTTP handler returning a page of console history.
+///
+/// The file is read on the blocking thread pool after the state lock has been
+/// released, so large files don't stall the worker or other requests.
+///
+/// # Returns
+/// * JSON array of log entries with their line numbers, oldest first
+/// * Bad request for an unknown level
+/// * Not found if file logging is disabled
+pub async fn logs_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<LogsQuery>,
+) -> impl Responder {
+    let level = match query.level.as_deref().map(LogLevel::parse) {
+        Some(Some(level)) => Some(level),
+        Some(None) => {
+            return HttpResponse::BadRequest().body(format!(
+                "Unknown level '{}'",
+                query.level.as_deref().unwrap_or_default()
+            ))
+        }
+        None => None,
+    };
+    let log_query = LogQuery {
+        offset: query.offset,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT),
+        since: query.since,
+        level,
+    };
+
+    let config = state.read().await.log_file_config.clone();
+    if !config.enabled {
+        return HttpResponse::NotFound().body("File logging is disabled");
+    }
+
+    match web::block(move || log_file::read_lines(&config.path, &log_query)).await {
+        Ok(Ok(lines)) => HttpResponse::Ok().json(lines),
+        Ok(Err(e)) => {
+            HttpResponse::InternalServerError().body(format!("Error reading logs: {}", e))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error reading logs: {}", e)),
+    }
+}
+
+/// HTTP handler streaming the whole log file, one JSON entry per line.
+///
+/// # Returns
+/// * The log file as an attachment
+/// * Not found if file logging is disabled or nothing has been logged yet
+pub async fn download_logs_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> HttpResponse {
+    let config = state.read().await.log_file_config.clone();
+    if !config.enabled {
+        return HttpResponse::NotFound().body("File logging is disabled");
+    }
+    let name = config
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "minecraft-console.log".to_string());
+    match NamedFile::open_async(&config.path).await {
+        Ok(file) => file
+            .use_last_modified(true)
+            .set_content_type(ContentType::plaintext().0)
+            .set_content_disposition(ContentDisposition::attachment(name))
+            .into_response(&req),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            HttpResponse::NotFound().body("Nothing has been logged yet")
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error reading logs: {}", e)),
+    }
+}
@@ -0,0 +1,77 @@
+//! HTTP handlers for viewing, changing and running macros.
+//!
+//! `POST /servers/{id}/macros/{name}/run` runs a macro on another server than
+//! the default one.
+
+use crate::audit::Caller;
+use crate::error::ServerError;
+use crate::macros::MacroDefinitions;
+use crate::routes::handlers::server_id;
+use crate::state::{self, AppState};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Optional body of `POST /macros/{name}/run`.
+#[derive(Debug, Default, Deserialize)]
+pub struct RunMacroRequest {
+    /// Go on with the next command after one failed, instead of stopping.
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+/// HTTP handler returning the macros.
+///
+/// # Returns
+/// * JSON object mapping each macro's name to its commands
+pub async fn get_macros_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    HttpResponse::Ok().json(state.read().await.macros.all())
+}
+
+/// HTTP handler replacing all macros without restarting the backend.
+///
+/// # Returns
+/// * JSON object with the new macros
+/// * Bad request if a name can't be used, or a macro has no or an invalid command
+pub async fn update_macros_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<MacroDefinitions>,
+) -> impl Responder {
+    let mut app_state = state.write().await;
+    if let Err(e) = app_state.macros.replace(req.into_inner()) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    info!(
+        "Macros updated: {:?}",
+        app_state.macros.all().keys().collect::<Vec<_>>()
+    );
+    HttpResponse::Ok().json(app_state.macros.all())
+}
+
+/// HTTP handler running a macro's commands in order.
+///
+/// The response waits until the last command was sent.
+///
+/// # Returns
+/// * JSON object with what came of each command and whether all were sent
+/// * Conflict if the server is not running
+/// * Not found if there is no such macro, or no server with the id in the path
+pub async fn run_macro_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    body: Option<web::Json<RunMacroRequest>>,
+) -> Result<HttpResponse, ServerError> {
+    let name = req.match_info().get("name").unwrap_or_default();
+    let options = body.map(web::Json::into_inner).unwrap_or_default();
+    let run = state::run_macro(
+        &state,
+        server_id(&req),
+        &Caller::http(&req),
+        name,
+        options.continue_on_error,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(run))
+}
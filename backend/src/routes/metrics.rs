@@ -0,0 +1,169 @@
+//! HTTP handler exposing metrics for Prometheus.
+
+use crate::metrics::{Exposition, ProcessStats};
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// HTTP handler returning the current metrics in Prometheus text format.
+///
+/// Process memory and CPU are only included while the server is running and
+/// `/proc` is available, TPS and heap figures only once the server reported them.
+///
+/// # Returns
+/// * Plain text response in the Prometheus exposition format
+pub async fn metrics_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let (
+        servers,
+        running,
+        uptime,
+        pid,
+        cpu_percent,
+        metrics,
+        performance,
+        overloads,
+        last_overload,
+    ) = {
+        let app_state = state.read().await;
+        let server = app_state.default_server();
+        let servers: Vec<(String, u8)> = app_state
+            .servers()
+            .into_iter()
+            .map(|server| (server.id().to_string(), server.is_running() as u8))
+            .collect();
+        (
+            servers,
+            server.is_running(),
+            server.uptime_seconds().unwrap_or(0),
+            server.server_pid(),
+            app_state
+                .resource_history
+                .latest()
+                .map(|sample| sample.cpu_percent),
+            app_state.metrics.clone(),
+            app_state.performance.clone(),
+            server.overload().warnings(),
+            server.overload().last_warning_at(),
+        )
+    };
+
+    let mut out = Exposition::default();
+    out.metric(
+        "minecraft_server_running",
+        "gauge",
+        "Whether the Minecraft server process is running (1) or not (0).",
+        running as u8,
+    );
+    out.labelled(
+        "minecraft_console_server_running",
+        "gauge",
+        "Whether each managed server's process is running (1) or not (0), by server id.",
+        "server",
+        servers,
+    );
+    out.metric(
+        "minecraft_server_uptime_seconds",
+        "gauge",
+        "Seconds since the current server process was started.",
+        uptime,
+    );
+    out.metric(
+        "minecraft_console_websocket_clients",
+        "gauge",
+        "Number of connected WebSocket clients.",
+        metrics.connected_clients.load(Ordering::Relaxed),
+    );
+    out.metric(
+        "minecraft_console_log_lines_broadcast_total",
+        "counter",
+        "Console log lines broadcast to WebSocket clients.",
+        metrics.log_lines_broadcast.load(Ordering::Relaxed),
+    );
+    out.metric(
+        "minecraft_console_commands_sent_total",
+        "counter",
+        "Commands written to the server console.",
+        metrics.commands_sent.load(Ordering::Relaxed),
+    );
+    out.metric(
+        "minecraft_console_broadcast_failures_total",
+        "counter",
+        "Messages that could not be delivered to a WebSocket client.",
+        metrics.broadcast_failures.load(Ordering::Relaxed),
+    );
+    out.metric(
+        "minecraft_server_overload_warnings_total",
+        "counter",
+        "\"Can't keep up!\" warnings printed by the server.",
+        overloads,
+    );
+    if let Some(last) = last_overload {
+        out.metric(
+            "minecraft_server_last_overload_timestamp_seconds",
+            "gauge",
+            "Unix time of the server's last \"Can't keep up!\" warning.",
+            last.timestamp(),
+        );
+    }
+    if let Some(stats) = pid.and_then(ProcessStats::read) {
+        out.metric(
+            "minecraft_server_resident_memory_bytes",
+            "gauge",
+            "Resident memory of the server process in bytes.",
+            stats.resident_memory_bytes,
+        );
+        out.metric(
+            "minecraft_server_cpu_seconds_total",
+            "counter",
+            "User and system CPU time used by the server process in seconds.",
+            stats.cpu_seconds,
+        );
+        if let Some(cpu_percent) = cpu_percent {
+            out.metric(
+                "minecraft_server_cpu_percent",
+                "gauge",
+                "CPU usage of the server process at its latest sample, in percent of one core.",
+                cpu_percent,
+            );
+        }
+    }
+
+    if running {
+        let reported = [
+            (
+                "minecraft_server_tps",
+                "Ticks per second reported by the server.",
+                performance.tps,
+            ),
+            (
+                "minecraft_server_mean_tick_milliseconds",
+                "Average tick time reported by the server in milliseconds.",
+                performance.mean_tick_ms,
+            ),
+            (
+                "minecraft_server_heap_used_megabytes",
+                "JVM heap in use reported by the server in megabytes.",
+                performance.memory_used_mb,
+            ),
+            (
+                "minecraft_server_heap_max_megabytes",
+                "Maximum JVM heap reported by the server in megabytes.",
+                performance.memory_max_mb,
+            ),
+        ];
+        for (name, help, value) in reported {
+            if let Some(value) = value {
+                out.metric(name, "gauge", help, value);
+            }
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type(CONTENT_TYPE)
+        .body(out.finish())
+}
@@ -3,6 +3,40 @@
 //! This module defines all HTTP endpoints for the application
 //! and their handler implementations.
 
+mod audit;
+mod auth;
+mod backup;
+mod bans;
+mod clients;
+mod crash_reports;
+mod events;
+mod frontend;
 mod handlers;
+mod health;
+mod history;
+mod lifecycle;
+mod logs;
+mod macros;
+mod metrics;
+mod moderation;
+mod mods;
+mod motd;
+mod notifications;
+mod ping;
+mod players;
+mod policy;
+mod properties;
+mod query;
+mod rcon;
+mod restarts;
+mod schedule;
+mod servers;
+mod stats;
+mod tls;
+mod version;
+mod webhooks;
+mod whitelist;
+mod worlds;
 
+pub use frontend::init_frontend;
 pub use handlers::init_routes;
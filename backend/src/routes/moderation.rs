@@ -0,0 +1,125 @@
+//! HTTP handlers for moderation commands.
+//!
+//! `POST /moderation/{action}` runs `kick`, `ban`, `pardon`, `op` or `deop`
+//! for a player on the running server and returns what the server printed in
+//! response. Player names and reasons are checked before anything is sent, so
+//! a request can never smuggle a second command onto the console.
+
+use crate::audit::{AuditAction, Caller};
+use crate::routes::bans::is_valid_player_name;
+use crate::server::log_parser;
+use crate::state::{self, AppState};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Moderation commands available at `/moderation/{action}`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Kick,
+    Ban,
+    Pardon,
+    Op,
+    Deop,
+}
+
+impl Action {
+    /// Returns the console command name.
+    fn command(self) -> &'static str {
+        match self {
+            Action::Kick => "kick",
+            Action::Ban => "ban",
+            Action::Pardon => "pardon",
+            Action::Op => "op",
+            Action::Deop => "deop",
+        }
+    }
+
+    /// Returns true if the command accepts a reason after the player name.
+    fn takes_reason(self) -> bool {
+        matches!(self, Action::Kick | Action::Ban)
+    }
+}
+
+/// Request body for `POST /moderation/{action}`.
+#[derive(Deserialize)]
+pub struct ModerationRequest {
+    /// Name of the player to act on.
+    pub player: String,
+    /// Reason shown to the player, for `kick` and `ban` only.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Response body for `POST /moderation/{action}`.
+#[derive(Serialize)]
+pub struct ModerationResponse {
+    /// The command that was sent.
+    pub command: String,
+    /// Messages the server printed in response, without log headers.
+    pub output: Vec<String>,
+}
+
+/// HTTP handler running a moderation command.
+///
+/// # Returns
+/// * JSON response with the command and the server's output
+/// * Bad request if the player name or reason is malformed, or the server rejected the command
+/// * Conflict if the server is not running
+pub async fn moderation_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    action: web::Path<Action>,
+    req: web::Json<ModerationRequest>,
+) -> impl Responder {
+    let action = action.into_inner();
+    let player = req.player.trim();
+    if !is_valid_player_name(player) {
+        return HttpResponse::BadRequest().body(format!("Invalid player name: {}", player));
+    }
+
+    let reason = req.reason.as_deref().unwrap_or("").trim();
+    if !reason.is_empty() && !action.takes_reason() {
+        return HttpResponse::BadRequest()
+            .body(format!("{} doesn't take a reason", action.command()));
+    }
+    if reason.contains(char::is_control) {
+        return HttpResponse::BadRequest().body("Reason must be a single line");
+    }
+
+    let command = format!("{} {} {}", action.command(), player, reason)
+        .trim_end()
+        .to_string();
+    let caller = Caller::http(&http_req);
+    let result = match state::check_command(&state, None, &caller, &command).await {
+        Ok(()) => state::command_output(&state, None, &command, None).await,
+        Err(e) => Err(e),
+    };
+    state::audit(
+        &state,
+        &caller,
+        None,
+        AuditAction::Command,
+        Some(&command),
+        &result,
+    )
+    .await;
+    let output = match result {
+        Ok(output) => output,
+        Err(e) => return e.error_response(),
+    };
+    state::record_command(&state, None, None, &command).await;
+    if let Some(message) = log_parser::command_error(&output) {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    HttpResponse::Ok().json(ModerationResponse {
+        command,
+        output: output
+            .iter()
+            .map(|line| log_parser::message_body(line).to_string())
+            .collect(),
+    })
+}
@@ -0,0 +1,267 @@
+//! HTTP handlers for the mods in the server's `mods` directory.
+//!
+//! `GET /mods` lists the jars with their mod id, name, version, size and
+//! hash. `POST /mods/{file}/disable` and `/enable` rename a jar to
+//! `.jar.disabled` and back. The loader only reads the directory at
+//! startup, so renaming while the server runs is refused unless `?force=true`
+//! is given; the change then applies on the next start.
+//!
+//! `POST /mods/upload` takes a jar as a multipart upload and installs it in
+//! the mods directory, refused likewise while the server runs unless
+//! `?allow_hot=true` is given.
+
+use crate::audit::{AuditAction, Caller};
+use crate::error::ServerError;
+use crate::server::mods::{self, ModInfo, OnDuplicate};
+use crate::state::AppState;
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Query parameters for `POST /mods/{file}/enable` and `/disable`.
+#[derive(Deserialize)]
+pub struct ToggleQuery {
+    /// Rename the jar even though the server is running.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Query parameters for `POST /mods/upload`.
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    /// Install the jar even though the server is running.
+    #[serde(default)]
+    pub allow_hot: bool,
+    /// What to do if a mod with the jar's name exists: `reject` (the default)
+    /// or `rename`, which adds a number to the name.
+    #[serde(default)]
+    pub on_duplicate: OnDuplicate,
+}
+
+/// HTTP handler listing the mods.
+///
+/// Jars are hashed on the blocking thread pool, since a modpack has hundreds
+/// of them.
+///
+/// # Returns
+/// * JSON array of mods sorted by file name, empty without a mods directory
+pub async fn list_mods_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let server_dir = state.read().await.default_server().server_dir();
+    match web::block(move || mods::list_mods(&server_dir)).await {
+        Ok(Ok(mods)) => HttpResponse::Ok().json(mods),
+        Ok(Err(e)) => {
+            HttpResponse::InternalServerError().body(format!("Error listing mods: {}", e))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error listing mods: {}", e)),
+    }
+}
+
+/// HTTP handler enabling a mod.
+pub async fn enable_mod_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<ToggleQuery>,
+) -> impl Responder {
+    toggle_mod(&req, &state, query.force, true).await
+}
+
+/// HTTP handler disabling a mod.
+pub async fn disable_mod_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<ToggleQuery>,
+) -> impl Responder {
+    toggle_mod(&req, &state, query.force, false).await
+}
+
+/// Enables or disables the mod named in the path.
+///
+/// # Returns
+/// * JSON response with the jar's new name and whether it changed
+/// * Bad request if the name isn't that of a jar in the mods directory
+/// * Not found if there is no such mod
+/// * Conflict if the server is running and `force` wasn't given, or both an
+///   enabled and a disabled copy exist
+async fn toggle_mod(
+    req: &HttpRequest,
+    state: &web::Data<Arc<RwLock<AppState>>>,
+    force: bool,
+    enable: bool,
+) -> HttpResponse {
+    let file = req.match_info().get("file").unwrap_or_default().to_string();
+    let (server_dir, running) = {
+        let app_state = state.read().await;
+        let server = app_state.default_server();
+        (server.server_dir(), server.is_running())
+    };
+    if running && !force {
+        return HttpResponse::Conflict().body(
+            "The server is running and only loads mods at startup; stop it first or pass ?force=true",
+        );
+    }
+
+    match web::block(move || mods::set_enabled(&server_dir, &file, enable)).await {
+        Ok(Ok(toggle)) => {
+            if toggle.changed {
+                info!(
+                    "Mod {} {}",
+                    toggle.file,
+                    if enable { "enabled" } else { "disabled" }
+                );
+            }
+            HttpResponse::Ok().json(toggle)
+        }
+        Ok(Err(e)) => match e.kind() {
+            ErrorKind::InvalidInput => HttpResponse::BadRequest().body(e.to_string()),
+            ErrorKind::NotFound => HttpResponse::NotFound().body(e.to_string()),
+            ErrorKind::AlreadyExists => HttpResponse::Conflict().body(e.to_string()),
+            _ => HttpResponse::InternalServerError().body(format!("Error renaming the mod: {}", e)),
+        },
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Error renaming the mod: {}", e))
+        }
+    }
+}
+
+/// HTTP handler uploading a mod.
+///
+/// The first part of the multipart body that has a file name is the jar;
+/// other parts are ignored. It is streamed to a temporary file in the mods
+/// directory and installed once complete, and the upload is recorded in the
+/// audit log.
+///
+/// # Returns
+/// * Created with the installed mod, its name changed if `on_duplicate=rename`
+///   made it so
+/// * Bad request if there is no file, its name isn't that of a jar, or it
+///   isn't a valid jar
+/// * Conflict if the server is running and `allow_hot` wasn't given, or a mod
+///   with that name exists
+/// * Payload too large if the jar is larger than `[mods] max_upload_mb`
+pub async fn upload_mod_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<UploadQuery>,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let (id, server_dir, running, max_size) = {
+        let app_state = state.read().await;
+        let server = app_state.default_server();
+        (
+            server.id().to_string(),
+            server.server_dir(),
+            server.is_running(),
+            app_state.mods_config.max_upload_size(),
+        )
+    };
+    if running && !query.allow_hot {
+        return HttpResponse::Conflict().body(
+            "The server is running and only loads mods at startup; stop it first or pass ?allow_hot=true",
+        );
+    }
+
+    let mut field = loop {
+        match payload.try_next().await {
+            Ok(Some(field)) => {
+                if field
+                    .content_disposition()
+                    .is_some_and(|disposition| disposition.get_filename().is_some())
+                {
+                    break field;
+                }
+            }
+            Ok(None) => return HttpResponse::BadRequest().body("No file was uploaded"),
+            Err(e) => return HttpResponse::BadRequest().body(format!("Invalid upload: {}", e)),
+        }
+    };
+    let file = field
+        .content_disposition()
+        .and_then(|disposition| disposition.get_filename())
+        .unwrap_or_default()
+        .to_string();
+    if let Err(e) = mods::check_upload_name(&file) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let upload = mods::upload_path(&server_dir);
+    let result = match receive(&mut field, &upload, max_size).await {
+        Ok(()) => {
+            let (upload, file) = (upload.clone(), file.clone());
+            let on_duplicate = query.on_duplicate;
+            web::block(move || mods::install_upload(&server_dir, &upload, &file, on_duplicate))
+                .await
+                .unwrap_or_else(|e| Err(Error::other(e.to_string())))
+        }
+        Err(e) => Err(e),
+    };
+    // Gone once installed, but not after a failed or cut off upload
+    let _ = tokio::fs::remove_file(&upload).await;
+
+    let audited: Result<&ModInfo, ServerError> = result
+        .as_ref()
+        .map_err(|e| Error::new(e.kind(), e.to_string()).into());
+    state.read().await.audit.record(
+        &Caller::http(&req),
+        &id,
+        AuditAction::UploadMod,
+        Some(&file),
+        &audited,
+    );
+
+    match result {
+        Ok(installed) => {
+            info!("Mod {} uploaded", installed.file);
+            HttpResponse::Created().json(installed)
+        }
+        Err(e) => match e.kind() {
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => {
+                HttpResponse::BadRequest().body(e.to_string())
+            }
+            ErrorKind::AlreadyExists => HttpResponse::Conflict().body(e.to_string()),
+            ErrorKind::FileTooLarge => HttpResponse::PayloadTooLarge().body(e.to_string()),
+            _ => {
+                HttpResponse::InternalServerError().body(format!("Error uploading the mod: {}", e))
+            }
+        },
+    }
+}
+
+/// Streams an uploaded file to `path`, creating the mods directory if needed.
+///
+/// # Returns
+/// * `io::Result<()>` - FileTooLarge once more than `max_size` bytes arrived,
+///   InvalidData if the upload broke off
+async fn receive(
+    field: &mut actix_multipart::Field,
+    path: &Path,
+    max_size: u64,
+) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut out = tokio::fs::File::create(path).await?;
+    let mut size = 0u64;
+    while let Some(chunk) = field
+        .try_next()
+        .await
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?
+    {
+        size += chunk.len() as u64;
+        if size > max_size {
+            return Err(Error::new(
+                ErrorKind::FileTooLarge,
+                format!("The jar is larger than {} bytes", max_size),
+            ));
+        }
+        out.write_all(&chunk).await?;
+    }
+    // On disk before it is linked into place
+    out.sync_all().await
+}
@@ -0,0 +1,169 @@
+//! HTTP handlers for viewing and changing the server's MOTD.
+//!
+//! `GET /motd` returns the MOTD from `server.properties` as text, as written
+//! in the file, and split into styled segments for a preview. `PUT /motd`
+//! writes a new one, escaped the way the server writes it itself.
+
+use crate::server::log_entry::LogEntry;
+use crate::server::motd::{self, MotdSegment, MOTD_KEY};
+use crate::server::properties::{self, ServerProperties, PROPERTIES_FILE};
+use crate::state::{self, AppState};
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// MOTD the server uses when `server.properties` has none.
+const DEFAULT_MOTD: &str = "A Minecraft Server";
+
+/// Request body for `PUT /motd`.
+#[derive(Deserialize)]
+pub struct UpdateMotdRequest {
+    /// The new MOTD, with `§` formatting codes and at most one line break.
+    pub motd: String,
+}
+
+/// Response body for `GET /motd`.
+#[derive(Serialize)]
+pub struct MotdResponse {
+    /// The MOTD with its formatting codes, e.g. `§cRed`.
+    pub motd: String,
+    /// The MOTD as written in `server.properties`, e.g. `\u00A7cRed`.
+    pub raw: String,
+    /// The MOTD without formatting codes.
+    pub plain: String,
+    /// The MOTD split into styled runs of text.
+    pub segments: Vec<MotdSegment>,
+}
+
+impl MotdResponse {
+    fn new(motd: String, raw: String) -> Self {
+        MotdResponse {
+            raw,
+            plain: motd::plain(&motd),
+            segments: motd::segments(&motd),
+            motd,
+        }
+    }
+}
+
+/// Response body for `PUT /motd`.
+#[derive(Serialize)]
+pub struct UpdateMotdResponse {
+    #[serde(flatten)]
+    pub motd: MotdResponse,
+    /// Whether the MOTD in `server.properties` changed.
+    pub changed: bool,
+    /// Whether the running server was sent `[motd] apply_command`.
+    pub applied: bool,
+    /// Present when the running server still shows the old MOTD.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// HTTP handler returning the MOTD.
+///
+/// # Returns
+/// * JSON object with the MOTD, its escaped form and a styled preview
+/// * Not found if the server directory has no properties file yet
+pub async fn get_motd_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let server_dir = state.read().await.default_server().server_dir();
+    match ServerProperties::load(&server_dir) {
+        Ok(props) => {
+            let raw = props.get(MOTD_KEY).unwrap_or(DEFAULT_MOTD).to_string();
+            HttpResponse::Ok().json(MotdResponse::new(properties::unescape(&raw), raw))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => HttpResponse::NotFound().body(format!(
+            "No {} found in {}",
+            PROPERTIES_FILE,
+            server_dir.display()
+        )),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("Error reading {}: {}", PROPERTIES_FILE, e)),
+    }
+}
+
+/// HTTP handler changing the MOTD.
+///
+/// The MOTD is written to `server.properties`. If the server is running and
+/// `[motd] apply_command` is set, the command is sent to it as well;
+/// otherwise the new MOTD shows after the next start.
+///
+/// # Returns
+/// * JSON response with the new MOTD, whether it changed and was applied
+/// * Bad request if the MOTD has more than two lines, is too long or has control characters
+pub async fn update_motd_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<UpdateMotdRequest>,
+) -> impl Responder {
+    let motd = req.into_inner().motd.replace("\r\n", "\n");
+    if let Err(e) = motd::validate(&motd) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let (server_dir, running, log_sender, apply_command) = {
+        let app_state = state.read().await;
+        (
+            app_state.default_server().server_dir(),
+            app_state.default_server().is_running(),
+            app_state.default_server().log_sender.clone(),
+            app_state.motd_config.command(&motd),
+        )
+    };
+
+    let mut props = match ServerProperties::load(&server_dir) {
+        Ok(props) => props,
+        Err(e) if e.kind() == ErrorKind::NotFound => ServerProperties::default(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Error reading {}: {}", PROPERTIES_FILE, e))
+        }
+    };
+    let raw = properties::escape(&motd);
+    let changed = props.set(MOTD_KEY, &raw);
+    if changed {
+        if let Err(e) = props.save(&server_dir) {
+            return HttpResponse::InternalServerError()
+                .body(format!("Error writing {}: {}", PROPERTIES_FILE, e));
+        }
+        info!("MOTD changed to {:?}", motd);
+    }
+
+    let mut applied = false;
+    let mut warning = None;
+    if changed && running {
+        match apply_command {
+            Some(command) => match state::command_response(&state, None, &command, None).await {
+                Ok(_) => applied = true,
+                Err(e) => {
+                    warn!("Could not apply the MOTD with '{}': {}", command, e);
+                    warning = Some(format!(
+                        "Could not apply the MOTD to the running server ({}); it shows after a restart.",
+                        e
+                    ));
+                }
+            },
+            None => {
+                warning =
+                    Some("The server is running; the new MOTD shows after a restart.".to_string())
+            }
+        }
+        if !applied {
+            let _ = log_sender.send(
+                LogEntry::console(
+                    "[Console]: MOTD updated; restart the server to apply".to_string(),
+                )
+                .into(),
+            );
+        }
+    }
+
+    HttpResponse::Ok().json(UpdateMotdResponse {
+        motd: MotdResponse::new(motd, raw),
+        changed,
+        applied,
+        warning,
+    })
+}
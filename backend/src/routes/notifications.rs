@@ -0,0 +1,19 @@
+//! HTTP handler for checking the webhook notification setup.
+
+use crate::state::AppState;
+use actix_web::{web, HttpResponse};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// HTTP handler posting a test message to every configured webhook.
+///
+/// # Returns
+/// * JSON array with whether each webhook, by its position in the config, accepted the message
+/// * Not found if no webhooks are configured
+pub async fn test_notifications_handler(state: web::Data<Arc<RwLock<AppState>>>) -> HttpResponse {
+    let notifier = state.read().await.notifier.clone();
+    if !notifier.is_enabled() {
+        return HttpResponse::NotFound().body("No notification webhooks are configured");
+    }
+    HttpResponse::Ok().json(notifier.send_test().await)
+}
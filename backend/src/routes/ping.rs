@@ -0,0 +1,35 @@
+//! HTTP handler for pinging any Minecraft server.
+//!
+//! `GET /ping?host=&port=` asks a server for the information shown in the
+//! multiplayer screen, whether or not this backend manages it.
+
+use crate::error::ServerError;
+use crate::server::ping::{self, DEFAULT_PORT};
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// How long to wait for the server to connect and answer.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Query parameters for `GET /ping`.
+#[derive(Deserialize)]
+pub struct PingQuery {
+    /// Host name or address, defaulting to this machine.
+    pub host: Option<String>,
+    /// Port, defaulting to 25565.
+    pub port: Option<u16>,
+}
+
+/// HTTP handler pinging a server.
+///
+/// # Returns
+/// * JSON response with the version, player counts, MOTD and latency
+/// * Bad gateway if the server can't be reached, or predates Minecraft 1.7
+/// * Gateway timeout if the server didn't answer in time
+pub async fn ping_handler(query: web::Query<PingQuery>) -> Result<HttpResponse, ServerError> {
+    let host = query.host.as_deref().unwrap_or("127.0.0.1");
+    let port = query.port.unwrap_or(DEFAULT_PORT);
+    let response = ping::ping(host, port, PING_TIMEOUT).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,137 @@
+//! HTTP handlers for messaging players.
+//!
+//! `POST /players/{name}/message` whispers to one player with `tell`, and
+//! `POST /broadcast` sends a message to everyone with `say`. Both return
+//! what the server printed in response. Like the moderation commands, names
+//! and messages are checked before anything is sent, so a request can never
+//! smuggle a second command onto the console.
+
+use crate::audit::{AuditAction, Caller};
+use crate::error::ServerError;
+use crate::routes::bans::is_valid_player_name;
+use crate::routes::handlers::server_id;
+use crate::server::log_parser;
+use crate::state::{self, AppState};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Longest message accepted, in characters; the game cuts chat messages at 256.
+const MAX_MESSAGE_LEN: usize = 256;
+
+/// Request body for `POST /players/{name}/message` and `POST /broadcast`.
+#[derive(Deserialize)]
+pub struct MessageRequest {
+    pub message: String,
+}
+
+/// Response body for `POST /players/{name}/message` and `POST /broadcast`.
+#[derive(Serialize)]
+pub struct MessageResponse {
+    /// The command that was sent.
+    pub command: String,
+    /// Messages the server printed in response, without log headers.
+    pub output: Vec<String>,
+}
+
+/// HTTP handler whispering a message to one player.
+///
+/// # Returns
+/// * JSON response with the command and the server's output
+/// * Bad request if the player name or message is malformed, or the player isn't online
+/// * Conflict if the server is not running
+/// * Not found if there is no server with the id in the path
+pub async fn message_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<MessageRequest>,
+) -> Result<HttpResponse, ServerError> {
+    let player = http_req.match_info().get("name").unwrap_or_default();
+    if !is_valid_player_name(player) {
+        return Ok(HttpResponse::BadRequest().body(format!("Invalid player name: {}", player)));
+    }
+    let message = match check_message(&req.message) {
+        Ok(message) => message,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let command = format!("tell {} {}", player, message);
+    send_message(&http_req, &state, command).await
+}
+
+/// HTTP handler sending a message to every player with `say`.
+///
+/// # Returns
+/// * JSON response with the command and the server's output
+/// * Bad request if the message is malformed
+/// * Conflict if the server is not running
+/// * Not found if there is no server with the id in the path
+pub async fn broadcast_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<MessageRequest>,
+) -> Result<HttpResponse, ServerError> {
+    let message = match check_message(&req.message) {
+        Ok(message) => message,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let command = format!("say {}", message);
+    send_message(&http_req, &state, command).await
+}
+
+/// Checks a message is a single line of text short enough for the chat.
+///
+/// # Returns
+/// * `Result<&str, String>` - The trimmed message, or why it was refused
+fn check_message(message: &str) -> Result<&str, String> {
+    let message = message.trim();
+    if message.is_empty() {
+        return Err("Message must not be empty".to_string());
+    }
+    if message.contains(char::is_control) {
+        return Err("Message must be a single line".to_string());
+    }
+    if message.chars().count() > MAX_MESSAGE_LEN {
+        return Err(format!(
+            "Message must be at most {} characters",
+            MAX_MESSAGE_LEN
+        ));
+    }
+    Ok(message)
+}
+
+/// Sends a message command through the command policy and returns the
+/// server's response, recording it like any other client command.
+async fn send_message(
+    http_req: &HttpRequest,
+    state: &web::Data<Arc<RwLock<AppState>>>,
+    command: String,
+) -> Result<HttpResponse, ServerError> {
+    let server = server_id(http_req);
+    let caller = Caller::http(http_req);
+    let result = match state::check_command(state, server, &caller, &command).await {
+        Ok(()) => state::command_output(state, server, &command, None).await,
+        Err(e) => Err(e),
+    };
+    state::audit(
+        state,
+        &caller,
+        server,
+        AuditAction::Command,
+        Some(&command),
+        &result,
+    )
+    .await;
+    let output = result?;
+    state::record_command(state, server, None, &command).await;
+    if let Some(message) = log_parser::command_error(&output) {
+        return Ok(HttpResponse::BadRequest().body(message));
+    }
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        command,
+        output: output
+            .iter()
+            .map(|line| log_parser::message_body(line).to_string())
+            .collect(),
+    }))
+}
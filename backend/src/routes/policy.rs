@@ -0,0 +1,38 @@
+//! HTTP handlers for viewing and changing the command policy.
+
+use crate::policy::CommandPolicy;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// HTTP handler returning the command policy.
+///
+/// # Returns
+/// * JSON object with the allowed and denied prefixes
+pub async fn get_policy_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    HttpResponse::Ok().json(&state.read().await.command_policy)
+}
+
+/// HTTP handler replacing the command policy without restarting the backend.
+///
+/// # Returns
+/// * JSON object with the new policy
+/// * Bad request if a prefix is empty
+pub async fn update_policy_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<CommandPolicy>,
+) -> impl Responder {
+    let policy = req.into_inner();
+    if let Err(e) = policy.validate() {
+        return HttpResponse::BadRequest().body(e);
+    }
+    info!(
+        "Command policy updated: allowed {:?}, denied {:?}",
+        policy.allowed_prefixes, policy.denied_prefixes
+    );
+    let mut app_state = state.write().await;
+    app_state.command_policy = policy;
+    HttpResponse::Ok().json(&app_state.command_policy)
+}
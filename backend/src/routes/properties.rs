@@ -0,0 +1,126 @@
+//! HTTP handlers for viewing and editing `server.properties`.
+
+use crate::server::log_entry::LogEntry;
+use crate::server::properties::{self, ServerProperties, PROPERTIES_FILE};
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Response body for `PUT /properties` (also accepted as `POST`).
+#[derive(Serialize)]
+pub struct UpdatePropertiesResponse {
+    /// Keys whose value actually changed.
+    pub changed: Vec<String>,
+    /// Present when something changed, since the server only reads the file at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// HTTP handler returning `server.properties` as a JSON object.
+///
+/// # Returns
+/// * JSON object mapping each key to its string value
+/// * Not found if the server directory has no properties file yet
+pub async fn get_properties_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let server_dir = state.read().await.default_server().server_dir();
+    match ServerProperties::load(&server_dir) {
+        Ok(props) => {
+            let map: Map<String, Value> = props
+                .entries()
+                .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+                .collect();
+            HttpResponse::Ok().json(map)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => HttpResponse::NotFound().body(format!(
+            "No {} found in {}",
+            PROPERTIES_FILE,
+            server_dir.display()
+        )),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("Error reading {}: {}", PROPERTIES_FILE, e)),
+    }
+}
+
+/// HTTP handler updating a subset of `server.properties`.
+///
+/// Accepts a JSON object of keys to change. Values may be strings, numbers or
+/// booleans. Every value is validated before anything is written, so a bad
+/// value leaves the file untouched.
+///
+/// # Returns
+/// * JSON response listing the changed keys, with a restart warning if any changed
+/// * Bad request if any key or value is invalid, e.g. contains a line break
+pub async fn update_properties_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<Map<String, Value>>,
+) -> impl Responder {
+    let mut updates = Vec::with_capacity(req.len());
+    for (key, value) in req.iter() {
+        let value = match value {
+            Value::String(s) => s.clone(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => n.to_string(),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .body(format!("{} must be a string, number or boolean", key))
+            }
+        };
+        if let Err(e) = properties::validate(key, &value) {
+            return HttpResponse::BadRequest().body(e.to_string());
+        }
+        updates.push((key.clone(), value));
+    }
+
+    let (server_dir, running, log_sender) = {
+        let app_state = state.read().await;
+        (
+            app_state.default_server().server_dir(),
+            app_state.default_server().is_running(),
+            app_state.default_server().log_sender.clone(),
+        )
+    };
+
+    let mut props = match ServerProperties::load(&server_dir) {
+        Ok(props) => props,
+        Err(e) if e.kind() == ErrorKind::NotFound => ServerProperties::default(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Error reading {}: {}", PROPERTIES_FILE, e))
+        }
+    };
+
+    let changed: Vec<String> = updates
+        .iter()
+        .filter(|(key, value)| props.set(key, value))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    if !changed.is_empty() {
+        if let Err(e) = props.save(&server_dir) {
+            return HttpResponse::InternalServerError()
+                .body(format!("Error writing {}: {}", PROPERTIES_FILE, e));
+        }
+    }
+
+    let warning = if changed.is_empty() {
+        None
+    } else if running {
+        let _ = log_sender.send(
+            LogEntry::console(format!(
+                "[Console]: {} updated ({}); restart the server to apply",
+                PROPERTIES_FILE,
+                changed.join(", ")
+            ))
+            .into(),
+        );
+        Some("The server is running; changes take effect after a restart.".to_string())
+    } else {
+        Some("Changes take effect the next time the server starts.".to_string())
+    };
+
+    HttpResponse::Ok().json(UpdatePropertiesResponse { changed, warning })
+}
@@ -0,0 +1,73 @@
+//! HTTP handler for the query protocol.
+//!
+//! `GET /query` (or `/servers/{id}/query`) asks a managed server with
+//! `enable-query=true` for its full player list, map and plugins. With a
+//! `host` parameter any server can be queried instead.
+
+use crate::error::ServerError;
+use crate::routes::handlers::server_id;
+use crate::server::query::{self, DEFAULT_PORT};
+use crate::state::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// How long to wait for each response from the server.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Query parameters for `GET /query`.
+#[derive(Deserialize)]
+pub struct QueryParams {
+    /// Host name or address of a server to query instead of the managed one.
+    pub host: Option<String>,
+    /// Query port, defaulting to 25565 with `host`, and to the managed
+    /// server's `query.port` without.
+    pub port: Option<u16>,
+    /// Only request the basic stat, which leaves out the version, plugins
+    /// and player names.
+    #[serde(default)]
+    pub basic: bool,
+}
+
+/// HTTP handler querying a server.
+///
+/// # Returns
+/// * JSON response with the basic or full stat
+/// * Conflict if the managed server isn't running or doesn't have query enabled
+/// * Not found if there is no server with the id in the path
+/// * Bad gateway if the server sent a malformed response
+/// * Gateway timeout if the server didn't answer in time
+pub async fn query_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    params: web::Query<QueryParams>,
+) -> Result<HttpResponse, ServerError> {
+    let (host, port) = match &params.host {
+        Some(host) => (host.clone(), params.port.unwrap_or(DEFAULT_PORT)),
+        None => {
+            let server_dir = {
+                let app_state = state.read().await;
+                let server = app_state.server(server_id(&req))?;
+                if !server.is_running() {
+                    return Err(ServerError::NotRunning);
+                }
+                server.server_dir()
+            };
+            let port = match params.port {
+                Some(port) => port,
+                None => query::server_port(&server_dir)?,
+            };
+            ("127.0.0.1".to_string(), port)
+        }
+    };
+
+    if params.basic {
+        let stat = query::basic_stat(&host, port, QUERY_TIMEOUT).await?;
+        Ok(HttpResponse::Ok().json(stat))
+    } else {
+        let stat = query::full_stat(&host, port, QUERY_TIMEOUT).await?;
+        Ok(HttpResponse::Ok().json(stat))
+    }
+}
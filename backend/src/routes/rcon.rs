@@ -0,0 +1,59 @@
+//! HTTP handler for running a command over RCON.
+//!
+//! `POST /rcon` (or `/servers/{id}/rcon`) returns the text the server answered
+//! to the command. Unlike `/command`, which collects whatever the console
+//! printed afterwards, the response over RCON belongs to this command only.
+//! Servers without RCON enabled fall back to the console.
+
+use crate::audit::{AuditAction, Caller};
+use crate::error::ServerError;
+use crate::routes::handlers::server_id;
+use crate::routes::servers::CommandRequest;
+use crate::state::{self, AppState};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Response body for `POST /rcon`.
+#[derive(Serialize)]
+pub struct RconResponse {
+    /// The server's answer, possibly empty.
+    pub response: String,
+}
+
+/// HTTP handler running a command over RCON.
+///
+/// # Returns
+/// * JSON response with the server's answer
+/// * Bad request if the command contains line breaks
+/// * Conflict if the server is not running
+/// * Not found if there is no server with the id in the path
+/// * Bad gateway if RCON is enabled but the command couldn't be run over it
+/// * Gateway timeout if the server didn't answer over RCON in time, or printed
+///   nothing within the response timeout without RCON
+pub async fn rcon_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<CommandRequest>,
+) -> Result<HttpResponse, ServerError> {
+    let command = req.command.trim().trim_start_matches('/');
+    let server = server_id(&http_req);
+    let caller = Caller::http(&http_req);
+    let result = match state::check_command(&state, server, &caller, command).await {
+        Ok(()) => state::command_response(&state, server, command, req.timeout()).await,
+        Err(e) => Err(e),
+    };
+    state::audit(
+        &state,
+        &caller,
+        server,
+        AuditAction::Rcon,
+        Some(command),
+        &result,
+    )
+    .await;
+    let response = result?;
+    state::record_command(&state, server, None, command).await;
+    Ok(HttpResponse::Ok().json(RconResponse { response }))
+}
@@ -0,0 +1,66 @@
+//! HTTP handlers for automatic restarts of crashed servers.
+//!
+//! `PUT /autorestart` turns them on or off for a server at runtime, and
+//! `GET /restarts` lists the recent unexpected exits and what was done about
+//! them. Both also exist under `/servers/{id}`.
+
+use crate::error::ServerError;
+use crate::routes::handlers::server_id;
+use crate::state::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Request body for `PUT /autorestart`.
+#[derive(Deserialize)]
+pub struct AutoRestartRequest {
+    pub enabled: bool,
+}
+
+/// HTTP handler listing a server's recent unexpected exits and restarts.
+///
+/// # Returns
+/// * JSON object with the auto-restart settings, whether a restart is waiting,
+///   and the history, newest first
+/// * Not found if there is no server with the id in the path
+pub async fn list_restarts_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    let app_state = state.read().await;
+    let auto_restart = &app_state.server(server_id(&req))?.auto_restart;
+    let config = auto_restart.config();
+    Ok(HttpResponse::Ok().json(json!({
+        "enabled": auto_restart.is_enabled(),
+        "max_attempts": config.max_attempts,
+        "window_secs": config.window.as_secs(),
+        "pending": auto_restart.is_pending(),
+        "restarts": auto_restart.history(),
+    })))
+}
+
+/// HTTP handler turning automatic restarts on or off for a server.
+///
+/// Turning them off cancels a restart that is waiting for its delay.
+///
+/// # Returns
+/// * JSON object with the new setting
+/// * Not found if there is no server with the id in the path
+pub async fn set_auto_restart_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<AutoRestartRequest>,
+) -> Result<HttpResponse, ServerError> {
+    let mut app_state = state.write().await;
+    let server = app_state.server_mut(server_id(&http_req))?;
+    server.auto_restart.set_enabled(req.enabled);
+    info!(
+        server = server.id(),
+        "Auto-restart {}",
+        if req.enabled { "enabled" } else { "disabled" }
+    );
+    Ok(HttpResponse::Ok().json(json!({ "enabled": req.enabled })))
+}
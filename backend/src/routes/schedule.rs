@@ -0,0 +1,107 @@
+//! HTTP handlers for viewing and changing scheduled tasks.
+
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Request body for `PUT /schedule`.
+#[derive(Deserialize)]
+pub struct UpdateScheduleRequest {
+    /// New backup interval such as `6h` or `30m`; null disables automatic backups.
+    pub backup_interval: Option<String>,
+}
+
+/// Request body for `PUT /schedule/restart`.
+#[derive(Deserialize)]
+pub struct UpdateRestartScheduleRequest {
+    /// New local time of day such as `04:00`; null disables daily restarts.
+    /// Ignored when `times` is given.
+    pub time: Option<String>,
+    /// New restart times, each `HH:MM` or a cron expression; empty disables restarts.
+    #[serde(default)]
+    pub times: Option<Vec<String>>,
+    /// Whether to restart without a countdown when nobody is online; unchanged if absent.
+    #[serde(default)]
+    pub skip_countdown_if_empty: Option<bool>,
+}
+
+/// HTTP handler returning the current schedule.
+///
+/// # Returns
+/// * JSON object with the backup interval and the restart times, each with
+///   their next and last run and last result
+pub async fn get_schedule_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let app_state = state.read().await;
+    HttpResponse::Ok().json(json!({
+        "backup": app_state.backup_schedule,
+        "backup_in_progress": app_state.backup_in_progress,
+        "restart": app_state.restart_schedule,
+    }))
+}
+
+/// HTTP handler changing the backup interval without restarting the backend.
+///
+/// # Returns
+/// * JSON object with the updated backup schedule
+/// * Bad request if the interval can't be parsed
+pub async fn update_schedule_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<UpdateScheduleRequest>,
+) -> impl Responder {
+    let mut app_state = state.write().await;
+    match app_state
+        .backup_schedule
+        .set_interval(req.backup_interval.as_deref())
+    {
+        Ok(()) => HttpResponse::Ok().json(json!({ "backup": app_state.backup_schedule })),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
+
+/// HTTP handler changing the scheduled restarts without restarting the backend.
+///
+/// Changing the times during a countdown cancels that restart.
+///
+/// # Returns
+/// * JSON object with the updated restart schedule
+/// * Bad request if a time can't be parsed
+pub async fn update_restart_schedule_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<UpdateRestartScheduleRequest>,
+) -> impl Responder {
+    let mut app_state = state.write().await;
+    let schedule = &mut app_state.restart_schedule;
+    let times = match &req.times {
+        Some(times) => times.clone(),
+        None => req.time.iter().cloned().collect(),
+    };
+    if let Err(e) = schedule.set_times(&times) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    if let Some(skip) = req.skip_countdown_if_empty {
+        schedule.skip_countdown_if_empty = skip;
+    }
+    HttpResponse::Ok().json(json!({ "restart": schedule }))
+}
+
+/// HTTP handler skipping the next scheduled restart, cancelling its
+/// countdown if it has started. Later restarts happen as usual.
+///
+/// # Returns
+/// * JSON object with the skipped restart and the updated restart schedule
+/// * Conflict if no restart is scheduled
+pub async fn skip_restart_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let mut app_state = state.write().await;
+    let schedule = &mut app_state.restart_schedule;
+    match schedule.skip_next() {
+        Some(skipped) => {
+            info!("Skipping the scheduled restart at {}", skipped);
+            HttpResponse::Ok().json(json!({ "skipped": skipped, "restart": schedule }))
+        }
+        None => HttpResponse::Conflict().body("No restart is scheduled"),
+    }
+}
@@ -0,0 +1,118 @@
+//! HTTP handlers for managing several servers.
+//!
+//! `GET /servers` lists every managed server and `POST /servers` adds one at
+//! runtime. Servers added this way last until the backend restarts; to keep
+//! one, add a `[[servers]]` table to the config file.
+//!
+//! `POST /servers/{id}/command` (and `/command` for the default server) sends
+//! a console command and returns what the server printed in response.
+
+use crate::audit::{AuditAction, Caller};
+use crate::error::ServerError;
+use crate::routes::handlers::{server_id, StatusResponse};
+use crate::server::{log_parser, ServerDefinition};
+use crate::state::{self, AppState};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Request body for `POST /servers/{id}/command`.
+#[derive(Deserialize)]
+pub struct CommandRequest {
+    /// The console command, without a leading slash.
+    pub command: String,
+    /// Seconds to wait for the server's output instead of the configured
+    /// response timeout, for commands that take longer to answer.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+impl CommandRequest {
+    /// Returns how long to wait for the response, None for the server's default.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+}
+
+/// Response body for `POST /servers/{id}/command`.
+#[derive(Serialize)]
+pub struct CommandResponse {
+    /// Messages the server printed in response, without log headers.
+    pub output: Vec<String>,
+}
+
+/// HTTP handler listing all managed servers.
+///
+/// # Returns
+/// * JSON array with the status of each server, sorted by id
+pub async fn list_servers_handler(state: web::Data<Arc<RwLock<AppState>>>) -> HttpResponse {
+    let app_state = state.read().await;
+    let servers: Vec<StatusResponse> = app_state
+        .servers()
+        .into_iter()
+        .map(|server| StatusResponse::new(&app_state, server))
+        .collect();
+    HttpResponse::Ok().json(servers)
+}
+
+/// HTTP handler adding a server.
+///
+/// The body is the same as a `[[servers]]` table of the config file.
+///
+/// # Returns
+/// * Created with the new server's status
+/// * Bad request if the id is invalid or the server path isn't a file
+/// * Conflict if a server with the id already exists
+pub async fn add_server_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<ServerDefinition>,
+) -> Result<HttpResponse, ServerError> {
+    let mut app_state = state.write().await;
+    let id = app_state.add_server(req.into_inner())?.id().to_string();
+    let server = app_state.server(Some(&id))?;
+    Ok(HttpResponse::Created().json(StatusResponse::new(&app_state, server)))
+}
+
+/// HTTP handler sending a console command.
+///
+/// # Returns
+/// * JSON response with the server's output
+/// * Bad request if the command contains line breaks, or the server rejected it
+/// * Conflict if the server is not running
+/// * Not found if there is no server with the id in the path
+/// * Gateway timeout if the server printed nothing within the response timeout
+pub async fn command_handler(
+    http_req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<CommandRequest>,
+) -> Result<HttpResponse, ServerError> {
+    let command = req.command.trim().trim_start_matches('/');
+    let server = server_id(&http_req);
+    let caller = Caller::http(&http_req);
+    let result = match state::check_command(&state, server, &caller, command).await {
+        Ok(()) => state::command_output(&state, server, command, req.timeout()).await,
+        Err(e) => Err(e),
+    };
+    state::audit(
+        &state,
+        &caller,
+        server,
+        AuditAction::Command,
+        Some(command),
+        &result,
+    )
+    .await;
+    let output = result?;
+    state::record_command(&state, server, None, command).await;
+    if let Some(message) = log_parser::command_error(&output) {
+        return Ok(HttpResponse::BadRequest().body(message));
+    }
+    Ok(HttpResponse::Ok().json(CommandResponse {
+        output: output
+            .iter()
+            .map(|line| log_parser::message_body(line).to_string())
+            .collect(),
+    }))
+}
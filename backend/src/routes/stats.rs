@@ -0,0 +1,37 @@
+//! HTTP handler for the server process's resource usage history.
+
+use crate::scheduler::parse_duration;
+use crate::state::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Window returned when the request doesn't specify one.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Query parameters for `GET /stats`.
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    /// How far back to return samples, e.g. `5m` or `1h`.
+    pub window: Option<String>,
+}
+
+/// HTTP handler returning the memory and CPU samples within a time window.
+///
+/// # Returns
+/// * JSON array of samples, oldest first
+/// * Bad request if the window can't be parsed
+pub async fn stats_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<StatsQuery>,
+) -> impl Responder {
+    let window = match query.window.as_deref().map(parse_duration) {
+        Some(Ok(window)) => window,
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e),
+        None => DEFAULT_WINDOW,
+    };
+    let samples = state.read().await.resource_history.since(window);
+    HttpResponse::Ok().json(samples)
+}
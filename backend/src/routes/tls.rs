@@ -0,0 +1,26 @@
+//! HTTP handler reloading the TLS certificate.
+//!
+//! `POST /admin/reload-tls` reads the certificate and key files again, e.g.
+//! after a Let's Encrypt renewal; sending the backend SIGHUP does the same.
+
+use crate::tls::Certificates;
+use actix_web::{web, HttpResponse};
+use std::sync::Arc;
+
+/// HTTP handler reloading the certificate and key files.
+///
+/// # Returns
+/// * No content once new connections use the reloaded certificate
+/// * Conflict if the backend isn't serving HTTPS
+/// * Internal server error if the files can't be used; the current certificate is kept
+pub async fn reload_tls_handler(
+    certificates: web::Data<Option<Arc<Certificates>>>,
+) -> HttpResponse {
+    let Some(certificates) = certificates.get_ref() else {
+        return HttpResponse::Conflict().body("TLS is not enabled");
+    };
+    match certificates.reload() {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
@@ -0,0 +1,46 @@
+//! HTTP handler reporting what versions are running.
+//!
+//! `GET /version` answers with the backend's version and the game version and
+//! mod loader the default server printed while starting;
+//! `GET /servers/{id}/version` does the same for another server.
+
+use crate::error::ServerError;
+use crate::health::VERSION;
+use crate::routes::handlers::server_id;
+use crate::server::log_parser::ServerVersion;
+use crate::state::AppState;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Response body of `GET /version`.
+#[derive(Serialize)]
+struct VersionResponse {
+    /// Version of the backend crate.
+    backend: &'static str,
+    /// Id of the server the rest is about.
+    server: String,
+    /// What the server printed; null until it has started far enough to print it.
+    #[serde(flatten)]
+    version: ServerVersion,
+}
+
+/// HTTP handler returning the backend's version and the server's.
+///
+/// # Returns
+/// * JSON with the backend version and the server's Minecraft version, mod
+///   loader and loader version, each null until the server printed it
+/// * Not found if there is no server with the id in the path
+pub async fn version_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    let app_state = state.read().await;
+    let server = app_state.server(server_id(&req))?;
+    Ok(HttpResponse::Ok().json(VersionResponse {
+        backend: VERSION,
+        server: server.id().to_string(),
+        version: server.version().clone(),
+    }))
+}
@@ -0,0 +1,15 @@
+//! HTTP handler for inspecting outbound webhook deliveries.
+
+use crate::state::AppState;
+use actix_web::{web, HttpResponse};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// HTTP handler listing recent webhook deliveries.
+///
+/// # Returns
+/// * JSON array of the last 100 deliveries with their status and attempts, newest first
+pub async fn list_deliveries_handler(state: web::Data<Arc<RwLock<AppState>>>) -> HttpResponse {
+    let webhooks = state.read().await.webhooks.clone();
+    HttpResponse::Ok().json(webhooks.deliveries())
+}
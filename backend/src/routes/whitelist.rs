@@ -0,0 +1,140 @@
+//! HTTP handlers for the whitelist.
+//!
+//! While the Minecraft server is running, players are added and removed with
+//! the `whitelist` console command and the server's answer is checked for
+//! errors. When it is stopped, `whitelist.json` is edited directly instead.
+//! Either way the response is the whitelist as it is afterwards.
+
+use crate::routes::bans::is_valid_player_name;
+use crate::server::{log_parser, whitelist};
+use crate::state::{self, AppState};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Request body for `POST /whitelist`.
+#[derive(Deserialize)]
+pub struct WhitelistRequest {
+    /// Name of the player to add.
+    pub name: String,
+}
+
+/// HTTP handler listing the whitelist.
+///
+/// # Returns
+/// * JSON array of `{uuid, name}` entries
+/// * Error response if `whitelist.json` could not be read
+pub async fn list_whitelist_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let server_dir = state.read().await.default_server().server_dir();
+    whitelist_response(&server_dir)
+}
+
+/// HTTP handler adding a player to the whitelist.
+///
+/// # Returns
+/// * The updated whitelist
+/// * Bad request if the name is invalid or the server rejected it, e.g. an
+///   online-mode server that couldn't resolve the player, with the server's message
+/// * Not found if an offline add targets a player the server has never seen
+pub async fn add_whitelist_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    req: web::Json<WhitelistRequest>,
+) -> impl Responder {
+    let name = req.name.trim();
+    if !is_valid_player_name(name) {
+        return HttpResponse::BadRequest().body(format!("Invalid player name: {}", name));
+    }
+    update_whitelist(&state, &format!("whitelist add {}", name), |server_dir| {
+        whitelist::add(server_dir, name).map(|_| ())
+    })
+    .await
+}
+
+/// HTTP handler removing a player from the whitelist.
+///
+/// # Returns
+/// * The updated whitelist
+/// * Bad request if the name is invalid or the server rejected the command
+/// * Not found if an offline removal targets a player that isn't whitelisted
+pub async fn remove_whitelist_handler(
+    state: web::Data<Arc<RwLock<AppState>>>,
+    name: web::Path<String>,
+) -> impl Responder {
+    let name = name.trim();
+    if !is_valid_player_name(name) {
+        return HttpResponse::BadRequest().body(format!("Invalid player name: {}", name));
+    }
+    update_whitelist(
+        &state,
+        &format!("whitelist remove {}", name),
+        |server_dir| match whitelist::remove(server_dir, name)? {
+            true => Ok(()),
+            false => Err(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} is not whitelisted.", name),
+            )),
+        },
+    )
+    .await
+}
+
+/// Applies a whitelist change through the console if the server is running,
+/// otherwise through the file, and responds with the resulting whitelist.
+///
+/// # Arguments
+/// * `command` - Console command making the change
+/// * `offline` - Makes the same change to `whitelist.json`
+async fn update_whitelist(
+    state: &RwLock<AppState>,
+    command: &str,
+    offline: impl FnOnce(&Path) -> std::io::Result<()>,
+) -> HttpResponse {
+    let (running, server_dir) = {
+        let app_state = state.read().await;
+        (
+            app_state.default_server().is_running(),
+            app_state.default_server().server_dir(),
+        )
+    };
+
+    if running {
+        match state::command_output(state, None, command, None).await {
+            Ok(output) => {
+                if let Some(message) = log_parser::command_error(&output) {
+                    return HttpResponse::BadRequest().body(message);
+                }
+            }
+            Err(e) => return e.error_response(),
+        }
+    } else {
+        match offline(&server_dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return HttpResponse::NotFound().body(e.to_string())
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError().body(format!(
+                    "Error writing {}: {}",
+                    whitelist::WHITELIST_FILE,
+                    e
+                ))
+            }
+        }
+    }
+    whitelist_response(&server_dir)
+}
+
+/// Responds with the contents of `whitelist.json`.
+fn whitelist_response(server_dir: &Path) -> HttpResponse {
+    match whitelist::load(server_dir) {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => HttpResponse::InternalServerError().body(format!(
+            "Error reading {}: {}",
+            whitelist::WHITELIST_FILE,
+            e
+        )),
+    }
+}
@@ -0,0 +1,172 @@
+//! HTTP handlers for the worlds in the server directory.
+//!
+//! `GET /worlds` lists every directory with a `level.dat` and `POST
+//! /worlds/active` switches `level-name` to one of them. The server only
+//! loads its world at startup, so switching while it runs needs
+//! `"restart": true`, which stops it, switches and starts it again. `GET
+//! /worlds/{name}/info` reads a world's seed, version and settings from its
+//! `level.dat`.
+
+use crate::audit::{AuditAction, Caller};
+use crate::error::ServerError;
+use crate::server::worlds;
+use crate::state::{self, AppState};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Request body for `POST /worlds/active`.
+#[derive(Deserialize)]
+pub struct ActiveWorldRequest {
+    /// Name of the world's directory.
+    pub name: String,
+    /// Restart a running server to load the world; without it a running
+    /// server is refused.
+    #[serde(default)]
+    pub restart: bool,
+}
+
+/// Response body for `POST /worlds/active`.
+#[derive(Serialize)]
+pub struct ActiveWorldResponse {
+    /// The active world.
+    pub name: String,
+    /// The world that was active before.
+    pub previous: String,
+    /// Whether `level-name` changed.
+    pub changed: bool,
+    /// Whether the server was restarted to load the world.
+    pub restarted: bool,
+}
+
+/// HTTP handler listing the worlds with their sizes.
+///
+/// Sizes are added up on the blocking thread pool, since a large world has
+/// many thousands of region files.
+///
+/// # Returns
+/// * JSON array of worlds sorted by name
+pub async fn list_worlds_handler(state: web::Data<Arc<RwLock<AppState>>>) -> impl Responder {
+    let server_dir = state.read().await.default_server().server_dir();
+    match web::block(move || worlds::list_worlds(&server_dir)).await {
+        Ok(Ok(worlds)) => HttpResponse::Ok().json(worlds),
+        Ok(Err(e)) => {
+            HttpResponse::InternalServerError().body(format!("Error listing worlds: {}", e))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error listing worlds: {}", e)),
+    }
+}
+
+/// HTTP handler returning the basic information of a world from its `level.dat`.
+///
+/// # Returns
+/// * JSON object with the seed, version, game mode, difficulty and last played time
+/// * Not found if there is no world by that name
+/// * Internal server error with code `invalid_level_dat` if `level.dat` is corrupt
+pub async fn world_info_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+) -> Result<HttpResponse, ServerError> {
+    let name = req.match_info().get("name").unwrap_or_default().to_string();
+    let server_dir = state.read().await.default_server().server_dir();
+    let world = name.clone();
+    let result = web::block(move || worlds::read_level_info(&server_dir, &world))
+        .await
+        .map_err(|e| ServerError::Io(Error::other(e.to_string())))?;
+    match result {
+        Ok(info) => Ok(HttpResponse::Ok().json(info)),
+        Err(e) => Err(match e.kind() {
+            ErrorKind::InvalidInput | ErrorKind::NotFound => ServerError::UnknownWorld { name },
+            ErrorKind::InvalidData => ServerError::InvalidLevelDat {
+                name,
+                reason: e.to_string(),
+            },
+            _ => ServerError::Io(e),
+        }),
+    }
+}
+
+/// HTTP handler switching the active world.
+///
+/// # Returns
+/// * JSON response with the active and the previous world
+/// * Bad request if the name isn't a plain directory name
+/// * Not found if there is no world by that name
+/// * Conflict if the server is running and `restart` wasn't given
+/// * Internal server error if the server failed to stop or start
+pub async fn set_active_world_handler(
+    req: HttpRequest,
+    state: web::Data<Arc<RwLock<AppState>>>,
+    body: web::Json<ActiveWorldRequest>,
+) -> Result<HttpResponse, ServerError> {
+    let name = body.name.trim();
+    let mut app_state = state.write().await;
+    let server = app_state.server_mut(None)?;
+    let server_dir = server.server_dir();
+    let previous = worlds::level_name(&server_dir);
+    if let Err(e) = worlds::check_world(&server_dir, name) {
+        return Ok(world_error_response(e));
+    }
+
+    let running = server.is_running();
+    if !running || previous == name {
+        return Ok(match worlds::set_active_world(&server_dir, name) {
+            Ok(changed) => {
+                if changed {
+                    info!("Active world changed from {} to {}", previous, name);
+                }
+                HttpResponse::Ok().json(ActiveWorldResponse {
+                    name: name.to_string(),
+                    previous,
+                    changed,
+                    restarted: false,
+                })
+            }
+            Err(e) => world_error_response(e),
+        });
+    }
+    if !body.restart {
+        return Ok(HttpResponse::Conflict().body(
+            "The server is running; pass \"restart\": true to restart it with the new world",
+        ));
+    }
+
+    // Not held while the server saves and shuts down
+    drop(app_state);
+    let result = state::restart_server(&state, None, || {
+        Ok(worlds::set_active_world(&server_dir, name)?)
+    })
+    .await;
+    state::audit(
+        &state,
+        &Caller::http(&req),
+        None,
+        AuditAction::Restart,
+        Some(&format!("level-name {}", name)),
+        &result,
+    )
+    .await;
+    let changed = result?;
+    info!(
+        "Active world changed from {} to {}, server restarted",
+        previous, name
+    );
+    Ok(HttpResponse::Ok().json(ActiveWorldResponse {
+        name: name.to_string(),
+        previous,
+        changed,
+        restarted: true,
+    }))
+}
+
+/// Maps an error about a world name to a response.
+fn world_error_response(e: Error) -> HttpResponse {
+    match e.kind() {
+        ErrorKind::InvalidInput => HttpResponse::BadRequest().body(e.to_string()),
+        ErrorKind::NotFound => HttpResponse::NotFound().body(e.to_string()),
+        _ => HttpResponse::InternalServerError().body(format!("Error switching worlds: {}", e)),
+    }
+}
@@ -0,0 +1,847 @@
+//! Background scheduler for recurring server tasks.
+//!
+//! This module runs automatic world backups at a configurable interval. The
+//! interval can be changed at runtime through the `/schedule` endpoints; the
+//! scheduler picks up the new value on its next tick.
+//!
+//! Scheduled restarts work the same way through `/schedule/restart`. Each
+//! restart time is either a daily `HH:MM` or a five-field cron expression
+//! such as `0 4 * * 1-5`. Players are warned 15, 10, 5 and 1 minutes ahead
+//! (`warning_minutes`), then the server is stopped and started again the way
+//! it was last started. `POST /schedule/restart/skip` skips the next one.
+//!
+//! It also polls the server for its tick rate and memory usage, and watches
+//! for the server process exiting without being stopped through the backend.
+
+use crate::config;
+use crate::server::backup;
+use crate::server::performance::{PerformanceReading, TPS_COMMANDS};
+use crate::state::{self, AppState, ServerEvent};
+use crate::supervisor;
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// How often the scheduler checks whether a task is due.
+const TICK: Duration = Duration::from_secs(1);
+/// How often the exit watcher checks whether the server process is still alive.
+const EXIT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Settings for scheduled restarts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RestartScheduleConfig {
+    /// Local time of day of a daily restart, e.g. `04:00`; shorthand for one entry of `times`.
+    pub time: Option<String>,
+    /// Restart times, each a daily `HH:MM` or a five-field cron expression.
+    pub times: Vec<String>,
+    /// Restart right away, without the countdown, when no players are online.
+    pub skip_countdown_if_empty: bool,
+    /// In-game warning sent during the countdown; `{minutes}` is replaced by the minutes left.
+    pub warning_message: String,
+    /// Minutes before a restart at which players are warned; the earliest starts the countdown.
+    pub warning_minutes: Vec<u32>,
+}
+
+impl Default for RestartScheduleConfig {
+    fn default() -> Self {
+        RestartScheduleConfig {
+            time: None,
+            times: Vec::new(),
+            skip_countdown_if_empty: true,
+            warning_message: "Server restarting in {minutes} minute(s)".to_string(),
+            warning_minutes: vec![15, 10, 5, 1],
+        }
+    }
+}
+
+impl RestartScheduleConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_RESTART_TIME` - local time of day of the daily restart, e.g. `04:00`
+    /// * `MC_RESTART_TIMES` - restart times or cron expressions separated by `;`
+    /// * `MC_RESTART_SKIP_IF_EMPTY` - `true` to skip the countdown when nobody is online
+    /// * `MC_RESTART_WARNING` - in-game warning message
+    /// * `MC_RESTART_WARNING_MINUTES` - minutes of the warnings separated by `,`
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(time) = config::env_string("MC_RESTART_TIME") {
+            self.time = Some(time);
+        }
+        if let Ok(times) = std::env::var("MC_RESTART_TIMES") {
+            self.times = config::env_list(&times, ';');
+        }
+        if let Ok(minutes) = std::env::var("MC_RESTART_WARNING_MINUTES") {
+            self.warning_minutes = config::env_list(&minutes, ',')
+                .iter()
+                .map(|minutes| {
+                    minutes.parse().map_err(|_| {
+                        format!("MC_RESTART_WARNING_MINUTES: invalid number '{}'", minutes)
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(skip) = config::env_parse("MC_RESTART_SKIP_IF_EMPTY")? {
+            self.skip_countdown_if_empty = skip;
+        }
+        if let Ok(message) = std::env::var("MC_RESTART_WARNING") {
+            self.warning_message = message;
+        }
+        Ok(())
+    }
+
+    /// Returns every configured restart time, `time` first.
+    pub fn all_times(&self) -> Vec<String> {
+        self.time.iter().chain(&self.times).cloned().collect()
+    }
+
+    /// Checks that the values make sense.
+    pub fn validate(&self) -> Result<(), String> {
+        for time in self.all_times() {
+            parse_restart_time(&time).map_err(|e| format!("restart time: {}", e))?;
+        }
+        if self.warning_minutes.contains(&0) {
+            return Err("restart warning_minutes must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns the warning minutes from the earliest to the last.
+    fn countdown_minutes(&self) -> Vec<u32> {
+        let mut minutes = self.warning_minutes.clone();
+        minutes.sort_unstable_by(|a, b| b.cmp(a));
+        minutes.dedup();
+        minutes
+    }
+}
+
+/// Settings for the warnings before a stop or restart requested with `?countdown=`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CountdownConfig {
+    /// How long before the stop or restart players are warned, e.g. `5m` or `30s`.
+    pub warnings: Vec<String>,
+    /// In-game warning; `{action}` is replaced by `stopping` or `restarting` and
+    /// `{time}` by the time left, e.g. `5 minutes`.
+    pub message: String,
+}
+
+impl Default for CountdownConfig {
+    fn default() -> Self {
+        CountdownConfig {
+            warnings: ["5m", "1m", "30s", "10s"].map(str::to_string).to_vec(),
+            message: "Server {action} in {time}".to_string(),
+        }
+    }
+}
+
+impl CountdownConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_COUNTDOWN_WARNINGS` - times before the action separated by `,`, e.g. `5m,1m,30s`
+    /// * `MC_COUNTDOWN_MESSAGE` - in-game warning message
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Ok(warnings) = std::env::var("MC_COUNTDOWN_WARNINGS") {
+            self.warnings = config::env_list(&warnings, ',');
+        }
+        if let Ok(message) = std::env::var("MC_COUNTDOWN_MESSAGE") {
+            self.message = message;
+        }
+        Ok(())
+    }
+
+    /// Checks that the values make sense.
+    pub fn validate(&self) -> Result<(), String> {
+        for warning in &self.warnings {
+            parse_duration(warning).map_err(|e| format!("countdown warning: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the times left at which players are warned during a countdown
+    /// of `length`, longest first: the start of the countdown, then every
+    /// configured warning within it.
+    fn marks(&self, length: Duration) -> Vec<Duration> {
+        let mut marks: Vec<Duration> = self
+            .warnings
+            .iter()
+            .filter_map(|warning| parse_duration(warning).ok())
+            .filter(|&mark| mark < length)
+            .collect();
+        marks.push(length);
+        marks.sort_unstable_by(|a, b| b.cmp(a));
+        marks.dedup();
+        marks
+    }
+}
+
+/// Runtime state of the backup schedule.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BackupSchedule {
+    /// Interval between automatic backups as written by the user, e.g. `6h`. None disables them.
+    pub interval: Option<String>,
+    /// When the next automatic backup will start.
+    pub next_run: Option<DateTime<Local>>,
+    /// When the last automatic backup started.
+    pub last_run: Option<DateTime<Local>>,
+    /// Outcome of the last automatic backup.
+    pub last_result: Option<String>,
+}
+
+impl BackupSchedule {
+    /// Sets a new interval and schedules the next run one interval from now.
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Error message if the interval can't be parsed
+    pub fn set_interval(&mut self, interval: Option<&str>) -> Result<(), String> {
+        match interval.map(str::trim).filter(|i| !i.is_empty()) {
+            Some(text) => {
+                let duration = parse_duration(text)?;
+                self.interval = Some(text.to_string());
+                self.next_run = Some(Local::now() + duration);
+            }
+            None => {
+                self.interval = None;
+                self.next_run = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the interval as a duration, if automatic backups are enabled.
+    fn interval_duration(&self) -> Option<Duration> {
+        self.interval
+            .as_deref()
+            .and_then(|i| parse_duration(i).ok())
+    }
+}
+
+/// Runtime state of the scheduled restarts.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestartSchedule {
+    /// Restart times as written by the user, e.g. `04:00` or `0 4 * * 1-5`. Empty disables them.
+    pub times: Vec<String>,
+    /// Whether the countdown is skipped when no players are online.
+    pub skip_countdown_if_empty: bool,
+    /// When the next restart will happen; the countdown starts before it.
+    pub next_run: Option<DateTime<Local>>,
+    /// When the last scheduled restart happened, or was skipped or cancelled.
+    pub last_run: Option<DateTime<Local>>,
+    /// Outcome of the last scheduled restart.
+    pub last_result: Option<String>,
+}
+
+impl RestartSchedule {
+    /// Sets new restart times and schedules the next run at the earliest of them.
+    ///
+    /// # Returns
+    /// * `Result<(), String>` - Error message if a time can't be parsed
+    pub fn set_times(&mut self, times: &[String]) -> Result<(), String> {
+        let times: Vec<String> = times
+            .iter()
+            .map(|time| time.trim().to_string())
+            .filter(|time| !time.is_empty())
+            .collect();
+        for time in &times {
+            parse_restart_time(time)?;
+        }
+        self.times = times;
+        self.next_run = self.next_after(Local::now());
+        Ok(())
+    }
+
+    /// Skips the next restart, scheduling the one after it instead. A
+    /// countdown in progress is cancelled.
+    ///
+    /// # Returns
+    /// * `Option<DateTime<Local>>` - The skipped restart, None if none was scheduled
+    pub fn skip_next(&mut self) -> Option<DateTime<Local>> {
+        let skipped = self.next_run?;
+        self.next_run = self.next_after(skipped);
+        self.last_run = Some(Local::now());
+        self.last_result = Some(format!(
+            "Skipped the restart at {}",
+            skipped.format("%F %R")
+        ));
+        Some(skipped)
+    }
+
+    /// Returns the first restart after `after` of any of the times.
+    fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        self.times
+            .iter()
+            .filter_map(|time| parse_restart_time(time).ok())
+            .filter_map(|time| time.next_after(after))
+            .min()
+    }
+}
+
+/// When a scheduled restart happens.
+enum RestartTime {
+    /// Every day at this local time.
+    Daily(NaiveTime),
+    /// Whenever the cron expression matches.
+    Cron(CronSchedule),
+}
+
+impl RestartTime {
+    /// Returns the first time after `after` this matches.
+    fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            RestartTime::Daily(time) => Some(next_occurrence(*time, after)),
+            RestartTime::Cron(cron) => cron.next_after(after),
+        }
+    }
+}
+
+/// Parses a restart time: `HH:MM` for a daily restart, or a five-field cron
+/// expression (`minute hour day-of-month month day-of-week`).
+fn parse_restart_time(text: &str) -> Result<RestartTime, String> {
+    if text.split_whitespace().count() > 1 {
+        CronSchedule::parse(text).map(RestartTime::Cron)
+    } else {
+        parse_time_of_day(text).map(RestartTime::Daily)
+    }
+}
+
+/// A five-field cron expression, each field held as a bit set of the values it matches.
+///
+/// Fields accept `*`, numbers, ranges like `1-5` and steps like `*/15` or
+/// `0-30/10`, separated by commas. Day of week runs from 0 (Sunday) to 7
+/// (Sunday again). As in cron, a restricted day of month and day of week
+/// match if either does.
+struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month is `*`.
+    any_day: bool,
+    /// Whether the day of week is `*`.
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// Parses a cron expression such as `30 4 * * 1-5`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "Invalid cron expression '{}': expected 5 fields (minute hour day month weekday)",
+                text
+            ));
+        };
+        let field = |field: &str, min: u32, max: u32| {
+            parse_cron_field(field, min, max)
+                .map_err(|e| format!("Invalid cron expression '{}': {}", text, e))
+        };
+        let mut weekday_bits = field(weekdays, 0, 7)?;
+        // 7 is Sunday too
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+
+    /// Returns whether the expression matches a date, ignoring the time.
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        self.months & (1 << date.month()) != 0 && day_matches
+    }
+
+    /// Returns the first time after `after` the expression matches, None if it
+    /// never does within the next few years (e.g. `0 0 31 2 *`).
+    fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut date = after.date_naive();
+        // Long enough to reach a 29 February
+        for _ in 0..(5 * 366) {
+            if self.matches_date(date) {
+                for hour in (0..24).filter(|hour| self.hours & (1 << hour) != 0) {
+                    for minute in (0..60).filter(|minute| self.minutes & (1 << minute) != 0) {
+                        let Some(time) = NaiveTime::from_hms_opt(hour, minute, 0) else {
+                            continue;
+                        };
+                        // Times skipped by a daylight saving change don't match
+                        let next = Local.from_local_datetime(&date.and_time(time)).earliest();
+                        if let Some(next) = next.filter(|&next| next > after) {
+                            return Some(next);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+/// Parses one cron field into a bit set of the values it matches.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let number = |text: &str| -> Result<u32, String> {
+        text.parse()
+            .ok()
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(|| format!("'{}' must be a number from {} to {}", text, min, max))
+    };
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|&step| step > 0)
+                    .ok_or_else(|| format!("invalid step in '{}'", part))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                None => {
+                    let value = number(range)?;
+                    // `5/10` means from 5 to the maximum, every 10
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("range '{}' is backwards", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// Parses a time of day such as `04:00` or `23:30`.
+///
+/// # Returns
+/// * `Result<NaiveTime, String>` - Error message describing what is wrong with the input
+pub fn parse_time_of_day(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .map_err(|_| format!("Invalid time '{}': expected HH:MM, e.g. 04:00", text))
+}
+
+/// Returns the first time after `after` at which the local clock shows `time`.
+///
+/// On a day where that time is skipped by a daylight saving change, the run
+/// moves to the next day.
+fn next_occurrence(time: NaiveTime, after: DateTime<Local>) -> DateTime<Local> {
+    let mut date = after.date_naive();
+    loop {
+        if let Some(next) = Local.from_local_datetime(&date.and_time(time)).earliest() {
+            if next > after {
+                return next;
+            }
+        }
+        date = date.succ_opt().unwrap_or(date);
+    }
+}
+
+/// Parses a duration such as `90s`, `30m`, `6h`, `1d` or a combination like `1h30m`.
+///
+/// # Returns
+/// * `Result<Duration, String>` - Error message describing what is wrong with the input
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in text.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("Invalid duration '{}': unknown unit '{}'", text, c)),
+        };
+        let value: u64 = number.parse().map_err(|_| {
+            format!(
+                "Invalid duration '{}': expected a number before '{}'",
+                text, c
+            )
+        })?;
+        total += value * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(format!(
+            "Invalid duration '{}': missing unit (s, m, h or d)",
+            text
+        ));
+    }
+    if total == 0 {
+        return Err(format!(
+            "Invalid duration '{}': must be greater than zero",
+            text
+        ));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Runs the backup scheduler forever.
+///
+/// Backups run inline in this loop, so a backup that takes longer than the
+/// interval simply delays the next one instead of overlapping it. Runs that
+/// were missed in the meantime are skipped.
+pub async fn run_backup_scheduler(state: Arc<RwLock<AppState>>) {
+    info!("Backup scheduler started");
+    let mut ticker = tokio::time::interval(TICK);
+    loop {
+        ticker.tick().await;
+
+        let due = {
+            let app_state = state.read().await;
+            let schedule = &app_state.backup_schedule;
+            schedule.next_run.is_some_and(|next| next <= Local::now())
+        };
+        if due {
+            scheduled_backup(&state).await;
+        }
+    }
+}
+
+/// Runs one scheduled backup, warning players beforehand and announcing the result.
+async fn scheduled_backup(state: &Arc<RwLock<AppState>>) {
+    let (running, warning, lead) = {
+        let mut app_state = state.write().await;
+        let now = Local::now();
+        app_state.backup_schedule.last_run = Some(now);
+        // Schedule the next run right away so a failing backup doesn't retry every tick
+        app_state.backup_schedule.next_run = app_state
+            .backup_schedule
+            .interval_duration()
+            .map(|interval| now + interval);
+        (
+            app_state.default_server().is_running(),
+            app_state.backup_config.warning_message.clone(),
+            app_state.backup_config.warning_lead,
+        )
+    };
+
+    if running && !lead.is_zero() {
+        say(
+            state,
+            &warning.replace("{seconds}", &lead.as_secs().to_string()),
+        )
+        .await;
+        tokio::time::sleep(lead).await;
+    }
+
+    let result = backup::run_backup(state).await;
+    let summary = match &result {
+        Ok(info) => format!("Completed: {}", info.name),
+        Err(e) => format!("Failed: {}", e),
+    };
+    if running {
+        let announcement = match &result {
+            Ok(_) => "Backup complete.",
+            Err(_) => "Backup failed, check the console.",
+        };
+        say(state, announcement).await;
+    }
+    state.write().await.backup_schedule.last_result = Some(summary);
+}
+
+/// Runs the restart scheduler forever.
+///
+/// Like backups, a restart runs inline in this loop, countdown included.
+/// Changing, skipping or disabling the restart during the countdown cancels it.
+pub async fn run_restart_scheduler(state: Arc<RwLock<AppState>>) {
+    info!("Restart scheduler started");
+    let minutes = state.read().await.restart_config.countdown_minutes();
+    let countdown = chrono::Duration::minutes(minutes.first().copied().unwrap_or(0).into());
+    let mut ticker = tokio::time::interval(TICK);
+    loop {
+        ticker.tick().await;
+
+        let due = state
+            .read()
+            .await
+            .restart_schedule
+            .next_run
+            .filter(|&next| next - countdown <= Local::now());
+        if let Some(restart_at) = due {
+            scheduled_restart(&state, restart_at).await;
+        }
+    }
+}
+
+/// Counts down to a scheduled restart, then restarts the default server.
+///
+/// The server is left alone if it isn't running when the countdown starts.
+async fn scheduled_restart(state: &Arc<RwLock<AppState>>, restart_at: DateTime<Local>) {
+    let (running, empty, skip_if_empty, warning, minutes) = {
+        let app_state = state.read().await;
+        let server = app_state.default_server();
+        (
+            server.is_running(),
+            server.online_players().is_empty(),
+            app_state.restart_schedule.skip_countdown_if_empty,
+            app_state.restart_config.warning_message.clone(),
+            app_state.restart_config.countdown_minutes(),
+        )
+    };
+
+    let result = if !running {
+        Some("Skipped: server not running".to_string())
+    } else if empty && skip_if_empty {
+        Some(restart(state).await)
+    } else {
+        countdown(state, restart_at, &warning, &minutes).await;
+        // The schedule may have been changed or disabled during the countdown
+        if state.read().await.restart_schedule.next_run == Some(restart_at) {
+            Some(restart(state).await)
+        } else {
+            say(state, "Scheduled restart cancelled.").await;
+            None
+        }
+    };
+
+    let mut app_state = state.write().await;
+    let schedule = &mut app_state.restart_schedule;
+    schedule.last_run = Some(Local::now());
+    match result {
+        Some(summary) => {
+            info!("Scheduled restart: {}", summary);
+            schedule.last_result = Some(summary);
+            schedule.next_run = schedule.next_after(restart_at);
+        }
+        None => schedule.last_result = Some("Cancelled".to_string()),
+    }
+}
+
+/// Warns players at each of the warning minutes still ahead, in game and
+/// with a `restart_warning` event, returning at the restart time or as soon
+/// as the schedule changes.
+async fn countdown(
+    state: &Arc<RwLock<AppState>>,
+    restart_at: DateTime<Local>,
+    warning: &str,
+    warning_minutes: &[u32],
+) {
+    for &minutes in warning_minutes {
+        let warn_at = restart_at - chrono::Duration::minutes(minutes.into());
+        // A countdown started late skips the warnings it missed
+        if Local::now() - warn_at > chrono::Duration::seconds(30) {
+            continue;
+        }
+        sleep_until(warn_at).await;
+        {
+            let app_state = state.read().await;
+            if app_state.restart_schedule.next_run != Some(restart_at) {
+                return;
+            }
+            app_state
+                .default_server()
+                .queue_event(ServerEvent::RestartWarning {
+                    minutes,
+                    restart_at,
+                });
+        }
+        say(state, &warning.replace("{minutes}", &minutes.to_string())).await;
+    }
+    sleep_until(restart_at).await;
+}
+
+/// Sleeps until a point in local time, returning at once if it has passed.
+async fn sleep_until(at: DateTime<Local>) {
+    if let Ok(wait) = (at - Local::now()).to_std() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Restarts the default server, returning a summary of the outcome.
+async fn restart(state: &Arc<RwLock<AppState>>) -> String {
+    match state::restart_server(state, None, || Ok(())).await {
+        Ok(()) => "Restarted".to_string(),
+        Err(e) => format!("Failed: {}", e),
+    }
+}
+
+/// Sends an in-game `say` message, ignoring failures (e.g. the server stopped meanwhile).
+async fn say(state: &Arc<RwLock<AppState>>, message: &str) {
+    say_to(state, None, message).await;
+}
+
+/// Sends an in-game `say` message to a server, ignoring failures.
+async fn say_to(state: &Arc<RwLock<AppState>>, server: Option<&str>, message: &str) {
+    let _ = state::send_command(state, server, &format!("say {}", message)).await;
+}
+
+/// Warns the players of a server during a countdown to a stop or restart,
+/// returning once the countdown is over.
+///
+/// Players are warned when the countdown starts and at each of the
+/// configured `[countdown]` warnings that fall within it.
+///
+/// # Arguments
+/// * `state` - Shared application state
+/// * `server` - Server whose players are warned
+/// * `length` - How long until the stop or restart
+/// * `action` - What happens then, `stopping` or `restarting`
+pub async fn countdown_warnings(
+    state: &Arc<RwLock<AppState>>,
+    server: &str,
+    length: Duration,
+    action: &str,
+) {
+    let config = state.read().await.countdown_config.clone();
+    let end = tokio::time::Instant::now() + length;
+    for mark in config.marks(length) {
+        tokio::time::sleep_until(end - mark).await;
+        let message = config
+            .message
+            .replace("{action}", action)
+            .replace("{time}", &format_time_left(mark));
+        say_to(state, Some(server), &message).await;
+    }
+    tokio::time::sleep_until(end).await;
+}
+
+/// Formats the time left in a countdown, e.g. `5 minutes` or `1 second`.
+fn format_time_left(left: Duration) -> String {
+    let seconds = left.as_secs();
+    let (count, unit) = if seconds >= 60 && seconds.is_multiple_of(60) {
+        (seconds / 60, "minute")
+    } else {
+        (seconds, "second")
+    };
+    if count == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
+
+/// What is known about the TPS command of the running server.
+enum TpsCommand {
+    /// Still trying the candidates; holds the index of the next one to try.
+    Detecting(usize),
+    /// This command reports the tick rate.
+    Found(String),
+    /// None of the candidates worked; stop asking until the server restarts.
+    Unsupported,
+}
+
+/// Polls the server for its tick rate and memory usage forever.
+///
+/// If no TPS command is configured, the known ones are tried in turn until
+/// one produces a tick rate. A candidate is only ruled out once the server
+/// answered it without one, so polls during startup don't count against it.
+pub async fn run_performance_poller(state: Arc<RwLock<AppState>>) {
+    let config = state.read().await.performance_config.clone();
+    let mut ticker = tokio::time::interval_at(
+        tokio::time::Instant::now() + config.interval,
+        config.interval,
+    );
+    let initial = || match &config.tps_command {
+        Some(command) => TpsCommand::Found(command.clone()),
+        None => TpsCommand::Detecting(0),
+    };
+    let mut tps_command = initial();
+    let mut polled_pid = None;
+
+    loop {
+        ticker.tick().await;
+
+        let pid = state.read().await.default_server().server_pid();
+        if pid.is_none() {
+            continue;
+        }
+        if pid != polled_pid {
+            // New server process, possibly a different distribution
+            polled_pid = pid;
+            tps_command = initial();
+            state.write().await.performance = Default::default();
+        }
+
+        let command = match &tps_command {
+            TpsCommand::Detecting(index) => Some(TPS_COMMANDS[*index].to_string()),
+            TpsCommand::Found(command) => Some(command.clone()),
+            TpsCommand::Unsupported => None,
+        };
+        if let Some(command) = command {
+            let lines = query(&state, &command).await;
+            let reading = PerformanceReading::parse(&lines);
+            reading.apply_to(&mut state.write().await.performance);
+
+            if let TpsCommand::Detecting(index) = tps_command {
+                tps_command = if reading.has_tps() {
+                    info!("Using '{}' to monitor TPS", command);
+                    TpsCommand::Found(command)
+                } else if lines.is_empty() {
+                    TpsCommand::Detecting(index)
+                } else if index + 1 < TPS_COMMANDS.len() {
+                    TpsCommand::Detecting(index + 1)
+                } else {
+                    warn!("No TPS command recognized by this server, TPS won't be reported");
+                    TpsCommand::Unsupported
+                };
+            }
+        }
+
+        if let Some(command) = &config.memory_command {
+            let lines = query(&state, command).await;
+            PerformanceReading::parse(&lines).apply_to(&mut state.write().await.performance);
+        }
+    }
+}
+
+/// Sends a command and collects the lines it prints, empty if the server isn't
+/// running or didn't answer.
+async fn query(state: &Arc<RwLock<AppState>>, command: &str) -> Vec<String> {
+    state::command_output(state, None, command, None)
+        .await
+        .unwrap_or_default()
+}
+
+/// Watches for server processes exiting on their own, forever.
+///
+/// Exits are reported to clients by
+/// [`ManagedServer::check_exited`](crate::state::ManagedServer::check_exited),
+/// and servers with auto-restart enabled are started again from a task of
+/// their own once their delay is over.
+pub async fn run_exit_watcher(state: Arc<RwLock<AppState>>) {
+    let mut ticker = tokio::time::interval(EXIT_CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let mut restarts = Vec::new();
+        for server in state.write().await.servers_mut() {
+            let Some(status) = server.check_exited() else {
+                continue;
+            };
+            if let Some((id, delay)) = server.schedule_restart(status.code()) {
+                restarts.push((server.id().to_string(), id, delay));
+            }
+        }
+        for (server_id, id, delay) in restarts {
+            tokio::spawn(supervisor::restart_after(
+                state.clone(),
+                server_id,
+                id,
+                delay,
+            ));
+        }
+    }
+}
@@ -0,0 +1,550 @@
+//! World backups.
+//!
+//! This file archives the active world directory into a timestamped
+//! `.tar.gz` file. When the server is running, autosaving is suspended with
+//! `save-off` and the world is flushed with `save-all flush` before the copy,
+//! so the archive never contains half-written chunks.
+//!
+//! Archives can also be listed and restored over the active world.
+
+use crate::config;
+use crate::notifications::{Notification, NotificationKind};
+use crate::server::log_entry::LogEntry;
+use crate::server::worlds::{self, DEFAULT_LEVEL_NAME, LEVEL_DAT};
+use crate::server::DEFAULT_SERVER_ID;
+use crate::state::{self, AppState, LogSender};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Backups directory name inside the server directory when none is configured.
+const DEFAULT_BACKUP_DIR: &str = "backups";
+/// Files the server keeps locked while running; they're useless in a backup.
+const SKIPPED_FILES: &[&str] = &["session.lock"];
+/// Extension of the archives written by this module.
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+
+/// Settings for world backups.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// Directory archives are written to. Defaults to `backups` in the server directory.
+    pub backup_dir: Option<PathBuf>,
+    /// World directory to back up. Defaults to `level-name` in the server directory.
+    pub world_dir: Option<PathBuf>,
+    /// Interval between automatic backups, e.g. `6h`. None disables them.
+    pub interval: Option<String>,
+    /// In-game warning sent before an automatic backup; `{seconds}` is replaced by the lead time.
+    pub warning_message: String,
+    /// How long before an automatic backup the warning is sent.
+    #[serde(rename = "warning_secs", deserialize_with = "config::secs")]
+    pub warning_lead: Duration,
+    /// How long to wait for "Saved the game" before a backup; flushing a large
+    /// world takes longer than the server's usual save timeout.
+    #[serde(rename = "save_timeout_secs", deserialize_with = "config::secs")]
+    pub save_timeout: Duration,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            backup_dir: None,
+            world_dir: None,
+            interval: None,
+            warning_message: "Backup starting in {seconds}s".to_string(),
+            warning_lead: Duration::from_secs(60),
+            save_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl BackupConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_BACKUP_DIR` - directory archives are written to
+    /// * `MC_WORLD_DIR` - world directory to back up and restore into
+    /// * `MC_BACKUP_INTERVAL` - interval between automatic backups, e.g. `6h`
+    /// * `MC_BACKUP_WARNING` - in-game warning message
+    /// * `MC_BACKUP_WARNING_SECS` - seconds between the warning and the backup
+    /// * `MC_BACKUP_SAVE_TIMEOUT_SECS` - seconds to wait for the save before a backup
+    pub fn apply_env(&mut self) -> std::result::Result<(), String> {
+        if let Some(dir) = config::env_path("MC_BACKUP_DIR") {
+            self.backup_dir = Some(dir);
+        }
+        if let Some(dir) = config::env_path("MC_WORLD_DIR") {
+            self.world_dir = Some(dir);
+        }
+        if let Some(interval) = config::env_string("MC_BACKUP_INTERVAL") {
+            self.interval = Some(interval);
+        }
+        if let Ok(message) = std::env::var("MC_BACKUP_WARNING") {
+            self.warning_message = message;
+        }
+        if let Some(lead) = config::env_secs("MC_BACKUP_WARNING_SECS")? {
+            self.warning_lead = lead;
+        }
+        if let Some(timeout) = config::env_secs("MC_BACKUP_SAVE_TIMEOUT_SECS")? {
+            self.save_timeout = timeout;
+        }
+        Ok(())
+    }
+
+    /// Returns the directory archives are written to for a given server directory.
+    pub fn backup_dir(&self, server_dir: &Path) -> PathBuf {
+        self.backup_dir
+            .clone()
+            .unwrap_or_else(|| server_dir.join(DEFAULT_BACKUP_DIR))
+    }
+
+    /// Returns the world directory to back up for a given server directory.
+    ///
+    /// A relative configured path is resolved against the server directory.
+    pub fn world_dir(&self, server_dir: &Path) -> PathBuf {
+        match &self.world_dir {
+            Some(dir) => server_dir.join(dir),
+            None => world_dir(server_dir),
+        }
+    }
+}
+
+/// Description of a finished backup archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    /// File name of the archive inside the backups directory.
+    pub name: String,
+    /// Size of the archive in bytes.
+    pub size: u64,
+    /// Full path of the archive on disk.
+    pub path: PathBuf,
+}
+
+/// An archive found in the backups directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupEntry {
+    /// File name of the archive.
+    pub name: String,
+    /// Size of the archive in bytes.
+    pub size: u64,
+    /// When the archive was written, in RFC 3339 format.
+    pub created: String,
+}
+
+/// Outcome of restoring (or dry-running the restore of) an archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+    /// The archive that was restored.
+    pub name: String,
+    /// Directory the world was restored into.
+    pub world: String,
+    /// True if nothing was changed on disk.
+    pub dry_run: bool,
+    /// Number of files and directories in the archive.
+    pub entries: usize,
+    /// Where the previous world was moved to, if there was one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_world: Option<String>,
+}
+
+/// Returns the directory of the active world, based on `level-name` in server.properties.
+pub fn world_dir(server_dir: &Path) -> PathBuf {
+    server_dir.join(worlds::level_name(server_dir))
+}
+
+/// Backs up the active world, coordinating with the server if it is running.
+///
+/// This function:
+/// 1. Sends `save-off` and `save-all flush` and waits for "Saved the game"
+/// 2. Archives the world directory on the blocking thread pool
+/// 3. Sends `save-on`, even if an earlier step failed
+///
+/// Progress is reported to WebSocket clients through the log channel. Only
+/// one backup runs at a time; a second call while one is in progress fails.
+///
+/// # Returns
+/// * `Result<BackupInfo>` - The archive that was written, or the first error encountered
+pub async fn run_backup(state: &Arc<RwLock<AppState>>) -> Result<BackupInfo> {
+    let (world_dir, backup_dir, save_timeout, log_sender, running) = {
+        let mut app_state = state.write().await;
+        if app_state.backup_in_progress {
+            return Err(Error::new(
+                ErrorKind::ResourceBusy,
+                "A backup is already in progress",
+            ));
+        }
+        app_state.backup_in_progress = true;
+        let server_dir = app_state.default_server().server_dir();
+        let backup_dir = app_state.backup_config.backup_dir(&server_dir);
+        let world_dir = app_state.backup_config.world_dir(&server_dir);
+        (
+            world_dir,
+            backup_dir,
+            app_state.backup_config.save_timeout,
+            app_state.default_server().log_sender.clone(),
+            app_state.default_server().is_running(),
+        )
+    };
+
+    progress(
+        &log_sender,
+        format!("Starting backup of {}", world_dir.display()),
+    );
+
+    let result = if running {
+        match suspend_saving(state, save_timeout).await {
+            Ok(()) => archive_in_background(world_dir, backup_dir).await,
+            Err(e) => Err(e),
+        }
+    } else {
+        archive_in_background(world_dir, backup_dir).await
+    };
+
+    if running {
+        // Always re-enable autosave, whatever happened above
+        let resumed = state::send_command(state, None, "save-on").await;
+        if let Err(e) = resumed {
+            progress(&log_sender, format!("Could not re-enable saving: {}", e));
+        }
+    }
+
+    let notifier = {
+        let mut app_state = state.write().await;
+        app_state.backup_in_progress = false;
+        app_state.notifier.clone()
+    };
+
+    let notification = match &result {
+        Ok(info) => {
+            let summary = format!("{} ({} bytes)", info.path.display(), info.size);
+            progress(&log_sender, format!("Backup complete: {}", summary));
+            Notification::new(
+                NotificationKind::BackupCompleted,
+                DEFAULT_SERVER_ID,
+                "Backup complete".to_string(),
+                Some(summary),
+            )
+        }
+        Err(e) => {
+            progress(&log_sender, format!("Backup failed: {}", e));
+            Notification::new(
+                NotificationKind::BackupFailed,
+                DEFAULT_SERVER_ID,
+                "Backup failed".to_string(),
+                Some(e.to_string()),
+            )
+        }
+    };
+    notifier.notify(notification);
+    result
+}
+
+/// Turns off autosave and flushes the world to disk, waiting up to `timeout`
+/// for confirmation.
+async fn suspend_saving(state: &Arc<RwLock<AppState>>, timeout: Duration) -> Result<()> {
+    state::send_command(state, None, "save-off").await?;
+    state::save_world(state, None, Some(timeout)).await?;
+    Ok(())
+}
+
+/// Runs [`create_archive`] on the blocking thread pool.
+async fn archive_in_background(world_dir: PathBuf, backup_dir: PathBuf) -> Result<BackupInfo> {
+    tokio::task::spawn_blocking(move || create_archive(&world_dir, &backup_dir))
+        .await
+        .map_err(Error::other)?
+}
+
+/// Writes the world directory to a timestamped `.tar.gz` in the backup directory.
+///
+/// The archive is written under a temporary name and renamed once complete,
+/// so a failed backup never leaves a truncated archive that looks valid.
+pub fn create_archive(world_dir: &Path, backup_dir: &Path) -> Result<BackupInfo> {
+    if !world_dir.is_dir() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("World directory {} does not exist", world_dir.display()),
+        ));
+    }
+    fs::create_dir_all(backup_dir)?;
+
+    let world_name = world_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| DEFAULT_LEVEL_NAME.to_string());
+    let name = format!(
+        "{}-{}{}",
+        world_name,
+        chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"),
+        ARCHIVE_EXTENSION
+    );
+    let path = backup_dir.join(&name);
+    let partial_path = backup_dir.join(format!("{}.partial", name));
+
+    let written = (|| {
+        let encoder = GzEncoder::new(File::create(&partial_path)?, Compression::default());
+        let mut archive = tar::Builder::new(encoder);
+        append_dir(&mut archive, world_dir, Path::new(&world_name))?;
+        archive.into_inner()?.finish()?;
+        fs::rename(&partial_path, &path)
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial_path);
+        return Err(e);
+    }
+
+    let size = fs::metadata(&path)?.len();
+    Ok(BackupInfo { name, size, path })
+}
+
+/// Recursively adds a directory to the archive under `archive_path`.
+fn append_dir(
+    archive: &mut tar::Builder<GzEncoder<File>>,
+    dir: &Path,
+    archive_path: &Path,
+) -> Result<()> {
+    archive.append_dir(archive_path, dir)?;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if SKIPPED_FILES.iter().any(|skipped| file_name == *skipped) {
+            continue;
+        }
+        let entry_path = archive_path.join(&file_name);
+        if entry.file_type()?.is_dir() {
+            append_dir(archive, &entry.path(), &entry_path)?;
+        } else {
+            archive.append_path_with_name(entry.path(), &entry_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the archives in the backups directory, newest first.
+///
+/// A missing backups directory is treated as empty.
+pub fn list_backups(backup_dir: &Path) -> Result<Vec<BackupEntry>> {
+    let entries = match fs::read_dir(backup_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !name.ends_with(ARCHIVE_EXTENSION) {
+            continue;
+        }
+        let created: chrono::DateTime<chrono::Local> = metadata.modified()?.into();
+        backups.push(BackupEntry {
+            name,
+            size: metadata.len(),
+            created: created.to_rfc3339(),
+        });
+    }
+    backups.sort_by(|a, b| b.created.cmp(&a.created));
+    Ok(backups)
+}
+
+/// Resolves an archive name to its path in the backups directory.
+///
+/// Only plain file names of existing archives are accepted, so a name can
+/// never point outside the backups directory.
+pub fn resolve_backup(backup_dir: &Path, name: &str) -> Result<PathBuf> {
+    let is_plain_name = !name.is_empty()
+        && !name.contains("..")
+        && !name.contains(['/', '\\'])
+        && name.ends_with(ARCHIVE_EXTENSION);
+    if !is_plain_name {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid backup name: {}", name),
+        ));
+    }
+    let path = backup_dir.join(name);
+    if !path.is_file() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("Backup {} does not exist", name),
+        ));
+    }
+    Ok(path)
+}
+
+/// Restores an archive over the active world.
+///
+/// The server must be stopped. The archive is extracted next to the world
+/// first and checked for a `level.dat`; only then is the current world moved
+/// aside (to `<world>-before-restore-<timestamp>`) and the extracted copy put
+/// in its place. With `dry_run`, the archive is only inspected.
+///
+/// # Returns
+/// * `Result<RestoreReport>` - What was (or would be) restored
+pub async fn restore_backup(
+    state: &Arc<RwLock<AppState>>,
+    name: &str,
+    dry_run: bool,
+) -> Result<RestoreReport> {
+    let (world_dir, backup_dir, log_sender) = {
+        let app_state = state.read().await;
+        if app_state.default_server().is_running() {
+            return Err(Error::new(
+                ErrorKind::ResourceBusy,
+                "Stop the server before restoring a backup",
+            ));
+        }
+        let server_dir = app_state.default_server().server_dir();
+        let backup_dir = app_state.backup_config.backup_dir(&server_dir);
+        let world_dir = app_state.backup_config.world_dir(&server_dir);
+        (
+            world_dir,
+            backup_dir,
+            app_state.default_server().log_sender.clone(),
+        )
+    };
+    let archive = resolve_backup(&backup_dir, name)?;
+    let name = name.to_string();
+
+    progress(
+        &log_sender,
+        format!(
+            "{}estoring {} into {}",
+            if dry_run { "Dry run: r" } else { "R" },
+            name,
+            world_dir.display()
+        ),
+    );
+
+    let sender = log_sender.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        restore_archive(&archive, &name, &world_dir, dry_run, &sender)
+    })
+    .await
+    .map_err(Error::other)?;
+
+    match &result {
+        Ok(report) if report.dry_run => progress(
+            &log_sender,
+            format!(
+                "Dry run complete: {} entries would be restored",
+                report.entries
+            ),
+        ),
+        Ok(report) => progress(&log_sender, format!("Restore of {} complete", report.name)),
+        Err(e) => progress(&log_sender, format!("Restore failed: {}", e)),
+    }
+    result
+}
+
+/// Blocking part of [`restore_backup`].
+fn restore_archive(
+    archive: &Path,
+    name: &str,
+    world_dir: &Path,
+    dry_run: bool,
+    log_sender: &LogSender,
+) -> Result<RestoreReport> {
+    let (entries, root) = inspect_archive(archive)?;
+    let mut report = RestoreReport {
+        name: name.to_string(),
+        world: world_dir.display().to_string(),
+        dry_run,
+        entries,
+        previous_world: None,
+    };
+    if dry_run {
+        return Ok(report);
+    }
+
+    let parent = world_dir
+        .parent()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "World has no parent directory"))?;
+    let stamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let staging_dir = parent.join(format!(".restore-{}", stamp));
+
+    progress(log_sender, format!("Extracting {} entries", entries));
+    let extracted = (|| {
+        fs::create_dir_all(&staging_dir)?;
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+        for entry in archive.entries()? {
+            // unpack_in refuses entries that would escape the staging directory
+            entry?.unpack_in(&staging_dir)?;
+        }
+        check_world(&staging_dir.join(&root))
+    })();
+    if let Err(e) = extracted {
+        let _ = fs::remove_dir_all(&staging_dir);
+        return Err(e);
+    }
+
+    if world_dir.exists() {
+        let world_name = world_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| DEFAULT_LEVEL_NAME.to_string());
+        let previous = parent.join(format!("{}-before-restore-{}", world_name, stamp));
+        progress(
+            log_sender,
+            format!("Moving current world to {}", previous.display()),
+        );
+        fs::rename(world_dir, &previous)?;
+        report.previous_world = Some(previous.display().to_string());
+    }
+
+    fs::rename(staging_dir.join(&root), world_dir)?;
+    let _ = fs::remove_dir_all(&staging_dir);
+    check_world(world_dir)?;
+    Ok(report)
+}
+
+/// Counts the entries of an archive and finds the directory holding `level.dat`.
+///
+/// Archives written by this backend contain a single top-level world
+/// directory, but any archive with `level.dat` one level deep is accepted.
+fn inspect_archive(archive: &Path) -> Result<(usize, PathBuf)> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+    let mut entries = 0;
+    let mut root = None;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        entries += 1;
+        let components: Vec<_> = path.components().collect();
+        if components.len() == 2 && path.file_name().is_some_and(|n| n == LEVEL_DAT) {
+            root = path.parent().map(Path::to_path_buf);
+        }
+    }
+    let root = root.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Archive does not contain a world ({} not found)", LEVEL_DAT),
+        )
+    })?;
+    Ok((entries, root))
+}
+
+/// Checks that a directory looks like a world, i.e. has a `level.dat`.
+fn check_world(world_dir: &Path) -> Result<()> {
+    if world_dir.join(LEVEL_DAT).is_file() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} missing from {}", LEVEL_DAT, world_dir.display()),
+        ))
+    }
+}
+
+/// Reports backup progress to connected clients.
+fn progress(log_sender: &LogSender, message: String) {
+    info!("{}", message);
+    let _ = log_sender.send(LogEntry::console(format!("[Backup]: {}", message)).into());
+}
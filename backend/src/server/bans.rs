@@ -0,0 +1,334 @@
+//! Access to the server's ban lists.
+//!
+//! This file reads and writes `banned-players.json` and `banned-ips.json`
+//! in the server directory. It is used when the Minecraft server is not
+//! running; while it is running, bans go through the console so the server
+//! stays the owner of these files.
+
+use chrono::{DateTime, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// File name of the player ban list inside the server directory.
+pub const BANNED_PLAYERS_FILE: &str = "banned-players.json";
+/// File name of the IP ban list inside the server directory.
+pub const BANNED_IPS_FILE: &str = "banned-ips.json";
+/// File name of the name/UUID cache the server maintains.
+pub(crate) const USER_CACHE_FILE: &str = "usercache.json";
+
+/// Date format used by Mojang in the ban lists, e.g. `2024-01-15 18:04:31 +0100`.
+const MOJANG_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+/// Value of `expires` for permanent bans.
+const EXPIRES_FOREVER: &str = "forever";
+/// Value of `source` for bans created by this backend.
+const BAN_SOURCE: &str = "Web Console";
+
+/// An entry of `banned-players.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BannedPlayer {
+    pub uuid: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub details: BanDetails,
+}
+
+/// An entry of `banned-ips.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BannedIp {
+    pub ip: String,
+    #[serde(flatten)]
+    pub details: BanDetails,
+}
+
+/// Fields shared by both ban lists.
+///
+/// The date fields are kept as the raw strings Mojang wrote so that rewriting
+/// a file never alters entries we did not touch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanDetails {
+    pub created: String,
+    pub source: String,
+    pub expires: String,
+    pub reason: String,
+    /// Any fields we don't know about, preserved on write.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl BanDetails {
+    /// Creates the details for a new permanent ban issued now.
+    fn new(reason: &str) -> Self {
+        BanDetails {
+            created: format_mojang_date(&Local::now().fixed_offset()),
+            source: BAN_SOURCE.to_string(),
+            expires: EXPIRES_FOREVER.to_string(),
+            reason: reason.to_string(),
+            extra: Map::new(),
+        }
+    }
+
+    /// Returns true if the ban has an expiry date that lies in the past.
+    pub fn is_expired(&self) -> bool {
+        match parse_mojang_date(&self.expires) {
+            Some(expires) => expires < Local::now().fixed_offset(),
+            None => false,
+        }
+    }
+}
+
+/// Parses a date in Mojang's ban list format (`yyyy-MM-dd HH:mm:ss Z`).
+///
+/// # Returns
+/// * `None` for `forever` or any value that doesn't match the format
+pub fn parse_mojang_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(value.trim(), MOJANG_DATE_FORMAT).ok()
+}
+
+/// Formats a date the way Mojang writes it in the ban lists.
+pub fn format_mojang_date(date: &DateTime<FixedOffset>) -> String {
+    date.format(MOJANG_DATE_FORMAT).to_string()
+}
+
+/// Reads `banned-players.json`, returning an empty list if the file doesn't exist.
+pub fn load_banned_players(server_dir: &Path) -> Result<Vec<BannedPlayer>> {
+    read_list(&server_dir.join(BANNED_PLAYERS_FILE))
+}
+
+/// Reads `banned-ips.json`, returning an empty list if the file doesn't exist.
+pub fn load_banned_ips(server_dir: &Path) -> Result<Vec<BannedIp>> {
+    read_list(&server_dir.join(BANNED_IPS_FILE))
+}
+
+/// Adds a permanent ban for a player by editing `banned-players.json`.
+///
+/// The player's UUID is looked up in the server's `usercache.json`, so only
+/// players that have joined the server before can be banned this way.
+///
+/// # Returns
+/// * `Result<bool>` - false if the player was already banned
+pub fn ban_player(server_dir: &Path, name: &str, reason: &str) -> Result<bool> {
+    let mut bans = load_banned_players(server_dir)?;
+    if bans.iter().any(|b| b.name.eq_ignore_ascii_case(name)) {
+        return Ok(false);
+    }
+
+    let (uuid, name) = lookup_uuid(server_dir, name)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("Player '{}' not found in {}", name, USER_CACHE_FILE),
+        )
+    })?;
+    bans.push(BannedPlayer {
+        uuid,
+        name,
+        details: BanDetails::new(reason),
+    });
+    write_list(&server_dir.join(BANNED_PLAYERS_FILE), &bans)?;
+    Ok(true)
+}
+
+/// Adds a permanent ban for an IP address by editing `banned-ips.json`.
+///
+/// # Returns
+/// * `Result<bool>` - false if the address was already banned
+pub fn ban_ip(server_dir: &Path, ip: &str, reason: &str) -> Result<bool> {
+    let mut bans = load_banned_ips(server_dir)?;
+    if bans.iter().any(|b| b.ip == ip) {
+        return Ok(false);
+    }
+    bans.push(BannedIp {
+        ip: ip.to_string(),
+        details: BanDetails::new(reason),
+    });
+    write_list(&server_dir.join(BANNED_IPS_FILE), &bans)?;
+    Ok(true)
+}
+
+/// Removes a player's ban from `banned-players.json`.
+///
+/// # Returns
+/// * `Result<bool>` - false if the player wasn't banned
+pub fn pardon_player(server_dir: &Path, name: &str) -> Result<bool> {
+    let mut bans = load_banned_players(server_dir)?;
+    let before = bans.len();
+    bans.retain(|b| !b.name.eq_ignore_ascii_case(name));
+    if bans.len() == before {
+        return Ok(false);
+    }
+    write_list(&server_dir.join(BANNED_PLAYERS_FILE), &bans)?;
+    Ok(true)
+}
+
+/// Removes an IP ban from `banned-ips.json`.
+///
+/// # Returns
+/// * `Result<bool>` - false if the address wasn't banned
+pub fn pardon_ip(server_dir: &Path, ip: &str) -> Result<bool> {
+    let mut bans = load_banned_ips(server_dir)?;
+    let before = bans.len();
+    bans.retain(|b| b.ip != ip);
+    if bans.len() == before {
+        return Ok(false);
+    }
+    write_list(&server_dir.join(BANNED_IPS_FILE), &bans)?;
+    Ok(true)
+}
+
+/// Entry of `usercache.json`; only the fields we need.
+#[derive(Deserialize)]
+struct CachedUser {
+    name: String,
+    uuid: String,
+}
+
+/// Looks up a player's UUID and correctly cased name in `usercache.json`.
+pub(crate) fn lookup_uuid(server_dir: &Path, name: &str) -> Result<Option<(String, String)>> {
+    let users: Vec<CachedUser> = read_list(&server_dir.join(USER_CACHE_FILE))?;
+    Ok(users
+        .into_iter()
+        .find(|u| u.name.eq_ignore_ascii_case(name))
+        .map(|u| (u.uuid, u.name)))
+}
+
+/// Reads a JSON array from a file, treating a missing or empty file as an empty list.
+pub(crate) fn read_list<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Vec<T>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    if contents.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(&contents).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Could not parse {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Writes a list back as pretty-printed JSON, like the server does.
+pub(crate) fn write_list<T: Serialize>(path: &Path, list: &[T]) -> Result<()> {
+    let json = serde_json::to_string_pretty(list)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    fs::write(path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Timelike};
+    use std::path::PathBuf;
+
+    /// Creates an empty directory for one test.
+    fn server_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft-console-bans-{}-{}",
+            std::process::id(),
+            test
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn details(expires: &str) -> BanDetails {
+        BanDetails {
+            expires: expires.to_string(),
+            ..BanDetails::new("test")
+        }
+    }
+
+    #[test]
+    fn parses_mojang_dates() {
+        let date = parse_mojang_date("2024-01-15 18:04:31 +0100").unwrap();
+        assert_eq!((date.year(), date.month(), date.day()), (2024, 1, 15));
+        assert_eq!((date.hour(), date.minute(), date.second()), (18, 4, 31));
+        assert_eq!(date.offset().local_minus_utc(), 3600);
+        assert_eq!(format_mojang_date(&date), "2024-01-15 18:04:31 +0100");
+
+        let date = parse_mojang_date(" 2023-07-01 00:00:00 -0430 ").unwrap();
+        assert_eq!(date.offset().local_minus_utc(), -(4 * 3600 + 30 * 60));
+    }
+
+    #[test]
+    fn rejects_other_date_formats() {
+        assert_eq!(parse_mojang_date(EXPIRES_FOREVER), None);
+        assert_eq!(parse_mojang_date("2024-01-15T18:04:31+01:00"), None);
+        assert_eq!(parse_mojang_date("2024-01-15 18:04:31"), None);
+        assert_eq!(parse_mojang_date(""), None);
+    }
+
+    #[test]
+    fn forever_never_expires() {
+        assert!(!details(EXPIRES_FOREVER).is_expired());
+        assert!(details("2000-01-01 00:00:00 +0000").is_expired());
+        assert!(!details("2999-01-01 00:00:00 +0000").is_expired());
+    }
+
+    #[test]
+    fn loads_banned_players() {
+        let dir = server_dir("load");
+        fs::write(
+            dir.join(BANNED_PLAYERS_FILE),
+            r#"[
+  {
+    "uuid": "069a79f4-44e9-4726-a5be-fca90e38aaf5",
+    "name": "Notch",
+    "created": "2024-01-15 18:04:31 +0100",
+    "source": "Server",
+    "expires": "forever",
+    "reason": "Banned by an operator."
+  },
+  {
+    "uuid": "853c80ef-3c37-49fd-aa49-938b674adae6",
+    "name": "jeb_",
+    "created": "2024-01-15 18:05:00 +0100",
+    "source": "Admin",
+    "expires": "2024-02-15 18:05:00 +0100",
+    "reason": "Griefing",
+    "note": "kept"
+  }
+]"#,
+        )
+        .unwrap();
+
+        let players = load_banned_players(&dir).unwrap();
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].name, "Notch");
+        assert_eq!(players[0].details.expires, EXPIRES_FOREVER);
+        assert!(!players[0].details.is_expired());
+        assert!(players[1].details.is_expired());
+        assert_eq!(players[1].details.extra["note"], "kept");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_or_empty_list_is_empty() {
+        let dir = server_dir("missing");
+        assert!(load_banned_players(&dir).unwrap().is_empty());
+        fs::write(dir.join(BANNED_IPS_FILE), "\n").unwrap();
+        assert!(load_banned_ips(&dir).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_list_is_an_error() {
+        let dir = server_dir("malformed");
+        for contents in [
+            "[{\"uuid\": \"069a79f4\", \"name\": \"Notch\"",
+            "{\"name\": \"Notch\"}",
+            "[{\"uuid\": \"069a79f4\", \"name\": \"Notch\"}]",
+        ] {
+            fs::write(dir.join(BANNED_PLAYERS_FILE), contents).unwrap();
+            let err = load_banned_players(&dir).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert!(err.to_string().contains(BANNED_PLAYERS_FILE));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! Crash reports the Minecraft server writes to `crash-reports/`.
+//!
+//! When the game crashes it writes a `crash-<date>-<side>.txt` report to the
+//! `crash-reports` directory in its working directory before exiting. After an
+//! unexpected exit the backend looks for a report written during the run and
+//! attaches it to the crash event; `GET /crash-reports` lists the reports and
+//! `GET /crash-reports/{name}` returns one.
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::fs;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+
+/// Directory, relative to the server directory, the game writes its reports to.
+pub const CRASH_REPORTS_DIR: &str = "crash-reports";
+
+/// Bytes of each report included in the listing.
+const PREVIEW_BYTES: usize = 1024;
+
+/// A crash report, as listed by `GET /crash-reports`.
+#[derive(Debug, Serialize)]
+pub struct CrashReportInfo {
+    pub name: String,
+    pub size_bytes: u64,
+    pub modified: DateTime<Local>,
+    /// The report's `Description:` line, e.g. `Ticking entity`.
+    pub description: Option<String>,
+    /// The start of the report; `GET /crash-reports/{name}` returns all of it.
+    pub preview: String,
+    /// True if the preview is only part of the report.
+    pub truncated: bool,
+}
+
+/// Returns the crash reports directory of a server directory.
+pub fn crash_reports_dir(server_dir: &Path) -> PathBuf {
+    server_dir.join(CRASH_REPORTS_DIR)
+}
+
+/// Lists the crash reports in a server directory, newest first.
+///
+/// A server that never crashed has no `crash-reports` directory, which
+/// lists as no reports.
+pub fn list_reports(server_dir: &Path) -> Result<Vec<CrashReportInfo>> {
+    let entries = match fs::read_dir(crash_reports_dir(server_dir)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut reports = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !is_report_name(&name) {
+            continue;
+        }
+        let (preview, truncated) = read_preview(&entry.path(), metadata.len())?;
+        reports.push(CrashReportInfo {
+            description: description(&preview),
+            name,
+            size_bytes: metadata.len(),
+            modified: metadata.modified()?.into(),
+            preview,
+            truncated,
+        });
+    }
+    reports.sort_by_key(|report| Reverse(report.modified));
+    Ok(reports)
+}
+
+/// Resolves a report name to its path in the crash reports directory.
+///
+/// Only plain file names of existing reports are accepted, so a name can
+/// never point outside the crash reports directory.
+pub fn resolve_report(server_dir: &Path, name: &str) -> Result<PathBuf> {
+    if !is_report_name(name) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid crash report name: {}", name),
+        ));
+    }
+    let path = crash_reports_dir(server_dir).join(name);
+    if !path.is_file() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("Crash report {} does not exist", name),
+        ));
+    }
+    Ok(path)
+}
+
+/// Finds the report a crashed run left behind.
+///
+/// # Arguments
+/// * `server_dir` - Working directory of the server
+/// * `since` - When the run started; older reports belong to earlier crashes
+///
+/// # Returns
+/// * `Option<PathBuf>` - Path of the newest report written since then
+pub fn find_new_report(server_dir: &Path, since: DateTime<Local>) -> Option<PathBuf> {
+    fs::read_dir(crash_reports_dir(server_dir))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.metadata().ok()?;
+            let modified: DateTime<Local> = metadata.modified().ok()?.into();
+            (metadata.is_file() && is_report_name(&name) && modified >= since)
+                .then_some((modified, entry.path()))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// Returns true for names the game gives its reports, e.g.
+/// `crash-2024-05-01_12.00.00-server.txt`.
+fn is_report_name(name: &str) -> bool {
+    !name.starts_with('.')
+        && name.ends_with(".txt")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Reads the start of a report.
+///
+/// # Returns
+/// * `(String, bool)` - The preview, and whether the report is longer
+fn read_preview(path: &Path, len: u64) -> Result<(String, bool)> {
+    let mut buf = Vec::with_capacity(PREVIEW_BYTES);
+    fs::File::open(path)?
+        .take(PREVIEW_BYTES as u64)
+        .read_to_end(&mut buf)?;
+    Ok((
+        String::from_utf8_lossy(&buf).into_owned(),
+        len > PREVIEW_BYTES as u64,
+    ))
+}
+
+/// Extracts the `Description:` line of a report.
+fn description(report: &str) -> Option<String> {
+    report
+        .lines()
+        .find_map(|line| line.strip_prefix("Description:"))
+        .map(|description| description.trim().to_string())
+}
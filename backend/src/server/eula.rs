@@ -0,0 +1,53 @@
+//! The `eula.txt` a Minecraft server needs before it starts.
+//!
+//! On its first start a server writes `eula=false` to `eula.txt`, prints that
+//! the EULA has to be accepted and exits. With `accept_eula` the backend
+//! writes `eula=true` before spawning the server; without it, the file is
+//! checked when the server exits so clients learn why it didn't start.
+
+use chrono::Local;
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+
+/// Name of the file in the server directory.
+pub const EULA_FILE: &str = "eula.txt";
+
+/// Reads whether the EULA was accepted.
+///
+/// # Returns
+/// * `Result<Option<bool>>` - None if there is no `eula.txt`, otherwise whether it says `eula=true`
+pub fn is_accepted(server_dir: &Path) -> Result<Option<bool>> {
+    match std::fs::read_to_string(server_dir.join(EULA_FILE)) {
+        Ok(contents) => Ok(Some(contents.lines().any(|line| {
+            line.split_once('=').is_some_and(|(key, value)| {
+                key.trim() == "eula" && value.trim().eq_ignore_ascii_case("true")
+            })
+        }))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true if the server wrote an `eula.txt` that doesn't accept the EULA,
+/// which is why a fresh server exits right after starting.
+pub fn is_refused(server_dir: &Path) -> bool {
+    matches!(is_accepted(server_dir), Ok(Some(false)))
+}
+
+/// Writes `eula=true` unless the EULA is already accepted.
+///
+/// # Returns
+/// * `Result<bool>` - True if the file was written
+pub fn accept(server_dir: &Path) -> Result<bool> {
+    if is_accepted(server_dir)? == Some(true) {
+        return Ok(false);
+    }
+    let contents = format!(
+        "#By changing the setting below to TRUE you are indicating your agreement to our EULA (https://aka.ms/MinecraftEULA).\n\
+         #Accepted by minecraft-console on {}\n\
+         eula=true\n",
+        Local::now().format("%a %b %d %H:%M:%S %Z %Y")
+    );
+    std::fs::write(server_dir.join(EULA_FILE), contents)?;
+    Ok(true)
+}
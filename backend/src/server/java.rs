@@ -0,0 +1,131 @@
+//! Finding and checking the Java runtime that runs jar servers.
+//!
+//! A jar is run with the `java` from `[server] java_path`, else from
+//! `JAVA_HOME` (the server's `[server.env]` first, then the backend's own
+//! environment), else the first one on `PATH`. Before the server is started,
+//! `java -version` is run so a missing or too old runtime is reported to the
+//! caller instead of the JVM exiting with an error only the console shows.
+
+use crate::error::ServerError;
+use crate::server::LaunchSpec;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// How long `java -version` may take before the runtime is considered unusable.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Version of a Java runtime, from `java -version`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JavaVersion {
+    /// Version as printed, e.g. `21.0.2` or `1.8.0_392`.
+    pub version: String,
+    /// Major version, e.g. 21, or 8 for `1.8`.
+    pub major: u32,
+    /// The runtime that was checked.
+    pub java: PathBuf,
+}
+
+/// Picks the `java` executable for a server.
+///
+/// # Arguments
+/// * `java_path` - The configured executable, if any
+/// * `env` - Extra environment variables of the server, checked for `JAVA_HOME`
+/// * `inherit_env` - Whether the server also gets the backend's environment
+///
+/// # Returns
+/// * `PathBuf` - The executable, or plain `java` to be looked up on `PATH`
+pub fn resolve(
+    java_path: Option<&Path>,
+    env: &BTreeMap<String, String>,
+    inherit_env: bool,
+) -> PathBuf {
+    if let Some(path) = java_path {
+        return path.to_path_buf();
+    }
+    let java_home = env
+        .get("JAVA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            inherit_env
+                .then(|| std::env::var_os("JAVA_HOME").map(PathBuf::from))
+                .flatten()
+        })
+        .filter(|home| !home.as_os_str().is_empty());
+    match java_home {
+        Some(home) => home.join("bin").join("java"),
+        None => PathBuf::from("java"),
+    }
+}
+
+/// Runs `java -version` and checks the runtime is at least `minimum`.
+///
+/// # Arguments
+/// * `launch` - The jar's launch spec, with the executable picked by [`resolve`]
+///   and the environment it runs in
+/// * `minimum` - Lowest major version accepted, 0 for any
+///
+/// # Returns
+/// * `Result<JavaVersion, ServerError>` - JavaUnavailable if the runtime can't be
+///   run or its version not read, JavaTooOld if it is older than `minimum`
+pub async fn check(launch: &LaunchSpec, minimum: u32) -> Result<JavaVersion, ServerError> {
+    let java = launch.java.as_path();
+    let unavailable = |reason: String| ServerError::JavaUnavailable {
+        java: java.to_path_buf(),
+        reason,
+    };
+    let mut command = Command::new(java);
+    command.arg("-version").kill_on_drop(true);
+    launch.set_env(&mut command);
+    let output = command.output();
+    let output = match tokio::time::timeout(VERSION_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(unavailable(e.to_string())),
+        Err(_) => return Err(unavailable("java -version did not finish".to_string())),
+    };
+    // Java prints its version to stderr, some wrappers to stdout
+    let printed = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stderr),
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let Some((version, major)) = parse_version(&printed) else {
+        let first_line = printed.lines().next().unwrap_or_default().trim();
+        return Err(unavailable(format!(
+            "could not read the version from '{}'",
+            first_line
+        )));
+    };
+    if major < minimum {
+        return Err(ServerError::JavaTooOld {
+            version,
+            major,
+            minimum,
+        });
+    }
+    Ok(JavaVersion {
+        version,
+        major,
+        java: java.to_path_buf(),
+    })
+}
+
+/// Reads the version from `java -version` output, e.g. `openjdk version
+/// "21.0.2" 2024-01-16`.
+///
+/// # Returns
+/// * `Option<(String, u32)>` - The quoted version and its major version
+fn parse_version(output: &str) -> Option<(String, u32)> {
+    let line = output.lines().find(|line| line.contains(" version \""))?;
+    let version = line.split('"').nth(1)?.to_string();
+    // Before Java 9 versions were 1.x, e.g. 1.8.0_392 for Java 8
+    let mut parts = version.split(['.', '_', '-', '+']);
+    let major = match parts.next()? {
+        "1" => parts.next()?,
+        major => major,
+    };
+    let major = major.parse().ok()?;
+    Some((version, major))
+}
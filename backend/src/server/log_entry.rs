@@ -0,0 +1,108 @@
+//! Structured console log entries.
+//!
+//! Every line forwarded to clients carries the stream it came from and the
+//! severity parsed from its header, so clients can filter by level. Clients
+//! that only understand plain text get the line rendered the way it always
+//! was, with stderr lines prefixed by `ERROR: `.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Where a log line came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStream {
+    /// The server's standard output.
+    Stdout,
+    /// The server's standard error.
+    Stderr,
+    /// A message generated by the console backend itself, e.g. backup progress.
+    Console,
+}
+
+/// Severity of a log line, as printed in the Minecraft log header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl LogLevel {
+    /// Parses a level name as printed by log4j, e.g. `INFO` or `WARN`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_uppercase().as_str() {
+            "TRACE" => Some(LogLevel::Trace),
+            "DEBUG" => Some(LogLevel::Debug),
+            "INFO" => Some(LogLevel::Info),
+            "WARN" | "WARNING" => Some(LogLevel::Warn),
+            "ERROR" | "SEVERE" => Some(LogLevel::Error),
+            "FATAL" => Some(LogLevel::Fatal),
+            _ => None,
+        }
+    }
+}
+
+/// One line of console output.
+///
+/// Serialized as a JSON frame tagged like the other events, e.g.
+/// `{"type":"log","stream":"stdout","level":"INFO","line":"...","timestamp":"..."}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "log")]
+pub struct LogEntry {
+    /// Stream the line was read from.
+    pub stream: LogStream,
+    /// Severity of the line, None if it couldn't be determined.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<LogLevel>,
+    /// The line as printed, without any prefix added by the backend.
+    pub line: String,
+    /// When the backend received the line.
+    pub timestamp: DateTime<Local>,
+}
+
+impl LogEntry {
+    /// Creates an entry for a line read from the server process.
+    pub fn new(stream: LogStream, level: Option<LogLevel>, line: String) -> Self {
+        LogEntry {
+            stream,
+            level,
+            line,
+            timestamp: Local::now(),
+        }
+    }
+
+    /// Creates an informational entry generated by the backend.
+    pub fn console(line: String) -> Self {
+        LogEntry::new(LogStream::Console, Some(LogLevel::Info), line)
+    }
+
+    /// Renders the entry as plain text for clients that don't understand JSON.
+    pub fn to_text(&self) -> String {
+        match self.stream {
+            LogStream::Stderr => format!("ERROR: {}", self.line),
+            LogStream::Stdout | LogStream::Console => self.line.clone(),
+        }
+    }
+
+    /// Renders the entry as a JSON frame.
+    pub fn to_json(&self) -> String {
+        // Only strings and enums, so this can't fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// How log entries are sent to a WebSocket client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Plain text lines, as sent before entries were structured.
+    #[default]
+    Text,
+    /// [`LogEntry`] JSON frames.
+    Json,
+}
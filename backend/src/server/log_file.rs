@@ -0,0 +1,150 @@
+//! Persisting console output to disk and reading it back.
+//!
+//! Every log entry broadcast to clients is also appended to a log file, one
+//! JSON object per line, so history survives beyond what clients keep in
+//! memory. The file is read back page by page for the `/logs` endpoint.
+
+use crate::config;
+use crate::server::log_entry::{LogEntry, LogLevel};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tracing::error;
+
+/// Settings for the console log file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogFileConfig {
+    /// Whether console output is written to the log file.
+    pub enabled: bool,
+    /// Path of the log file, relative to the backend's working directory.
+    pub path: PathBuf,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        LogFileConfig {
+            enabled: true,
+            path: PathBuf::from("minecraft-console.log"),
+        }
+    }
+}
+
+impl LogFileConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_LOG_FILE` - path of the log file
+    /// * `MC_LOG_FILE_ENABLED` - `true` or `false`
+    pub fn apply_env(&mut self) -> std::result::Result<(), String> {
+        if let Some(path) = config::env_path("MC_LOG_FILE") {
+            self.path = path;
+        }
+        if let Some(enabled) = config::env_parse("MC_LOG_FILE_ENABLED")? {
+            self.enabled = enabled;
+        }
+        Ok(())
+    }
+}
+
+/// A line of the log file as returned by `/logs`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    /// 1-based line number in the log file.
+    pub line_number: usize,
+    /// The logged entry.
+    #[serde(flatten)]
+    pub entry: LogEntry,
+}
+
+/// Filters and paging for [`read_lines`].
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    /// Number of matching lines to skip.
+    pub offset: usize,
+    /// Maximum number of lines to return.
+    pub limit: usize,
+    /// Only return entries logged at or after this time.
+    pub since: Option<DateTime<Local>>,
+    /// Only return entries at this level or more severe.
+    pub level: Option<LogLevel>,
+}
+
+/// Starts a task appending log entries to the log file.
+///
+/// The file is opened in append mode, so history from earlier runs is kept.
+/// If it can't be opened or written, an error is printed and entries are
+/// dropped rather than holding up the broadcaster.
+///
+/// # Returns
+/// * `UnboundedSender<LogEntry>` - Channel to send entries to be written
+pub fn spawn_writer(path: PathBuf) -> UnboundedSender<LogEntry> {
+    let (sender, mut receiver) = unbounded_channel::<LogEntry>();
+    tokio::spawn(async move {
+        let mut file = match tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => file,
+            Err(e) => {
+                error!("Could not open log file {}: {}", path.display(), e);
+                return;
+            }
+        };
+        while let Some(entry) = receiver.recv().await {
+            let line = format!("{}\n", entry.to_json());
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                error!("Error writing log file {}: {}", path.display(), e);
+            }
+        }
+    });
+    sender
+}
+
+/// Reads a page of matching entries from the log file.
+///
+/// This reads the file synchronously, so call it from a blocking context
+/// (e.g. `web::block`). Lines that aren't valid entries are skipped. A
+/// missing file is treated as empty.
+///
+/// # Returns
+/// * `Result<Vec<LogLine>>` - The matching lines, oldest first
+pub fn read_lines(path: &Path, query: &LogQuery) -> Result<Vec<LogLine>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut lines = Vec::new();
+    let mut skipped = 0;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let Ok(entry) = serde_json::from_str::<LogEntry>(&line?) else {
+            continue;
+        };
+        if query.since.is_some_and(|since| entry.timestamp < since)
+            || query
+                .level
+                .is_some_and(|level| entry.level.is_none_or(|l| l < level))
+        {
+            continue;
+        }
+        if skipped < query.offset {
+            skipped += 1;
+            continue;
+        }
+        lines.push(LogLine {
+            line_number: index + 1,
+            entry,
+        });
+        if lines.len() == query.limit {
+            break;
+        }
+    }
+    Ok(lines)
+}
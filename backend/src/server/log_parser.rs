@@ -0,0 +1,253 @@
+//! Pattern matching on Minecraft server console output.
+//!
+//! This file recognizes console lines that carry meaning for the backend,
+//! such as players joining or leaving, so they can be turned into structured
+//! events instead of every client parsing raw text.
+
+use crate::server::log_entry::LogLevel;
+use serde::Serialize;
+
+/// Console messages meaning a command was rejected. Commands that changed
+/// nothing (e.g. opping an operator) print other messages and aren't failures.
+const COMMAND_FAILURES: &[&str] = &[
+    "That player does not exist",
+    "No player was found",
+    "Unknown or incomplete command",
+    "Incorrect argument for command",
+];
+
+/// Something noteworthy recognized in a console line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogEvent {
+    /// A player joined the game.
+    PlayerJoined(String),
+    /// A player left the game.
+    PlayerLeft(String),
+    /// The server finished starting and accepts players (`Done (1.234s)! For help, ...`).
+    ServerReady,
+    /// The server printed its game version or mod loader while starting.
+    Version(ServerVersion),
+    /// The server fell behind its tick rate (`Can't keep up! Is the server overloaded?`).
+    Overloaded {
+        /// How far behind the server is in milliseconds.
+        behind_ms: Option<u64>,
+        /// Ticks the server is behind or skipped.
+        ticks_behind: Option<u64>,
+    },
+}
+
+/// Game version and mod loader a server printed while starting. Each line
+/// carries some of it; fields a line doesn't mention are None.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ServerVersion {
+    /// Minecraft version, e.g. `1.21.1`.
+    pub minecraft: Option<String>,
+    /// Mod loader or server software, e.g. `forge`, `neoforge`, `fabric` or `paper`.
+    pub loader: Option<String>,
+    /// Version of the loader, e.g. `47.2.0`.
+    pub loader_version: Option<String>,
+}
+
+impl ServerVersion {
+    /// Fills in the fields `other` knows, keeping the rest.
+    pub fn merge(&mut self, other: &ServerVersion) {
+        if other.minecraft.is_some() {
+            self.minecraft = other.minecraft.clone();
+        }
+        if other.loader.is_some() {
+            self.loader = other.loader.clone();
+            self.loader_version = other.loader_version.clone();
+        }
+    }
+}
+
+/// Parses a console line into a [`LogEvent`] if it matches a known pattern.
+///
+/// # Arguments
+/// * `line` - A raw line of server output
+///
+/// # Returns
+/// * `Option<LogEvent>` - The recognized event, or None for ordinary output
+pub fn parse_line(line: &str) -> Option<LogEvent> {
+    let message = message_body(line);
+
+    if let Some(name) = message.strip_suffix(" joined the game") {
+        return player_name(name).map(LogEvent::PlayerJoined);
+    }
+    if let Some(name) = message.strip_suffix(" left the game") {
+        return player_name(name).map(LogEvent::PlayerLeft);
+    }
+    if message.starts_with("Done (") && message.contains(")! For help") {
+        return Some(LogEvent::ServerReady);
+    }
+    if let Some(rest) = message.strip_prefix("Can't keep up!") {
+        return Some(parse_overload(rest));
+    }
+    parse_version(message).map(LogEvent::Version)
+}
+
+/// Reads how far behind the server is from the rest of a "Can't keep up!" line:
+/// - 1.13+: `Is the server overloaded? Running 2345ms or 46 ticks behind`
+/// - older: `Did the system time change, or is the server overloaded? Running
+///   2345ms behind, skipping 46 tick(s)`
+fn parse_overload(rest: &str) -> LogEvent {
+    let number = |text: &str| {
+        let digits: String = text.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse().ok()
+    };
+    let behind_ms = rest
+        .split_once("Running ")
+        .and_then(|(_, rest)| number(rest));
+    let ticks_behind = rest
+        .split_once("ms or ")
+        .or_else(|| rest.split_once("skipping "))
+        .and_then(|(_, rest)| number(rest));
+    LogEvent::Overloaded {
+        behind_ms,
+        ticks_behind,
+    }
+}
+
+/// Recognizes the lines naming the game version or mod loader:
+/// - vanilla: `Starting minecraft server version 1.21.1`
+/// - Forge and NeoForge: `Forge mod loading, version 47.2.0, for MC 1.20.1 with MCP ...`
+/// - Fabric and Quilt: `Loading Minecraft 1.20.1 with Fabric Loader 0.14.21`
+/// - Paper and Spigot: `This server is running Paper version 1.21.1-119-master@abc (...)`,
+///   with `(MC: 1.20.1)` on Spigot
+fn parse_version(message: &str) -> Option<ServerVersion> {
+    let word = |text: &str| text.split_whitespace().next().map(str::to_string);
+    if let Some(version) = message.strip_prefix("Starting minecraft server version ") {
+        return Some(ServerVersion {
+            minecraft: word(version),
+            ..ServerVersion::default()
+        });
+    }
+    if let Some((loader, rest)) = message.split_once(" mod loading, version ") {
+        let (loader_version, rest) = rest.split_once(", for MC ")?;
+        return Some(ServerVersion {
+            minecraft: word(rest),
+            loader: Some(loader.trim().to_lowercase()),
+            loader_version: Some(loader_version.trim().to_string()),
+        });
+    }
+    if let Some(rest) = message.strip_prefix("Loading Minecraft ") {
+        let (minecraft, loader) = rest.split_once(" with ")?;
+        let (loader, loader_version) = loader.split_once(" Loader ")?;
+        return Some(ServerVersion {
+            minecraft: word(minecraft),
+            loader: Some(loader.trim().to_lowercase()),
+            loader_version: word(loader_version),
+        });
+    }
+    if let Some(rest) = message.strip_prefix("This server is running ") {
+        let (loader, rest) = rest.split_once(" version ")?;
+        let minecraft = rest
+            .split_once("(MC: ")
+            .and_then(|(_, minecraft)| minecraft.split_once(')'))
+            .map(|(minecraft, _)| minecraft.trim().to_string());
+        return Some(ServerVersion {
+            minecraft,
+            loader: Some(loader.trim().to_lowercase()),
+            loader_version: word(rest),
+        });
+    }
+    None
+}
+
+/// Looks for an error in the console output that followed a command, e.g. an
+/// online-mode server that couldn't resolve a player's UUID.
+///
+/// # Arguments
+/// * `lines` - Output collected after sending the command
+///
+/// # Returns
+/// * `Option<String>` - The server's error message, None if the command succeeded
+pub fn command_error(lines: &[String]) -> Option<String> {
+    lines
+        .iter()
+        .map(|line| message_body(line))
+        .find(|message| COMMAND_FAILURES.iter().any(|m| message.starts_with(m)))
+        .map(str::to_string)
+}
+
+/// Returns the message part of a console line, without the log header.
+///
+/// Handles the vanilla `[12:00:00] [Server thread/INFO]: msg`, the Forge
+/// `[12:00:00] [Server thread/INFO] [minecraft/MinecraftServer]: msg` and the
+/// Paper `[12:00:00 INFO]: msg` layouts. Lines without a header are returned
+/// as they are.
+pub fn message_body(line: &str) -> &str {
+    let line = line.trim_end();
+    if line.starts_with('[') {
+        if let Some(index) = line.find("]: ") {
+            return &line[index + 3..];
+        }
+    }
+    line
+}
+
+/// Returns the severity from a console line's header.
+///
+/// The level is the last word of the bracketed group that carries it: the
+/// thread group in vanilla and Forge (`[Server thread/WARN]`) or the time
+/// group in Paper (`[12:00:00 WARN]`).
+///
+/// # Returns
+/// * `Option<LogLevel>` - None for lines without a recognizable header
+pub fn parse_level(line: &str) -> Option<LogLevel> {
+    let header_end = line.find("]: ")?;
+    line[..=header_end]
+        .split('[')
+        .filter_map(|group| group.strip_suffix("] ").or_else(|| group.strip_suffix(']')))
+        .find_map(|group| {
+            let word = group.rsplit(['/', ' ']).next()?;
+            LogLevel::parse(word)
+        })
+}
+
+/// Extracts the player name from the text preceding "joined/left the game".
+///
+/// Plugins commonly decorate names with rank prefixes like `[VIP] ` and
+/// color codes, and renamed players are shown as `Name (formerly known as
+/// Old)`. Those decorations are stripped; if nothing but decoration remains
+/// the text is kept as is, so names with unusual characters still come
+/// through.
+fn player_name(raw: &str) -> Option<String> {
+    let cleaned = strip_color_codes(raw);
+    let mut name = cleaned.trim();
+
+    if let Some(index) = name.find(" (formerly known as ") {
+        name = &name[..index];
+    }
+
+    let mut undecorated = name;
+    while undecorated.starts_with('[') {
+        match undecorated.find(']') {
+            Some(end) => undecorated = undecorated[end + 1..].trim_start(),
+            None => break,
+        }
+    }
+    if !undecorated.is_empty() {
+        name = undecorated;
+    }
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Removes `§x` formatting codes from a string.
+pub fn strip_color_codes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
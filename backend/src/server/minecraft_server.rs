@@ -4,12 +4,491 @@
 //! that handles starting, stopping, and interacting with the Minecraft
 //! server process using Tokio's async process handling.
 
-use std::io::Result;
-use std::path::Path;
-use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use crate::config;
+use crate::error::ServerError;
+use crate::metrics::Metrics;
+use crate::server::java;
+use crate::server::log_entry::{LogEntry, LogLevel, LogStream};
+use crate::server::log_parser;
+use crate::server::process::{self, ConsoleInput, ConsolePipes, ProcessHandle, ServerProcess};
+use crate::state::{Broadcast, LogSender, ServerEvent};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn, Instrument};
+
+/// How long to wait for the output readers to finish after the process exited.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Read errors in a row after which an output reader gives up, so a stream
+/// that keeps failing doesn't spin.
+const MAX_READ_ERRORS: u32 = 10;
+/// Server started when neither `/start` nor the config names one.
+const DEFAULT_SERVER_PATH: &str = "server.jar";
+
+/// Identifies a managed server, e.g. in `/servers/{id}/start`.
+pub type ServerId = String;
+
+/// Id of the server configured in the `[server]` section. The routes without
+/// a `/servers/{id}` prefix act on this server.
+pub const DEFAULT_SERVER_ID: &str = "default";
+
+/// Returns true if `id` can name a server: 1 to 32 ASCII letters, digits, `-` or `_`.
+pub fn is_valid_server_id(id: &str) -> bool {
+    (1..=32).contains(&id.len())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Returns true if a variable can be set in the server's environment: the
+/// name is not empty and has no `=`, and neither contains a NUL byte.
+pub fn is_valid_env_var(name: &str, value: &str) -> bool {
+    !name.is_empty() && !name.contains(['=', '\0']) && !value.contains('\0')
+}
+
+/// Settings controlling how the server process is launched and managed.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Script, executable or jar started when `/start` doesn't name one.
+    pub server_path: Option<String>,
+    /// Directory the server runs in. Defaults to the directory of the server path.
+    pub working_dir: Option<PathBuf>,
+    /// Arguments passed to `java` before `-jar`, e.g. `-Xmx4G`. Only used for jars.
+    pub jvm_args: Vec<String>,
+    /// `java` executable that runs jars. Defaults to the one in `JAVA_HOME`, then on `PATH`.
+    pub java_path: Option<PathBuf>,
+    /// Lowest Java major version a jar is started with, e.g. 21; 0 accepts any.
+    pub min_java_version: u32,
+    /// Whether `eula=true` is written to `eula.txt` before the server starts.
+    pub accept_eula: bool,
+    /// Arguments passed to the server executable (or to the jar, after `java -jar`).
+    pub args: Vec<String>,
+    /// Extra environment variables set for the server process.
+    pub env: BTreeMap<String, String>,
+    /// Whether the server process starts with only `env` instead of also
+    /// inheriting the backend's environment.
+    pub env_clear: bool,
+    /// Console command that shuts the server down (`stop` for vanilla, `end` for some proxies).
+    pub stop_command: String,
+    /// Commands sent in order before the stop command, e.g. `save-all`.
+    pub pre_stop_commands: Vec<String>,
+    /// Delay after each pre-stop command before sending the next one.
+    #[serde(rename = "stop_command_delay_ms", deserialize_with = "config::millis")]
+    pub stop_command_delay: Duration,
+    /// How long to wait for the process to exit after the stop command before
+    /// sending it SIGTERM.
+    #[serde(rename = "stop_timeout_secs", deserialize_with = "config::secs")]
+    pub stop_timeout: Duration,
+    /// How long to wait for the process to exit after SIGTERM before killing it.
+    #[serde(rename = "terminate_timeout_secs", deserialize_with = "config::secs")]
+    pub terminate_timeout: Duration,
+    /// How long to wait for "Saved the game" after `save-all flush`.
+    #[serde(rename = "save_timeout_secs", deserialize_with = "config::secs")]
+    pub save_timeout: Duration,
+    /// How long to wait for the first line of a command's output before the
+    /// command is considered unanswered.
+    #[serde(rename = "response_timeout_secs", deserialize_with = "config::secs")]
+    pub response_timeout: Duration,
+    /// Number of lines buffered for output subscribers before the oldest are dropped.
+    pub output_buffer_lines: usize,
+    /// Commands that may wait for the stdin writer before senders have to wait too.
+    pub command_queue_len: usize,
+    /// Whether starting a server runs a simulated one instead of a process,
+    /// for trying the backend and its clients without Minecraft or Java.
+    pub dry_run: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            server_path: None,
+            working_dir: None,
+            jvm_args: Vec::new(),
+            java_path: None,
+            min_java_version: 0,
+            accept_eula: false,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            env_clear: false,
+            stop_command: "stop".to_string(),
+            pre_stop_commands: Vec::new(),
+            stop_command_delay: Duration::from_millis(500),
+            stop_timeout: Duration::from_secs(60),
+            terminate_timeout: Duration::from_secs(30),
+            save_timeout: Duration::from_secs(10),
+            response_timeout: Duration::from_secs(5),
+            output_buffer_lines: 1024,
+            command_queue_len: 64,
+            dry_run: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Returns the directory the server runs in when started without a path:
+    /// `working_dir`, or else the directory of `server_path`, or of
+    /// `server.jar` if no path is configured either. This is where its files
+    /// are read and written even before the server was first started.
+    pub fn server_dir(&self) -> PathBuf {
+        if let Some(dir) = &self.working_dir {
+            return dir.clone();
+        }
+        let path = Path::new(self.server_path.as_deref().unwrap_or(DEFAULT_SERVER_PATH));
+        // Resolved like the path /start runs, but the file needn't exist yet
+        let path = std::fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .unwrap_or_else(|_| path.to_path_buf());
+        path.parent().map(Path::to_path_buf).unwrap_or_default()
+    }
+
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_SERVER_PATH` - script or executable that starts the server
+    /// * `MC_WORKING_DIR` - directory the server runs in
+    /// * `MC_JVM_ARGS` - arguments for `java` separated by spaces
+    /// * `MC_JAVA_PATH` - `java` executable that runs jars
+    /// * `MC_MIN_JAVA_VERSION` - lowest Java major version, 0 for any
+    /// * `MC_ACCEPT_EULA` - `true` to accept the EULA before starting
+    /// * `MC_SERVER_ARGS` - arguments for the server separated by spaces
+    /// * `MC_ENV_CLEAR` - `true` to not pass the backend's environment on to the server
+    /// * `MC_STOP_COMMAND` - the stop command
+    /// * `MC_PRE_STOP_COMMANDS` - pre-stop commands separated by `;`
+    /// * `MC_STOP_COMMAND_DELAY_MS` - milliseconds to wait after each pre-stop command
+    /// * `MC_STOP_TIMEOUT_SECS` - seconds to wait after the stop command before SIGTERM
+    /// * `MC_TERMINATE_TIMEOUT_SECS` - seconds to wait after SIGTERM before killing the process
+    /// * `MC_SAVE_TIMEOUT_SECS` - seconds to wait for a save confirmation
+    /// * `MC_RESPONSE_TIMEOUT_SECS` - seconds to wait for a command's output
+    /// * `MC_OUTPUT_BUFFER_LINES` - lines buffered for output subscribers
+    /// * `MC_COMMAND_QUEUE_LEN` - commands waiting for the stdin writer
+    /// * `MC_DRY_RUN` - `true` to simulate the server instead of starting it
+    pub fn apply_env(&mut self) -> std::result::Result<(), String> {
+        if let Some(path) = config::env_string("MC_SERVER_PATH") {
+            self.server_path = Some(path);
+        }
+        if let Some(dir) = config::env_path("MC_WORKING_DIR") {
+            self.working_dir = Some(dir);
+        }
+        if let Ok(args) = std::env::var("MC_JVM_ARGS") {
+            self.jvm_args = config::env_list(&args, ' ');
+        }
+        if let Some(path) = config::env_path("MC_JAVA_PATH") {
+            self.java_path = Some(path);
+        }
+        if let Some(version) = config::env_parse("MC_MIN_JAVA_VERSION")? {
+            self.min_java_version = version;
+        }
+        if let Some(accept) = config::env_parse("MC_ACCEPT_EULA")? {
+            self.accept_eula = accept;
+        }
+        if let Ok(args) = std::env::var("MC_SERVER_ARGS") {
+            self.args = config::env_list(&args, ' ');
+        }
+        if let Some(clear) = config::env_parse("MC_ENV_CLEAR")? {
+            self.env_clear = clear;
+        }
+        if let Some(command) = config::env_string("MC_STOP_COMMAND") {
+            self.stop_command = command;
+        }
+        if let Ok(commands) = std::env::var("MC_PRE_STOP_COMMANDS") {
+            self.pre_stop_commands = config::env_list(&commands, ';');
+        }
+        if let Some(ms) = config::env_parse("MC_STOP_COMMAND_DELAY_MS")? {
+            self.stop_command_delay = Duration::from_millis(ms);
+        }
+        if let Some(timeout) = config::env_secs("MC_STOP_TIMEOUT_SECS")? {
+            self.stop_timeout = timeout;
+        }
+        if let Some(timeout) = config::env_secs("MC_TERMINATE_TIMEOUT_SECS")? {
+            self.terminate_timeout = timeout;
+        }
+        if let Some(timeout) = config::env_secs("MC_SAVE_TIMEOUT_SECS")? {
+            self.save_timeout = timeout;
+        }
+        if let Some(timeout) = config::env_secs("MC_RESPONSE_TIMEOUT_SECS")? {
+            self.response_timeout = timeout;
+        }
+        if let Some(lines) = config::env_parse("MC_OUTPUT_BUFFER_LINES")? {
+            self.output_buffer_lines = lines;
+        }
+        if let Some(len) = config::env_parse("MC_COMMAND_QUEUE_LEN")? {
+            self.command_queue_len = len;
+        }
+        if let Some(dry_run) = config::env_parse("MC_DRY_RUN")? {
+            self.dry_run = dry_run;
+        }
+        Ok(())
+    }
+}
+
+/// A server managed in addition to the default one, from a `[[servers]]`
+/// table of the config file or `POST /servers`.
+///
+/// Only what differs between servers is set here; the stop command, timeouts,
+/// buffer sizes and `accept_eula` are taken from the `[server]` section.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerDefinition {
+    /// Id used in routes, e.g. `creative` for `/servers/creative/start`.
+    pub id: ServerId,
+    /// Script, executable or jar that starts the server.
+    pub server_path: String,
+    /// Directory the server runs in. Defaults to the directory of the server path.
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    /// Arguments passed to `java` before `-jar`. Only used for jars.
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    /// `java` executable that runs the jar, defaulting to `[server] java_path`.
+    #[serde(default)]
+    pub java_path: Option<PathBuf>,
+    /// Lowest Java major version, defaulting to `[server] min_java_version`.
+    #[serde(default)]
+    pub min_java_version: Option<u32>,
+    /// Arguments passed to the server.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extra environment variables set for the server process.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl ServerDefinition {
+    /// Builds the settings for this server, taking everything it doesn't set from `base`.
+    pub fn server_config(&self, base: &ServerConfig) -> ServerConfig {
+        ServerConfig {
+            server_path: Some(self.server_path.clone()),
+            working_dir: self.working_dir.clone(),
+            jvm_args: self.jvm_args.clone(),
+            java_path: self.java_path.clone().or_else(|| base.java_path.clone()),
+            min_java_version: self.min_java_version.unwrap_or(base.min_java_version),
+            args: self.args.clone(),
+            env: self.env.clone(),
+            ..base.clone()
+        }
+    }
+}
+
+/// What to launch: the server executable or jar, where to run it, and with what.
+///
+/// Its `Debug` output leaves out the values of `env`, which may hold secrets.
+#[derive(Clone)]
+pub struct LaunchSpec {
+    /// Absolute path of the script, executable or jar that starts the server.
+    pub path: PathBuf,
+    /// Directory the server runs in.
+    pub working_dir: PathBuf,
+    /// Arguments passed to the executable, or to the jar after `java -jar`.
+    pub args: Vec<String>,
+    /// Arguments passed to `java` before `-jar`, for jars.
+    pub jvm_args: Vec<String>,
+    /// `java` executable that runs jars.
+    pub java: PathBuf,
+    /// Extra environment variables set for the process.
+    pub env: BTreeMap<String, String>,
+    /// Whether the process gets only `env` rather than also the backend's environment.
+    pub env_clear: bool,
+    /// Whether the EULA is accepted in the working directory before starting.
+    pub accept_eula: bool,
+    /// Whether a simulated server runs instead of the path; see `[server] dry_run`.
+    pub dry_run: bool,
+}
+
+impl std::fmt::Debug for LaunchSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LaunchSpec")
+            .field("path", &self.path)
+            .field("working_dir", &self.working_dir)
+            .field("args", &self.args)
+            .field("jvm_args", &self.jvm_args)
+            .field("java", &self.java)
+            .field("env", &self.env.keys().collect::<Vec<_>>())
+            .field("env_clear", &self.env_clear)
+            .field("accept_eula", &self.accept_eula)
+            .field("dry_run", &self.dry_run)
+            .finish()
+    }
+}
+
+impl LaunchSpec {
+    /// Builds a launch spec for a path, checking that it names an existing file
+    /// that can be run: a jar, or a script or executable with execute permission.
+    ///
+    /// # Arguments
+    /// * `path` - Script, executable or jar that starts the server
+    /// * `working_dir` - Directory to run in, defaulting to the directory of `path`
+    ///
+    /// # Returns
+    /// * `Result<LaunchSpec, ServerError>` - InvalidPath if the path doesn't exist, isn't a
+    ///   file or isn't executable
+    pub fn new(
+        path: impl AsRef<Path>,
+        working_dir: Option<PathBuf>,
+    ) -> std::result::Result<Self, ServerError> {
+        let path = path.as_ref();
+        let path = std::fs::canonicalize(path).map_err(|e| ServerError::InvalidPath {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        if !path.is_file() {
+            return Err(ServerError::InvalidPath {
+                path,
+                reason: "not a file".to_string(),
+            });
+        }
+        let working_dir = match working_dir {
+            Some(dir) => dir,
+            // A canonical path to a file always has a parent
+            None => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let launch = LaunchSpec {
+            path,
+            working_dir,
+            args: Vec::new(),
+            jvm_args: Vec::new(),
+            java: PathBuf::from("java"),
+            env: BTreeMap::new(),
+            env_clear: false,
+            accept_eula: false,
+            dry_run: false,
+        };
+        // Jars are run by java, so only need to be readable
+        if !launch.is_jar() && !is_executable(&launch.path) {
+            return Err(ServerError::InvalidPath {
+                path: launch.path,
+                reason: "not executable".to_string(),
+            });
+        }
+        Ok(launch)
+    }
+
+    /// Builds the launch spec for `/start` from an optional path and the server settings.
+    ///
+    /// Without a path the configured server path is used, and without that
+    /// `server.jar` in the backend's working directory. Jars are run with the
+    /// `java` picked by [`java::resolve`]. In a dry run nothing is run, so
+    /// the path isn't checked.
+    ///
+    /// # Returns
+    /// * `Result<LaunchSpec, ServerError>` - InvalidPath if the path doesn't exist or isn't a file
+    pub fn from_config(
+        path: Option<String>,
+        config: &ServerConfig,
+    ) -> std::result::Result<Self, ServerError> {
+        let path = path
+            .or_else(|| config.server_path.clone())
+            .unwrap_or_else(|| DEFAULT_SERVER_PATH.to_string());
+        let mut launch = if config.dry_run {
+            LaunchSpec::simulated(path, config.working_dir.clone())?
+        } else {
+            LaunchSpec::new(path, config.working_dir.clone())?
+        };
+        launch.args = config.args.clone();
+        launch.jvm_args = config.jvm_args.clone();
+        launch.env = config.env.clone();
+        launch.env_clear = config.env_clear;
+        launch.resolve_java(config);
+        launch.accept_eula = config.accept_eula;
+        Ok(launch)
+    }
+
+    /// Builds the launch spec of a dry run, which only uses the path to find
+    /// the working directory.
+    ///
+    /// # Returns
+    /// * `Result<LaunchSpec, ServerError>` - InvalidPath if the path can't be made absolute
+    fn simulated(
+        path: impl AsRef<Path>,
+        working_dir: Option<PathBuf>,
+    ) -> std::result::Result<Self, ServerError> {
+        let path = path.as_ref();
+        let path = std::path::absolute(path).map_err(|e| ServerError::InvalidPath {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        let working_dir = match working_dir {
+            Some(dir) => dir,
+            None => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        Ok(LaunchSpec {
+            path,
+            working_dir,
+            args: Vec::new(),
+            jvm_args: Vec::new(),
+            java: PathBuf::from("java"),
+            env: BTreeMap::new(),
+            env_clear: false,
+            accept_eula: false,
+            dry_run: true,
+        })
+    }
+
+    /// Returns true if the path is a jar, which is run with `java -jar`.
+    pub fn is_jar(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("jar"))
+    }
+
+    /// Picks the `java` that runs a jar, which may depend on `JAVA_HOME` in `env`.
+    pub fn resolve_java(&mut self, config: &ServerConfig) {
+        self.java = java::resolve(config.java_path.as_deref(), &self.env, !self.env_clear);
+    }
+
+    /// Sets the process environment on a command: `env`, on top of the
+    /// backend's own environment unless `env_clear` is set.
+    pub fn set_env(&self, command: &mut Command) {
+        if self.env_clear {
+            command.env_clear();
+        }
+        command.envs(&self.env);
+    }
+
+    /// Builds the command that runs the server.
+    pub fn command(&self) -> Command {
+        let mut command = if self.is_jar() {
+            let mut java = Command::new(&self.java);
+            java.args(&self.jvm_args).arg("-jar").arg(&self.path);
+            java
+        } else {
+            Command::new(&self.path)
+        };
+        command.args(&self.args).current_dir(&self.working_dir);
+        self.set_env(&mut command);
+        command
+    }
+}
+
+/// How far a stop had to escalate before the process exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopStage {
+    /// The process exited after the stop command.
+    Command,
+    /// The process exited after SIGTERM.
+    Terminate,
+    /// The process was killed.
+    Kill,
+}
+
+/// How a stopped process ended.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StopOutcome {
+    pub stage: StopStage,
+    /// None if the process was killed by a signal.
+    pub exit_code: Option<i32>,
+}
 
 /// Represents the Minecraft server process.
 ///
@@ -19,147 +498,783 @@ use tokio::sync::mpsc::UnboundedSender;
 /// - Sending commands to the server
 /// - Capturing and forwarding server output
 pub struct MinecraftServer {
-    /// The child process running the Minecraft server, None if not running.
-    child: Option<tokio::process::Child>,
-    /// Channel sender to forward log messages to other parts of the application.
-    pub log_sender: UnboundedSender<String>,
+    /// The process running the Minecraft server, None if not running.
+    process: Option<Box<dyn ProcessHandle>>,
+    /// Script, executable or jar the server was started from.
+    path: PathBuf,
+    /// Directory the server process was launched in (holds world, properties, ban lists).
+    working_dir: PathBuf,
+    /// Copy of the console output as text for short-lived listeners that wait for
+    /// a command's response. Unlike the log channel this doesn't go through AppState.
+    output: broadcast::Sender<String>,
+    /// Settings the server was started with.
+    config: ServerConfig,
+    /// Queue to the task writing the server's stdin, None if stdin isn't available.
+    commands: Option<CommandSender>,
+    /// Tasks forwarding stdout and stderr, finished once the process closes its output.
+    readers: Vec<JoinHandle<()>>,
+    /// Channel for the console lines reporting how a stop escalates.
+    log_sender: LogSender,
 }
 
-impl MinecraftServer {
+impl ServerProcess for MinecraftServer {
     /// Starts the Minecraft server process asynchronously.
     ///
     /// This function:
-    /// 1. Spawns the server process
+    /// 1. Spawns the server process, or in a dry run the simulated server
     /// 2. Sets up stdout and stderr redirection
     /// 3. Creates tasks to capture and forward the log output
+    /// 4. Creates a task writing queued commands to stdin
     ///
     /// # Arguments
+    /// * `launch` - What to run and where
     /// * `log_sender` - Channel sender to forward log messages
-    /// * `file_path` - Optional file path to the server executable
+    /// * `config` - Settings for managing the process
+    /// * `metrics` - Shared counters to update
     ///
     /// # Returns
-    /// * `Result<Self>` - New MinecraftServer instance or IO error
-    pub async fn start(
-        log_sender: UnboundedSender<String>,
-        file_path: Option<String>,
-    ) -> Result<Self> {
-        let (cmd_path, working_dir) = if let Some(ref path) = file_path {
-            let p = Path::new(path);
-            let dir = p
-                .parent()
-                .map(|d| d.to_path_buf())
-                .unwrap_or_else(|| std::env::current_dir().unwrap());
-            (path.clone(), dir)
-        } else {
-            // Default to looking for server files in current directory or common locations
-            let current_dir = std::env::current_dir().unwrap();
-            (
-                "server.jar".to_string(), // Generic default - user should specify path
-                current_dir,
-            )
-        };
-        let mut command = Command::new(cmd_path);
-        command.current_dir(working_dir);
+    /// * `Result<Self, ServerError>` - New MinecraftServer instance, or SpawnFailed
+    fn start(
+        launch: LaunchSpec,
+        log_sender: LogSender,
+        config: ServerConfig,
+        metrics: Arc<Metrics>,
+    ) -> BoxFuture<'static, std::result::Result<Self, ServerError>> {
+        async move {
+            if launch.dry_run {
+                warn!(
+                    "Dry run: simulating the server instead of starting {}",
+                    launch.path.display()
+                );
+            }
+            let (process, pipes) = process::start(&launch, &config)?;
+            let ConsolePipes {
+                stdin,
+                stdout,
+                stderr,
+            } = pipes;
 
-        // Configure process I/O streams
-        command
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-
-        // Spawn the server process
-        let mut child = command.spawn()?;
-
-        // Set up stdout handling
-        if let Some(stdout) = child.stdout.take() {
-            let mut reader = BufReader::new(stdout).lines();
-            let sender_clone = log_sender.clone();
-            tokio::spawn(async move {
-                while let Ok(Some(line)) = reader.next_line().await {
-                    // Forward each stdout line to the log channel without duplicate printing
-                    if sender_clone.send(line).is_err() {
-                        println!("Failed to send stdout log to channel");
-                        break;
+            let (output, _) = broadcast::channel(config.output_buffer_lines);
+            let mut readers = Vec::new();
+
+            // Commands are written by a task of their own so that senders only
+            // need the state lock to clone the queue, not for the write itself
+            let commands = stdin
+                .map(|stdin| CommandSender::spawn(stdin, &config, metrics, log_sender.clone()));
+
+            // Set up stdout handling
+            if let Some(stdout) = stdout {
+                let mut reader = BufReader::new(stdout);
+                let sender_clone = log_sender.clone();
+                let output_clone = output.clone();
+                readers.push(tokio::spawn(
+                    async move {
+                        // Lines without a header (e.g. stack traces) continue the previous entry
+                        let mut last_level = None;
+                        let mut buf = Vec::new();
+                        while let Some(line) = read_line(&mut reader, &mut buf).await {
+                            let level = log_parser::parse_level(&line).or(last_level);
+                            last_level = level;
+                            let entry = LogEntry::new(LogStream::Stdout, level, line);
+                            // Having no output subscribers is the normal case
+                            let _ = output_clone.send(entry.to_text());
+                            // Forward each stdout line to the log channel without duplicate printing
+                            if sender_clone.send(entry.into()).is_err() {
+                                error!("Failed to send stdout log to channel");
+                                break;
+                            }
+                        }
+                        debug!("Stdout reader task completed");
                     }
-                }
-                println!("Stdout reader task completed");
-            });
-        }
+                    .in_current_span(),
+                ));
+            }
 
-        // Set up stderr handling
-        if let Some(stderr) = child.stderr.take() {
-            let mut reader = BufReader::new(stderr).lines();
-            let sender_clone = log_sender.clone();
-            tokio::spawn(async move {
-                while let Ok(Some(line)) = reader.next_line().await {
-                    // Prefix stderr lines with "ERROR:" for clarity but don't print duplicates
-                    let error_line = format!("ERROR: {}", line);
-                    if sender_clone.send(error_line).is_err() {
-                        println!("Failed to send stderr log to channel");
-                        break;
+            // Set up stderr handling
+            if let Some(stderr) = stderr {
+                let mut reader = BufReader::new(stderr);
+                let sender_clone = log_sender.clone();
+                let output_clone = output.clone();
+                readers.push(tokio::spawn(
+                    async move {
+                        let mut buf = Vec::new();
+                        while let Some(line) = read_line(&mut reader, &mut buf).await {
+                            // Anything on stderr without a level of its own is treated as an error
+                            let level = log_parser::parse_level(&line).unwrap_or(LogLevel::Error);
+                            let entry = LogEntry::new(LogStream::Stderr, Some(level), line);
+                            let _ = output_clone.send(entry.to_text());
+                            if sender_clone.send(entry.into()).is_err() {
+                                error!("Failed to send stderr log to channel");
+                                break;
+                            }
+                        }
+                        debug!("Stderr reader task completed");
                     }
-                }
-                println!("Stderr reader task completed");
-            });
-        }
+                    .in_current_span(),
+                ));
+            }
 
-        Ok(MinecraftServer {
-            child: Some(child),
-            log_sender,
-        })
+            Ok(MinecraftServer {
+                process: Some(process),
+                path: launch.path,
+                working_dir: launch.working_dir,
+                output,
+                config,
+                commands,
+                readers,
+                log_sender,
+            })
+        }
+        .boxed()
     }
 
     /// Stops the Minecraft server process gracefully.
     ///
-    /// First attempts to send a "stop" command to the server via stdin.
-    /// If that fails, falls back to killing the process.
+    /// Sends the configured pre-stop commands in order, each followed by a
+    /// short delay, then the stop command. They are queued behind any commands
+    /// still waiting to be written. If the process doesn't exit within the stop
+    /// timeout, or stdin is unavailable or a write fails, the process is asked
+    /// to shut down with a signal instead, and killed if that doesn't help
+    /// either; see [`terminate`]. Each step is announced on the console.
+    ///
+    /// # Arguments
+    /// * `stop_sent` - Whether the stop command was already sent another way,
+    ///   e.g. over RCON, so only the wait for the process to exit is left
     ///
     /// # Returns
-    /// * `Result<()>` - Success or IO error
-    pub async fn stop(&mut self) -> Result<()> {
-        if let Some(child) = &mut self.child {
-            // Attempt to gracefully shut down the server by sending "stop\n"
-            if let Some(stdin) = child.stdin.as_mut() {
-                stdin.write_all(b"stop\n").await?;
-                stdin.flush().await?;
-            } else {
-                // Fallback to killing the process if stdin is not available
-                child.kill().await?;
+    /// * `Result<Option<StopOutcome>, ServerError>` - How the process ended, None
+    ///   if it wasn't running, or Io if waiting for or killing it failed
+    fn stop(
+        &mut self,
+        stop_sent: bool,
+    ) -> BoxFuture<'_, std::result::Result<Option<StopOutcome>, ServerError>> {
+        async move {
+            let mut outcome = None;
+            if let Some(process) = &mut self.process {
+                let graceful = match &self.commands {
+                    _ if stop_sent => Ok(()),
+                    Some(commands) if commands.is_closed() => Err(Error::new(
+                        ErrorKind::BrokenPipe,
+                        "the server closed its console input",
+                    )),
+                    Some(commands) => {
+                        let mut result = Ok(());
+                        for command in &self.config.pre_stop_commands {
+                            result = commands.write(command).await;
+                            if result.is_err() {
+                                break;
+                            }
+                            tokio::time::sleep(self.config.stop_command_delay).await;
+                        }
+                        if result.is_ok() {
+                            result = commands.write(&self.config.stop_command).await;
+                        }
+                        result
+                    }
+                    None => Err(Error::new(
+                        ErrorKind::NotConnected,
+                        "stdin is not available",
+                    )),
+                };
+
+                let stop_command = &self.config.stop_command;
+                let exited = match graceful {
+                    Ok(()) => {
+                        announce_stop(
+                            &self.log_sender,
+                            format!(
+                                "Sent '{}', waiting up to {}s for the server to exit",
+                                stop_command,
+                                self.config.stop_timeout.as_secs()
+                            ),
+                        );
+                        match tokio::time::timeout(self.config.stop_timeout, process.wait()).await {
+                            Ok(status) => Some(status?.code()),
+                            Err(_) => {
+                                warn!(
+                                    "Server did not exit within {:?} of '{}'",
+                                    self.config.stop_timeout, stop_command
+                                );
+                                announce_stop(
+                                    &self.log_sender,
+                                    format!(
+                                        "Server did not exit within {}s of '{}'",
+                                        self.config.stop_timeout.as_secs(),
+                                        stop_command
+                                    ),
+                                );
+                                None
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Could not send the stop command: {}", e);
+                        announce_stop(
+                            &self.log_sender,
+                            format!("Could not send '{}': {}", stop_command, e),
+                        );
+                        None
+                    }
+                };
+                outcome = Some(match exited {
+                    Some(exit_code) => StopOutcome {
+                        stage: StopStage::Command,
+                        exit_code,
+                    },
+                    None => {
+                        terminate(process, self.config.terminate_timeout, &self.log_sender).await?
+                    }
+                });
+                self.process = None;
+                self.commands = None;
+                // Let the last lines of output through before the caller reports the stop
+                for reader in self.readers.drain(..) {
+                    let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, reader).await;
+                }
             }
-            // Wait for the server process to exit
-            child.wait().await?;
-            self.child = None;
+            Ok(outcome)
         }
-        Ok(())
+        .boxed()
+    }
+
+    /// Checks whether the process has exited on its own, without blocking.
+    ///
+    /// # Returns
+    /// * `Option<ExitStatus>` - The exit status if the process has exited since the last check
+    fn try_wait(&mut self) -> Option<ExitStatus> {
+        let status = self.process.as_mut()?.try_wait().ok().flatten()?;
+        self.process = None;
+        self.commands = None;
+        Some(status)
     }
 
     /// Checks if the Minecraft server process is currently running.
     ///
     /// # Returns
     /// * `bool` - True if the server is running, false otherwise
-    pub fn is_running(&self) -> bool {
-        self.child.is_some()
+    fn is_running(&self) -> bool {
+        self.process.is_some()
+    }
+
+    /// Returns true if commands can be written to the server's console, false
+    /// if it isn't running or its console input is closed.
+    fn console_available(&self) -> bool {
+        self.process.is_some()
+            && self
+                .commands
+                .as_ref()
+                .is_some_and(|commands| !commands.is_closed())
+    }
+
+    /// Returns the OS process ID of the server, if it is running and not simulated.
+    fn pid(&self) -> Option<u32> {
+        self.process.as_ref().and_then(|process| process.id())
+    }
+
+    /// Returns the script, executable or jar the server was started from.
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the directory the server process was launched in.
+    fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+
+    /// Subscribes to the server's console output from this point on.
+    ///
+    /// Used to capture the response to a command: subscribe first, then send
+    /// the command, then read lines from the returned receiver.
+    fn subscribe_output(&self) -> broadcast::Receiver<String> {
+        self.output.subscribe()
+    }
+
+    /// Returns a handle for sending commands to the server console.
+    ///
+    /// The handle can be used after the caller has let go of the server, so
+    /// the write doesn't have to happen while holding the state lock.
+    ///
+    /// # Returns
+    /// * `Result<CommandSender, ServerError>` - NotRunning or StdinUnavailable on error
+    fn command_sender(&self) -> std::result::Result<CommandSender, ServerError> {
+        if self.process.is_none() {
+            return Err(ServerError::NotRunning);
+        }
+        self.commands.clone().ok_or(ServerError::StdinUnavailable)
+    }
+}
+
+/// A handle to the queue of commands waiting to be written to a server's stdin.
+///
+/// A single writer task takes commands off the queue and writes them one at
+/// a time in the order they were queued, so commands from different clients
+/// never interleave within a line, and a command is written before any
+/// command queued after it.
+#[derive(Clone)]
+pub struct CommandSender {
+    queue: mpsc::Sender<QueuedCommand>,
+    /// Shared counters, updated as commands are sent.
+    metrics: Arc<Metrics>,
+    /// Set once the server closed its console input; later commands fail at once.
+    closed: Arc<AtomicBool>,
+}
+
+/// A command line waiting to be written, with where to report the outcome.
+struct QueuedCommand {
+    line: String,
+    written: oneshot::Sender<Result<()>>,
+}
+
+impl CommandSender {
+    /// Starts the task writing queued commands to a server's console input.
+    ///
+    /// # Arguments
+    /// * `stdin` - The console input, owned by the writer task from now on
+    /// * `config` - Settings of the server, for the length of the queue
+    /// * `metrics` - Shared counters to update as commands are sent
+    /// * `log_sender` - Channel for the event queued if the console input closes
+    ///
+    /// # Returns
+    /// * `CommandSender` - A handle to the queue; the task ends once every handle is gone
+    pub fn spawn(
+        stdin: ConsoleInput,
+        config: &ServerConfig,
+        metrics: Arc<Metrics>,
+        log_sender: LogSender,
+    ) -> Self {
+        let (queue, rx) = mpsc::channel(config.command_queue_len);
+        let closed = Arc::new(AtomicBool::new(false));
+        let writer = write_commands(stdin, rx, closed.clone(), log_sender);
+        tokio::spawn(writer.in_current_span());
+        CommandSender {
+            queue,
+            metrics,
+            closed,
+        }
+    }
+
+    /// Returns true once a write failed because the server closed its console
+    /// input, e.g. a wrapper script that detached it.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
     }
 
-    /// Sends a command to the Minecraft server console.
+    /// Sends a command to the server console, waiting until it has been written.
+    ///
+    /// Commands containing control characters are rejected rather than
+    /// written, since an embedded line break would end the command early and
+    /// run whatever follows it as a second command.
     ///
     /// # Arguments
     /// * `command` - The command to send to the server
     ///
     /// # Returns
-    /// * `Result<()>` - Success or IO error
-    pub async fn send_command(&mut self, command: &str) -> Result<()> {
-        if let Some(child) = &mut self.child {
-            if let Some(stdin) = child.stdin.as_mut() {
-                // Append newline to ensure command is executed
-                stdin.write_all(format!("{}\n", command).as_bytes()).await?;
-                stdin.flush().await?;
-                return Ok(());
+    /// * `Result<(), ServerError>` - InvalidCommand, StdinUnavailable if the
+    ///   server closed its console input, or CommandFailed on error
+    pub async fn send(&self, command: &str) -> std::result::Result<(), ServerError> {
+        if command.contains(char::is_control) {
+            return Err(ServerError::InvalidCommand);
+        }
+        if self.is_closed() {
+            return Err(ServerError::StdinUnavailable);
+        }
+        self.write(command).await.map_err(|source| {
+            // The process may still be running with its console input closed
+            if source.kind() == ErrorKind::BrokenPipe {
+                ServerError::StdinUnavailable
+            } else {
+                ServerError::CommandFailed { source }
             }
+        })?;
+        Metrics::increment(&self.metrics.commands_sent);
+        Ok(())
+    }
+
+    /// Queues a line for the writer task and waits for the write to finish.
+    async fn write(&self, line: &str) -> Result<()> {
+        let written = self.enqueue(line).await?;
+        written.await.map_err(|_| stdin_closed())?
+    }
+
+    /// Queues a line for the writer task, waiting only while the queue is full.
+    ///
+    /// Once this returns, the line's place in the queue is fixed: it is written
+    /// after every line queued before it and before every line queued after it.
+    ///
+    /// # Returns
+    /// * `Result<oneshot::Receiver<Result<()>>>` - Resolves with the outcome once the
+    ///   line was written, or BrokenPipe if the writer task is gone
+    async fn enqueue(&self, line: &str) -> Result<oneshot::Receiver<Result<()>>> {
+        let (written, result) = oneshot::channel();
+        let command = QueuedCommand {
+            line: line.to_string(),
+            written,
+        };
+        self.queue.send(command).await.map_err(|_| stdin_closed())?;
+        Ok(result)
+    }
+}
+
+/// The error for a line that can't be queued or whose write never finished,
+/// because the writer task ended with the process.
+fn stdin_closed() -> Error {
+    Error::new(ErrorKind::BrokenPipe, "the server's stdin was closed")
+}
+
+/// Writes queued commands to the server's stdin until every sender is gone.
+///
+/// The first write that fails with a broken pipe while the process runs on
+/// sets `closed` and queues [`ServerEvent::ConsoleUnavailable`].
+async fn write_commands(
+    mut stdin: ConsoleInput,
+    mut queue: mpsc::Receiver<QueuedCommand>,
+    closed: Arc<AtomicBool>,
+    log_sender: LogSender,
+) {
+    while let Some(command) = queue.recv().await {
+        let result = write_line(&mut stdin, &command.line).await;
+        let broken_pipe = matches!(&result, Err(e) if e.kind() == ErrorKind::BrokenPipe);
+        if broken_pipe && !closed.swap(true, Ordering::Relaxed) {
+            warn!("The server closed its console input, commands can't be written to it");
+            let _ = log_sender.send(Broadcast::Event(ServerEvent::ConsoleUnavailable));
         }
-        Err(std::io::Error::new(
-            std::io::ErrorKind::NotConnected,
-            "Server is not running or stdin is not available",
-        ))
+        // The sender may have stopped waiting for the result
+        let _ = command.written.send(result);
+    }
+}
+
+/// Shuts the process down without its console: asks it to exit with
+/// [`ProcessHandle::terminate`] and kills it if it hasn't exited within
+/// `timeout`. A process that can't be asked is killed straight away: one on
+/// Windows, see [`ChildProcess::terminate`](process::ChildProcess#method.terminate),
+/// or a simulated server.
+///
+/// # Returns
+/// * `Result<StopOutcome>` - How the process ended
+async fn terminate(
+    process: &mut Box<dyn ProcessHandle>,
+    timeout: Duration,
+    log_sender: &LogSender,
+) -> Result<StopOutcome> {
+    match process.terminate() {
+        Ok(true) => {
+            announce_stop(
+                log_sender,
+                format!(
+                    "Sent SIGTERM, waiting up to {}s for the server to exit",
+                    timeout.as_secs()
+                ),
+            );
+            match tokio::time::timeout(timeout, process.wait()).await {
+                Ok(status) => {
+                    return Ok(StopOutcome {
+                        stage: StopStage::Terminate,
+                        exit_code: status?.code(),
+                    })
+                }
+                Err(_) => {
+                    warn!("Server did not exit within {:?} of SIGTERM", timeout);
+                    announce_stop(
+                        log_sender,
+                        format!(
+                            "Server did not exit within {}s of SIGTERM",
+                            timeout.as_secs()
+                        ),
+                    );
+                }
+            }
+        }
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Could not send SIGTERM: {}", e);
+            announce_stop(log_sender, format!("Could not send SIGTERM: {}", e));
+        }
+    }
+    warn!("Killing the server");
+    announce_stop(log_sender, "Killing the server".to_string());
+    process.kill().await?;
+    Ok(StopOutcome {
+        stage: StopStage::Kill,
+        exit_code: None,
+    })
+}
+
+/// Shows a step of stopping the server on its console.
+fn announce_stop(log_sender: &LogSender, message: String) {
+    // Nobody may be listening any more while the backend shuts down
+    let _ = log_sender.send(LogEntry::console(format!("[Stop]: {}", message)).into());
+}
+
+/// Reads a line of output, without its line ending.
+///
+/// Bytes that aren't valid UTF-8, e.g. from a mod printing in another
+/// encoding, become replacement characters instead of ending the capture.
+/// A read error is logged and reading carries on, keeping whatever part of
+/// the line was read before it; only after `MAX_READ_ERRORS` errors in a row
+/// does the reader give up.
+///
+/// # Arguments
+/// * `reader` - Output of the server process
+/// * `buf` - Buffer reused between lines
+///
+/// # Returns
+/// * `Option<String>` - The line, None once the stream ended or kept failing
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> Option<String> {
+    buf.clear();
+    let mut errors = 0;
+    loop {
+        match reader.read_until(b'\n', buf).await {
+            Ok(0) if buf.is_empty() => return None,
+            Ok(_) => break,
+            Err(e) => {
+                errors += 1;
+                warn!("Error reading server output: {}", e);
+                if errors >= MAX_READ_ERRORS {
+                    error!("Giving up reading server output after {} errors", errors);
+                    return None;
+                }
+            }
+        }
+    }
+    if buf.ends_with(b"\n") {
+        buf.pop();
+        if buf.ends_with(b"\r") {
+            buf.pop();
+        }
+    }
+    Some(String::from_utf8_lossy(buf).into_owned())
+}
+
+/// Returns true if the file has an execute permission bit set.
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o111 != 0)
+}
+
+/// Returns true for any file; Windows decides what can run by extension when spawning.
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+/// Writes a command to the server's stdin followed by a newline and flushes it.
+async fn write_line(stdin: &mut ConsoleInput, command: &str) -> Result<()> {
+    // Append newline to ensure command is executed
+    stdin.write_all(format!("{}\n", command).as_bytes()).await?;
+    stdin.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    /// Server output that hands out its chunks one read at a time, an error
+    /// where a chunk is None.
+    struct FlakyOutput(std::collections::VecDeque<Option<&'static [u8]>>);
+
+    impl tokio::io::AsyncRead for FlakyOutput {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<Result<()>> {
+            std::task::Poll::Ready(match self.0.pop_front() {
+                Some(Some(chunk)) => {
+                    buf.put_slice(chunk);
+                    Ok(())
+                }
+                Some(None) => Err(Error::new(ErrorKind::InvalidData, "decode error")),
+                None => Ok(()),
+            })
+        }
+    }
+
+    /// Writes an executable shell script into a directory of its own.
+    #[cfg(unix)]
+    fn script(test: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft-console-process-{}-{}",
+            std::process::id(),
+            test
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.sh");
+        std::fs::write(&path, format!("#!/bin/sh\n{}", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// Starts a script as the server.
+    ///
+    /// # Returns
+    /// * The server, and the receiving end of its log channel, which has to be
+    ///   kept so the output readers don't stop for lack of a receiver
+    #[cfg(unix)]
+    async fn start_script(
+        path: &Path,
+    ) -> (
+        MinecraftServer,
+        mpsc::UnboundedReceiver<(ServerId, Broadcast)>,
+    ) {
+        let (log_channel, log_receiver) = unbounded_channel();
+        let config = ServerConfig {
+            stop_timeout: Duration::from_secs(5),
+            ..ServerConfig::default()
+        };
+        let server = MinecraftServer::start(
+            LaunchSpec::new(path, None).unwrap(),
+            LogSender::new("test".to_string(), log_channel),
+            config,
+            Arc::default(),
+        )
+        .await
+        .unwrap();
+        (server, log_receiver)
+    }
+
+    #[tokio::test]
+    async fn queued_commands_are_written_in_order_while_the_server_is_busy() {
+        // A console that takes a byte at a time keeps the writer blocked on the first command
+        let (stdin, console) = duplex(1);
+        let (log_channel, _log_receiver) = unbounded_channel();
+        let commands = CommandSender::spawn(
+            Box::new(stdin),
+            &ServerConfig::default(),
+            Arc::default(),
+            LogSender::new("test".to_string(), log_channel),
+        );
+
+        let mut written = Vec::new();
+        for i in 0..20 {
+            written.push(commands.enqueue(&format!("say {}", i)).await.unwrap());
+        }
+        tokio::task::yield_now().await;
+        assert!(written.iter_mut().all(|result| result.try_recv().is_err()));
+
+        let mut lines = BufReader::new(console).lines();
+        for i in 0..20 {
+            let line = lines.next_line().await.unwrap();
+            assert_eq!(line.as_deref(), Some(format!("say {}", i).as_str()));
+        }
+        for result in written {
+            assert!(result.await.unwrap().is_ok());
+        }
+    }
+
+    #[test]
+    fn launch_spec_debug_leaves_out_env_values() {
+        let mut launch = LaunchSpec::simulated("server.jar", None).unwrap();
+        launch
+            .env
+            .insert("RCON_PASSWORD".to_string(), "hunter2".to_string());
+        let debug = format!("{:?}", launch);
+        assert!(debug.contains("RCON_PASSWORD"));
+        assert!(!debug.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn writing_after_stdin_is_dropped_is_stdin_unavailable() {
+        let (stdin, console) = duplex(64);
+        let (log_channel, mut log_receiver) = unbounded_channel();
+        let commands = CommandSender::spawn(
+            Box::new(stdin),
+            &ServerConfig::default(),
+            Arc::default(),
+            LogSender::new("test".to_string(), log_channel),
+        );
+        drop(console);
+
+        let err = commands.send("list").await.unwrap_err();
+        assert!(matches!(err, ServerError::StdinUnavailable), "{:?}", err);
+        assert!(commands.is_closed());
+        assert!(matches!(
+            log_receiver.try_recv(),
+            Ok((_, Broadcast::Event(ServerEvent::ConsoleUnavailable)))
+        ));
+        // Later commands fail without reaching the writer, and the event isn't repeated
+        let err = commands.send("list").await.unwrap_err();
+        assert!(matches!(err, ServerError::StdinUnavailable), "{:?}", err);
+        assert!(log_receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_becomes_replacement_characters() {
+        let mut reader: &[u8] = b"bad \xff\xfe bytes\r\nafter\n\xc3";
+        let mut buf = Vec::new();
+        assert_eq!(
+            read_line(&mut reader, &mut buf).await.as_deref(),
+            Some("bad \u{FFFD}\u{FFFD} bytes")
+        );
+        assert_eq!(
+            read_line(&mut reader, &mut buf).await.as_deref(),
+            Some("after")
+        );
+        // A truncated character at the end of the output
+        assert_eq!(
+            read_line(&mut reader, &mut buf).await.as_deref(),
+            Some("\u{FFFD}")
+        );
+        assert_eq!(read_line(&mut reader, &mut buf).await, None);
+    }
+
+    #[tokio::test]
+    async fn reading_resumes_after_an_error() {
+        let output = FlakyOutput(
+            [
+                Some(&b"first\n"[..]),
+                None,
+                Some(b"second\nthi"),
+                None,
+                Some(b"rd\n"),
+            ]
+            .into(),
+        );
+        let mut reader = BufReader::new(output);
+        let mut buf = Vec::new();
+        assert_eq!(
+            read_line(&mut reader, &mut buf).await.as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            read_line(&mut reader, &mut buf).await.as_deref(),
+            Some("second")
+        );
+        // The part read before the error is kept
+        assert_eq!(
+            read_line(&mut reader, &mut buf).await.as_deref(),
+            Some("third")
+        );
+        assert_eq!(read_line(&mut reader, &mut buf).await, None);
+    }
+
+    #[tokio::test]
+    async fn reading_gives_up_after_errors_in_a_row() {
+        let mut chunks: std::collections::VecDeque<_> =
+            std::iter::repeat_n(None, MAX_READ_ERRORS as usize).collect();
+        chunks.push_back(Some(&b"never read\n"[..]));
+        let mut reader = BufReader::new(FlakyOutput(chunks));
+        assert_eq!(read_line(&mut reader, &mut Vec::new()).await, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn output_reader_survives_invalid_utf8() {
+        let path = script(
+            "invalid-utf8",
+            "read go\nprintf 'bad \\377\\376 bytes\\n'\necho after\nread stop\n",
+        );
+        let (mut server, _log_receiver) = start_script(&path).await;
+        let mut output = server.subscribe_output();
+        server.send_command("go").await.unwrap();
+        assert_eq!(output.recv().await.unwrap(), "bad \u{FFFD}\u{FFFD} bytes");
+        assert_eq!(output.recv().await.unwrap(), "after");
+
+        let outcome = server.stop(false).await.unwrap().unwrap();
+        assert_eq!(outcome.stage, StopStage::Command);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
     }
 }
@@ -3,6 +3,32 @@
 //! This module contains the core functionality for starting, stopping,
 //! and communicating with the Minecraft server process.
 
+pub mod backup;
+pub mod bans;
+pub mod crash_reports;
+pub mod eula;
+pub mod java;
+pub mod log_entry;
+pub mod log_file;
+pub mod log_parser;
 mod minecraft_server;
+pub mod mods;
+pub mod motd;
+pub mod nbt;
+pub mod output;
+pub mod performance;
+pub mod ping;
+pub mod ports;
+pub mod process;
+pub mod properties;
+pub mod query;
+pub mod rcon;
+mod simulated;
+pub mod whitelist;
+pub mod worlds;
 
-pub use minecraft_server::MinecraftServer;
+pub use minecraft_server::{
+    is_valid_env_var, is_valid_server_id, CommandSender, LaunchSpec, MinecraftServer, ServerConfig,
+    ServerDefinition, ServerId, StopOutcome, StopStage, DEFAULT_SERVER_ID,
+};
+pub use process::ServerProcess;
@@ -0,0 +1,433 @@
+//! The mods in a server's `mods` directory.
+//!
+//! Every `.jar` in the directory is a mod the loader picks up; renaming it to
+//! `.jar.disabled` makes the loader skip it without deleting it. Each jar
+//! describes itself in its own metadata file:
+//! - NeoForge: `META-INF/neoforge.mods.toml`
+//! - Forge (and NeoForge before 20.5): `META-INF/mods.toml`
+//! - Fabric and Quilt: `fabric.mod.json`
+//!
+//! Jars without metadata, e.g. libraries, or with metadata that can't be
+//! parsed are still listed, just without a mod id. Only plain file names in
+//! the directory are used and symbolic links are skipped, so nothing outside
+//! it is read or renamed. Listing hashes every jar; call these functions from
+//! a blocking context (e.g. `web::block`).
+//!
+//! Uploaded jars are written to a hidden temporary file in the mods
+//! directory, which the loader ignores, and only linked under their name once
+//! complete, so the server never sees half a jar.
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use toml::Value as TomlValue;
+use zip::ZipArchive;
+
+/// Directory of the server directory holding the mods.
+pub const MODS_DIR: &str = "mods";
+/// Extension of mod jars.
+const JAR_EXTENSION: &str = ".jar";
+/// Suffix the loader ignores, added to disable a mod.
+const DISABLED_SUFFIX: &str = ".disabled";
+/// Largest metadata file read from a jar; real ones are a few KB.
+const MAX_METADATA_SIZE: u64 = 1024 * 1024;
+/// Names tried for an upload whose name is taken, before giving up.
+const MAX_VERSIONED_NAMES: usize = 1000;
+
+/// Number of uploads started so far, making their temporary files unique.
+static UPLOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Settings for managing mods.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModsConfig {
+    /// Largest jar accepted by `POST /mods/upload`, in MiB.
+    pub max_upload_mb: u64,
+}
+
+impl Default for ModsConfig {
+    fn default() -> Self {
+        ModsConfig { max_upload_mb: 256 }
+    }
+}
+
+impl ModsConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_MODS_MAX_UPLOAD_MB` - largest jar accepted by `POST /mods/upload`
+    pub fn apply_env(&mut self) -> std::result::Result<(), String> {
+        if let Some(size) = config::env_parse("MC_MODS_MAX_UPLOAD_MB")? {
+            self.max_upload_mb = size;
+        }
+        Ok(())
+    }
+
+    /// Returns the upload limit in bytes.
+    pub fn max_upload_size(&self) -> u64 {
+        self.max_upload_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+/// What to do with an upload named like a mod that is already there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDuplicate {
+    /// Refuse the upload.
+    #[default]
+    Reject,
+    /// Store it with a number added to the name, e.g. `create-1.jar`.
+    Rename,
+}
+
+/// A mod jar, as listed by `GET /mods`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModInfo {
+    /// Name of the jar in the mods directory, ending in `.jar.disabled` if disabled.
+    pub file: String,
+    /// Whether the loader loads the jar.
+    pub enabled: bool,
+    /// Size of the jar in bytes.
+    pub size: u64,
+    /// SHA-256 of the jar in hex, for comparing with a download.
+    pub sha256: String,
+    /// Loader the metadata is for: `neoforge`, `forge` or `fabric`; absent
+    /// for jars without metadata.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loader: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mod_id: Option<String>,
+    /// Name of the mod as shown in the game's mod list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// What a mod jar says about itself.
+#[derive(Debug, Default)]
+struct ModMetadata {
+    loader: Option<&'static str>,
+    mod_id: Option<String>,
+    name: Option<String>,
+    version: Option<String>,
+}
+
+/// Outcome of enabling or disabling a mod.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModToggle {
+    /// Name of the jar after the change.
+    pub file: String,
+    pub enabled: bool,
+    /// Whether the jar was renamed; false if it already was in that state.
+    pub changed: bool,
+}
+
+/// Returns the mods directory of a server directory.
+pub fn mods_dir(server_dir: &Path) -> PathBuf {
+    server_dir.join(MODS_DIR)
+}
+
+/// Lists the mod jars, enabled and disabled, sorted by file name.
+///
+/// # Returns
+/// * `Result<Vec<ModInfo>>` - The mods, empty if there is no mods directory
+pub fn list_mods(server_dir: &Path) -> Result<Vec<ModInfo>> {
+    let entries = match fs::read_dir(mods_dir(server_dir)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut mods = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        // Symbolic links could point anywhere, so only regular files count
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let file = entry.file_name().to_string_lossy().into_owned();
+        let Some(enabled) = jar_state(&file) else {
+            continue;
+        };
+        mods.push(mod_info(&entry.path(), file, enabled)?);
+    }
+    mods.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(mods)
+}
+
+/// Describes a jar, with what its metadata says if it has any.
+fn mod_info(path: &Path, file: String, enabled: bool) -> Result<ModInfo> {
+    let metadata = read_metadata(path).unwrap_or_default();
+    Ok(ModInfo {
+        size: fs::metadata(path)?.len(),
+        sha256: sha256(path)?,
+        loader: metadata.loader,
+        mod_id: metadata.mod_id,
+        name: metadata.name,
+        version: metadata.version,
+        file,
+        enabled,
+    })
+}
+
+/// Checks that an uploaded file's name is the plain name of a jar.
+///
+/// # Returns
+/// * `Result<()>` - InvalidInput if it isn't
+pub fn check_upload_name(file: &str) -> Result<()> {
+    if is_plain_name(file) && file.ends_with(JAR_EXTENSION) && file != JAR_EXTENSION {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{}' is not the name of a .jar file", file),
+        ))
+    }
+}
+
+/// Returns the temporary file an upload is written to before it is
+/// installed: a hidden file in the mods directory, which the loader skips.
+pub fn upload_path(server_dir: &Path) -> PathBuf {
+    mods_dir(server_dir).join(format!(
+        ".upload-{}-{}.tmp",
+        std::process::id(),
+        UPLOADS.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Installs an uploaded jar under its name in the mods directory.
+///
+/// The upload is linked to its new name, which fails rather than replacing
+/// a jar that appeared in the meantime, and then removed; on error it is
+/// removed too.
+///
+/// # Arguments
+/// * `server_dir` - The server directory
+/// * `upload` - The complete upload, from [`upload_path`]
+/// * `file` - Name the jar was uploaded as
+/// * `on_duplicate` - What to do if a mod, enabled or disabled, has that name
+///
+/// # Returns
+/// * `Result<ModInfo>` - The installed mod; InvalidInput if `file` isn't the
+///   name of a jar, InvalidData if the upload isn't a zip archive,
+///   AlreadyExists if the name is taken and `on_duplicate` is `Reject`
+pub fn install_upload(
+    server_dir: &Path,
+    upload: &Path,
+    file: &str,
+    on_duplicate: OnDuplicate,
+) -> Result<ModInfo> {
+    let result = link_upload(server_dir, upload, file, on_duplicate);
+    let _ = fs::remove_file(upload);
+    let installed = result?;
+    mod_info(&mods_dir(server_dir).join(&installed), installed, true)
+}
+
+/// Links an upload to the first free name for it, returning that name.
+fn link_upload(
+    server_dir: &Path,
+    upload: &Path,
+    file: &str,
+    on_duplicate: OnDuplicate,
+) -> Result<String> {
+    check_upload_name(file)?;
+    ZipArchive::new(File::open(upload)?).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("'{}' is not a valid jar: {}", file, e),
+        )
+    })?;
+
+    let dir = mods_dir(server_dir);
+    let stem = &file[..file.len() - JAR_EXTENSION.len()];
+    let names = std::iter::once(file.to_string())
+        .chain((1..MAX_VERSIONED_NAMES).map(|n| format!("{}-{}{}", stem, n, JAR_EXTENSION)));
+    for name in names {
+        let disabled = format!("{}{}", name, DISABLED_SUFFIX);
+        let taken = match fs::symlink_metadata(dir.join(&disabled)) {
+            Ok(_) => true,
+            Err(e) if e.kind() == ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        };
+        let result = if taken {
+            Err(Error::from(ErrorKind::AlreadyExists))
+        } else {
+            fs::hard_link(upload, dir.join(&name))
+        };
+        match result {
+            Ok(()) => return Ok(name),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                if on_duplicate == OnDuplicate::Reject {
+                    return Err(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("A mod named '{}' already exists in {}", file, MODS_DIR),
+                    ));
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(Error::new(
+        ErrorKind::AlreadyExists,
+        format!("No free name left for '{}' in {}", file, MODS_DIR),
+    ))
+}
+
+/// Returns true if a name is a single path component without control characters.
+fn is_plain_name(file: &str) -> bool {
+    !file.contains(['/', '\\']) && !file.contains(char::is_control)
+}
+
+/// Returns whether a file name is that of an enabled (`.jar`) or disabled
+/// (`.jar.disabled`) mod, None if it is neither.
+fn jar_state(file: &str) -> Option<bool> {
+    if file.ends_with(JAR_EXTENSION) {
+        Some(true)
+    } else if file
+        .strip_suffix(DISABLED_SUFFIX)
+        .is_some_and(|jar| jar.ends_with(JAR_EXTENSION))
+    {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Hashes a file with SHA-256.
+fn sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Reads the metadata of a jar, trying each loader's file in turn.
+///
+/// # Returns
+/// * `Option<ModMetadata>` - None if the jar isn't a valid zip or has no metadata
+fn read_metadata(path: &Path) -> Option<ModMetadata> {
+    let mut jar = ZipArchive::new(File::open(path).ok()?).ok()?;
+    let mut metadata = if let Some(toml) = read_entry(&mut jar, "META-INF/neoforge.mods.toml") {
+        parse_mods_toml(&toml, "neoforge")
+    } else if let Some(toml) = read_entry(&mut jar, "META-INF/mods.toml") {
+        parse_mods_toml(&toml, "forge")
+    } else {
+        parse_fabric_json(&read_entry(&mut jar, "fabric.mod.json")?)
+    }?;
+    // Forge jars usually take their version from the manifest at build time
+    if metadata.version.as_deref() == Some("${file.jarVersion}") {
+        metadata.version = read_entry(&mut jar, "META-INF/MANIFEST.MF")
+            .and_then(|manifest| manifest_value(&manifest, "Implementation-Version"));
+    }
+    Some(metadata)
+}
+
+/// Reads a text file from a jar, None if it is missing, too large or not UTF-8.
+fn read_entry(jar: &mut ZipArchive<File>, name: &str) -> Option<String> {
+    let entry = jar.by_name(name).ok()?;
+    if entry.size() > MAX_METADATA_SIZE {
+        return None;
+    }
+    let mut text = String::new();
+    // The size in the directory may lie, so the read is capped too
+    entry
+        .take(MAX_METADATA_SIZE)
+        .read_to_string(&mut text)
+        .ok()?;
+    Some(text)
+}
+
+/// Reads the first mod of a Forge or NeoForge `mods.toml`.
+fn parse_mods_toml(text: &str, loader: &'static str) -> Option<ModMetadata> {
+    let toml: TomlValue = toml::from_str(text).ok()?;
+    let first = toml.get("mods")?.as_array()?.first()?;
+    let field = |key: &str| first.get(key)?.as_str().map(str::to_string);
+    Some(ModMetadata {
+        loader: Some(loader),
+        mod_id: field("modId"),
+        name: field("displayName"),
+        version: field("version"),
+    })
+}
+
+/// Reads a Fabric or Quilt `fabric.mod.json`.
+fn parse_fabric_json(text: &str) -> Option<ModMetadata> {
+    let json: JsonValue = serde_json::from_str(text).ok()?;
+    let field = |key: &str| json.get(key)?.as_str().map(str::to_string);
+    Some(ModMetadata {
+        loader: Some("fabric"),
+        mod_id: field("id"),
+        name: field("name"),
+        version: field("version"),
+    })
+}
+
+/// Returns the value of a main attribute from a jar manifest.
+fn manifest_value(manifest: &str, key: &str) -> Option<String> {
+    manifest.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == key).then(|| value.trim().to_string())
+    })
+}
+
+/// Enables or disables a mod by renaming it between `.jar` and `.jar.disabled`.
+///
+/// # Arguments
+/// * `server_dir` - The server directory
+/// * `file` - Name of the jar in the mods directory, enabled or disabled
+/// * `enable` - Whether to enable the mod
+///
+/// # Returns
+/// * `Result<ModToggle>` - The new name; InvalidInput if `file` isn't the plain
+///   name of a jar, NotFound if there is no such mod, AlreadyExists if both
+///   an enabled and a disabled copy exist
+pub fn set_enabled(server_dir: &Path, file: &str, enable: bool) -> Result<ModToggle> {
+    let jar = file.strip_suffix(DISABLED_SUFFIX).unwrap_or(file);
+    if !is_plain_name(file) || !jar.ends_with(JAR_EXTENSION) || jar == JAR_EXTENSION {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{}' is not the name of a mod jar", file),
+        ));
+    }
+    let disabled = format!("{}{}", jar, DISABLED_SUFFIX);
+    let dir = mods_dir(server_dir);
+    let is_file =
+        |name: &str| fs::symlink_metadata(dir.join(name)).is_ok_and(|meta| meta.is_file());
+
+    let (from, to) = if enable {
+        (disabled.as_str(), jar)
+    } else {
+        (jar, disabled.as_str())
+    };
+    match (is_file(from), is_file(to)) {
+        (true, true) => Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("Both {} and {} exist; remove one first", jar, disabled),
+        )),
+        (true, false) => {
+            fs::rename(dir.join(from), dir.join(to))?;
+            Ok(ModToggle {
+                file: to.to_string(),
+                enabled: enable,
+                changed: true,
+            })
+        }
+        (false, true) => Ok(ModToggle {
+            file: to.to_string(),
+            enabled: enable,
+            changed: false,
+        }),
+        (false, false) => Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No mod named '{}' in {}", jar, MODS_DIR),
+        )),
+    }
+}
@@ -0,0 +1,160 @@
+//! The server's message of the day.
+//!
+//! The MOTD is the `motd` key of `server.properties`, shown under the
+//! server's name in the multiplayer list. It may span two lines and use `§`
+//! formatting codes, e.g. `§c` for red or `§l` for bold; [`segments`] splits
+//! it into styled runs of text so a client can preview it without knowing the
+//! codes.
+//!
+//! The server only reads the MOTD at startup. With `[motd] apply_command`
+//! set, a change is also sent to the running server, for plugins that can
+//! change the MOTD live.
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+
+/// Property key holding the MOTD.
+pub const MOTD_KEY: &str = "motd";
+/// Lines the multiplayer list shows.
+const MAX_LINES: usize = 2;
+/// Longest MOTD accepted, in characters including formatting codes.
+const MAX_LEN: usize = 512;
+
+/// Colors of the `§0` to `§f` codes, by the names the game uses in JSON text.
+const COLORS: [&str; 16] = [
+    "black",
+    "dark_blue",
+    "dark_green",
+    "dark_aqua",
+    "dark_red",
+    "dark_purple",
+    "gold",
+    "gray",
+    "dark_gray",
+    "blue",
+    "green",
+    "aqua",
+    "red",
+    "light_purple",
+    "yellow",
+    "white",
+];
+
+/// Settings for changing the MOTD.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotdConfig {
+    /// Command applying a new MOTD to the running server, with `{motd}`
+    /// replaced by the MOTD; None leaves it for the next start.
+    pub apply_command: Option<String>,
+}
+
+impl MotdConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_MOTD_APPLY_COMMAND` - command applying a new MOTD to the running server
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(command) = config::env_string("MC_MOTD_APPLY_COMMAND") {
+            self.apply_command = Some(command);
+        }
+        Ok(())
+    }
+
+    /// Returns the command applying a MOTD, None if none is configured.
+    ///
+    /// Line breaks are written as `\n`, since a command is a single line.
+    pub fn command(&self, motd: &str) -> Option<String> {
+        self.apply_command
+            .as_ref()
+            .map(|command| command.replace("{motd}", &motd.replace('\n', "\\n")))
+    }
+}
+
+/// A run of MOTD text in one style.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MotdSegment {
+    pub text: String,
+    /// Name of the color, e.g. `red`; absent for the default color.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<&'static str>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+}
+
+/// Splits a MOTD into runs of text in the same style.
+///
+/// A color code resets the other formatting, as in the game, and `§r`
+/// resets everything. Unknown codes are dropped. Line breaks stay in the
+/// text of the segment they fall in.
+pub fn segments(motd: &str) -> Vec<MotdSegment> {
+    let mut segments = Vec::new();
+    let mut current = MotdSegment::default();
+    let mut chars = motd.chars();
+    while let Some(c) = chars.next() {
+        if c != '§' {
+            current.text.push(c);
+            continue;
+        }
+        let Some(code) = chars.next() else {
+            break;
+        };
+        let mut style = MotdSegment {
+            text: String::new(),
+            ..current.clone()
+        };
+        match code.to_ascii_lowercase() {
+            digit @ ('0'..='9' | 'a'..='f') => {
+                style = MotdSegment {
+                    color: digit.to_digit(16).map(|index| COLORS[index as usize]),
+                    ..MotdSegment::default()
+                }
+            }
+            'k' => style.obfuscated = true,
+            'l' => style.bold = true,
+            'm' => style.strikethrough = true,
+            'n' => style.underlined = true,
+            'o' => style.italic = true,
+            'r' => style = MotdSegment::default(),
+            _ => continue,
+        }
+        if !current.text.is_empty() {
+            segments.push(std::mem::replace(&mut current, style));
+        } else {
+            current = style;
+        }
+    }
+    if !current.text.is_empty() {
+        segments.push(current);
+    }
+    segments
+}
+
+/// Returns the MOTD without formatting codes.
+pub fn plain(motd: &str) -> String {
+    segments(motd)
+        .into_iter()
+        .map(|segment| segment.text)
+        .collect()
+}
+
+/// Checks that a MOTD fits the multiplayer list.
+///
+/// # Returns
+/// * `Result<(), String>` - Why the MOTD was refused
+pub fn validate(motd: &str) -> Result<(), String> {
+    if motd.chars().count() > MAX_LEN {
+        return Err(format!("The MOTD must be at most {} characters", MAX_LEN));
+    }
+    if motd.lines().count() > MAX_LINES {
+        return Err(format!("The MOTD must be at most {} lines", MAX_LINES));
+    }
+    if motd.chars().any(|c| c.is_control() && c != '\n') {
+        return Err(
+            "The MOTD must not contain control characters other than line breaks".to_string(),
+        );
+    }
+    Ok(())
+}
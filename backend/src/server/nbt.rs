@@ -0,0 +1,333 @@
+//! Reader for NBT, the named binary tag format of `level.dat`.
+//!
+//! An NBT file is a single named compound tag, usually gzip-compressed. Each
+//! tag starts with a type byte; named tags (the root and the entries of a
+//! compound) follow it with a name, a big-endian u16 length and that many
+//! bytes of (modified) UTF-8. Compounds are entries up to an end tag (type
+//! 0), lists a type byte and an i32 count of unnamed payloads, and arrays an
+//! i32 count of numbers. All numbers are big-endian.
+//!
+//! Only reading is supported. The input is untrusted, so lengths are checked
+//! against the data left before anything is allocated, and nesting is
+//! limited.
+
+use flate2::read::GzDecoder;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Result};
+
+/// Deepest nesting of compounds and lists accepted, as in the game.
+const MAX_DEPTH: usize = 512;
+/// Largest uncompressed file accepted; a `level.dat` is a few KB.
+const MAX_UNCOMPRESSED: u64 = 16 * 1024 * 1024;
+/// First bytes of gzip data.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A tag's payload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Returns the entry of a compound by name, None for other tags.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.get(name),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of compound entry names, e.g. `["Data", "Version", "Name"]`.
+    pub fn path(&self, names: &[&str]) -> Option<&Tag> {
+        names.iter().try_fold(self, |tag, name| tag.get(name))
+    }
+
+    /// Returns the value of any integer tag widened to i64.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value.into()),
+            Tag::Short(value) => Some(value.into()),
+            Tag::Int(value) => Some(value.into()),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the text of a string tag.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Reads an NBT file, decompressing it first if it is gzip-compressed.
+///
+/// # Returns
+/// * `Result<(String, Tag)>` - Name and payload of the root compound, or
+///   InvalidData if the data isn't valid NBT
+pub fn read(data: &[u8]) -> Result<(String, Tag)> {
+    if data.starts_with(&GZIP_MAGIC) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(data)
+            .take(MAX_UNCOMPRESSED + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| invalid_data(format!("not valid gzip data: {}", e)))?;
+        if decompressed.len() as u64 > MAX_UNCOMPRESSED {
+            return Err(invalid_data(format!(
+                "larger than {} bytes uncompressed",
+                MAX_UNCOMPRESSED
+            )));
+        }
+        return read_uncompressed(&decompressed);
+    }
+    read_uncompressed(data)
+}
+
+/// Reads uncompressed NBT data, which must be a single named compound.
+fn read_uncompressed(mut data: &[u8]) -> Result<(String, Tag)> {
+    let buf = &mut data;
+    let kind = read_u8(buf)?;
+    if kind != 10 {
+        return Err(invalid_data(format!(
+            "the root tag must be a compound, found type {}",
+            kind
+        )));
+    }
+    let name = read_string(buf)?;
+    let root = read_payload(buf, kind, 0)?;
+    Ok((name, root))
+}
+
+/// Reads the payload of a tag of the given type from the front of a buffer.
+fn read_payload(buf: &mut &[u8], kind: u8, depth: usize) -> Result<Tag> {
+    Ok(match kind {
+        1 => Tag::Byte(read_u8(buf)? as i8),
+        2 => Tag::Short(i16::from_be_bytes(take(buf)?)),
+        3 => Tag::Int(i32::from_be_bytes(take(buf)?)),
+        4 => Tag::Long(i64::from_be_bytes(take(buf)?)),
+        5 => Tag::Float(f32::from_be_bytes(take(buf)?)),
+        6 => Tag::Double(f64::from_be_bytes(take(buf)?)),
+        7 => Tag::ByteArray(read_array(buf, 1, |bytes| bytes[0] as i8)?),
+        8 => Tag::String(read_string(buf)?),
+        9 => {
+            let depth = nested(depth)?;
+            let element = read_u8(buf)?;
+            // Lists of end tags are how the game writes empty lists
+            if element == 0 {
+                take::<4>(buf)?;
+                return Ok(Tag::List(Vec::new()));
+            }
+            let count = read_length(buf, 1)?;
+            let mut items = Vec::with_capacity(count);
+            for _ in 0..count {
+                items.push(read_payload(buf, element, depth)?);
+            }
+            Tag::List(items)
+        }
+        10 => {
+            let depth = nested(depth)?;
+            let mut entries = BTreeMap::new();
+            loop {
+                let kind = read_u8(buf)?;
+                if kind == 0 {
+                    break;
+                }
+                let name = read_string(buf)?;
+                entries.insert(name, read_payload(buf, kind, depth)?);
+            }
+            Tag::Compound(entries)
+        }
+        11 => Tag::IntArray(read_array(buf, 4, |bytes| {
+            i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        })?),
+        12 => Tag::LongArray(read_array(buf, 8, |bytes| {
+            let mut value = [0; 8];
+            value.copy_from_slice(bytes);
+            i64::from_be_bytes(value)
+        })?),
+        kind => return Err(invalid_data(format!("unknown tag type {}", kind))),
+    })
+}
+
+/// Returns the depth of a compound or list inside one at `depth`, or an
+/// error if that is nested too deeply.
+fn nested(depth: usize) -> Result<usize> {
+    if depth >= MAX_DEPTH {
+        return Err(invalid_data(format!(
+            "tags nested deeper than {} levels",
+            MAX_DEPTH
+        )));
+    }
+    Ok(depth + 1)
+}
+
+/// Takes a fixed number of bytes from the front of a buffer.
+fn take<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N]> {
+    let (bytes, rest) = buf.split_first_chunk::<N>().ok_or_else(unexpected_end)?;
+    *buf = rest;
+    Ok(*bytes)
+}
+
+/// Reads a byte from the front of a buffer.
+fn read_u8(buf: &mut &[u8]) -> Result<u8> {
+    take::<1>(buf).map(|[byte]| byte)
+}
+
+/// Reads the i32 count of a list or array whose items take at least
+/// `item_size` bytes each, checking that the buffer can hold them.
+fn read_length(buf: &mut &[u8], item_size: usize) -> Result<usize> {
+    let count = i32::from_be_bytes(take(buf)?);
+    // Some writers give empty lists a negative count
+    let count = usize::try_from(count).unwrap_or(0);
+    if count > buf.len() / item_size {
+        return Err(unexpected_end());
+    }
+    Ok(count)
+}
+
+/// Reads an array of `item_size`-byte numbers from the front of a buffer.
+fn read_array<T>(buf: &mut &[u8], item_size: usize, item: fn(&[u8]) -> T) -> Result<Vec<T>> {
+    let count = read_length(buf, item_size)?;
+    let (items, rest) = buf.split_at(count * item_size);
+    *buf = rest;
+    Ok(items.chunks_exact(item_size).map(item).collect())
+}
+
+/// Reads a string prefixed by its u16 length in bytes.
+///
+/// Java writes modified UTF-8, which differs from UTF-8 only for NUL and
+/// characters outside the Basic Multilingual Plane; those become
+/// replacement characters.
+fn read_string(buf: &mut &[u8]) -> Result<String> {
+    let length = u16::from_be_bytes(take(buf)?) as usize;
+    if length > buf.len() {
+        return Err(unexpected_end());
+    }
+    let (text, rest) = buf.split_at(length);
+    *buf = rest;
+    Ok(String::from_utf8_lossy(text).into_owned())
+}
+
+/// Builds the error for data that ends in the middle of a tag.
+fn unexpected_end() -> Error {
+    invalid_data("data ends in the middle of a tag".to_string())
+}
+
+/// Builds the error for data that isn't valid NBT.
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// A gzip'd `level.dat` as written by 1.21.1, with every tag type.
+    const LEVEL_DAT: &[u8] = include_bytes!("../tests/fixtures/level.dat");
+
+    fn decompressed() -> Vec<u8> {
+        let mut data = Vec::new();
+        GzDecoder::new(LEVEL_DAT).read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn reads_the_level_dat_fixture() {
+        let (name, root) = read(LEVEL_DAT).unwrap();
+        assert_eq!(name, "");
+        let data = root.get("Data").unwrap();
+        assert_eq!(
+            data.get("LevelName").and_then(Tag::as_str),
+            Some("Fixture World")
+        );
+        assert_eq!(
+            data.path(&["WorldGenSettings", "seed"])
+                .and_then(Tag::as_i64),
+            Some(-4172144997902289642)
+        );
+        let spawn: Vec<_> = ["SpawnX", "SpawnY", "SpawnZ"]
+            .iter()
+            .map(|key| data.get(key).and_then(Tag::as_i64))
+            .collect();
+        assert_eq!(spawn, [Some(64), Some(72), Some(-128)]);
+        assert_eq!(data.get("SpawnAngle"), Some(&Tag::Float(90.0)));
+        assert_eq!(data.get("BorderSize"), Some(&Tag::Double(59999968.0)));
+        assert_eq!(
+            data.get("ServerBrands"),
+            Some(&Tag::List(vec![Tag::String("vanilla".to_string())]))
+        );
+        assert_eq!(
+            data.path(&["DataPacks", "Disabled"]),
+            Some(&Tag::List(Vec::new()))
+        );
+        assert_eq!(
+            data.get("WanderingTraderId"),
+            Some(&Tag::IntArray(vec![1, 2, 3, 4]))
+        );
+        assert_eq!(data.get("Seeds"), Some(&Tag::LongArray(vec![1, -1])));
+        assert_eq!(data.get("Flags"), Some(&Tag::ByteArray(vec![1, 0, 1])));
+    }
+
+    #[test]
+    fn reads_uncompressed_data() {
+        assert_eq!(read(&decompressed()).unwrap(), read(LEVEL_DAT).unwrap());
+    }
+
+    #[test]
+    fn truncated_data_is_invalid() {
+        let data = decompressed();
+        for len in 0..data.len() {
+            let e = read(&data[..len]).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "cut at {}", len);
+        }
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&data).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let e = read(&gzip[..gzip.len() / 2]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn unknown_tag_type_is_invalid() {
+        // A root compound holding a tag of type 13, which doesn't exist
+        let data = [10, 0, 0, 13, 0, 1, b'x', 0, 0];
+        let e = read(&data).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("unknown tag type 13"), "{}", e);
+    }
+
+    #[test]
+    fn root_must_be_a_compound() {
+        let e = read(&[8, 0, 0, 0, 0]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn deep_nesting_is_invalid() {
+        // Lists of lists, one level deeper than allowed
+        let mut data = vec![10, 0, 0, 9, 0, 1, b'x'];
+        for _ in 0..MAX_DEPTH {
+            data.extend_from_slice(&[9, 0, 0, 0, 1]);
+        }
+        data.extend_from_slice(&[0, 0, 0, 0, 0]);
+        let e = read(&data).unwrap_err();
+        assert!(e.to_string().contains("nested deeper"), "{}", e);
+    }
+}
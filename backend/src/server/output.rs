@@ -0,0 +1,68 @@
+//! Helpers for reading a command's response from the console output.
+//!
+//! The Minecraft console has no notion of request and response, so these
+//! functions read from an output subscription (see
+//! `MinecraftServer::subscribe_output`) for a bounded amount of time.
+
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::time::{timeout_at, Instant};
+
+/// Collects output lines until the console goes quiet.
+///
+/// Waits up to `timeout` for the first line. After that, reading stops once no
+/// line has arrived for `quiet`, or `max_wait` after the first line, whichever
+/// comes first.
+///
+/// # Arguments
+/// * `rx` - Output subscription created before the command was sent
+/// * `timeout` - How long to wait for the first line
+/// * `quiet` - How long to wait for a further line before considering the output complete
+/// * `max_wait` - Upper bound on the time spent collecting after the first line
+///
+/// # Returns
+/// * `Option<Vec<String>>` - The collected lines, None if nothing arrived within `timeout`
+pub async fn collect_lines(
+    rx: &mut Receiver<String>,
+    timeout: Duration,
+    quiet: Duration,
+    max_wait: Duration,
+) -> Option<Vec<String>> {
+    let first = wait_for_line(rx, |_| true, timeout).await?;
+    let deadline = Instant::now() + max_wait;
+    let mut lines = vec![first];
+    loop {
+        let wait_until = (Instant::now() + quiet).min(deadline);
+        match timeout_at(wait_until, rx.recv()).await {
+            Ok(Ok(line)) => lines.push(line),
+            // Missed some lines because we fell behind; keep reading what's left
+            Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) | Err(_) => break,
+        }
+    }
+    Some(lines)
+}
+
+/// Waits for an output line matching a predicate.
+///
+/// # Arguments
+/// * `rx` - Output subscription created before the command was sent
+/// * `matches` - Predicate identifying the line to wait for
+/// * `timeout` - How long to wait in total
+///
+/// # Returns
+/// * `Option<String>` - The matching line, or None if it didn't appear in time
+pub async fn wait_for_line(
+    rx: &mut Receiver<String>,
+    matches: impl Fn(&str) -> bool,
+    timeout: Duration,
+) -> Option<String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match timeout_at(deadline, rx.recv()).await {
+            Ok(Ok(line)) if matches(&line) => return Some(line),
+            Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) | Err(_) => return None,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Parsing tick rate and memory figures from command output.
+//!
+//! Neither vanilla nor the common server distributions report performance
+//! other than as console text, and every one of them formats it differently.
+//! This file understands the output of:
+//! - Paper/Spigot `tps`: `TPS from last 1m, 5m, 15m: 20.0, 19.98, 19.99`
+//! - Forge `forge tps`: `Overall: Mean tick time: 0.741 ms. Mean TPS: 20.000`
+//! - NeoForge `neoforge tps`: `Overall: 20.000 TPS (0.741 ms/tick)`
+//! - Vanilla 1.20.3+ `tick query`: `Average time per tick: 2.3ms (Target: 50.0ms)`
+//! - EssentialsX `gc`/`lag`: `Maximum memory: 4,096 MB.` and friends
+//!
+//! It also counts the "Can't keep up!" warnings servers print on their own
+//! when they fall behind, an early sign of lag between polls.
+
+use crate::config;
+use crate::server::log_parser::{message_body, strip_color_codes};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Commands tried in order to find one the server understands when no TPS command is configured.
+pub const TPS_COMMANDS: &[&str] = &["neoforge tps", "forge tps", "tps", "tick query"];
+
+/// Window over which overload warnings are counted as recent, and the least
+/// time between two `overloaded` events, so a burst of warnings doesn't flood
+/// clients and webhooks.
+const OVERLOAD_WINDOW: Duration = Duration::from_secs(60);
+
+/// Settings for the periodic performance poll.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PerformanceConfig {
+    /// Command reporting the tick rate. None tries each of [`TPS_COMMANDS`] once.
+    pub tps_command: Option<String>,
+    /// Command reporting memory usage, e.g. `gc` with EssentialsX. None skips memory.
+    pub memory_command: Option<String>,
+    /// Time between polls.
+    #[serde(rename = "interval_secs", deserialize_with = "config::secs")]
+    pub interval: Duration,
+    /// Time between readings of the server process's memory and CPU usage.
+    #[serde(rename = "resource_interval_secs", deserialize_with = "config::secs")]
+    pub resource_interval: Duration,
+}
+
+impl Default for PerformanceConfig {
+    fn default() -> Self {
+        PerformanceConfig {
+            tps_command: None,
+            memory_command: None,
+            interval: Duration::from_secs(30),
+            resource_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl PerformanceConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_TPS_COMMAND` - command reporting the tick rate
+    /// * `MC_MEMORY_COMMAND` - command reporting memory usage
+    /// * `MC_PERFORMANCE_INTERVAL_SECS` - seconds between polls
+    /// * `MC_RESOURCE_INTERVAL_SECS` - seconds between process resource readings
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(command) = config::env_string("MC_TPS_COMMAND") {
+            self.tps_command = Some(command);
+        }
+        if let Some(command) = config::env_string("MC_MEMORY_COMMAND") {
+            self.memory_command = Some(command);
+        }
+        if let Some(interval) = config::env_secs("MC_PERFORMANCE_INTERVAL_SECS")? {
+            self.interval = interval;
+        }
+        if let Some(interval) = config::env_secs("MC_RESOURCE_INTERVAL_SECS")? {
+            self.resource_interval = interval;
+        }
+        if self.interval.is_zero() {
+            return Err("performance interval must be greater than zero".to_string());
+        }
+        if self.resource_interval.is_zero() {
+            return Err("resource interval must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Latest performance figures reported by the server. Fields the server
+/// doesn't report stay None.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PerformanceStats {
+    /// Ticks per second, 20 at full speed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tps: Option<f64>,
+    /// Average time spent per tick in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_tick_ms: Option<f64>,
+    /// Heap in use in megabytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_used_mb: Option<f64>,
+    /// Maximum heap size in megabytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_max_mb: Option<f64>,
+    /// When any of the values above was last refreshed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Local>>,
+}
+
+impl PerformanceStats {
+    /// Returns true if nothing has been reported yet.
+    pub fn is_empty(&self) -> bool {
+        self.updated_at.is_none()
+    }
+}
+
+/// Counts a server's "Can't keep up!" warnings.
+///
+/// Every warning is counted, but only the first of a burst is announced;
+/// warnings within [`OVERLOAD_WINDOW`] of the last announced one are held
+/// back and reported with the next announced one.
+#[derive(Debug, Default)]
+pub struct OverloadStats {
+    /// Warnings since the backend started.
+    warnings: u64,
+    last_warning_at: Option<DateTime<Local>>,
+    /// How far behind the server said it was in the last warning.
+    last_behind_ms: Option<u64>,
+    /// When the warnings within the last window came.
+    recent: VecDeque<Instant>,
+    /// When the last warning was announced.
+    announced: Option<Instant>,
+    /// Warnings since the last announced one that weren't announced.
+    held_back: u64,
+}
+
+/// The overload warnings counted for a server, as shown by `/status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct OverloadSummary {
+    /// Warnings since the backend started.
+    pub warnings: u64,
+    /// Warnings within the last minute.
+    pub warnings_last_minute: usize,
+    /// When the last warning came; absent if there was none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_warning_at: Option<DateTime<Local>>,
+    /// How far behind the server said it was in the last warning, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_behind_ms: Option<u64>,
+}
+
+impl OverloadStats {
+    /// Counts a warning.
+    ///
+    /// # Arguments
+    /// * `behind_ms` - How far behind the server said it was
+    ///
+    /// # Returns
+    /// * `Option<u64>` - The number of warnings held back since the last announced
+    ///   one if this one should be announced, None if it is held back
+    pub fn record(&mut self, behind_ms: Option<u64>) -> Option<u64> {
+        let now = Instant::now();
+        self.warnings += 1;
+        self.last_warning_at = Some(Local::now());
+        self.last_behind_ms = behind_ms;
+        while self
+            .recent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= OVERLOAD_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+        if self
+            .announced
+            .is_some_and(|at| now.duration_since(at) < OVERLOAD_WINDOW)
+        {
+            self.held_back += 1;
+            return None;
+        }
+        self.announced = Some(now);
+        Some(std::mem::take(&mut self.held_back))
+    }
+
+    /// Returns the number of warnings since the backend started.
+    pub fn warnings(&self) -> u64 {
+        self.warnings
+    }
+
+    /// Returns when the last warning came.
+    pub fn last_warning_at(&self) -> Option<DateTime<Local>> {
+        self.last_warning_at
+    }
+
+    /// Summarizes the warnings counted so far.
+    pub fn summary(&self) -> OverloadSummary {
+        OverloadSummary {
+            warnings: self.warnings,
+            warnings_last_minute: self
+                .recent
+                .iter()
+                .filter(|at| at.elapsed() < OVERLOAD_WINDOW)
+                .count(),
+            last_warning_at: self.last_warning_at,
+            last_behind_ms: self.last_behind_ms,
+        }
+    }
+}
+
+/// Values recognized in one command's output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PerformanceReading {
+    /// Ticks per second.
+    pub tps: Option<f64>,
+    /// Average tick time in milliseconds.
+    pub mean_tick_ms: Option<f64>,
+    /// Maximum heap size in megabytes.
+    pub memory_max_mb: Option<f64>,
+    /// Heap currently reserved by the JVM in megabytes.
+    pub memory_allocated_mb: Option<f64>,
+    /// Unused part of the reserved heap in megabytes.
+    pub memory_free_mb: Option<f64>,
+}
+
+impl PerformanceReading {
+    /// Parses the output lines of a TPS or memory command.
+    ///
+    /// Only the first (overall) figure of each kind is used; Forge follows the
+    /// overall line with one line per dimension.
+    pub fn parse(lines: &[String]) -> Self {
+        let mut reading = PerformanceReading::default();
+        let mut target_tick_ms = None;
+        for line in lines {
+            let text = strip_color_codes(message_body(line)).replace('*', "");
+            let text = text.trim();
+
+            if let Some(rest) = text.strip_prefix("TPS from last 1m, 5m, 15m:") {
+                reading.tps = reading.tps.or_else(|| first_number(rest));
+            } else if text.starts_with("Overall") {
+                if let Some(rest) = after(text, "Mean TPS:") {
+                    // Forge
+                    reading.tps = reading.tps.or_else(|| first_number(rest));
+                    reading.mean_tick_ms = reading
+                        .mean_tick_ms
+                        .or_else(|| after(text, "Mean tick time:").and_then(first_number));
+                } else if text.contains(" TPS (") {
+                    // NeoForge
+                    let rest = text.split_once(':').map_or(text, |(_, rest)| rest);
+                    reading.tps = reading.tps.or_else(|| first_number(rest));
+                    reading.mean_tick_ms = reading
+                        .mean_tick_ms
+                        .or_else(|| after(text, " TPS (").and_then(first_number));
+                }
+            } else if let Some(rest) = text.strip_prefix("Average time per tick:") {
+                // Vanilla `tick query`
+                reading.mean_tick_ms = first_number(rest);
+                target_tick_ms = after(rest, "Target:").and_then(first_number);
+            } else if let Some(rest) = text.strip_prefix("Maximum memory:") {
+                reading.memory_max_mb = first_number(rest);
+            } else if let Some(rest) = text.strip_prefix("Allocated memory:") {
+                reading.memory_allocated_mb = first_number(rest);
+            } else if let Some(rest) = text.strip_prefix("Free memory:") {
+                reading.memory_free_mb = first_number(rest);
+            }
+        }
+
+        // Vanilla only reports tick time; the server can't run faster than its target rate
+        if reading.tps.is_none() {
+            if let (Some(mspt), Some(target)) = (reading.mean_tick_ms, target_tick_ms) {
+                if mspt > 0.0 && target > 0.0 {
+                    reading.tps = Some((1000.0 / mspt).min(1000.0 / target));
+                }
+            }
+        }
+        reading
+    }
+
+    /// Returns true if the output contained a tick rate.
+    pub fn has_tps(&self) -> bool {
+        self.tps.is_some()
+    }
+
+    /// Returns true if the output contained memory figures.
+    pub fn has_memory(&self) -> bool {
+        self.memory_max_mb.is_some() || self.memory_allocated_mb.is_some()
+    }
+
+    /// Copies the recognized values into `stats`, leaving the others as they were.
+    pub fn apply_to(&self, stats: &mut PerformanceStats) {
+        if self.has_tps() {
+            stats.tps = self.tps;
+            stats.mean_tick_ms = self.mean_tick_ms;
+        }
+        if self.has_memory() {
+            stats.memory_max_mb = self.memory_max_mb;
+            stats.memory_used_mb = self
+                .memory_allocated_mb
+                .zip(self.memory_free_mb)
+                .map(|(allocated, free)| allocated - free);
+        }
+        if self.has_tps() || self.has_memory() {
+            stats.updated_at = Some(Local::now());
+        }
+    }
+}
+
+/// Returns the text following `marker`, if present.
+fn after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    text.find(marker).map(|index| &text[index + marker.len()..])
+}
+
+/// Parses the first number in `text`, ignoring thousands separators.
+fn first_number(text: &str) -> Option<f64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let number: String = text[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .filter(|c| *c != ',')
+        .collect();
+    number.trim_end_matches('.').parse().ok()
+}
@@ -0,0 +1,346 @@
+//! Client for the Server List Ping protocol, as used by the multiplayer screen.
+//!
+//! Any Minecraft server since 1.7 answers a status request with its version,
+//! player counts and MOTD as JSON, whether or not this backend manages it.
+//! A ping packet sent afterwards measures the round trip.
+//!
+//! Packets are framed by a VarInt length followed by a VarInt packet id. A
+//! VarInt stores 7 bits per byte, least significant group first, with the high
+//! bit set on every byte but the last.
+
+use crate::error::ServerError;
+use crate::server::log_parser::strip_color_codes;
+use crate::server::properties::ServerProperties;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Port Minecraft listens on unless `server-port` says otherwise.
+pub const DEFAULT_PORT: u16 = 25565;
+/// Protocol version sent in the handshake. Servers answer status requests
+/// whatever the version, and -1 is the convention for "not a real client".
+const PROTOCOL_VERSION: i32 = -1;
+/// Handshake state that asks for the status instead of logging in.
+const NEXT_STATE_STATUS: i32 = 1;
+/// Packet id of the handshake, the status request and the status response.
+const PACKET_STATUS: i32 = 0x00;
+/// Packet id of the ping and pong packets.
+const PACKET_PING: i32 = 0x01;
+/// Largest packet accepted; the status JSON is at most 32767 characters plus
+/// the favicon, which is well below this.
+const MAX_PACKET_LEN: i32 = 1 << 21;
+/// First byte of a pre-1.7 server's kick packet, which it sends in reply to
+/// a handshake it doesn't understand.
+const LEGACY_KICK: u8 = 0xFF;
+
+/// What a server reports about itself in the multiplayer screen.
+#[derive(Debug, Clone, Serialize)]
+pub struct PingResponse {
+    /// Version name, e.g. `1.21.1` or `Paper 1.21.1`.
+    pub version: String,
+    /// Protocol number of that version.
+    pub protocol: i32,
+    /// Players currently online.
+    pub players_online: u32,
+    /// Maximum number of players.
+    pub players_max: u32,
+    /// Message of the day without formatting codes.
+    pub motd: String,
+    /// Round trip of the ping packet in milliseconds.
+    pub latency_ms: u64,
+}
+
+/// The status JSON, limited to the fields we report.
+#[derive(Deserialize)]
+struct Status {
+    version: StatusVersion,
+    #[serde(default)]
+    players: StatusPlayers,
+    #[serde(default)]
+    description: Value,
+}
+
+#[derive(Deserialize)]
+struct StatusVersion {
+    name: String,
+    protocol: i32,
+}
+
+#[derive(Default, Deserialize)]
+struct StatusPlayers {
+    online: u32,
+    max: u32,
+}
+
+/// Returns the port a managed server listens on, from `server-port` in its
+/// `server.properties`.
+pub fn server_port(server_dir: &Path) -> u16 {
+    ServerProperties::load(server_dir)
+        .ok()
+        .and_then(|props| props.get("server-port").and_then(|port| port.parse().ok()))
+        .unwrap_or(DEFAULT_PORT)
+}
+
+/// Asks a server for its status and measures the latency.
+///
+/// # Arguments
+/// * `host` - Host name or address of the server
+/// * `port` - Port the server listens on
+/// * `limit` - How long to wait for the connection and for each response
+///
+/// # Returns
+/// * `Result<PingResponse, ServerError>` - UnsupportedProtocol for servers older
+///   than 1.7, or PingFailed
+pub async fn ping(
+    host: &str,
+    port: u16,
+    limit: Duration,
+) -> std::result::Result<PingResponse, ServerError> {
+    let mut stream = timeout(limit, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "connection timed out"))
+        .and_then(|connected| connected)
+        .map_err(|source| {
+            ServerError::network("Server list ping", source, |source| {
+                ServerError::PingFailed { source }
+            })
+        })?;
+
+    timeout(limit, exchange(&mut stream, host, port))
+        .await
+        .unwrap_or_else(|_| Err(Error::new(ErrorKind::TimedOut, "no response from server")))
+        .map_err(|source| {
+            if source.kind() == ErrorKind::Unsupported {
+                ServerError::UnsupportedProtocol
+            } else {
+                ServerError::network("Server list ping", source, |source| {
+                    ServerError::PingFailed { source }
+                })
+            }
+        })
+}
+
+/// Runs the handshake, status request and ping on a connected stream.
+async fn exchange(stream: &mut TcpStream, host: &str, port: u16) -> Result<PingResponse> {
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, PROTOCOL_VERSION);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, NEXT_STATE_STATUS);
+    send(stream, PACKET_STATUS, &handshake).await?;
+    send(stream, PACKET_STATUS, &[]).await?;
+
+    // Old servers kick the unknown handshake instead of answering it
+    let first = stream.read_u8().await?;
+    if first == LEGACY_KICK {
+        return Err(Error::new(ErrorKind::Unsupported, "legacy server"));
+    }
+    let (id, body) = receive(stream, Some(first)).await?;
+    if id != PACKET_STATUS {
+        return Err(invalid_data(format!("unexpected packet id {:#04x}", id)));
+    }
+    let mut body = body.as_slice();
+    let json = read_string(&mut body)?;
+    let status: Status = serde_json::from_str(&json)
+        .map_err(|e| invalid_data(format!("invalid status JSON: {}", e)))?;
+
+    let payload = chrono::Utc::now().timestamp_millis();
+    let sent = Instant::now();
+    send(stream, PACKET_PING, &payload.to_be_bytes()).await?;
+    let (id, body) = receive(stream, None).await?;
+    let latency = sent.elapsed();
+    if id != PACKET_PING || body != payload.to_be_bytes() {
+        return Err(invalid_data("pong didn't match the ping".to_string()));
+    }
+
+    Ok(PingResponse {
+        version: strip_color_codes(&status.version.name),
+        protocol: status.version.protocol,
+        players_online: status.players.online,
+        players_max: status.players.max,
+        motd: strip_color_codes(&chat_text(&status.description)),
+        latency_ms: latency.as_millis() as u64,
+    })
+}
+
+/// Flattens a chat component (a string, or an object with `text` and `extra`)
+/// into plain text.
+fn chat_text(component: &Value) -> String {
+    match component {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(chat_text).collect(),
+        Value::Object(fields) => {
+            let mut text = fields.get("text").map(chat_text).unwrap_or_default();
+            if let Some(extra) = fields.get("extra") {
+                text.push_str(&chat_text(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+/// Writes one packet: its length, id and body.
+async fn send(stream: &mut TcpStream, id: i32, body: &[u8]) -> Result<()> {
+    let mut payload = Vec::with_capacity(body.len() + 5);
+    write_varint(&mut payload, id);
+    payload.extend_from_slice(body);
+    let mut packet = Vec::with_capacity(payload.len() + 5);
+    write_varint(&mut packet, payload.len() as i32);
+    packet.extend_from_slice(&payload);
+    stream.write_all(&packet).await
+}
+
+/// Reads one packet.
+///
+/// # Arguments
+/// * `first` - First byte of the length, if it has already been read
+///
+/// # Returns
+/// * `Result<(i32, Vec<u8>)>` - Packet id and body
+async fn receive(stream: &mut TcpStream, mut first: Option<u8>) -> Result<(i32, Vec<u8>)> {
+    let mut length = None;
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = match first.take() {
+            Some(byte) => byte,
+            None => stream.read_u8().await?,
+        };
+        value |= ((byte & 0x7F) as u32) << shift;
+        if byte & 0x80 == 0 {
+            length = Some(value as i32);
+            break;
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("VarInt is too long".to_string()))?;
+    if !(1..=MAX_PACKET_LEN).contains(&length) {
+        return Err(invalid_data(format!("invalid packet length {}", length)));
+    }
+    let mut packet = vec![0; length as usize];
+    stream.read_exact(&mut packet).await?;
+    let mut body = packet.as_slice();
+    let id = read_varint(&mut body)?;
+    Ok((id, body.to_vec()))
+}
+
+/// Appends a VarInt.
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    // Negative values are sent as their two's complement, always five bytes
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+/// Reads a VarInt from the front of a buffer, advancing past it.
+fn read_varint(buf: &mut &[u8]) -> Result<i32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or_else(|| invalid_data("packet ended inside a VarInt".to_string()))?;
+        *buf = rest;
+        value |= ((byte & 0x7F) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(invalid_data("VarInt is too long".to_string()))
+}
+
+/// Appends a string prefixed by its length in bytes.
+fn write_string(buf: &mut Vec<u8>, text: &str) {
+    write_varint(buf, text.len() as i32);
+    buf.extend_from_slice(text.as_bytes());
+}
+
+/// Reads a length-prefixed string from the front of a buffer.
+fn read_string(buf: &mut &[u8]) -> Result<String> {
+    let length = read_varint(buf)?;
+    let length = usize::try_from(length)
+        .ok()
+        .filter(|&length| length <= buf.len())
+        .ok_or_else(|| invalid_data(format!("invalid string length {}", length)))?;
+    let (text, rest) = buf.split_at(length);
+    *buf = rest;
+    String::from_utf8(text.to_vec()).map_err(|e| invalid_data(e.to_string()))
+}
+
+/// Builds the error for a malformed response.
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: i32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn varint_boundary_values() {
+        let cases: [(i32, &[u8]); 8] = [
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (255, &[0xFF, 0x01]),
+            (16_383, &[0xFF, 0x7F]),
+            (2_097_151, &[0xFF, 0xFF, 0x7F]),
+            (i32::MAX, &[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(encode(value), bytes, "encoding {}", value);
+            let mut buf = bytes;
+            assert_eq!(read_varint(&mut buf).unwrap(), value);
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn negative_varints_take_five_bytes() {
+        assert_eq!(encode(-1), [0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(encode(i32::MIN), [0x80, 0x80, 0x80, 0x80, 0x08]);
+        for value in [-1, -128, i32::MIN] {
+            let bytes = encode(value);
+            assert_eq!(read_varint(&mut bytes.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn read_varint_leaves_the_rest_of_the_buffer() {
+        let mut buf: &[u8] = &[0xAC, 0x02, 0x05, 0x42];
+        assert_eq!(read_varint(&mut buf).unwrap(), 300);
+        assert_eq!(read_varint(&mut buf).unwrap(), 5);
+        assert_eq!(buf, [0x42]);
+    }
+
+    #[test]
+    fn overlong_varint_is_rejected() {
+        let mut buf: &[u8] = &[0xFF, 0xFF, 0xFF, 0xFF, 0x8F, 0x01];
+        let err = read_varint(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "VarInt is too long");
+    }
+
+    #[test]
+    fn truncated_varint_is_rejected() {
+        let mut buf: &[u8] = &[0x80, 0x80];
+        let err = read_varint(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), "packet ended inside a VarInt");
+    }
+}
@@ -0,0 +1,139 @@
+//! Checking that the ports a server listens on are free before starting it.
+//!
+//! A server whose port is still held, e.g. by a JVM that hasn't finished
+//! shutting down, loads its world and only then fails with "FAILED TO BIND TO
+//! PORT". Binding each port briefly beforehand finds that out in an instant.
+//! On Linux the process holding the port is looked up in `/proc` so the
+//! error can name it.
+
+use crate::error::ServerError;
+use crate::server::properties::ServerProperties;
+use crate::server::{ping, query, rcon};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, TcpListener, UdpSocket};
+use std::path::Path;
+
+/// A port the server will listen on.
+#[derive(Debug, Clone, Copy)]
+struct ServerPort {
+    port: u16,
+    /// Whether the port is UDP (query) rather than TCP.
+    udp: bool,
+    /// What the port is for, e.g. `rcon`.
+    purpose: &'static str,
+}
+
+/// Lists the ports the server will listen on, from its `server.properties`:
+/// `server-port`, plus `query.port` and `rcon.port` when those are enabled.
+fn server_ports(props: &ServerProperties) -> Vec<ServerPort> {
+    let port = |key: &str| props.get(key).and_then(|port| port.parse().ok());
+    let server_port = port("server-port").unwrap_or(ping::DEFAULT_PORT);
+    let mut ports = vec![ServerPort {
+        port: server_port,
+        udp: false,
+        purpose: "server",
+    }];
+    if props.get("enable-query") == Some("true") {
+        ports.push(ServerPort {
+            port: port("query.port").unwrap_or(query::DEFAULT_PORT),
+            udp: true,
+            purpose: "query",
+        });
+    }
+    if props.get("enable-rcon") == Some("true") {
+        ports.push(ServerPort {
+            port: port("rcon.port").unwrap_or(rcon::DEFAULT_PORT),
+            udp: false,
+            purpose: "rcon",
+        });
+    }
+    ports
+}
+
+/// Checks that the ports the server will listen on are free.
+///
+/// # Arguments
+/// * `server_dir` - Directory with the server's `server.properties`
+///
+/// # Returns
+/// * `Result<(), ServerError>` - PortInUse for the first port that is taken
+pub fn check(server_dir: &Path) -> Result<(), ServerError> {
+    let props = ServerProperties::load(server_dir).unwrap_or_default();
+    // The server binds server-ip, or every address if it's empty
+    let ip = props
+        .get("server-ip")
+        .and_then(|ip| ip.parse().ok())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    for port in server_ports(&props) {
+        let bound = if port.udp {
+            UdpSocket::bind((ip, port.port)).map(drop)
+        } else {
+            TcpListener::bind((ip, port.port)).map(drop)
+        };
+        if bound.is_err_and(|e| e.kind() == ErrorKind::AddrInUse) {
+            return Err(ServerError::PortInUse {
+                port: port.port,
+                purpose: port.purpose,
+                pid: owner_pid(port.port, port.udp),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Finds the process with a socket bound to a port, from the socket tables
+/// and file descriptors in `/proc`. Only processes whose descriptors the
+/// backend may read are found.
+#[cfg(target_os = "linux")]
+fn owner_pid(port: u16, udp: bool) -> Option<u32> {
+    let tables: &[&str] = if udp {
+        &["/proc/net/udp", "/proc/net/udp6"]
+    } else {
+        &["/proc/net/tcp", "/proc/net/tcp6"]
+    };
+    // Each line is "sl local_address rem_address st ... inode", with the
+    // local address as hex "ADDR:PORT"; TCP listeners have state 0A
+    let inodes: Vec<String> = tables
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(table).ok())
+        .flat_map(|contents| {
+            contents
+                .lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let fields: Vec<&str> = line.split_whitespace().collect();
+                    let local_port = fields.get(1)?.rsplit(':').next()?;
+                    let listening = udp || fields.get(3) == Some(&"0A");
+                    if u16::from_str_radix(local_port, 16).ok()? != port || !listening {
+                        return None;
+                    }
+                    Some(format!("socket:[{}]", fields.get(9)?))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    if inodes.is_empty() {
+        return None;
+    }
+    std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .find_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let fds = std::fs::read_dir(entry.path().join("fd")).ok()?;
+            fds.flatten()
+                .filter_map(|fd| std::fs::read_link(fd.path()).ok())
+                .any(|link| {
+                    inodes
+                        .iter()
+                        .any(|inode| link.as_os_str() == inode.as_str())
+                })
+                .then_some(pid)
+        })
+}
+
+/// Looking up the owner of a port is only implemented for Linux.
+#[cfg(not(target_os = "linux"))]
+fn owner_pid(_port: u16, _udp: bool) -> Option<u32> {
+    None
+}
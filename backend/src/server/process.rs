@@ -0,0 +1,247 @@
+//! The running server as the app state sees it, and what runs it.
+//!
+//! [`ServerProcess`] is what a [`ManagedServer`](crate::state::ManagedServer)
+//! holds while its server runs: it is started, stopped and sent commands
+//! through this trait. [`MinecraftServer`](super::MinecraftServer) is the real implementation; the
+//! tests put a mock in its place to drive the handlers without a process.
+//!
+//! Below that, [`ProcessHandle`] is the part that differs between a real
+//! server and the simulated one of a dry run: starting it, waiting for it to
+//! exit and ending it. Everything above that (writing queued commands to the
+//! console, reading and parsing its output, escalating a stop) works on the
+//! console pipes a process hands out when it starts, so it is the same for
+//! both. [`ChildProcess`] runs the launch spec as an OS process;
+//! [`SimulatedProcess`] plays a server in a task, for dry runs.
+
+use crate::error::ServerError;
+use crate::metrics::Metrics;
+use crate::server::simulated::SimulatedProcess;
+use crate::server::{CommandSender, LaunchSpec, ServerConfig, StopOutcome};
+use crate::state::LogSender;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::io::Result;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::process::Child;
+use tokio::sync::broadcast;
+
+/// Console input of a server, written by the command writer task.
+pub type ConsoleInput = Box<dyn AsyncWrite + Unpin + Send>;
+/// Console output of a server, read by an output reader task.
+pub type ConsoleOutput = Box<dyn AsyncRead + Unpin + Send>;
+
+/// The pipes to a server's console, each None if the server doesn't have it.
+pub struct ConsolePipes {
+    pub stdin: Option<ConsoleInput>,
+    pub stdout: Option<ConsoleOutput>,
+    pub stderr: Option<ConsoleOutput>,
+}
+
+/// A running server: started, stopped and sent commands by the app state.
+pub trait ServerProcess: Send + Sync {
+    /// Starts the server.
+    ///
+    /// # Arguments
+    /// * `launch` - What to run and where
+    /// * `log_sender` - Channel sender to forward log messages
+    /// * `config` - Settings for managing the process
+    /// * `metrics` - Shared counters to update
+    ///
+    /// # Returns
+    /// * `Result<Self, ServerError>` - The running server, or SpawnFailed
+    fn start(
+        launch: LaunchSpec,
+        log_sender: LogSender,
+        config: ServerConfig,
+        metrics: Arc<Metrics>,
+    ) -> BoxFuture<'static, std::result::Result<Self, ServerError>>
+    where
+        Self: Sized;
+
+    /// Stops the server.
+    ///
+    /// # Arguments
+    /// * `stop_sent` - Whether the stop command was already sent another way,
+    ///   e.g. over RCON, so only the wait for the process to exit is left
+    ///
+    /// # Returns
+    /// * `Result<Option<StopOutcome>, ServerError>` - How the server ended, None
+    ///   if it wasn't running, or Io if waiting for or killing it failed
+    fn stop(
+        &mut self,
+        stop_sent: bool,
+    ) -> BoxFuture<'_, std::result::Result<Option<StopOutcome>, ServerError>>;
+
+    /// Sends a command to the server console, waiting until it has been written.
+    ///
+    /// This holds on to the server for the whole write; a caller holding the
+    /// state lock should take a [`ServerProcess::command_sender`] instead and
+    /// send once the lock is released.
+    ///
+    /// # Returns
+    /// * `Result<(), ServerError>` - See [`CommandSender::send`]
+    fn send_command<'a>(
+        &'a self,
+        command: &'a str,
+    ) -> BoxFuture<'a, std::result::Result<(), ServerError>> {
+        async move { self.command_sender()?.send(command).await }.boxed()
+    }
+
+    /// Returns true if the server is running.
+    fn is_running(&self) -> bool;
+
+    /// Returns a handle for sending commands to the server console, usable
+    /// after the caller has let go of the server.
+    ///
+    /// # Returns
+    /// * `Result<CommandSender, ServerError>` - NotRunning or StdinUnavailable on error
+    fn command_sender(&self) -> std::result::Result<CommandSender, ServerError>;
+
+    /// Checks whether the server has exited on its own, without blocking.
+    ///
+    /// # Returns
+    /// * `Option<ExitStatus>` - The exit status if it has exited since the last check
+    fn try_wait(&mut self) -> Option<ExitStatus>;
+
+    /// Returns true if commands can be written to the server's console, false
+    /// if it isn't running or its console input is closed.
+    fn console_available(&self) -> bool;
+
+    /// Returns the OS process ID of the server, if it is running and not simulated.
+    fn pid(&self) -> Option<u32>;
+
+    /// Returns the script, executable or jar the server was started from.
+    fn path(&self) -> &Path;
+
+    /// Returns the directory the server was launched in.
+    fn working_dir(&self) -> &Path;
+
+    /// Subscribes to the server's console output from this point on.
+    ///
+    /// Used to capture the response to a command: subscribe first, then send
+    /// the command, then read lines from the returned receiver.
+    fn subscribe_output(&self) -> broadcast::Receiver<String>;
+}
+
+/// A started process behind a [`MinecraftServer`](super::MinecraftServer),
+/// which can be waited for and ended.
+pub trait ProcessHandle: Send + Sync {
+    /// Starts the process.
+    ///
+    /// # Returns
+    /// * `Result<(Self, ConsolePipes), ServerError>` - The process and its
+    ///   console, or SpawnFailed
+    fn start(
+        launch: &LaunchSpec,
+        config: &ServerConfig,
+    ) -> std::result::Result<(Self, ConsolePipes), ServerError>
+    where
+        Self: Sized;
+
+    /// Returns the OS process ID, None for a simulated server or once the process exited.
+    fn id(&self) -> Option<u32>;
+
+    /// Returns the exit status if the server has exited, without blocking.
+    fn try_wait(&mut self) -> Result<Option<ExitStatus>>;
+
+    /// Waits for the server to exit.
+    fn wait(&mut self) -> BoxFuture<'_, Result<ExitStatus>>;
+
+    /// Asks the server to shut down without its console, i.e. sends SIGTERM.
+    ///
+    /// # Returns
+    /// * `Result<bool>` - False if the server can't be asked, so it has to be killed
+    fn terminate(&mut self) -> Result<bool>;
+
+    /// Kills the server and waits for it to exit.
+    fn kill(&mut self) -> BoxFuture<'_, Result<()>>;
+}
+
+/// Starts the process for a launch spec: the simulated server in a dry run,
+/// otherwise the real one.
+pub fn start(
+    launch: &LaunchSpec,
+    config: &ServerConfig,
+) -> std::result::Result<(Box<dyn ProcessHandle>, ConsolePipes), ServerError> {
+    if launch.dry_run {
+        let (process, pipes) = SimulatedProcess::start(launch, config)?;
+        Ok((Box::new(process), pipes))
+    } else {
+        let (process, pipes) = ChildProcess::start(launch, config)?;
+        Ok((Box::new(process), pipes))
+    }
+}
+
+/// A server running as a child process of the backend.
+pub struct ChildProcess {
+    child: Child,
+}
+
+impl ProcessHandle for ChildProcess {
+    fn start(
+        launch: &LaunchSpec,
+        _config: &ServerConfig,
+    ) -> std::result::Result<(Self, ConsolePipes), ServerError> {
+        let mut command = launch.command();
+
+        // Configure process I/O streams
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        // Spawn the server process
+        let mut child = command
+            .spawn()
+            .map_err(|source| ServerError::SpawnFailed { source })?;
+        let pipes = ConsolePipes {
+            stdin: child
+                .stdin
+                .take()
+                .map(|stdin| Box::new(stdin) as ConsoleInput),
+            stdout: child
+                .stdout
+                .take()
+                .map(|out| Box::new(out) as ConsoleOutput),
+            stderr: child
+                .stderr
+                .take()
+                .map(|err| Box::new(err) as ConsoleOutput),
+        };
+        Ok((ChildProcess { child }, pipes))
+    }
+
+    fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+
+    fn wait(&mut self) -> BoxFuture<'_, Result<ExitStatus>> {
+        self.child.wait().boxed()
+    }
+
+    /// Sends SIGTERM, which makes the JVM run its shutdown hooks and so save
+    /// the world. Not on Windows, which can only send a console's Ctrl+C to
+    /// every process attached to it, which would include the backend itself.
+    fn terminate(&mut self) -> Result<bool> {
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id() {
+            use nix::sys::signal::{kill, Signal};
+            use nix::unistd::Pid;
+            kill(Pid::from_raw(pid as i32), Signal::SIGTERM)
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn kill(&mut self) -> BoxFuture<'_, Result<()>> {
+        self.child.kill().boxed()
+    }
+}
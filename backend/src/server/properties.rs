@@ -0,0 +1,380 @@
+//! Reading and writing `server.properties`.
+//!
+//! This file parses the server's properties file into an ordered list of
+//! lines so that comments, blank lines and key order survive a round trip,
+//! and validates values for the keys whose type we know.
+//!
+//! Values are kept as they are written in the file. The server writes them
+//! with Java's escaping, e.g. `\u00A7` for `§` and `\n` for a line break;
+//! [`unescape`] and [`escape`] convert between that and the actual text.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// File name of the properties file inside the server directory.
+pub const PROPERTIES_FILE: &str = "server.properties";
+
+/// Keys that only accept `true` or `false`.
+const BOOLEAN_KEYS: &[&str] = &[
+    "allow-flight",
+    "allow-nether",
+    "broadcast-console-to-ops",
+    "broadcast-rcon-to-ops",
+    "enable-command-block",
+    "enable-jmx-monitoring",
+    "enable-query",
+    "enable-rcon",
+    "enable-status",
+    "enforce-secure-profile",
+    "enforce-whitelist",
+    "force-gamemode",
+    "generate-structures",
+    "hardcore",
+    "hide-online-players",
+    "log-ips",
+    "online-mode",
+    "prevent-proxy-connections",
+    "pvp",
+    "require-resource-pack",
+    "spawn-monsters",
+    "sync-chunk-writes",
+    "use-native-transport",
+    "white-list",
+];
+
+/// Keys that only accept integers, with their inclusive bounds.
+const INTEGER_KEYS: &[(&str, i64, i64)] = &[
+    ("entity-broadcast-range-percentage", 10, 1000),
+    ("function-permission-level", 1, 4),
+    ("max-chained-neighbor-updates", i64::MIN, i64::MAX),
+    ("max-players", 0, i32::MAX as i64),
+    ("max-tick-time", -1, i64::MAX),
+    ("max-world-size", 1, 29_999_984),
+    ("network-compression-threshold", -1, i32::MAX as i64),
+    ("op-permission-level", 0, 4),
+    ("player-idle-timeout", 0, i32::MAX as i64),
+    ("query.port", 1, 65535),
+    ("rate-limit", 0, i32::MAX as i64),
+    ("rcon.port", 1, 65535),
+    ("server-port", 1, 65535),
+    ("simulation-distance", 3, 32),
+    ("spawn-protection", 0, i32::MAX as i64),
+    ("view-distance", 3, 32),
+];
+
+/// Keys that only accept one of a fixed set of values.
+const ENUM_KEYS: &[(&str, &[&str])] = &[
+    ("difficulty", &["peaceful", "easy", "normal", "hard"]),
+    (
+        "gamemode",
+        &["survival", "creative", "adventure", "spectator"],
+    ),
+];
+
+/// A single line of the properties file.
+#[derive(Debug, Clone)]
+enum Line {
+    /// A comment or blank line, kept verbatim.
+    Other(String),
+    /// A `key=value` entry.
+    Entry { key: String, value: String },
+}
+
+/// The parsed contents of `server.properties`.
+#[derive(Debug, Clone, Default)]
+pub struct ServerProperties {
+    lines: Vec<Line>,
+}
+
+impl ServerProperties {
+    /// Parses the contents of a properties file.
+    pub fn parse(contents: &str) -> Self {
+        let lines = contents
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!') {
+                    return Line::Other(line.to_string());
+                }
+                match trimmed.find(['=', ':']) {
+                    Some(index) => Line::Entry {
+                        key: trimmed[..index].trim().to_string(),
+                        value: trimmed[index + 1..].trim_start().to_string(),
+                    },
+                    None => Line::Entry {
+                        key: trimmed.trim().to_string(),
+                        value: String::new(),
+                    },
+                }
+            })
+            .collect();
+        ServerProperties { lines }
+    }
+
+    /// Reads `server.properties` from the server directory.
+    pub fn load(server_dir: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(server_dir.join(PROPERTIES_FILE))?;
+        Ok(Self::parse(&contents))
+    }
+
+    /// Writes the properties back to the server directory atomically.
+    ///
+    /// The new contents are written to a temporary file next to the original
+    /// which is then renamed over it, so the server never sees a half-written file.
+    pub fn save(&self, server_dir: &Path) -> Result<()> {
+        let path = server_dir.join(PROPERTIES_FILE);
+        let temp_path = server_dir.join(format!("{}.tmp", PROPERTIES_FILE));
+        fs::write(&temp_path, self.to_string())?;
+        fs::rename(&temp_path, &path)
+    }
+
+    /// Returns the value of a key, if present.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Sets a key, appending it to the end of the file if it doesn't exist yet.
+    ///
+    /// # Returns
+    /// * `bool` - True if the stored value changed
+    pub fn set(&mut self, key: &str, value: &str) -> bool {
+        for line in &mut self.lines {
+            if let Line::Entry { key: k, value: v } = line {
+                if k == key {
+                    if v == value {
+                        return false;
+                    }
+                    *v = value.to_string();
+                    return true;
+                }
+            }
+        }
+        self.lines.push(Line::Entry {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+        true
+    }
+
+    /// Iterates over all `(key, value)` pairs in file order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value } => Some((key.as_str(), value.as_str())),
+            Line::Other(_) => None,
+        })
+    }
+}
+
+impl std::fmt::Display for ServerProperties {
+    /// Renders the properties in file format, one line per entry or comment.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Other(text) => writeln!(f, "{}", text)?,
+                Line::Entry { key, value } => writeln!(f, "{}={}", key, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a value is acceptable for a key.
+///
+/// Only keys with a known type are checked; unknown keys (e.g. ones added by
+/// mods) accept any value.
+///
+/// # Returns
+/// * `Result<()>` - InvalidInput error describing the problem if the value is rejected
+pub fn validate(key: &str, value: &str) -> Result<()> {
+    if key.is_empty() || key.contains(['=', ':', '#', '!']) || key.contains(char::is_whitespace) {
+        return Err(invalid(format!("'{}' is not a valid property key", key)));
+    }
+
+    // A line break would end the entry early and turn the rest into another line
+    if value.contains(['\n', '\r']) {
+        return Err(invalid(format!("{} must not contain line breaks", key)));
+    }
+
+    if BOOLEAN_KEYS.contains(&key) && value != "true" && value != "false" {
+        return Err(invalid(format!("{} must be true or false", key)));
+    }
+
+    if let Some((_, min, max)) = INTEGER_KEYS.iter().find(|(k, _, _)| *k == key) {
+        match value.parse::<i64>() {
+            Ok(number) if (*min..=*max).contains(&number) => {}
+            _ => {
+                return Err(invalid(format!(
+                    "{} must be an integer between {} and {}",
+                    key, min, max
+                )))
+            }
+        }
+    }
+
+    if let Some((_, allowed)) = ENUM_KEYS.iter().find(|(k, _)| *k == key) {
+        if !allowed.contains(&value) {
+            return Err(invalid(format!(
+                "{} must be one of: {}",
+                key,
+                allowed.join(", ")
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes a value as written in the properties file, e.g. `\u00A7cHi` to `§cHi`.
+///
+/// Unknown escapes stand for the character after the backslash, as in Java.
+/// A `\u` not followed by four hex digits is kept as it is.
+pub fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut units = Vec::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            flush_units(&mut units, &mut text);
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => {
+                let digits: String = chars.clone().take(4).collect();
+                match u16::from_str_radix(&digits, 16) {
+                    Ok(unit)
+                        if digits.len() == 4 && digits.chars().all(|d| d.is_ascii_hexdigit()) =>
+                    {
+                        // Characters outside the BMP are written as two UTF-16 escapes
+                        units.push(unit);
+                        for _ in 0..4 {
+                            chars.next();
+                        }
+                    }
+                    _ => {
+                        flush_units(&mut units, &mut text);
+                        text.push_str("\\u");
+                    }
+                }
+            }
+            Some(escaped) => {
+                flush_units(&mut units, &mut text);
+                text.push(match escaped {
+                    't' => '\t',
+                    'n' => '\n',
+                    'r' => '\r',
+                    'f' => '\u{c}',
+                    other => other,
+                });
+            }
+            // A trailing backslash continues the line in Java; there's nothing to continue
+            None => {}
+        }
+    }
+    flush_units(&mut units, &mut text);
+    text
+}
+
+/// Appends UTF-16 units collected from `\u` escapes as text.
+fn flush_units(units: &mut Vec<u16>, text: &mut String) {
+    text.extend(
+        char::decode_utf16(units.drain(..)).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+    );
+}
+
+/// Encodes a value the way the server writes it to the properties file.
+///
+/// Line breaks and tabs become `\n`, `\r` and `\t`, characters that would
+/// end a key or start a comment are escaped with a backslash, and anything
+/// outside printable ASCII becomes `\uXXXX`.
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            // Leading spaces would be skipped when the file is read
+            ' ' if index == 0 => escaped.push_str("\\ "),
+            ' '..='~' => escaped.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{:04X}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Escapes a MOTD, stores it in a properties file, reads the file back and unescapes it.
+    fn round_trip(motd: &str) -> String {
+        let mut props = ServerProperties::default();
+        props.set("motd", &escape(motd));
+        let props = ServerProperties::parse(&props.to_string());
+        unescape(props.get("motd").unwrap())
+    }
+
+    #[test]
+    fn escapes_unicode_as_utf16_units() {
+        assert_eq!(escape("§cHi"), "\\u00A7cHi");
+        assert_eq!(escape("é"), "\\u00E9");
+        // Outside the BMP, as a surrogate pair
+        assert_eq!(escape("🎮"), "\\uD83C\\uDFAE");
+    }
+
+    #[test]
+    fn escapes_line_breaks_and_backslashes() {
+        assert_eq!(escape("a\nb"), "a\\nb");
+        assert_eq!(escape("a\\nb"), "a\\\\nb");
+        assert_eq!(escape("C:\\server\\"), "C\\:\\\\server\\\\");
+    }
+
+    #[test]
+    fn unescapes_what_the_server_writes() {
+        assert_eq!(unescape("\\u00A7cHi"), "§cHi");
+        assert_eq!(unescape("\\uD83C\\uDFAE"), "🎮");
+        assert_eq!(unescape("a\\nb\\tc"), "a\nb\tc");
+        assert_eq!(unescape("a\\\\nb"), "a\\nb");
+        assert_eq!(unescape("\\=\\:\\#"), "=:#");
+    }
+
+    #[test]
+    fn unescape_keeps_an_incomplete_unicode_escape() {
+        assert_eq!(unescape("\\u00"), "\\u00");
+        assert_eq!(unescape("\\u00G1"), "\\u00G1");
+        // A lone surrogate can't be decoded
+        assert_eq!(unescape("\\uD83C!"), "\u{FFFD}!");
+    }
+
+    #[test]
+    fn motd_round_trips() {
+        for motd in [
+            "A Minecraft Server",
+            "§6Gold §lbold§r\n§7second line",
+            "日本語のサーバー 🎮",
+            "back\\slash \\n not a break",
+            " leading space = key: #!",
+            "\\",
+            "",
+        ] {
+            assert_eq!(round_trip(motd), motd);
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Client for the GameSpy 4 query protocol served on `query.port`.
+//!
+//! Unlike the Server List Ping, which only includes a sample of the online
+//! players, a full stat query lists every player, along with the map and the
+//! plugins. Servers only answer it with `enable-query=true` in
+//! `server.properties`.
+//!
+//! Every exchange is a single UDP datagram each way. A request starts with
+//! `FE FD`, the request type and a session id; the server answers with the
+//! same type and session id. Stat requests need a challenge token, which the
+//! server hands out in answer to a handshake.
+
+use crate::error::ServerError;
+use crate::server::log_parser::strip_color_codes;
+use crate::server::properties::ServerProperties;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+
+/// Port the query listener uses unless `query.port` says otherwise.
+pub const DEFAULT_PORT: u16 = 25565;
+/// Magic bytes every request starts with.
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+/// Request type of the handshake asking for a challenge token.
+const TYPE_HANDSHAKE: u8 = 0x09;
+/// Request type of the basic and full stat requests.
+const TYPE_STAT: u8 = 0x00;
+/// Only the low four bits of each byte of the session id are used.
+const SESSION_MASK: i32 = 0x0F0F_0F0F;
+/// Largest datagram we expect; a full stat with many players fits easily.
+const MAX_DATAGRAM_LEN: usize = 65_535;
+/// Constant padding before the key/value section of a full stat response.
+const FULL_STAT_PADDING: &[u8] = b"splitnum\x00\x80\x00";
+/// Constant padding before the player section of a full stat response.
+const PLAYERS_PADDING: &[u8] = b"\x01player_\x00\x00";
+
+/// Summary returned by a basic stat request.
+#[derive(Debug, Clone, Serialize)]
+pub struct BasicStat {
+    /// Message of the day without formatting codes.
+    pub motd: String,
+    /// Always `SMP` for Minecraft.
+    pub game_type: String,
+    /// Name of the main world.
+    pub map: String,
+    pub players_online: u32,
+    pub players_max: u32,
+    /// Port and address the game listens on.
+    pub host_port: u16,
+    pub host_ip: String,
+}
+
+/// Details returned by a full stat request.
+#[derive(Debug, Clone, Serialize)]
+pub struct FullStat {
+    /// Message of the day without formatting codes.
+    pub motd: String,
+    pub game_type: String,
+    /// Minecraft version, e.g. `1.21.1`.
+    pub version: String,
+    /// Server software, e.g. `Paper on 1.21.1`; empty for vanilla.
+    pub server_mod: String,
+    /// Plugins with their versions; empty for vanilla.
+    pub plugins: Vec<String>,
+    pub map: String,
+    pub players_online: u32,
+    pub players_max: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+    /// Names of all players online.
+    pub players: Vec<String>,
+}
+
+/// Works out the query port of a managed server.
+///
+/// # Returns
+/// * `Result<u16, ServerError>` - `query.port`, falling back to `server-port`, or
+///   QueryDisabled if the server doesn't have `enable-query=true`
+pub fn server_port(server_dir: &Path) -> std::result::Result<u16, ServerError> {
+    let props = ServerProperties::load(server_dir).unwrap_or_default();
+    if props.get("enable-query") != Some("true") {
+        return Err(ServerError::QueryDisabled);
+    }
+    Ok(props
+        .get("query.port")
+        .or_else(|| props.get("server-port"))
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT))
+}
+
+/// Runs a basic stat query.
+///
+/// # Arguments
+/// * `host` - Host name or address of the server
+/// * `port` - Query port
+/// * `limit` - How long to wait for each response
+///
+/// # Returns
+/// * `Result<BasicStat, ServerError>` - Timeout, or QueryFailed on malformed responses
+pub async fn basic_stat(
+    host: &str,
+    port: u16,
+    limit: Duration,
+) -> std::result::Result<BasicStat, ServerError> {
+    let result = async {
+        let session = Session::open(host, port, limit).await?;
+        let response = session.stat(false).await?;
+        parse_basic(&response)
+    };
+    result.await.map_err(|source| {
+        ServerError::network("Query", source, |source| ServerError::QueryFailed {
+            source,
+        })
+    })
+}
+
+/// Runs a full stat query.
+///
+/// # Arguments
+/// * `host` - Host name or address of the server
+/// * `port` - Query port
+/// * `limit` - How long to wait for each response
+///
+/// # Returns
+/// * `Result<FullStat, ServerError>` - Timeout, or QueryFailed on malformed responses
+pub async fn full_stat(
+    host: &str,
+    port: u16,
+    limit: Duration,
+) -> std::result::Result<FullStat, ServerError> {
+    let result = async {
+        let session = Session::open(host, port, limit).await?;
+        let response = session.stat(true).await?;
+        parse_full(&response)
+    };
+    result.await.map_err(|source| {
+        ServerError::network("Query", source, |source| ServerError::QueryFailed {
+            source,
+        })
+    })
+}
+
+/// A socket connected to the server, with the challenge token from the handshake.
+struct Session {
+    socket: UdpSocket,
+    id: i32,
+    token: i32,
+    limit: Duration,
+}
+
+impl Session {
+    /// Resolves the server, connects a socket to it and runs the handshake.
+    async fn open(host: &str, port: u16, limit: Duration) -> Result<Self> {
+        let address = lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "host has no address"))?;
+        let local: SocketAddr = if address.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(address).await?;
+
+        let mut session = Session {
+            socket,
+            id: std::process::id() as i32 & SESSION_MASK,
+            token: 0,
+            limit,
+        };
+        let response = session.request(TYPE_HANDSHAKE, &[]).await?;
+        let mut body = response.as_slice();
+        let token = read_string(&mut body)?;
+        session.token = token
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid challenge token '{}'", token)))?;
+        Ok(session)
+    }
+
+    /// Requests a basic or full stat, returning the response after its header.
+    async fn stat(&self, full: bool) -> Result<Vec<u8>> {
+        let mut payload = self.token.to_be_bytes().to_vec();
+        if full {
+            payload.extend_from_slice(&[0; 4]);
+        }
+        self.request(TYPE_STAT, &payload).await
+    }
+
+    /// Sends a request and waits for the matching response.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - The response without its type and session id
+    async fn request(&self, kind: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut packet = MAGIC.to_vec();
+        packet.push(kind);
+        packet.extend_from_slice(&self.id.to_be_bytes());
+        packet.extend_from_slice(payload);
+        self.socket.send(&packet).await?;
+
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+        loop {
+            let len = timeout(self.limit, self.socket.recv(&mut buf))
+                .await
+                .map_err(|_| Error::new(ErrorKind::TimedOut, "no response from server"))??;
+            let response = &buf[..len];
+            if response.len() < 5 {
+                return Err(invalid_data(format!(
+                    "response of {} bytes is too short",
+                    len
+                )));
+            }
+            // Skip stray answers to an earlier request
+            let id = i32::from_be_bytes([response[1], response[2], response[3], response[4]]);
+            if response[0] == kind && id == self.id {
+                return Ok(response[5..].to_vec());
+            }
+        }
+    }
+}
+
+/// Parses the body of a basic stat response.
+fn parse_basic(response: &[u8]) -> Result<BasicStat> {
+    let mut body = response;
+    let motd = strip_color_codes(&read_string(&mut body)?);
+    let game_type = read_string(&mut body)?;
+    let map = read_string(&mut body)?;
+    let players_online = parse_number(&read_string(&mut body)?)?;
+    let players_max = parse_number(&read_string(&mut body)?)?;
+    if body.len() < 2 {
+        return Err(invalid_data(
+            "response ended before the host port".to_string(),
+        ));
+    }
+    // The only little-endian field of the protocol
+    let host_port = u16::from_le_bytes([body[0], body[1]]);
+    body = &body[2..];
+    let host_ip = read_string(&mut body)?;
+    Ok(BasicStat {
+        motd,
+        game_type,
+        map,
+        players_online,
+        players_max,
+        host_port,
+        host_ip,
+    })
+}
+
+/// Parses the body of a full stat response.
+fn parse_full(response: &[u8]) -> Result<FullStat> {
+    let mut body = response
+        .strip_prefix(FULL_STAT_PADDING)
+        .ok_or_else(|| invalid_data("missing full stat padding".to_string()))?;
+
+    // Key/value pairs, ended by an empty key
+    let mut values = HashMap::new();
+    loop {
+        let key = read_string(&mut body)?;
+        if key.is_empty() {
+            break;
+        }
+        let value = read_string(&mut body)?;
+        values.insert(key, value);
+    }
+    let mut value = |key: &str| values.remove(key).unwrap_or_default();
+    let motd = strip_color_codes(&value("hostname"));
+    let game_type = value("gametype");
+    let version = value("version");
+    let (server_mod, plugins) = parse_plugins(&value("plugins"));
+    let map = value("map");
+    let players_online = parse_number(&value("numplayers"))?;
+    let players_max = parse_number(&value("maxplayers"))?;
+    let host_port = value("hostport")
+        .parse()
+        .map_err(|_| invalid_data("invalid host port".to_string()))?;
+    let host_ip = value("hostip");
+
+    // Player names, ended by an empty name
+    let mut body = body
+        .strip_prefix(PLAYERS_PADDING)
+        .ok_or_else(|| invalid_data("missing player section padding".to_string()))?;
+    let mut players = Vec::new();
+    loop {
+        let name = read_string(&mut body)?;
+        if name.is_empty() {
+            break;
+        }
+        players.push(name);
+    }
+
+    Ok(FullStat {
+        motd,
+        game_type,
+        version,
+        server_mod,
+        plugins,
+        map,
+        players_online,
+        players_max,
+        host_port,
+        host_ip,
+        players,
+    })
+}
+
+/// Splits the `plugins` value, e.g. `Paper on 1.21.1: Essentials 2.20; LuckPerms 5.4`,
+/// into the server software and its plugins.
+fn parse_plugins(text: &str) -> (String, Vec<String>) {
+    match text.split_once(": ") {
+        Some((server_mod, plugins)) => (
+            server_mod.to_string(),
+            plugins
+                .split("; ")
+                .filter(|plugin| !plugin.is_empty())
+                .map(str::to_string)
+                .collect(),
+        ),
+        None => (text.to_string(), Vec::new()),
+    }
+}
+
+/// Reads a NUL-terminated string from the front of a buffer, advancing past it.
+fn read_string(buf: &mut &[u8]) -> Result<String> {
+    let end = buf
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| invalid_data("unterminated string".to_string()))?;
+    // Older servers send ISO-8859-1, in which every byte is a character
+    let text = match std::str::from_utf8(&buf[..end]) {
+        Ok(text) => text.to_string(),
+        Err(_) => buf[..end].iter().map(|&byte| byte as char).collect(),
+    };
+    *buf = &buf[end + 1..];
+    Ok(text)
+}
+
+/// Parses a player count.
+fn parse_number(text: &str) -> Result<u32> {
+    text.parse()
+        .map_err(|_| invalid_data(format!("invalid number '{}'", text)))
+}
+
+/// Builds the error for a malformed response.
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,345 @@
+//! Client for the Source RCON protocol spoken by Minecraft servers.
+//!
+//! Commands written to stdin only show up as console output, with nothing to
+//! tell which lines belong to which command. RCON answers each command with
+//! its response directly, so it is used whenever the server has it enabled
+//! (`enable-rcon=true` in `server.properties`).
+//!
+//! Every packet is a little-endian `i32` length followed by the request id,
+//! the packet type, a NUL-terminated body and one more NUL. Responses longer
+//! than 4096 bytes arrive split over several packets with the same id; to know
+//! when the last one has arrived, each command is followed by an empty packet
+//! of an unknown type, which the server answers only after the command.
+
+use crate::config;
+use crate::error::ServerError;
+use crate::server::log_parser::strip_color_codes;
+use crate::server::properties::ServerProperties;
+use serde::Deserialize;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+/// Port Minecraft listens on for RCON unless `rcon.port` says otherwise.
+pub const DEFAULT_PORT: u16 = 25575;
+/// Longest command body Minecraft accepts in one packet.
+const MAX_COMMAND_LEN: usize = 1446;
+/// Longest packet Minecraft sends: a 4096 byte body plus id, type and padding.
+const MAX_PACKET_LEN: i32 = 4096 + 10;
+/// Delay before the first reconnect attempt after a failed connection.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between reconnect attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Packet type of a login request.
+const TYPE_LOGIN: i32 = 3;
+/// Packet type of a command request, and of the server's login response.
+const TYPE_COMMAND: i32 = 2;
+/// Packet type of a command response.
+const TYPE_RESPONSE: i32 = 0;
+
+/// Settings for connecting to the server's RCON port.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RconConfig {
+    /// Whether commands that need a response may go through RCON.
+    pub enabled: bool,
+    /// Host RCON listens on.
+    pub host: String,
+    /// Port, defaulting to `rcon.port` from `server.properties`.
+    pub port: Option<u16>,
+    /// Password, defaulting to `rcon.password` from `server.properties`.
+    pub password: Option<String>,
+    /// How long to wait for the connection and for each response.
+    #[serde(rename = "timeout_secs", deserialize_with = "config::secs")]
+    pub timeout: Duration,
+}
+
+impl Default for RconConfig {
+    fn default() -> Self {
+        RconConfig {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port: None,
+            password: None,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RconConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_RCON_ENABLED` - `true` or `false`
+    /// * `MC_RCON_HOST` - host RCON listens on
+    /// * `MC_RCON_PORT` - RCON port
+    /// * `MC_RCON_PASSWORD` - RCON password
+    /// * `MC_RCON_TIMEOUT_SECS` - seconds to wait for a connection or response
+    pub fn apply_env(&mut self) -> std::result::Result<(), String> {
+        if let Some(enabled) = config::env_parse("MC_RCON_ENABLED")? {
+            self.enabled = enabled;
+        }
+        if let Some(host) = config::env_string("MC_RCON_HOST") {
+            self.host = host;
+        }
+        if let Some(port) = config::env_parse("MC_RCON_PORT")? {
+            self.port = Some(port);
+        }
+        if let Some(password) = config::env_string("MC_RCON_PASSWORD") {
+            self.password = Some(password);
+        }
+        if let Some(timeout) = config::env_secs("MC_RCON_TIMEOUT_SECS")? {
+            self.timeout = timeout;
+        }
+        Ok(())
+    }
+
+    /// Works out where to connect for a server.
+    ///
+    /// RCON is used if the server enables it in `server.properties`, or if a
+    /// password is configured here (e.g. for a server behind a proxy).
+    ///
+    /// # Returns
+    /// * `Option<(String, String)>` - Address and password, None if RCON isn't available
+    fn target(&self, server_dir: &Path) -> Option<(String, String)> {
+        if !self.enabled {
+            return None;
+        }
+        let props = ServerProperties::load(server_dir).unwrap_or_default();
+        let password = match &self.password {
+            Some(password) => password.clone(),
+            None if props.get("enable-rcon") == Some("true") => {
+                props.get("rcon.password").unwrap_or_default().to_string()
+            }
+            None => return None,
+        };
+        if password.is_empty() {
+            return None;
+        }
+        let port = self
+            .port
+            .or_else(|| props.get("rcon.port").and_then(|port| port.parse().ok()))
+            .unwrap_or(DEFAULT_PORT);
+        Some((format!("{}:{}", self.host, port), password))
+    }
+}
+
+/// An RCON client for one server, connecting when first needed.
+///
+/// A broken connection (e.g. after the server restarted) is replaced on the
+/// next command. Failed connection attempts back off, so commands fail fast
+/// while the server is still starting instead of each waiting for a timeout.
+pub struct Rcon {
+    config: RconConfig,
+    state: Mutex<ConnectionState>,
+}
+
+/// The connection and reconnect backoff, guarded by the client's lock so
+/// commands on one connection never interleave.
+struct ConnectionState {
+    connection: Option<Connection>,
+    /// Earliest time to try connecting again.
+    retry_at: Option<Instant>,
+    /// Delay applied after the next failed attempt.
+    backoff: Duration,
+}
+
+impl Rcon {
+    /// Creates a client that hasn't connected yet.
+    pub fn new(config: RconConfig) -> Self {
+        Rcon {
+            config,
+            state: Mutex::new(ConnectionState {
+                connection: None,
+                retry_at: None,
+                backoff: MIN_BACKOFF,
+            }),
+        }
+    }
+
+    /// Returns true if commands can be sent to the server over RCON, i.e. it
+    /// is enabled here and the server has a password for it.
+    pub fn is_available(&self, server_dir: &Path) -> bool {
+        self.config.target(server_dir).is_some()
+    }
+
+    /// Runs a command over RCON and returns its response without color codes.
+    ///
+    /// # Arguments
+    /// * `server_dir` - Directory holding the server's `server.properties`
+    /// * `command` - The command, without a leading slash
+    ///
+    /// # Returns
+    /// * `Result<String, ServerError>` - RconDisabled if the server doesn't have RCON
+    ///   enabled, InvalidCommand for multi-line or overlong commands, Timeout if the
+    ///   server didn't answer in time, or RconFailed
+    pub async fn exec(
+        &self,
+        server_dir: &Path,
+        command: &str,
+    ) -> std::result::Result<String, ServerError> {
+        if command.contains(char::is_control) {
+            return Err(ServerError::InvalidCommand);
+        }
+        if command.len() > MAX_COMMAND_LEN {
+            return Err(ServerError::RconFailed {
+                source: Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("commands are limited to {} bytes", MAX_COMMAND_LEN),
+                ),
+            });
+        }
+        let (address, password) = self
+            .config
+            .target(server_dir)
+            .ok_or(ServerError::RconDisabled)?;
+
+        let mut state = self.state.lock().await;
+        // A kept connection may have been closed by a server restart; if it
+        // fails, retry once on a fresh connection
+        if let Some(connection) = &mut state.connection {
+            match connection.exec(command, self.config.timeout).await {
+                Ok(response) => return Ok(strip_color_codes(&response)),
+                Err(_) => state.connection = None,
+            }
+        }
+
+        if let Some(retry_at) = state.retry_at {
+            if Instant::now() < retry_at {
+                return Err(ServerError::RconFailed {
+                    source: Error::new(
+                        ErrorKind::NotConnected,
+                        format!(
+                            "not connected, retrying in {}s",
+                            (retry_at - Instant::now()).as_secs() + 1
+                        ),
+                    ),
+                });
+            }
+        }
+        let mut connection = match Connection::open(&address, &password, self.config.timeout).await
+        {
+            Ok(connection) => connection,
+            Err(source) => {
+                state.retry_at = Some(Instant::now() + state.backoff);
+                state.backoff = (state.backoff * 2).min(MAX_BACKOFF);
+                return Err(ServerError::network("RCON", source, |source| {
+                    ServerError::RconFailed { source }
+                }));
+            }
+        };
+        state.retry_at = None;
+        state.backoff = MIN_BACKOFF;
+        let result = connection.exec(command, self.config.timeout).await;
+        if result.is_ok() {
+            state.connection = Some(connection);
+        }
+        result
+            .map(|response| strip_color_codes(&response))
+            .map_err(|source| {
+                ServerError::network("RCON", source, |source| ServerError::RconFailed { source })
+            })
+    }
+}
+
+/// An authenticated RCON connection.
+struct Connection {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Connection {
+    /// Connects and logs in.
+    async fn open(address: &str, password: &str, limit: Duration) -> Result<Self> {
+        let stream = timeout(limit, TcpStream::connect(address))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "connection timed out"))??;
+        let mut connection = Connection { stream, next_id: 1 };
+
+        let id = connection.next_id();
+        connection.send(id, TYPE_LOGIN, password).await?;
+        loop {
+            let (response_id, kind, _) = connection.receive(limit).await?;
+            // Some servers send an empty response before the login result
+            if kind != TYPE_COMMAND {
+                continue;
+            }
+            if response_id == -1 {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    "RCON password was rejected",
+                ));
+            }
+            if response_id == id {
+                return Ok(connection);
+            }
+        }
+    }
+
+    /// Sends a command and collects its response, however many packets it spans.
+    async fn exec(&mut self, command: &str, limit: Duration) -> Result<String> {
+        let id = self.next_id();
+        let end_id = self.next_id();
+        self.send(id, TYPE_COMMAND, command).await?;
+        // The server answers the unknown packet type after the command's last fragment
+        self.send(end_id, TYPE_RESPONSE, "").await?;
+
+        let mut response = String::new();
+        loop {
+            let (response_id, _, body) = self.receive(limit).await?;
+            if response_id == end_id {
+                return Ok(response);
+            }
+            if response_id == id {
+                response.push_str(&body);
+            }
+        }
+    }
+
+    /// Returns a fresh request id.
+    fn next_id(&mut self) -> i32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    /// Writes one packet.
+    async fn send(&mut self, id: i32, kind: i32, body: &str) -> Result<()> {
+        let length = (body.len() + 10) as i32;
+        let mut packet = Vec::with_capacity(body.len() + 14);
+        packet.extend_from_slice(&length.to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        self.stream.write_all(&packet).await
+    }
+
+    /// Reads one packet.
+    ///
+    /// # Returns
+    /// * `Result<(i32, i32, String)>` - Request id, packet type and body
+    async fn receive(&mut self, limit: Duration) -> Result<(i32, i32, String)> {
+        timeout(limit, async {
+            let length = self.stream.read_i32_le().await?;
+            if !(10..=MAX_PACKET_LEN).contains(&length) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid RCON packet length {}", length),
+                ));
+            }
+            let mut packet = vec![0; length as usize];
+            self.stream.read_exact(&mut packet).await?;
+            let id = i32::from_le_bytes([packet[0], packet[1], packet[2], packet[3]]);
+            let kind = i32::from_le_bytes([packet[4], packet[5], packet[6], packet[7]]);
+            let body = String::from_utf8_lossy(&packet[8..packet.len() - 2]).into_owned();
+            Ok((id, kind, body))
+        })
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "no response from RCON"))?
+    }
+}
@@ -0,0 +1,255 @@
+//! The simulated server of a dry run.
+//!
+//! With `[server] dry_run` set, starting a server runs a task instead of a
+//! process. It prints a vanilla-like startup, answers `list`, `save-all`,
+//! `save-off`, `save-on`, `say` and the stop command with canned responses,
+//! echoes any other command, and every few seconds prints a fake player
+//! joining, chatting or leaving. It talks over in-memory pipes, so the
+//! backend writes its commands and reads its output exactly as for a real
+//! server: events, command responses and backups all work.
+//!
+//! Every line comes from the `Dry run` thread, e.g.
+//! `[12:00:00] [Dry run/INFO]: Done (0.001s)!`, so the output can't be
+//! mistaken for that of a real server.
+
+use crate::error::ServerError;
+use crate::server::process::{ConsoleInput, ConsoleOutput, ConsolePipes, ProcessHandle};
+use crate::server::{LaunchSpec, ServerConfig};
+use chrono::Local;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::collections::BTreeSet;
+use std::io::Result;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::io::{duplex, AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, Instant};
+
+/// Game version the simulated server claims to run.
+const MINECRAFT_VERSION: &str = "1.21.1";
+/// Bytes buffered in each pipe before the writing side has to wait.
+const PIPE_CAPACITY: usize = 64 * 1024;
+/// Time between the fake lines printed while idle.
+const IDLE_LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// Player slots reported by `list`.
+const MAX_PLAYERS: usize = 20;
+/// Name of the fake player.
+const PLAYER: &str = "DryRunPlayer";
+
+/// A running simulated server, standing in for the child process.
+pub struct SimulatedProcess {
+    /// Task playing the server; it returns the exit code.
+    task: JoinHandle<i32>,
+    /// How the task ended, once it has been waited for.
+    status: Option<ExitStatus>,
+}
+
+impl SimulatedProcess {
+    /// Waits for the task playing the server to end.
+    async fn wait_for_task(&mut self) -> ExitStatus {
+        if let Some(status) = self.status {
+            return status;
+        }
+        let status = exit_status((&mut self.task).await.ok());
+        self.status = Some(status);
+        status
+    }
+}
+
+impl ProcessHandle for SimulatedProcess {
+    /// Starts the task playing the server. The stop command from `config`
+    /// makes it exit, as it would a real server.
+    fn start(
+        _launch: &LaunchSpec,
+        config: &ServerConfig,
+    ) -> std::result::Result<(Self, ConsolePipes), ServerError> {
+        let (stdin, console_in) = duplex(PIPE_CAPACITY);
+        let (console_out, stdout) = duplex(PIPE_CAPACITY);
+        let task = tokio::spawn(run(console_in, console_out, config.stop_command.clone()));
+        let pipes = ConsolePipes {
+            stdin: Some(Box::new(stdin) as ConsoleInput),
+            stdout: Some(Box::new(stdout) as ConsoleOutput),
+            stderr: None,
+        };
+        Ok((SimulatedProcess { task, status: None }, pipes))
+    }
+
+    fn id(&self) -> Option<u32> {
+        None
+    }
+
+    fn try_wait(&mut self) -> Result<Option<ExitStatus>> {
+        if self.status.is_none() && self.task.is_finished() {
+            if let Some(result) = (&mut self.task).now_or_never() {
+                self.status = Some(exit_status(result.ok()));
+            }
+        }
+        Ok(self.status)
+    }
+
+    fn wait(&mut self) -> BoxFuture<'_, Result<ExitStatus>> {
+        async move { Ok(self.wait_for_task().await) }.boxed()
+    }
+
+    /// There is no process to signal, so the simulated server is killed.
+    fn terminate(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Ends the simulated server at once, like killing a process.
+    fn kill(&mut self) -> BoxFuture<'_, Result<()>> {
+        self.task.abort();
+        async move {
+            self.wait_for_task().await;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Builds the exit status of a process that exited with `code`, or was
+/// killed if None.
+#[cfg(unix)]
+fn exit_status(code: Option<i32>) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    // A wait status holds the exit code in its second byte, or else the signal
+    const SIGKILL: i32 = 9;
+    ExitStatus::from_raw(code.map_or(SIGKILL, |code| (code & 0xff) << 8))
+}
+
+/// Builds the exit status of a process that exited with `code`, or was
+/// killed if None, which Windows reports as exit code 1.
+#[cfg(not(unix))]
+fn exit_status(code: Option<i32>) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code.unwrap_or(1) as u32)
+}
+
+/// Plays the server until it gets the stop command.
+///
+/// # Returns
+/// * `i32` - The exit code, 0 like a server that stopped cleanly
+async fn run(console_in: DuplexStream, console_out: DuplexStream, stop_command: String) -> i32 {
+    let mut server = Simulation {
+        out: console_out,
+        players: BTreeSet::new(),
+        idle_lines: 0,
+    };
+    server.start().await;
+
+    let mut commands = BufReader::new(console_in).lines();
+    let mut console_open = true;
+    let mut idle = interval_at(Instant::now() + IDLE_LOG_INTERVAL, IDLE_LOG_INTERVAL);
+    loop {
+        tokio::select! {
+            line = commands.next_line(), if console_open => match line {
+                Ok(Some(command)) => {
+                    let command = command.trim().trim_start_matches('/');
+                    if command == stop_command {
+                        server.stop().await;
+                        return 0;
+                    }
+                    server.command(command).await;
+                }
+                // Like a real server, keep running without a console
+                Ok(None) | Err(_) => console_open = false,
+            },
+            _ = idle.tick() => server.idle().await,
+        }
+    }
+}
+
+/// State of the simulated server.
+struct Simulation {
+    /// The server's side of the output pipe.
+    out: DuplexStream,
+    /// Players the fake join and leave lines made online.
+    players: BTreeSet<String>,
+    /// Number of idle lines printed so far, picking the next one.
+    idle_lines: usize,
+}
+
+impl Simulation {
+    /// Prints a console line in the vanilla layout.
+    async fn print(&mut self, level: &str, message: &str) {
+        let line = format!(
+            "[{}] [Dry run/{}]: {}\n",
+            Local::now().format("%H:%M:%S"),
+            level,
+            message
+        );
+        // Nobody may be reading any more, which only matters to the backend
+        let _ = self.out.write_all(line.as_bytes()).await;
+    }
+
+    /// Prints the startup, ending with the line that marks the server ready.
+    async fn start(&mut self) {
+        self.print(
+            "INFO",
+            &format!("Starting minecraft server version {}", MINECRAFT_VERSION),
+        )
+        .await;
+        self.print(
+            "WARN",
+            "Dry run: no Minecraft server is running, this output is simulated",
+        )
+        .await;
+        self.print("INFO", "Preparing level \"world\"").await;
+        self.print("INFO", "Done (0.001s)! For help, type \"help\"")
+            .await;
+    }
+
+    /// Prints what a server prints while stopping.
+    async fn stop(&mut self) {
+        self.print("INFO", "Stopping the server").await;
+        self.print("INFO", "Saving players").await;
+        self.print("INFO", "Saving worlds").await;
+    }
+
+    /// Answers a console command.
+    async fn command(&mut self, command: &str) {
+        let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+        match name {
+            "list" => {
+                let names: Vec<&str> = self.players.iter().map(String::as_str).collect();
+                let message = format!(
+                    "There are {} of a max of {} players online: {}",
+                    names.len(),
+                    MAX_PLAYERS,
+                    names.join(", ")
+                );
+                self.print("INFO", &message).await;
+            }
+            "save-all" => {
+                self.print("INFO", "Saving the game (this may take a moment!)")
+                    .await;
+                self.print("INFO", "Saved the game").await;
+            }
+            "save-off" => self.print("INFO", "Automatic saving is now disabled").await,
+            "save-on" => self.print("INFO", "Automatic saving is now enabled").await,
+            "say" => self.print("INFO", &format!("[Server] {}", args)).await,
+            _ => {
+                let message = format!("Dry run: received '{}', nothing was run", command);
+                self.print("INFO", &message).await;
+            }
+        }
+    }
+
+    /// Prints the next of the fake lines: a player joining, chatting and leaving.
+    async fn idle(&mut self) {
+        let message = match self.idle_lines % 3 {
+            0 => {
+                self.players.insert(PLAYER.to_string());
+                format!("{} joined the game", PLAYER)
+            }
+            1 => format!("<{}> Hello from the dry run", PLAYER),
+            _ => {
+                self.players.remove(PLAYER);
+                format!("{} left the game", PLAYER)
+            }
+        };
+        self.idle_lines += 1;
+        self.print("INFO", &message).await;
+    }
+}
@@ -0,0 +1,72 @@
+//! Access to the server's whitelist.
+//!
+//! This file reads and writes `whitelist.json` in the server directory. Like
+//! the ban lists, the file is only edited directly while the server is
+//! stopped; while it is running, changes go through the console.
+
+use crate::server::bans::{lookup_uuid, read_list, write_list, USER_CACHE_FILE};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// File name of the whitelist inside the server directory.
+pub const WHITELIST_FILE: &str = "whitelist.json";
+
+/// An entry of `whitelist.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WhitelistEntry {
+    pub uuid: String,
+    pub name: String,
+    /// Any fields we don't know about, preserved on write.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Reads `whitelist.json`, returning an empty list if the file doesn't exist.
+pub fn load(server_dir: &Path) -> Result<Vec<WhitelistEntry>> {
+    read_list(&server_dir.join(WHITELIST_FILE))
+}
+
+/// Adds a player by editing `whitelist.json`.
+///
+/// The player's UUID is looked up in the server's `usercache.json`, so only
+/// players that have joined the server before can be added this way.
+///
+/// # Returns
+/// * `Result<bool>` - false if the player was already whitelisted
+pub fn add(server_dir: &Path, name: &str) -> Result<bool> {
+    let mut entries = load(server_dir)?;
+    if entries.iter().any(|e| e.name.eq_ignore_ascii_case(name)) {
+        return Ok(false);
+    }
+
+    let (uuid, name) = lookup_uuid(server_dir, name)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("Player '{}' not found in {}", name, USER_CACHE_FILE),
+        )
+    })?;
+    entries.push(WhitelistEntry {
+        uuid,
+        name,
+        extra: Map::new(),
+    });
+    write_list(&server_dir.join(WHITELIST_FILE), &entries)?;
+    Ok(true)
+}
+
+/// Removes a player from `whitelist.json`.
+///
+/// # Returns
+/// * `Result<bool>` - false if the player wasn't whitelisted
+pub fn remove(server_dir: &Path, name: &str) -> Result<bool> {
+    let mut entries = load(server_dir)?;
+    let before = entries.len();
+    entries.retain(|e| !e.name.eq_ignore_ascii_case(name));
+    if entries.len() == before {
+        return Ok(false);
+    }
+    write_list(&server_dir.join(WHITELIST_FILE), &entries)?;
+    Ok(true)
+}
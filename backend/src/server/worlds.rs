@@ -0,0 +1,246 @@
+//! The worlds in a server directory.
+//!
+//! Any directory of the server directory with a `level.dat` is a world, and
+//! `level-name` in `server.properties` decides which one the server loads.
+//! Worlds can be tens of GB, so listing them with their sizes walks every
+//! file; call these functions from a blocking context (e.g. `web::block`).
+//!
+//! A world's seed, version and game rules come from its `level.dat`, an NBT
+//! file with everything under a `Data` compound.
+
+use crate::server::nbt::{self, Tag};
+use crate::server::properties::{self, ServerProperties};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// File that marks a directory as a Minecraft world.
+pub const LEVEL_DAT: &str = "level.dat";
+/// World directory name used when `level-name` isn't set.
+pub const DEFAULT_LEVEL_NAME: &str = "world";
+/// Property key naming the active world.
+const LEVEL_NAME_KEY: &str = "level-name";
+
+/// A world in the server directory, as listed by `GET /worlds`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldInfo {
+    /// Name of the world's directory, the value for `level-name`.
+    pub name: String,
+    /// Size of all its files in bytes.
+    pub size: u64,
+    /// When the world was last saved, i.e. `level.dat` was written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Local>>,
+    /// Whether `level-name` names this world.
+    pub active: bool,
+}
+
+/// Basic information about a world from its `level.dat`, as returned by
+/// `GET /worlds/{name}/info`. Fields the file doesn't have, e.g. the
+/// version in worlds from before 1.9, are left out.
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelInfo {
+    /// Name of the world's directory.
+    pub name: String,
+    /// Name of the world as shown in the game (`Data.LevelName`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level_name: Option<String>,
+    /// World seed as a decimal string, since seeds use all 64 bits and
+    /// JavaScript numbers don't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<String>,
+    /// Game version that last saved the world, e.g. `1.21.1`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Default game mode: `survival`, `creative`, `adventure` or `spectator`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_mode: Option<&'static str>,
+    /// Difficulty: `peaceful`, `easy`, `normal` or `hard`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<&'static str>,
+    pub hardcore: bool,
+    /// World spawn point as `[x, y, z]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spawn: Option<[i32; 3]>,
+    /// When the world was last played.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_played: Option<DateTime<Local>>,
+}
+
+/// Returns the name of the active world from `level-name` in server.properties.
+pub fn level_name(server_dir: &Path) -> String {
+    ServerProperties::load(server_dir)
+        .ok()
+        .and_then(|props| props.get(LEVEL_NAME_KEY).map(properties::unescape))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_LEVEL_NAME.to_string())
+}
+
+/// Lists the worlds in the server directory, sorted by name.
+///
+/// # Returns
+/// * `Result<Vec<WorldInfo>>` - The worlds, empty if the directory doesn't exist
+pub fn list_worlds(server_dir: &Path) -> Result<Vec<WorldInfo>> {
+    let entries = match fs::read_dir(server_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let active = level_name(server_dir);
+
+    let mut worlds = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let Ok(level_dat) = entry.path().join(LEVEL_DAT).metadata() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        worlds.push(WorldInfo {
+            size: directory_size(&entry.path())?,
+            modified: level_dat.modified().ok().map(DateTime::from),
+            active: name == active,
+            name,
+        });
+    }
+    worlds.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(worlds)
+}
+
+/// Adds up the sizes of the files in a directory and its subdirectories.
+///
+/// Symbolic links aren't followed, so a link can't make a world count twice
+/// or loop forever.
+fn directory_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += directory_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Checks that a name is that of a world in the server directory.
+///
+/// Only plain directory names of existing worlds are accepted, so the server
+/// can't be pointed outside its directory or at a world it would generate.
+///
+/// # Returns
+/// * `Result<()>` - InvalidInput for names that aren't plain directory names,
+///   NotFound if there is no such world
+pub fn check_world(server_dir: &Path, name: &str) -> Result<()> {
+    let plain = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\'])
+        && !name.contains(char::is_control);
+    if !plain {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("'{}' is not a world directory name", name),
+        ));
+    }
+    if !server_dir.join(name).join(LEVEL_DAT).is_file() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No world named '{}' ({} not found)", name, LEVEL_DAT),
+        ));
+    }
+    Ok(())
+}
+
+/// Makes a world the active one by setting `level-name` in server.properties.
+///
+/// # Returns
+/// * `Result<bool>` - True if `level-name` changed, or an error from [`check_world`]
+pub fn set_active_world(server_dir: &Path, name: &str) -> Result<bool> {
+    check_world(server_dir, name)?;
+    let mut props = match ServerProperties::load(server_dir) {
+        Ok(props) => props,
+        Err(e) if e.kind() == ErrorKind::NotFound => ServerProperties::default(),
+        Err(e) => return Err(e),
+    };
+    let changed = props.set(LEVEL_NAME_KEY, &properties::escape(name));
+    if changed {
+        props.save(server_dir)?;
+    }
+    Ok(changed)
+}
+
+/// Reads the basic information of a world from its `level.dat`.
+///
+/// # Returns
+/// * `Result<LevelInfo>` - The information, an error from [`check_world`], or
+///   InvalidData if `level.dat` isn't valid NBT or has no `Data` compound
+pub fn read_level_info(server_dir: &Path, name: &str) -> Result<LevelInfo> {
+    check_world(server_dir, name)?;
+    let (_, root) = nbt::read(&fs::read(server_dir.join(name).join(LEVEL_DAT))?)?;
+    let data = root
+        .get("Data")
+        .filter(|data| matches!(data, Tag::Compound(_)))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "no Data compound"))?;
+
+    // 1.16 moved the seed into the world generation settings
+    let seed = data
+        .path(&["WorldGenSettings", "seed"])
+        .or_else(|| data.get("RandomSeed"))
+        .and_then(Tag::as_i64);
+    let game_mode = data
+        .get("GameType")
+        .and_then(Tag::as_i64)
+        .and_then(|mode| match mode {
+            0 => Some("survival"),
+            1 => Some("creative"),
+            2 => Some("adventure"),
+            3 => Some("spectator"),
+            _ => None,
+        });
+    let difficulty = data
+        .get("Difficulty")
+        .and_then(Tag::as_i64)
+        .and_then(|difficulty| match difficulty {
+            0 => Some("peaceful"),
+            1 => Some("easy"),
+            2 => Some("normal"),
+            3 => Some("hard"),
+            _ => None,
+        });
+    // 1.21.5 moved the spawn point into a compound of its own
+    let spawn = match data.path(&["spawn", "pos"]) {
+        Some(Tag::IntArray(pos)) => <[i32; 3]>::try_from(pos.as_slice()).ok(),
+        _ => match ["SpawnX", "SpawnY", "SpawnZ"].map(|key| data.get(key).and_then(Tag::as_i64)) {
+            [Some(x), Some(y), Some(z)] => Some([x as i32, y as i32, z as i32]),
+            _ => None,
+        },
+    };
+    Ok(LevelInfo {
+        name: name.to_string(),
+        level_name: data
+            .get("LevelName")
+            .and_then(Tag::as_str)
+            .map(str::to_string),
+        seed: seed.map(|seed| seed.to_string()),
+        version: data
+            .path(&["Version", "Name"])
+            .and_then(Tag::as_str)
+            .map(str::to_string),
+        game_mode,
+        difficulty,
+        hardcore: data.get("hardcore").and_then(Tag::as_i64).unwrap_or(0) != 0,
+        spawn,
+        last_played: data
+            .get("LastPlayed")
+            .and_then(Tag::as_i64)
+            .and_then(DateTime::from_timestamp_millis)
+            .map(DateTime::from),
+    })
+}
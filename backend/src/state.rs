@@ -1,128 +1,1544 @@
 // Defines a struct (e.g., AppState) to hold shared data like the process handle, making it accessible across different route handlers.
 
-use crate::server::MinecraftServer;
-use std::collections::HashMap;
-use std::io::Result;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use crate::audit::{AuditAction, AuditLog, Caller};
+use crate::broadcaster::{Broadcaster, ClientInfo};
+use crate::config::Config;
+use crate::error::ServerError;
+use crate::history::{CommandHistory, CommandStatus};
+use crate::lifecycle::{Lifecycle, RunEnd, RunOutcome};
+use crate::macros::{MacroRun, MacroStep, Macros};
+use crate::metrics::Metrics;
+use crate::notifications::{Notification, Notifier};
+use crate::policy::CommandPolicy;
+use crate::scheduler::{BackupSchedule, CountdownConfig, RestartSchedule, RestartScheduleConfig};
+use crate::server::backup::BackupConfig;
+use crate::server::crash_reports;
+use crate::server::eula;
+use crate::server::java::{self, JavaVersion};
+use crate::server::log_entry::LogEntry;
+use crate::server::log_file::LogFileConfig;
+use crate::server::log_parser::{self, LogEvent, ServerVersion};
+use crate::server::mods::ModsConfig;
+use crate::server::motd::MotdConfig;
+use crate::server::output;
+use crate::server::performance::{OverloadStats, PerformanceConfig, PerformanceStats};
+use crate::server::ports;
+use crate::server::rcon::{Rcon, RconConfig};
+use crate::server::{
+    is_valid_env_var, is_valid_server_id, CommandSender, LaunchSpec, MinecraftServer, ServerConfig,
+    ServerDefinition, ServerId, ServerProcess, StopOutcome, DEFAULT_SERVER_ID,
+};
+use crate::stats::ResourceHistory;
+use crate::supervisor::{AutoRestart, AutoRestartConfig, RestartDecision, RestartOutcome};
+use crate::webhooks::Webhooks;
+use crate::websocket::WebSocketConfig;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{broadcast, RwLock};
+use tracing::{error, info, info_span, warn, Instrument, Span};
 
-// Unique ID counter for WebSocket clients
-static NEXT_CLIENT_ID: AtomicUsize = AtomicUsize::new(1);
+/// A command's output is complete once the console is quiet this long.
+const COMMAND_OUTPUT_QUIET: Duration = Duration::from_millis(250);
+/// Upper bound on how long a command's output is collected after its first line.
+const COMMAND_OUTPUT_MAX_WAIT: Duration = Duration::from_secs(2);
 
-/// AppState holds the shared state for your application.
-pub struct AppState {
-    /// An optional instance of the Minecraft server.
-    pub minecraft_server: Option<MinecraftServer>,
-    /// A sender for forwarding log messages.
-    pub log_sender: UnboundedSender<String>,
-    /// Map of connected WebSocket clients
-    subscribers: HashMap<usize, UnboundedSender<String>>,
+/// Structured events broadcast to WebSocket clients alongside the raw log lines.
+///
+/// Events are sent as JSON text frames, e.g. `{"type":"player_join","name":"Steve"}`,
+/// or to clients reading plain text as a console line, see [`ServerEvent::to_text`].
+/// Lifecycle events (`started` through `crashed`) let clients follow the
+/// server's state without polling `/status`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A player joined the game.
+    PlayerJoin { name: String },
+    /// A player left the game.
+    PlayerLeave { name: String },
+    /// The server process was spawned.
+    Started { path: PathBuf },
+    /// The server finished starting and accepts players.
+    Ready,
+    /// A stop was requested; the world is being saved and the server shut down.
+    Stopping,
+    /// The server process exited after a stop, or with exit code 0 on its own.
+    Stopped { exit_code: Option<i32> },
+    /// The server process exited unexpectedly.
+    Crashed {
+        exit_code: Option<i32>,
+        /// Crash report the game wrote before exiting.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        crash_report: Option<PathBuf>,
+    },
+    /// The server exited without a stop and will be started again after the delay.
+    Restarting {
+        attempt: u32,
+        max_attempts: u32,
+        delay_secs: u64,
+    },
+    /// The server exited again after too many restarts and is left stopped.
+    RestartAbandoned { attempts: u32 },
+    /// The server exited because the EULA isn't accepted, instead of `stopped`
+    /// or `crashed`. It is started once `eula_file` says `eula=true`, or with
+    /// `accept_eula`.
+    EulaRequired { eula_file: PathBuf },
+    /// The server printed its game version or mod loader while starting,
+    /// e.g. `{"type":"version","minecraft":"1.20.1","loader":"forge","loader_version":"47.2.0"}`.
+    /// Fields the line didn't mention are null; `/version` has all that is known.
+    Version(ServerVersion),
+    /// The server is still running but closed its console input, so commands
+    /// go over RCON if the server has it enabled and fail otherwise.
+    ConsoleUnavailable,
+    /// The server printed "Can't keep up! Is the server overloaded?". During a
+    /// burst of warnings this is sent at most once a minute, with `held_back`
+    /// counting the warnings since the last one that weren't sent; `/status`
+    /// counts every warning.
+    Overloaded {
+        behind_ms: Option<u64>,
+        ticks_behind: Option<u64>,
+        held_back: u64,
+    },
+    /// A scheduled restart happens in `minutes` minutes.
+    RestartWarning {
+        minutes: u32,
+        restart_at: DateTime<Local>,
+    },
 }
 
-impl AppState {
-    /// Creates a new instance of AppState with the provided log sender.
-    pub fn new(log_sender: UnboundedSender<String>) -> Self {
-        AppState {
+impl ServerEvent {
+    /// Returns the SSE event name for this event: `status` for lifecycle
+    /// changes and `player` for players joining or leaving.
+    pub fn sse_name(&self) -> &'static str {
+        match self {
+            ServerEvent::PlayerJoin { .. } | ServerEvent::PlayerLeave { .. } => "player",
+            _ => "status",
+        }
+    }
+
+    /// Renders the event as a console line for WebSocket clients reading
+    /// plain text, e.g. `--- Server stopped (exit code 0) ---`.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The line, None for events the server's own output
+    ///   already shows: players joining and leaving, its version and overload warnings
+    pub fn to_text(&self) -> Option<String> {
+        let text = match self {
+            ServerEvent::PlayerJoin { .. }
+            | ServerEvent::PlayerLeave { .. }
+            | ServerEvent::Version(_)
+            | ServerEvent::Overloaded { .. } => return None,
+            ServerEvent::Started { path } => format!("Server started from {}", path.display()),
+            ServerEvent::Ready => "Server is ready".to_string(),
+            ServerEvent::Stopping => "Server stopping, saving the world".to_string(),
+            ServerEvent::Stopped { exit_code } => match exit_code {
+                Some(code) => format!("Server stopped (exit code {})", code),
+                None => "Server stopped".to_string(),
+            },
+            ServerEvent::Crashed {
+                exit_code,
+                crash_report,
+            } => {
+                let mut text = match exit_code {
+                    Some(code) => format!("Server crashed (exit code {})", code),
+                    None => "Server was killed".to_string(),
+                };
+                if let Some(report) = crash_report {
+                    text.push_str(&format!(", crash report {}", report.display()));
+                }
+                text
+            }
+            ServerEvent::Restarting {
+                attempt,
+                delay_secs,
+                ..
+            } => format!("Restarting in {}s (attempt {})", delay_secs, attempt),
+            ServerEvent::RestartAbandoned { attempts } => {
+                format!("Gave up restarting after {} attempts", attempts)
+            }
+            ServerEvent::EulaRequired { eula_file } => format!(
+                "Server needs the EULA accepted, set eula=true in {}",
+                eula_file.display()
+            ),
+            ServerEvent::ConsoleUnavailable => {
+                "Server closed its console input, commands go over RCON if it has it enabled"
+                    .to_string()
+            }
+            ServerEvent::RestartWarning {
+                minutes,
+                restart_at,
+            } => format!(
+                "Scheduled restart in {} minutes, at {}",
+                minutes,
+                restart_at.format("%H:%M")
+            ),
+        };
+        Some(format!("--- {} ---", text))
+    }
+}
+
+impl From<LogEvent> for ServerEvent {
+    fn from(event: LogEvent) -> Self {
+        match event {
+            LogEvent::PlayerJoined(name) => ServerEvent::PlayerJoin { name },
+            LogEvent::PlayerLeft(name) => ServerEvent::PlayerLeave { name },
+            LogEvent::ServerReady => ServerEvent::Ready,
+            LogEvent::Version(version) => ServerEvent::Version(version),
+            LogEvent::Overloaded {
+                behind_ms,
+                ticks_behind,
+            } => ServerEvent::Overloaded {
+                behind_ms,
+                ticks_behind,
+                held_back: 0,
+            },
+        }
+    }
+}
+
+/// An item on the log channel, broadcast to clients in the order it was sent.
+///
+/// Lifecycle events go through the same channel as log lines so a client
+/// never sees `stopped` before the server's last lines of output.
+#[derive(Debug, Clone)]
+pub enum Broadcast {
+    /// A line of console output.
+    Log(LogEntry),
+    /// A structured event.
+    Event(ServerEvent),
+}
+
+impl From<LogEntry> for Broadcast {
+    fn from(entry: LogEntry) -> Self {
+        Broadcast::Log(entry)
+    }
+}
+
+/// Sends one server's log entries and events to the log broadcaster.
+///
+/// All servers share a single channel so output is broadcast in the order it
+/// was produced; each item is tagged with the server it came from.
+#[derive(Debug, Clone)]
+pub struct LogSender {
+    server_id: ServerId,
+    channel: UnboundedSender<(ServerId, Broadcast)>,
+}
+
+impl LogSender {
+    /// Creates a sender tagging what it sends with `server_id`.
+    pub fn new(server_id: ServerId, channel: UnboundedSender<(ServerId, Broadcast)>) -> Self {
+        LogSender { server_id, channel }
+    }
+
+    /// Queues an item for the clients of this sender's server.
+    pub fn send(&self, item: Broadcast) -> Result<(), SendError<(ServerId, Broadcast)>> {
+        self.channel.send((self.server_id.clone(), item))
+    }
+}
+
+/// How a single start differs from the configured launch.
+#[derive(Default)]
+pub struct StartOptions {
+    /// Script, executable or jar to run, defaulting to the configured server path.
+    pub file_path: Option<String>,
+    /// Accept the EULA before starting, even if `accept_eula` isn't configured.
+    pub accept_eula: bool,
+    /// Start even if a port the server listens on looks taken.
+    pub force: bool,
+    /// Environment variables set on top of `[server.env]`.
+    pub env: BTreeMap<String, String>,
+    /// Overrides `[server] env_clear` for this start.
+    pub env_clear: Option<bool>,
+}
+
+/// A Minecraft server managed by the backend, with its own process, console
+/// clients and replay buffer.
+pub struct ManagedServer {
+    /// Id used in routes like `/servers/{id}/start`.
+    id: ServerId,
+    /// The running Minecraft server, None while stopped or stopping.
+    pub minecraft_server: Option<Box<dyn ServerProcess>>,
+    /// Whether the server was taken out of `minecraft_server` to be stopped
+    /// without the state lock, see [`stop_server`].
+    stopping: bool,
+    /// A sender for forwarding log messages and events to this server's clients.
+    pub log_sender: LogSender,
+    /// Connected WebSocket and SSE clients and the replay buffer, behind their own lock.
+    broadcaster: Arc<Broadcaster>,
+    /// Working directory of the most recently started server, kept after it stops
+    /// so its data files (ban lists, properties) can still be managed.
+    server_dir: Option<PathBuf>,
+    /// When the current server process was started, None while stopped.
+    pub started_at: Option<Instant>,
+    /// When a stop or restart requested with `?countdown=` happens, None without one.
+    pub countdown_until: Option<DateTime<Local>>,
+    /// Wall-clock time the current server process was started, for `/status`
+    /// and the run history. Durations use [`ManagedServer::started_at`].
+    start_time: Option<DateTime<Local>>,
+    /// Number of times the server was started again after its first start.
+    pub restart_count: usize,
+    /// What the server was last started with, reused by automatic restarts.
+    last_launch: Option<LaunchSpec>,
+    /// Java runtime the last jar was started with, None if the server isn't a jar.
+    java_version: Option<JavaVersion>,
+    /// Whether the last run exited because the EULA isn't accepted.
+    eula_required: bool,
+    /// Game version and mod loader the current run printed while starting.
+    version: ServerVersion,
+    /// "Can't keep up!" warnings, counted across runs.
+    overload: OverloadStats,
+    /// Automatic restarts after unexpected exits.
+    pub auto_restart: AutoRestart,
+    /// Names of the players online, from the join and leave events.
+    online_players: BTreeSet<String>,
+    /// Whether the running server finished starting, from the ready event.
+    ready: bool,
+    /// Settings for managing the server process (stop command, timeouts).
+    pub server_config: ServerConfig,
+    /// Counters exported at `/metrics`, shared by all servers.
+    metrics: Arc<Metrics>,
+    /// Client for the server's RCON port, used for commands that need a response.
+    rcon: Arc<Rcon>,
+    /// Sends webhook notifications for this server's events.
+    notifier: Notifier,
+    /// Posts this server's events to the outbound webhooks.
+    webhooks: Webhooks,
+    /// Records this server's runs when they end.
+    lifecycle: Lifecycle,
+    /// Span of this server, the parent of what is logged about its process.
+    span: Span,
+}
+
+impl ManagedServer {
+    /// Creates a stopped server, sharing the log channel, metrics and
+    /// notification handles of the app.
+    fn new(id: ServerId, server_config: ServerConfig, app_state: &AppState) -> Self {
+        ManagedServer {
+            log_sender: LogSender::new(id.clone(), app_state.log_channel.clone()),
+            broadcaster: Arc::new(Broadcaster::new(
+                id.clone(),
+                &app_state.websocket_config,
+                app_state.metrics.clone(),
+            )),
+            span: info_span!("server", id = %id),
+            id,
             minecraft_server: None,
-            log_sender,
-            subscribers: HashMap::new(),
+            stopping: false,
+            server_dir: None,
+            started_at: None,
+            start_time: None,
+            countdown_until: None,
+            restart_count: 0,
+            last_launch: None,
+            java_version: None,
+            eula_required: false,
+            version: ServerVersion::default(),
+            overload: OverloadStats::default(),
+            auto_restart: AutoRestart::new(app_state.auto_restart_config.clone()),
+            online_players: BTreeSet::new(),
+            ready: false,
+            server_config,
+            metrics: app_state.metrics.clone(),
+            rcon: Arc::new(Rcon::new(app_state.rcon_config.clone())),
+            notifier: app_state.notifier.clone(),
+            webhooks: app_state.webhooks.clone(),
+            lifecycle: app_state.lifecycle.clone(),
+        }
+    }
+
+    /// Returns the id of this server.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Starts the Minecraft server.
+    ///
+    /// The path is checked before anything is spawned; see [`LaunchSpec::from_config`].
+    /// A pending automatic restart is cancelled.
+    ///
+    /// # Arguments
+    /// * `options` - Path, environment and checks for this start; see [`StartOptions`]
+    ///
+    /// # Returns
+    /// * `Result<(), ServerError>` - AlreadyRunning if a server is running, Stopping if one is
+    ///   being stopped, InvalidPath if the path isn't a usable file, InvalidEnvVar if a
+    ///   variable can't be set, or SpawnFailed
+    pub async fn start_minecraft(&mut self, options: StartOptions) -> Result<(), ServerError> {
+        self.check_stopped()?;
+        if let Some((name, _)) = options
+            .env
+            .iter()
+            .find(|(name, value)| !is_valid_env_var(name, value))
+        {
+            return Err(ServerError::InvalidEnvVar { name: name.clone() });
         }
+        let mut launch = LaunchSpec::from_config(options.file_path, &self.server_config)?;
+        launch.accept_eula |= options.accept_eula;
+        if !options.env.is_empty() || options.env_clear.is_some() {
+            launch.env.extend(options.env);
+            launch.env_clear = options.env_clear.unwrap_or(launch.env_clear);
+            // The request may set JAVA_HOME or hide the backend's
+            launch.resolve_java(&self.server_config);
+        }
+        // A simulated server listens on no port
+        if !options.force && !launch.dry_run {
+            ports::check(&launch.working_dir)?;
+        }
+        if self.auto_restart.cancel() {
+            info!(server = %self.id, "Cancelled the automatic restart");
+        }
+        self.launch(launch).await
     }
 
-    /// Starts the Minecraft server if it isn't already running.
+    /// Checks that no server is running or being stopped, so one can be started.
     ///
-    /// This method calls the `MinecraftServer::start` function from `server.rs`
-    /// and stores the resulting server instance in the state.
-    pub async fn start_minecraft(&mut self, file_path: Option<String>) -> Result<()> {
-        if self.minecraft_server.is_none() {
-            let server = MinecraftServer::start(self.log_sender.clone(), file_path).await?;
-            self.minecraft_server = Some(server);
+    /// # Returns
+    /// * `Result<(), ServerError>` - AlreadyRunning or Stopping
+    fn check_stopped(&self) -> Result<(), ServerError> {
+        if let Some(server) = &self.minecraft_server {
+            return Err(ServerError::AlreadyRunning {
+                path: server.path().to_path_buf(),
+                uptime_seconds: self.uptime_seconds(),
+            });
+        }
+        if self.stopping {
+            return Err(ServerError::Stopping);
         }
         Ok(())
     }
 
-    /// Stops the Minecraft server if it is currently running.
-    pub async fn stop_minecraft(&mut self) -> Result<()> {
-        if let Some(server) = &mut self.minecraft_server {
-            server.stop().await?;
-            self.minecraft_server = None;
+    /// Spawns the server process and remembers how it was launched.
+    async fn launch(&mut self, launch: LaunchSpec) -> Result<(), ServerError> {
+        // Refuse a missing or too old Java before the JVM fails on the console
+        let java_version = if launch.is_jar() && !launch.dry_run {
+            let minimum = self.server_config.min_java_version;
+            Some(java::check(&launch, minimum).await?)
+        } else {
+            None
+        };
+        if launch.accept_eula && !launch.dry_run && eula::accept(&launch.working_dir)? {
+            info!(server = %self.id, "Accepted the EULA in {}", launch.working_dir.display());
+        }
+        // The output readers spawned here stay in the server's span
+        let server = MinecraftServer::start(
+            launch.clone(),
+            self.log_sender.clone(),
+            self.server_config.clone(),
+            self.metrics.clone(),
+        )
+        .instrument(self.span.clone())
+        .await?;
+        // A server directory is only remembered once something has been started,
+        // so any start after that is a restart.
+        if self.server_dir.is_some() {
+            self.restart_count += 1;
         }
+        self.server_dir = Some(server.working_dir().to_path_buf());
+        self.started_at = Some(Instant::now());
+        self.start_time = Some(Local::now());
+        self.last_launch = Some(launch);
+        self.java_version = java_version;
+        self.eula_required = false;
+        let path = server.path().to_path_buf();
+        self.minecraft_server = Some(Box::new(server));
+        self.queue_event(ServerEvent::Started { path });
         Ok(())
     }
 
+    /// Takes the running server out to be stopped by [`stop_server`] without
+    /// the state lock, and tells clients it is stopping.
+    ///
+    /// A server waiting for an automatic restart stays stopped instead.
+    ///
+    /// # Returns
+    /// * `Result<Option<Stop>, ServerError>` - The server to stop, None if only a
+    ///   pending automatic restart was cancelled; Stopping if a stop is already
+    ///   under way, NotRunning if no server is running or waiting to restart
+    fn begin_stop(&mut self) -> Result<Option<Stop>, ServerError> {
+        if self.stopping {
+            return Err(ServerError::Stopping);
+        }
+        let Some(process) = self.minecraft_server.take() else {
+            if self.auto_restart.cancel() {
+                info!(server = %self.id, "Cancelled the automatic restart");
+                return Ok(None);
+            }
+            return Err(ServerError::NotRunning);
+        };
+        self.stopping = true;
+        self.queue_event(ServerEvent::Stopping);
+        Ok(Some(Stop {
+            process,
+            server_dir: self.server_dir(),
+            save_timeout: self.server_config.save_timeout,
+            stop_command: self.server_config.stop_command.clone(),
+            rcon: self.rcon.clone(),
+            span: self.span.clone(),
+        }))
+    }
+
+    /// Records how a stop begun with [`ManagedServer::begin_stop`] went. A
+    /// server that failed to stop is put back as the running one.
+    fn finish_stop(
+        &mut self,
+        process: Box<dyn ServerProcess>,
+        result: &Result<Option<StopOutcome>, ServerError>,
+    ) {
+        self.stopping = false;
+        let Ok(outcome) = result else {
+            self.minecraft_server = Some(process);
+            return;
+        };
+        let exit_code = outcome.and_then(|outcome| outcome.exit_code);
+        self.end_run(
+            RunOutcome::Stopped,
+            RunEnd {
+                exit_code,
+                stop_stage: outcome.map(|outcome| outcome.stage),
+                crash_report: None,
+            },
+        );
+        self.queue_event(ServerEvent::Stopped { exit_code });
+    }
+
+    /// Returns true while the server is being stopped.
+    pub fn is_stopping(&self) -> bool {
+        self.stopping
+    }
+
+    /// Returns the names of the players online.
+    pub fn online_players(&self) -> &BTreeSet<String> {
+        &self.online_players
+    }
+
+    /// Returns true once the running server has finished starting.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Notices a server process that exited without being stopped through the
+    /// backend, e.g. a crash or `stop` typed into the console.
+    ///
+    /// A zero exit code is reported as [`ServerEvent::Stopped`], anything else
+    /// (including being killed by a signal) as [`ServerEvent::Crashed`], with
+    /// the crash report the game wrote during the run if there is one. A server
+    /// that left `eula=false` in `eula.txt` exited because the EULA isn't
+    /// accepted, which is reported as [`ServerEvent::EulaRequired`] instead.
+    ///
+    /// # Returns
+    /// * `Option<ExitStatus>` - The exit status if the server exited since the last check
+    pub fn check_exited(&mut self) -> Option<ExitStatus> {
+        let status = self.minecraft_server.as_mut()?.try_wait()?;
+        self.minecraft_server = None;
+        let exit_code = status.code();
+        let outcome = if status.success() {
+            RunOutcome::Exited
+        } else {
+            RunOutcome::Crashed
+        };
+        let crash_report = match (outcome, self.start_time) {
+            (RunOutcome::Crashed, Some(start_time)) => {
+                crash_reports::find_new_report(&self.server_dir(), start_time)
+            }
+            _ => None,
+        };
+        self.end_run(
+            outcome,
+            RunEnd {
+                exit_code,
+                stop_stage: None,
+                crash_report: crash_report.clone(),
+            },
+        );
+        // The game writes eula=false and exits on its first start, so the file
+        // tells apart a missing EULA from a crash without racing the output
+        self.eula_required = eula::is_refused(&self.server_dir());
+        let _span = self.span.enter();
+        if self.eula_required {
+            warn!("Minecraft server exited because the EULA is not accepted");
+            self.queue_event(ServerEvent::EulaRequired {
+                eula_file: self.server_dir().join(eula::EULA_FILE),
+            });
+        } else if status.success() {
+            info!("Minecraft server exited on its own");
+            self.queue_event(ServerEvent::Stopped { exit_code });
+        } else {
+            warn!("Minecraft server exited unexpectedly: {}", status);
+            if let Some(report) = &crash_report {
+                warn!("Crash report: {}", report.display());
+            }
+            self.queue_event(ServerEvent::Crashed {
+                exit_code,
+                crash_report,
+            });
+        }
+        Some(status)
+    }
+
+    /// Schedules an automatic restart after an unexpected exit, if enabled,
+    /// and tells clients about it.
+    ///
+    /// # Arguments
+    /// * `exit_code` - Exit code of the process, None if it was killed by a signal
+    ///
+    /// # Returns
+    /// * `Option<(u64, Duration)>` - Id and delay for [`crate::supervisor::restart_after`],
+    ///   None if the server isn't restarted
+    pub fn schedule_restart(&mut self, exit_code: Option<i32>) -> Option<(u64, Duration)> {
+        // Restarting can't help until someone accepts the EULA
+        if self.eula_required {
+            return None;
+        }
+        let _span = self.span.enter();
+        match self.auto_restart.on_exit(exit_code) {
+            RestartDecision::Disabled => None,
+            RestartDecision::Scheduled { id, attempt, delay } => {
+                info!("Restarting in {}s (attempt {})", delay.as_secs(), attempt);
+                self.queue_event(ServerEvent::Restarting {
+                    attempt,
+                    max_attempts: self.auto_restart.config().max_attempts,
+                    delay_secs: delay.as_secs(),
+                });
+                Some((id, delay))
+            }
+            RestartDecision::Abandoned { attempts } => {
+                warn!("Not restarting again after {} restarts", attempts);
+                self.queue_event(ServerEvent::RestartAbandoned { attempts });
+                None
+            }
+        }
+    }
+
+    /// Starts the server again with its last launch spec, unless the restart
+    /// was cancelled while waiting.
+    pub async fn run_restart(&mut self, id: u64) {
+        if !self.auto_restart.take_pending(id) {
+            return;
+        }
+        let Some(launch) = self.last_launch.clone() else {
+            return;
+        };
+        match self.launch(launch).await {
+            Ok(()) => self
+                .auto_restart
+                .finish(id, RestartOutcome::Restarted, None),
+            Err(e) => {
+                error!(server = %self.id, "Could not restart: {}", e);
+                self.auto_restart
+                    .finish(id, RestartOutcome::Failed, Some(e.to_string()));
+            }
+        }
+    }
+
     /// Returns true if the Minecraft server is currently running.
     pub fn is_running(&self) -> bool {
-        self.minecraft_server.is_some()
+        self.minecraft_server
+            .as_ref()
+            .is_some_and(|server| server.is_running())
     }
 
-    /// Sends a command to the Minecraft server console.
-    pub async fn send_command(&mut self, command: &str) -> Result<()> {
-        if let Some(server) = &mut self.minecraft_server {
-            server.send_command(command).await
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::NotConnected,
-                "Minecraft server is not running",
-            ))
+    /// Records the run of the process that just ended in the run history.
+    fn end_run(&mut self, outcome: RunOutcome, end: RunEnd) {
+        if let (Some(started), Some(start_time)) = (self.started_at.take(), self.start_time.take())
+        {
+            self.lifecycle
+                .record(&self.id, start_time, started.elapsed(), outcome, end);
         }
     }
 
-    /// Registers a new WebSocket client and returns a channel for receiving logs
-    pub fn register_client(&mut self) -> (usize, UnboundedReceiver<String>) {
-        let client_id = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
-        let (sender, client_receiver) = unbounded_channel();
-        self.subscribers.insert(client_id, sender);
-        println!(
-            "[WebSocket]: Client #{} connected. Total clients: {}",
-            client_id,
-            self.subscribers.len()
+    /// Returns when the current server process was started.
+    pub fn start_time(&self) -> Option<DateTime<Local>> {
+        self.start_time
+    }
+
+    /// Returns the game version and mod loader the server printed while
+    /// starting, as far as it got; all None until then.
+    pub fn version(&self) -> &ServerVersion {
+        &self.version
+    }
+
+    /// Returns the "Can't keep up!" warnings counted since the backend started.
+    pub fn overload(&self) -> &OverloadStats {
+        &self.overload
+    }
+
+    /// Returns true if the last run exited because the EULA isn't accepted.
+    pub fn eula_required(&self) -> bool {
+        self.eula_required
+    }
+
+    /// Returns the Java runtime the last jar was started with.
+    pub fn java_version(&self) -> Option<&JavaVersion> {
+        self.java_version.as_ref()
+    }
+
+    /// Returns how many whole seconds the current server process has been running.
+    pub fn uptime_seconds(&self) -> Option<u64> {
+        self.started_at.map(|started| started.elapsed().as_secs())
+    }
+
+    /// Returns the server's data directory.
+    ///
+    /// This is the working directory of the last started server, falling back to
+    /// the configured one (see [`ServerConfig::server_dir`]) if it hasn't been
+    /// started yet.
+    pub fn server_dir(&self) -> PathBuf {
+        self.server_dir
+            .clone()
+            .unwrap_or_else(|| self.server_config.server_dir())
+    }
+
+    /// Returns a handle for sending commands to the server console, usable
+    /// after the state lock has been released.
+    ///
+    /// # Returns
+    /// * `Result<CommandSender, ServerError>` - NotRunning or StdinUnavailable on error
+    pub fn command_sender(&self) -> Result<CommandSender, ServerError> {
+        self.minecraft_server
+            .as_ref()
+            .ok_or(ServerError::NotRunning)?
+            .command_sender()
+    }
+
+    /// Returns a command sender together with a subscription to the output
+    /// from this point on, for reading the response to a command.
+    fn command_handles(&self) -> Result<(CommandSender, broadcast::Receiver<String>), ServerError> {
+        let server = self
+            .minecraft_server
+            .as_ref()
+            .ok_or(ServerError::NotRunning)?;
+        Ok((server.command_sender()?, server.subscribe_output()))
+    }
+
+    /// Returns the server's RCON client.
+    pub fn rcon(&self) -> Arc<Rcon> {
+        self.rcon.clone()
+    }
+
+    /// Returns true if commands can be written to the running server's console.
+    pub fn console_available(&self) -> bool {
+        self.minecraft_server
+            .as_ref()
+            .is_some_and(|server| server.console_available())
+    }
+
+    /// Returns the OS process ID of the running server.
+    pub fn server_pid(&self) -> Option<u32> {
+        self.minecraft_server
+            .as_ref()
+            .and_then(|server| server.pid())
+    }
+
+    /// Returns the server's broadcaster, for sending output to its clients
+    /// and registering new clients without holding the state lock.
+    pub fn broadcaster(&self) -> Arc<Broadcaster> {
+        self.broadcaster.clone()
+    }
+
+    /// Broadcast a structured event to all connected WebSocket clients,
+    /// send it as a notification if it is one of the selected events, and post
+    /// it to the outbound webhooks
+    pub fn broadcast_event(&mut self, event: &ServerEvent) {
+        let announced;
+        let event = match event {
+            ServerEvent::Overloaded {
+                behind_ms,
+                ticks_behind,
+                ..
+            } => match self.overload.record(*behind_ms) {
+                Some(held_back) => {
+                    announced = ServerEvent::Overloaded {
+                        behind_ms: *behind_ms,
+                        ticks_behind: *ticks_behind,
+                        held_back,
+                    };
+                    &announced
+                }
+                // Counted, but part of a burst that was already announced
+                None => return,
+            },
+            event => event,
+        };
+        match event {
+            ServerEvent::PlayerJoin { name } => {
+                self.online_players.insert(name.clone());
+            }
+            ServerEvent::PlayerLeave { name } => {
+                self.online_players.remove(name);
+            }
+            ServerEvent::Ready => self.ready = true,
+            ServerEvent::Version(version) => self.version.merge(version),
+            ServerEvent::Started { .. } => {
+                self.online_players.clear();
+                self.ready = false;
+                self.version = ServerVersion::default();
+            }
+            ServerEvent::Stopped { .. } | ServerEvent::Crashed { .. } => {
+                self.online_players.clear();
+                self.ready = false;
+            }
+            _ => {}
+        }
+        if let Some(notification) = Notification::from_event(&self.id, event) {
+            self.notifier.notify(notification);
+        }
+        self.webhooks.dispatch(&self.id, event);
+        self.broadcaster.broadcast_event(event);
+    }
+
+    /// Queues an event on the log channel, behind any output still waiting to be broadcast.
+    pub fn queue_event(&self, event: ServerEvent) {
+        if self.log_sender.send(Broadcast::Event(event)).is_err() {
+            error!("Log channel closed, dropping event");
+        }
+    }
+}
+
+/// AppState holds the shared state for your application.
+///
+/// Each Minecraft server lives in its own [`ManagedServer`]. Backups, ban
+/// lists, properties, resource sampling and TPS polling work on the default
+/// server, the one configured in the `[server]` section.
+pub struct AppState {
+    /// Managed servers by id, always including [`DEFAULT_SERVER_ID`].
+    servers: HashMap<ServerId, ManagedServer>,
+    /// Channel all servers send their output and events through.
+    log_channel: UnboundedSender<(ServerId, Broadcast)>,
+    /// Settings of the `[server]` section, the base for servers added at runtime.
+    base_server_config: ServerConfig,
+    /// Settings for RCON clients, shared by all servers.
+    rcon_config: RconConfig,
+    /// Settings for automatic restarts, the starting point of each server's.
+    auto_restart_config: AutoRestartConfig,
+    /// Commands clients may send, adjustable at runtime.
+    pub command_policy: CommandPolicy,
+    /// Recent commands sent by clients, across all servers.
+    pub command_history: CommandHistory,
+    /// Settings for world backups.
+    pub backup_config: BackupConfig,
+    /// Settings for changing the MOTD.
+    pub motd_config: MotdConfig,
+    /// Settings for managing mods.
+    pub mods_config: ModsConfig,
+    /// True while a backup is being written, to keep backups from overlapping.
+    pub backup_in_progress: bool,
+    /// Schedule of automatic backups, adjustable at runtime.
+    pub backup_schedule: BackupSchedule,
+    /// Settings for daily restarts.
+    pub restart_config: RestartScheduleConfig,
+    /// Schedule of the daily restart, adjustable at runtime.
+    pub restart_schedule: RestartSchedule,
+    /// Warnings before a stop or restart requested with `?countdown=`.
+    pub countdown_config: CountdownConfig,
+    /// Counters exported at `/metrics`.
+    pub metrics: Arc<Metrics>,
+    /// Recent memory and CPU samples of the default server's process.
+    pub resource_history: ResourceHistory,
+    /// Settings for polling the server's tick rate and memory usage.
+    pub performance_config: PerformanceConfig,
+    /// Latest tick rate and memory usage reported by the default server.
+    pub performance: PerformanceStats,
+    /// Settings for WebSocket connections.
+    pub websocket_config: WebSocketConfig,
+    /// Settings for the console log file.
+    pub log_file_config: LogFileConfig,
+    /// Sends webhook notifications.
+    pub notifier: Notifier,
+    /// Posts server events to the outbound webhooks.
+    pub webhooks: Webhooks,
+    /// Records administrative actions.
+    pub audit: AuditLog,
+    /// Recent runs of every server's process.
+    pub lifecycle: Lifecycle,
+    /// Named lists of commands, adjustable at runtime.
+    pub macros: Macros,
+}
+
+impl AppState {
+    /// Creates a new instance of AppState with the provided log channel and configuration.
+    ///
+    /// The default server is set up from the `[server]` section and one more
+    /// server for each `[[servers]]` table.
+    pub fn new(log_channel: UnboundedSender<(ServerId, Broadcast)>, config: Config) -> Self {
+        let backup_config = config.backup;
+        let mut backup_schedule = BackupSchedule::default();
+        if let Err(e) = backup_schedule.set_interval(backup_config.interval.as_deref()) {
+            warn!("Automatic backups disabled: {}", e);
+        }
+        let restart_config = config.restart_schedule;
+        let mut restart_schedule = RestartSchedule {
+            skip_countdown_if_empty: restart_config.skip_countdown_if_empty,
+            ..Default::default()
+        };
+        if let Err(e) = restart_schedule.set_times(&restart_config.all_times()) {
+            warn!("Scheduled restarts disabled: {}", e);
+        }
+
+        let mut state = AppState {
+            servers: HashMap::new(),
+            log_channel,
+            base_server_config: config.server.clone(),
+            rcon_config: config.rcon,
+            auto_restart_config: config.auto_restart,
+            command_policy: config.command_policy,
+            command_history: CommandHistory::new(&config.history),
+            backup_config,
+            motd_config: config.motd,
+            mods_config: config.mods,
+            backup_in_progress: false,
+            backup_schedule,
+            restart_config,
+            restart_schedule,
+            countdown_config: config.countdown,
+            metrics: Arc::new(Metrics::default()),
+            resource_history: ResourceHistory::default(),
+            performance_config: config.performance,
+            websocket_config: config.websocket,
+            log_file_config: config.log_file,
+            performance: PerformanceStats::default(),
+            notifier: Notifier::new(config.notifications),
+            webhooks: Webhooks::new(config.webhooks),
+            audit: AuditLog::new(config.audit),
+            lifecycle: Lifecycle::new(&config.lifecycle),
+            macros: Macros::new(&config.macros),
+        };
+        state.insert_server(DEFAULT_SERVER_ID.to_string(), config.server);
+        for definition in config.servers {
+            let server_config = definition.server_config(&state.base_server_config);
+            state.insert_server(definition.id, server_config);
+        }
+        state
+    }
+
+    /// Adds a server defined at runtime.
+    ///
+    /// The server isn't started, but its path is checked so a typo is
+    /// reported now rather than on the first start.
+    ///
+    /// # Returns
+    /// * `Result<&ManagedServer, ServerError>` - InvalidServerId, ServerExists or InvalidPath
+    pub fn add_server(
+        &mut self,
+        definition: ServerDefinition,
+    ) -> Result<&ManagedServer, ServerError> {
+        if !is_valid_server_id(&definition.id) {
+            return Err(ServerError::InvalidServerId { id: definition.id });
+        }
+        if self.servers.contains_key(&definition.id) {
+            return Err(ServerError::ServerExists { id: definition.id });
+        }
+        let server_config = definition.server_config(&self.base_server_config);
+        LaunchSpec::from_config(None, &server_config)?;
+        info!(server = %definition.id, "Added server");
+        Ok(self.insert_server(definition.id, server_config))
+    }
+
+    /// Creates and stores a stopped server.
+    fn insert_server(&mut self, id: ServerId, server_config: ServerConfig) -> &ManagedServer {
+        let server = ManagedServer::new(id.clone(), server_config, self);
+        self.servers.entry(id).or_insert(server)
+    }
+
+    /// Looks up a server by id.
+    ///
+    /// # Arguments
+    /// * `id` - Id of the server, or None for the default server
+    ///
+    /// # Returns
+    /// * `Result<&ManagedServer, ServerError>` - UnknownServer if there is no such server
+    pub fn server(&self, id: Option<&str>) -> Result<&ManagedServer, ServerError> {
+        let id = id.unwrap_or(DEFAULT_SERVER_ID);
+        self.servers
+            .get(id)
+            .ok_or_else(|| ServerError::UnknownServer { id: id.to_string() })
+    }
+
+    /// Looks up a server by id for changing it; see [`AppState::server`].
+    pub fn server_mut(&mut self, id: Option<&str>) -> Result<&mut ManagedServer, ServerError> {
+        let id = id.unwrap_or(DEFAULT_SERVER_ID);
+        self.servers
+            .get_mut(id)
+            .ok_or_else(|| ServerError::UnknownServer { id: id.to_string() })
+    }
+
+    /// Returns the server configured in the `[server]` section.
+    pub fn default_server(&self) -> &ManagedServer {
+        &self.servers[DEFAULT_SERVER_ID]
+    }
+
+    /// Returns all servers, sorted by id.
+    pub fn servers(&self) -> Vec<&ManagedServer> {
+        let mut servers: Vec<_> = self.servers.values().collect();
+        servers.sort_by(|a, b| a.id.cmp(&b.id));
+        servers
+    }
+
+    /// Returns whether output and events can still reach the log broadcaster.
+    pub fn broadcaster_open(&self) -> bool {
+        !self.log_channel.is_closed()
+    }
+
+    /// Returns the number of WebSocket clients connected to any server.
+    pub fn websocket_clients(&self) -> usize {
+        self.servers
+            .values()
+            .map(|server| server.broadcaster.websocket_clients())
+            .sum()
+    }
+
+    /// Describes the clients connected to any server, ordered by id.
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        let mut clients: Vec<ClientInfo> = self
+            .servers
+            .values()
+            .flat_map(|server| server.broadcaster.clients())
+            .collect();
+        clients.sort_by_key(|client| client.id);
+        clients
+    }
+
+    /// Disconnects a client of any server on an admin's request.
+    ///
+    /// # Returns
+    /// * `Result<ServerId, ServerError>` - The client's server, or UnknownClient
+    pub fn disconnect_client(&self, client_id: usize) -> Result<ServerId, ServerError> {
+        self.servers
+            .values()
+            .find_map(|server| {
+                server
+                    .broadcaster
+                    .disconnect_client(client_id)
+                    .then(|| server.id().to_string())
+            })
+            .ok_or(ServerError::UnknownClient { id: client_id })
+    }
+
+    /// Returns all servers for changing them, in no particular order.
+    pub fn servers_mut(&mut self) -> impl Iterator<Item = &mut ManagedServer> {
+        self.servers.values_mut()
+    }
+}
+
+/// Sends a console command to a server.
+///
+/// The state lock is only held to look up the server; the command is written
+/// after it has been released, so a slow write doesn't hold up other clients
+/// or the log broadcaster. If the server's console input is closed while the
+/// process keeps running, the command is tried once over RCON when the server
+/// has it enabled, and the response is broadcast as console output.
+///
+/// # Arguments
+/// * `server` - Id of the server to send the command to, or None for the default server
+/// * `command` - The console command
+///
+/// # Returns
+/// * `Result<(), ServerError>` - UnknownServer, NotRunning, InvalidCommand, StdinUnavailable
+///   or CommandFailed on error
+pub async fn send_command(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    command: &str,
+) -> Result<(), ServerError> {
+    let (commands, rcon, server_dir, log_sender) = {
+        let app_state = state.read().await;
+        let server = app_state.server(server)?;
+        (
+            server.command_sender(),
+            server.rcon(),
+            server.server_dir(),
+            server.log_sender.clone(),
+        )
+    };
+    let result = match commands {
+        Ok(commands) => commands.send(command).await,
+        Err(e) => Err(e),
+    };
+    let Err(ServerError::StdinUnavailable) = result else {
+        return result;
+    };
+    match rcon.exec(&server_dir, command).await {
+        Ok(response) => {
+            info!("Console input is closed, sent '{}' over RCON", command);
+            for line in response.lines().filter(|line| !line.trim().is_empty()) {
+                let _ = log_sender.send(LogEntry::console(line.to_string()).into());
+            }
+            Ok(())
+        }
+        Err(_) => Err(ServerError::StdinUnavailable),
+    }
+}
+
+/// Checks a command from a client against the command policy and the
+/// caller's scope, logging it and recording it in the command history if denied.
+///
+/// # Arguments
+/// * `server` - Id of the server the command is for, or None for the default server
+/// * `caller` - Who sent the command; its scope is None when logins are disabled
+/// * `command` - The console command
+///
+/// # Returns
+/// * `Result<(), ServerError>` - CommandDenied naming the rule that denied it, or
+///   Forbidden naming the scope the command needs
+pub async fn check_command(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    caller: &Caller,
+    command: &str,
+) -> Result<(), ServerError> {
+    let mut app_state = state.write().await;
+    let result = app_state.command_policy.check(command, caller.scope);
+    if let Err(e) = &result {
+        let server = server.unwrap_or(DEFAULT_SERVER_ID);
+        info!(
+            server,
+            user = caller.user.as_deref(),
+            "Denied command '{}': {}",
+            command,
+            e
         );
-        return (client_id, client_receiver);
-    }
-
-    /// Unregisters a WebSocket client when they disconnect
-    pub fn unregister_client(&mut self, client_id: usize) {
-        if self.subscribers.remove(&client_id).is_some() {
-            println!(
-                "[WebSocket]: Client #{} disconnected. Total clients: {}",
-                client_id,
-                self.subscribers.len()
-            );
-        }
-    }
-
-    /// Broadcast a message to all connected WebSocket clients
-    pub fn broadcast_log(&mut self, message: String) {
-        // Only log client count if we have subscribers
-        if !self.subscribers.is_empty() {
-            // Track any clients that need to be disconnected
-            let mut disconnected_clients = Vec::new();
-
-            // For all the clients in the subscribers map
-            // we send the message
-            // If the send fails, we log the error and mark the client for disconnection
-            // This is to avoid sending messages to clients that are no longer connected
-            for (&client_id, client_receiver) in &self.subscribers {
-                match client_receiver.send(message.clone()) {
-                    Ok(_) => {} // Success case - no need to log every message
-                    Err(e) => {
-                        println!(
-                            "[WebSocket]: Error sending log to client #{}: {:?}",
-                            client_id, e
-                        );
-                        disconnected_clients.push(client_id);
-                    }
+        app_state
+            .command_history
+            .record(server, caller.client_id, command, CommandStatus::Denied);
+    }
+    result
+}
+
+/// Records a client's command in the command history once it reached the server.
+///
+/// # Arguments
+/// * `server` - Id of the server the command was for, or None for the default server
+/// * `client_id` - WebSocket client that sent the command, None for HTTP requests
+/// * `command` - The console command
+pub async fn record_command(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    client_id: Option<usize>,
+    command: &str,
+) {
+    state.write().await.command_history.record(
+        server.unwrap_or(DEFAULT_SERVER_ID),
+        client_id,
+        command,
+        CommandStatus::Executed,
+    );
+}
+
+/// Runs a macro's commands on a server in order, `[macros] delay_ms` apart.
+///
+/// Each command is checked against the command policy and the caller's
+/// scope, sent like a command on its own and recorded in the command
+/// history, and each step is shown on the server's console. The run is
+/// recorded in the audit log once, with all of the macro's commands.
+///
+/// # Arguments
+/// * `server` - Id of the server, or None for the default server
+/// * `caller` - Who asked for the run
+/// * `name` - Name of the macro
+/// * `continue_on_error` - Whether to go on with the next command after one failed
+///
+/// # Returns
+/// * `Result<MacroRun, ServerError>` - What came of each command; UnknownServer,
+///   UnknownMacro or NotRunning before anything was sent
+pub async fn run_macro(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    caller: &Caller,
+    name: &str,
+    continue_on_error: bool,
+) -> Result<MacroRun, ServerError> {
+    let (commands, delay, broadcaster) = {
+        let app_state = state.read().await;
+        let managed = app_state.server(server)?;
+        let commands = app_state
+            .macros
+            .get(name)
+            .ok_or_else(|| ServerError::UnknownMacro {
+                name: name.to_string(),
+            })?
+            .to_vec();
+        if !managed.is_running() {
+            return Err(ServerError::NotRunning);
+        }
+        (commands, app_state.macros.delay(), managed.broadcaster())
+    };
+    let announce = |message: String| {
+        broadcaster.broadcast_log(LogEntry::console(format!("[Macro]: {}", message)));
+    };
+
+    let mut steps = Vec::with_capacity(commands.len());
+    let mut failure = None;
+    for (index, command) in commands.iter().enumerate() {
+        if index > 0 {
+            tokio::time::sleep(delay).await;
+        }
+        let result = match check_command(state, server, caller, command).await {
+            Ok(()) => send_command(state, server, command).await,
+            Err(e) => Err(e),
+        };
+        let step = format!("{} {}/{}", name, index + 1, commands.len());
+        match result {
+            Ok(()) => {
+                record_command(state, server, caller.client_id, command).await;
+                announce(format!("{} sent: {}", step, command));
+                steps.push(MacroStep {
+                    command: command.clone(),
+                    sent: true,
+                    error: None,
+                });
+            }
+            Err(e) => {
+                warn!("Macro {} failed at '{}': {}", step, command, e);
+                announce(format!("{} failed: {}: {}", step, command, e));
+                steps.push(MacroStep {
+                    command: command.clone(),
+                    sent: false,
+                    error: Some(e.to_string()),
+                });
+                failure.get_or_insert(e);
+                if !continue_on_error {
+                    break;
                 }
             }
+        }
+    }
+
+    let completed = failure.is_none();
+    let arguments = format!("{}: {}", name, commands.join("; "));
+    let result = failure.map_or(Ok(()), Err);
+    audit(
+        state,
+        caller,
+        server,
+        AuditAction::Macro,
+        Some(&arguments),
+        &result,
+    )
+    .await;
+    Ok(MacroRun {
+        name: name.to_string(),
+        steps,
+        completed,
+    })
+}
+
+/// Records an administrative action and its result in the audit log.
+///
+/// # Arguments
+/// * `caller` - Who asked for the action
+/// * `server` - Id of the server the action was for, or None for the default server
+/// * `action` - What was done
+/// * `arguments` - The command, or the script path of a start
+/// * `result` - What came of it
+pub async fn audit<T>(
+    state: &RwLock<AppState>,
+    caller: &Caller,
+    server: Option<&str>,
+    action: AuditAction,
+    arguments: Option<&str>,
+    result: &Result<T, ServerError>,
+) {
+    state.read().await.audit.record(
+        caller,
+        server.unwrap_or(DEFAULT_SERVER_ID),
+        action,
+        arguments,
+        result,
+    );
+}
 
-            // Clean up disconnected clients
-            for client_id in disconnected_clients {
-                println!(
-                    "[WebSocket]: Client #{} disconnected due to send failure",
-                    client_id
-                );
-                self.unregister_client(client_id);
+/// Announces a client's command to every client of the server as a console
+/// line, if `audit_commands` is enabled.
+///
+/// # Arguments
+/// * `server` - Id of the server the command is for, or None for the default server
+/// * `client_id` - WebSocket client that sent the command
+/// * `command` - The console command
+pub async fn announce_command(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    client_id: usize,
+    command: &str,
+) {
+    let app_state = state.read().await;
+    if !app_state.websocket_config.audit_commands {
+        return;
+    }
+    if let Ok(server) = app_state.server(server) {
+        server.broadcaster.broadcast_log(LogEntry::console(format!(
+            "[Audit]: [{}] <client {}> ran: {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            client_id,
+            command.trim()
+        )));
+    }
+}
+
+/// A running server taken out of its [`ManagedServer`] to be stopped
+/// without holding the state lock.
+struct Stop {
+    process: Box<dyn ServerProcess>,
+    /// Data directory of the server, for its RCON settings.
+    server_dir: PathBuf,
+    /// How long to wait for the world to be saved.
+    save_timeout: Duration,
+    /// Sent over RCON if the server closed its console input.
+    stop_command: String,
+    rcon: Arc<Rcon>,
+    /// Span of the server, the parent of what is logged about the stop.
+    span: Span,
+}
+
+impl Stop {
+    /// Saves the world and stops the server.
+    ///
+    /// If saving fails the server is stopped anyway. If the server closed its
+    /// console input, the stop command goes over RCON, or without RCON the
+    /// server is sent a signal.
+    async fn run(&mut self) -> Result<Option<StopOutcome>, ServerError> {
+        let rx = self.process.subscribe_output();
+        let saved = match self.process.send_command("save-all flush").await {
+            Ok(()) => wait_for_save(rx, self.save_timeout).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            warn!(parent: &self.span, "Could not save the world before stopping: {}", e);
+        }
+        let stop_sent = !self.process.console_available()
+            && self.rcon.is_available(&self.server_dir)
+            && self.stop_over_rcon().await;
+        self.process
+            .stop(stop_sent)
+            .instrument(self.span.clone())
+            .await
+    }
+
+    /// Sends the stop command over RCON, for a server that closed its console input.
+    ///
+    /// # Returns
+    /// * `bool` - True if the server accepted the command
+    async fn stop_over_rcon(&self) -> bool {
+        let command = &self.stop_command;
+        match self.rcon.exec(&self.server_dir, command).await {
+            Ok(_) => {
+                info!(parent: &self.span, "Console input is closed, sent '{}' over RCON", command);
+                true
+            }
+            Err(e) => {
+                warn!(parent: &self.span, "Could not send '{}' over RCON: {}", command, e);
+                false
             }
         }
     }
 }
+
+/// Stops a server, taking the state lock only to take the server out and to
+/// record the outcome, so status requests and commands to other servers
+/// aren't held up while it saves and shuts down.
+///
+/// The world is saved first; if that fails the server is stopped anyway.
+/// A server waiting for an automatic restart stays stopped instead.
+///
+/// # Arguments
+/// * `server` - Id of the server to stop, or None for the default server
+///
+/// # Returns
+/// * `Result<Option<StopOutcome>, ServerError>` - How the running server was
+///   stopped, None if only a pending automatic restart was cancelled; UnknownServer,
+///   Stopping if it is already being stopped, NotRunning if no server is running or
+///   waiting to restart, or the stop's IO error
+pub async fn stop_server(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+) -> Result<Option<StopOutcome>, ServerError> {
+    let (id, stop) = {
+        let mut app_state = state.write().await;
+        let managed = app_state.server_mut(server)?;
+        (managed.id.clone(), managed.begin_stop()?)
+    };
+    let Some(mut stop) = stop else {
+        return Ok(None);
+    };
+    let result = stop.run().await;
+    // Servers aren't removed, so the server is still there
+    if let Ok(managed) = state.write().await.server_mut(Some(&id)) {
+        managed.finish_stop(stop.process, &result);
+    }
+    result
+}
+
+/// Stops a server, makes a change while it is stopped, and starts it again
+/// the way it was last started. The server is started again even if the
+/// change failed.
+///
+/// The state lock is only held while the server is taken out, put back and
+/// started, as in [`stop_server`].
+///
+/// # Arguments
+/// * `server` - Id of the server to restart, or None for the default server
+/// * `change` - Runs between the stop and the start, e.g. editing server.properties
+///
+/// # Returns
+/// * `Result<T, ServerError>` - What the change returned; UnknownServer, NotRunning
+///   if the server was never started, or the error of the stop, the change or the
+///   start, e.g. AlreadyRunning if another request started it meanwhile
+pub async fn restart_server<T>(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    change: impl FnOnce() -> Result<T, ServerError>,
+) -> Result<T, ServerError> {
+    let launch = state
+        .read()
+        .await
+        .server(server)?
+        .last_launch
+        .clone()
+        .ok_or(ServerError::NotRunning)?;
+    stop_server(state, server).await?;
+    let changed = change();
+    let mut app_state = state.write().await;
+    let managed = app_state.server_mut(server)?;
+    managed.check_stopped()?;
+    managed.launch(launch).await?;
+    changed
+}
+
+/// Saves a server's world, waiting for the confirmation without holding the state lock.
+///
+/// # Arguments
+/// * `server` - Id of the server to save, or None for the default server
+/// * `timeout` - How long to wait for the confirmation, or None for the server's save timeout
+///
+/// # Returns
+/// * `Result<(), ServerError>` - UnknownServer, NotRunning, Timeout if the save
+///   wasn't confirmed in time, or the command's error
+pub async fn save_world(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<(), ServerError> {
+    let (commands, rx, timeout) = {
+        let app_state = state.read().await;
+        let server = app_state.server(server)?;
+        let (commands, rx) = server.command_handles()?;
+        (
+            commands,
+            rx,
+            timeout.unwrap_or(server.server_config.save_timeout),
+        )
+    };
+    flush_world(&commands, rx, timeout).await
+}
+
+/// Sends `save-all flush` and waits for the "Saved the game" confirmation.
+///
+/// # Arguments
+/// * `rx` - Output subscription created before the command is sent
+/// * `timeout` - How long to wait for the confirmation
+async fn flush_world(
+    commands: &CommandSender,
+    rx: broadcast::Receiver<String>,
+    timeout: Duration,
+) -> Result<(), ServerError> {
+    commands.send("save-all flush").await?;
+    wait_for_save(rx, timeout).await
+}
+
+/// Waits for the "Saved the game" confirmation of `save-all flush`.
+async fn wait_for_save(
+    mut rx: broadcast::Receiver<String>,
+    timeout: Duration,
+) -> Result<(), ServerError> {
+    output::wait_for_line(&mut rx, |line| line.contains("Saved the game"), timeout)
+        .await
+        .map(|_| ())
+        .ok_or_else(|| ServerError::no_response("Save", timeout))
+}
+
+/// Sends a console command and collects the lines it prints.
+///
+/// The output subscription is created before the command is written so no
+/// response line can be missed, and the state lock is released before the
+/// command is written. Lines are collected until the console goes quiet.
+///
+/// # Arguments
+/// * `server` - Id of the server to run the command on, or None for the default server
+/// * `command` - The console command
+/// * `timeout` - How long to wait for the first line, or None for the server's response timeout
+///
+/// # Returns
+/// * `Result<Vec<String>, ServerError>` - The output, or why the command wasn't sent;
+///   Timeout if the server printed nothing in time
+pub async fn command_output(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    command: &str,
+    timeout: Option<Duration>,
+) -> Result<Vec<String>, ServerError> {
+    let (commands, mut rx, timeout) = {
+        let app_state = state.read().await;
+        let server = app_state.server(server)?;
+        let (commands, rx) = server.command_handles()?;
+        (
+            commands,
+            rx,
+            timeout.unwrap_or(server.server_config.response_timeout),
+        )
+    };
+    commands.send(command).await?;
+    output::collect_lines(
+        &mut rx,
+        timeout,
+        COMMAND_OUTPUT_QUIET,
+        COMMAND_OUTPUT_MAX_WAIT,
+    )
+    .await
+    .ok_or_else(|| ServerError::no_response("Command", timeout))
+}
+
+/// Runs a command and returns its response as text.
+///
+/// With RCON enabled the response is exactly what the server answered to the
+/// command. Otherwise the command goes through the console and the output that
+/// follows it is collected, as with [`command_output`].
+///
+/// # Arguments
+/// * `server` - Id of the server to run the command on, or None for the default server
+/// * `command` - The console command
+/// * `timeout` - How long to wait for console output, or None for the server's response
+///   timeout; RCON keeps its own timeout
+///
+/// # Returns
+/// * `Result<String, ServerError>` - The response lines joined by newlines, or why the
+///   command couldn't be run
+pub async fn command_response(
+    state: &RwLock<AppState>,
+    server: Option<&str>,
+    command: &str,
+    timeout: Option<Duration>,
+) -> Result<String, ServerError> {
+    let (rcon, server_dir) = {
+        let app_state = state.read().await;
+        let server = app_state.server(server)?;
+        if !server.is_running() {
+            return Err(ServerError::NotRunning);
+        }
+        (server.rcon(), server.server_dir())
+    };
+    match rcon.exec(&server_dir, command).await {
+        Err(ServerError::RconDisabled) => {
+            let output = command_output(state, server, command, timeout).await?;
+            Ok(output
+                .iter()
+                .map(|line| log_parser::message_body(line))
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        result => result,
+    }
+}
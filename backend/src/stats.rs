@@ -0,0 +1,106 @@
+//! Resource usage sampling for the Minecraft server process.
+//!
+//! A background task reads the memory and CPU usage of the server's child
+//! process (see [`ProcessStats`]) every `[performance] resource_interval_secs`
+//! and keeps a rolling window of samples in AppState. The latest sample is
+//! shown in `/status` and `/metrics`, and the whole series is available at
+//! `/stats` for charting.
+
+use crate::metrics::ProcessStats;
+use crate::state::AppState;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Number of samples kept, one hour at the default interval of 5s.
+const HISTORY_LEN: usize = 720;
+
+/// One measurement of the server process.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceSample {
+    /// When the sample was taken.
+    pub timestamp: DateTime<Local>,
+    /// Resident memory in bytes.
+    pub memory_bytes: u64,
+    /// CPU usage since the previous sample, in percent of one core.
+    pub cpu_percent: f64,
+}
+
+/// Rolling window of recent resource samples.
+#[derive(Debug, Default)]
+pub struct ResourceHistory {
+    samples: VecDeque<ResourceSample>,
+}
+
+impl ResourceHistory {
+    /// Adds a sample, dropping the oldest one if the window is full.
+    pub fn push(&mut self, sample: ResourceSample) {
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Returns the most recent sample.
+    pub fn latest(&self) -> Option<&ResourceSample> {
+        self.samples.back()
+    }
+
+    /// Returns the samples taken within `window` of now, oldest first.
+    pub fn since(&self, window: Duration) -> Vec<ResourceSample> {
+        let cutoff = chrono::Duration::from_std(window)
+            .ok()
+            .and_then(|window| Local::now().checked_sub_signed(window));
+        self.samples
+            .iter()
+            .filter(|sample| cutoff.is_none_or(|cutoff| sample.timestamp >= cutoff))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Samples the server process forever.
+///
+/// Only the PID of our own child process is read, and the child isn't reaped
+/// until it is stopped, so the PID can't be reused by another process while
+/// we sample it. When the PID changes (the server was restarted) the CPU
+/// baseline is reset; while the server is stopped nothing is sampled.
+pub async fn run_sampler(state: Arc<RwLock<AppState>>) {
+    let interval = state.read().await.performance_config.resource_interval;
+    let mut ticker = tokio::time::interval(interval);
+    // PID, CPU seconds and time of the previous reading
+    let mut previous: Option<(u32, f64, Instant)> = None;
+    loop {
+        ticker.tick().await;
+
+        let pid = state.read().await.default_server().server_pid();
+        let Some((pid, stats)) = pid.and_then(|pid| ProcessStats::read(pid).map(|s| (pid, s)))
+        else {
+            previous = None;
+            continue;
+        };
+        let now = Instant::now();
+
+        let cpu_percent = match previous {
+            Some((prev_pid, prev_cpu, prev_at)) if prev_pid == pid => {
+                let elapsed = now.duration_since(prev_at).as_secs_f64();
+                ((stats.cpu_seconds - prev_cpu) / elapsed * 100.0).max(0.0)
+            }
+            _ => 0.0,
+        };
+        previous = Some((pid, stats.cpu_seconds, now));
+
+        let mut app_state = state.write().await;
+        // The server may have stopped while we were reading /proc
+        if app_state.default_server().server_pid() == Some(pid) {
+            app_state.resource_history.push(ResourceSample {
+                timestamp: Local::now(),
+                memory_bytes: stats.resident_memory_bytes,
+                cpu_percent,
+            });
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Automatic restarts of servers that exit unexpectedly.
+//!
+//! When the exit watcher notices a server process that exited without being
+//! stopped through `/stop`, whatever its exit code, a restart is scheduled
+//! with the launch spec the server was last started with. Each attempt waits
+//! twice as long as the one before, and once `max_attempts` restarts happened
+//! within the window the server is left stopped, so a crash loop doesn't go on
+//! forever. Attempts are broadcast to the console as `restarting` and
+//! `restart_abandoned` events and kept for `GET /restarts`.
+//!
+//! A stop through `/stop` never leads to a restart, and cancels one that is
+//! still waiting, as does starting the server by hand.
+
+use crate::config;
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Number of restarts kept for `GET /restarts`.
+const RESTART_HISTORY: usize = 50;
+
+/// Settings for restarting crashed servers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoRestartConfig {
+    /// Whether servers are restarted when they exit unexpectedly. Can be
+    /// changed per server at runtime with `PUT /autorestart`.
+    pub enabled: bool,
+    /// Most restarts within the window before giving up.
+    pub max_attempts: u32,
+    /// Period over which restarts are counted.
+    #[serde(rename = "window_secs", deserialize_with = "config::secs")]
+    pub window: Duration,
+    /// Wait before the first restart, doubled for each further one in the window.
+    #[serde(rename = "initial_delay_secs", deserialize_with = "config::secs")]
+    pub initial_delay: Duration,
+    /// Longest wait before a restart.
+    #[serde(rename = "max_delay_secs", deserialize_with = "config::secs")]
+    pub max_delay: Duration,
+}
+
+impl Default for AutoRestartConfig {
+    fn default() -> Self {
+        AutoRestartConfig {
+            enabled: false,
+            max_attempts: 3,
+            window: Duration::from_secs(10 * 60),
+            initial_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl AutoRestartConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_AUTO_RESTART` - `true` to restart crashed servers
+    /// * `MC_AUTO_RESTART_MAX_ATTEMPTS` - most restarts within the window
+    /// * `MC_AUTO_RESTART_WINDOW_SECS` - seconds over which restarts are counted
+    /// * `MC_AUTO_RESTART_DELAY_SECS` - seconds before the first restart
+    /// * `MC_AUTO_RESTART_MAX_DELAY_SECS` - longest wait before a restart
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(enabled) = config::env_parse("MC_AUTO_RESTART")? {
+            self.enabled = enabled;
+        }
+        if let Some(max_attempts) = config::env_parse("MC_AUTO_RESTART_MAX_ATTEMPTS")? {
+            self.max_attempts = max_attempts;
+        }
+        if let Some(window) = config::env_secs("MC_AUTO_RESTART_WINDOW_SECS")? {
+            self.window = window;
+        }
+        if let Some(delay) = config::env_secs("MC_AUTO_RESTART_DELAY_SECS")? {
+            self.initial_delay = delay;
+        }
+        if let Some(delay) = config::env_secs("MC_AUTO_RESTART_MAX_DELAY_SECS")? {
+            self.max_delay = delay;
+        }
+        Ok(())
+    }
+
+    /// Checks that the values make sense.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("auto_restart max_attempts must be at least 1".to_string());
+        }
+        if self.window.is_zero() {
+            return Err("auto_restart window_secs must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What became of a scheduled restart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartOutcome {
+    /// Still waiting for its delay.
+    Pending,
+    /// The server was started again.
+    Restarted,
+    /// Starting the server failed.
+    Failed,
+    /// The server was started or stopped by hand, or auto-restart was
+    /// disabled, before the delay was over.
+    Cancelled,
+    /// Too many restarts in the window; the server was left stopped.
+    Abandoned,
+}
+
+/// An unexpected exit and what was done about it, as listed by `GET /restarts`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestartRecord {
+    /// Number of the attempt within the window, starting at 1.
+    pub attempt: u32,
+    /// Exit code of the process, absent if it was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub exited_at: DateTime<Utc>,
+    /// Wait before the restart.
+    pub delay_secs: u64,
+    pub outcome: RestartOutcome,
+    /// Why the restart failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What to do about an unexpected exit, decided by [`AutoRestart::on_exit`].
+#[derive(Debug, Clone, Copy)]
+pub enum RestartDecision {
+    /// Auto-restart is disabled for the server.
+    Disabled,
+    /// Restart after the delay, unless the restart is cancelled first.
+    Scheduled {
+        /// Identifies the restart to [`AutoRestart::take_pending`].
+        id: u64,
+        attempt: u32,
+        delay: Duration,
+    },
+    /// Too many restarts in the window.
+    Abandoned { attempts: u32 },
+}
+
+/// Auto-restart state of one server.
+#[derive(Debug)]
+pub struct AutoRestart {
+    config: AutoRestartConfig,
+    /// Whether the server is restarted, starting out as configured.
+    enabled: bool,
+    /// When restarts within the window were scheduled, oldest first.
+    attempts: VecDeque<Instant>,
+    /// Id of the restart waiting for its delay.
+    pending: Option<u64>,
+    next_id: u64,
+    /// Recent exits with their restarts, oldest first, with the ids of their restarts.
+    history: VecDeque<(u64, RestartRecord)>,
+}
+
+impl AutoRestart {
+    /// Creates the state for a server that hasn't exited yet.
+    pub fn new(config: AutoRestartConfig) -> Self {
+        AutoRestart {
+            enabled: config.enabled,
+            config,
+            attempts: VecDeque::new(),
+            pending: None,
+            next_id: 1,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Returns true if the server is restarted when it exits unexpectedly.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turns auto-restart on or off; turning it off cancels a pending restart.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.cancel();
+        }
+    }
+
+    /// Returns the settings the server was created with.
+    pub fn config(&self) -> &AutoRestartConfig {
+        &self.config
+    }
+
+    /// Returns true while a restart is waiting for its delay.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Returns the recent exits with their restarts, newest first.
+    pub fn history(&self) -> Vec<RestartRecord> {
+        self.history
+            .iter()
+            .rev()
+            .map(|(_, record)| record.clone())
+            .collect()
+    }
+
+    /// Decides whether to restart a server that exited unexpectedly, and
+    /// records the decision.
+    ///
+    /// # Arguments
+    /// * `exit_code` - Exit code of the process, None if it was killed by a signal
+    pub fn on_exit(&mut self, exit_code: Option<i32>) -> RestartDecision {
+        if !self.enabled {
+            return RestartDecision::Disabled;
+        }
+        let now = Instant::now();
+        while self
+            .attempts
+            .front()
+            .is_some_and(|&attempt| now.duration_since(attempt) > self.config.window)
+        {
+            self.attempts.pop_front();
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let count = self.attempts.len() as u32;
+        let (decision, record) = if count >= self.config.max_attempts {
+            (
+                RestartDecision::Abandoned { attempts: count },
+                RestartRecord {
+                    attempt: count + 1,
+                    exit_code,
+                    exited_at: Utc::now(),
+                    delay_secs: 0,
+                    outcome: RestartOutcome::Abandoned,
+                    error: None,
+                },
+            )
+        } else {
+            self.attempts.push_back(now);
+            let attempt = count + 1;
+            let delay = self
+                .config
+                .initial_delay
+                .saturating_mul(2u32.saturating_pow(count))
+                .min(self.config.max_delay);
+            self.pending = Some(id);
+            (
+                RestartDecision::Scheduled { id, attempt, delay },
+                RestartRecord {
+                    attempt,
+                    exit_code,
+                    exited_at: Utc::now(),
+                    delay_secs: delay.as_secs(),
+                    outcome: RestartOutcome::Pending,
+                    error: None,
+                },
+            )
+        };
+        while self.history.len() >= RESTART_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((id, record));
+        decision
+    }
+
+    /// Claims a scheduled restart once its delay is over.
+    ///
+    /// # Returns
+    /// * `bool` - False if the restart was cancelled in the meantime
+    pub fn take_pending(&mut self, id: u64) -> bool {
+        if self.pending != Some(id) {
+            return false;
+        }
+        self.pending = None;
+        true
+    }
+
+    /// Cancels the pending restart, if any.
+    ///
+    /// # Returns
+    /// * `bool` - True if a restart was pending
+    pub fn cancel(&mut self) -> bool {
+        match self.pending.take() {
+            Some(id) => {
+                self.finish(id, RestartOutcome::Cancelled, None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records what became of a restart.
+    pub fn finish(&mut self, id: u64, outcome: RestartOutcome, error: Option<String>) {
+        if let Some((_, record)) = self.history.iter_mut().find(|(entry, _)| *entry == id) {
+            record.outcome = outcome;
+            record.error = error;
+        }
+    }
+}
+
+/// Waits for a scheduled restart's delay, then starts the server again unless
+/// the restart was cancelled in the meantime.
+///
+/// # Arguments
+/// * `state` - Shared application state
+/// * `server_id` - Server to restart
+/// * `id` - Restart from [`RestartDecision::Scheduled`]
+/// * `delay` - How long to wait first
+pub async fn restart_after(
+    state: Arc<RwLock<AppState>>,
+    server_id: String,
+    id: u64,
+    delay: Duration,
+) {
+    tokio::time::sleep(delay).await;
+    let mut app_state = state.write().await;
+    if let Ok(server) = app_state.server_mut(Some(&server_id)) {
+        server.run_restart(id).await;
+    }
+}
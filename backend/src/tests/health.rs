@@ -0,0 +1,66 @@
+//! The backend's own liveness and readiness probes.
+
+use super::TestServer;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde_json::Value;
+
+#[actix_web::test]
+async fn ready_once_the_broadcaster_runs() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    // The tests don't run the log broadcaster unless they say so
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["state_lock"], true);
+    assert_eq!(body["broadcaster"], false);
+
+    let broadcaster = server.health.broadcaster_running();
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["ready"], true);
+    assert_eq!(body["broadcaster"], true);
+
+    // A broadcaster that ended, e.g. by panicking, makes the backend not ready again
+    drop(broadcaster);
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[actix_web::test]
+async fn health_is_ok_without_the_broadcaster() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(body["broadcaster"], false);
+    assert!(body["uptime_seconds"].is_u64());
+}
+
+#[actix_web::test]
+async fn health_is_ok_while_the_state_is_locked() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let _locked = server.state.write().await;
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let resp = tokio::time::timeout(
+        std::time::Duration::from_secs(1),
+        test::call_service(&app, req),
+    )
+    .await
+    .expect("/health waited for the state lock");
+    assert_eq!(resp.status(), StatusCode::OK);
+}
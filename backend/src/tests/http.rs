@@ -0,0 +1,479 @@
+//! Starting, stopping and watching the server over HTTP and WebSocket.
+
+use super::TestServer;
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn status_of_a_stopped_server() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["id"], "default");
+    assert_eq!(body["running"], false);
+    assert_eq!(body["players_online"], 0);
+}
+
+#[actix_web::test]
+async fn start_runs_the_server() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(test::read_body(resp).await, "Minecraft server started.");
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["running"], true);
+    assert_eq!(body["console"], "stdin");
+}
+
+#[actix_web::test]
+async fn start_refuses_a_running_server() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "already_running");
+    assert!(body["path"]
+        .as_str()
+        .is_some_and(|path| path.ends_with("server.jar")));
+}
+
+#[actix_web::test]
+async fn start_rejects_an_invalid_env_var() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post()
+        .uri("/start")
+        .set_json(json!({ "env": { "A=B": "1" } }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "invalid_env_var");
+}
+
+#[actix_web::test]
+async fn stop_stops_the_server() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(test::read_body(resp).await, "Minecraft server stopped.");
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["running"], false);
+}
+
+#[actix_web::test]
+async fn stop_without_a_running_server() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "not_running");
+    assert_eq!(body["error"], "Minecraft server is not running");
+}
+
+#[actix_web::test]
+async fn command_returns_the_servers_response() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post()
+        .uri("/command")
+        .set_json(json!({ "command": "list" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    // The last lines of the startup may come first
+    let output = body["output"].as_array().cloned().unwrap_or_default();
+    assert!(output.iter().any(|line| line
+        .as_str()
+        .is_some_and(|line| line.ends_with("There are 0 of a max of 20 players online:"))));
+}
+
+#[actix_web::test]
+async fn unknown_server_is_not_found() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::get()
+        .uri("/servers/missing/status")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "unknown_server");
+}
+
+#[actix_web::test]
+async fn websocket_upgrade() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::get()
+        .uri("/ws")
+        .insert_header((header::CONNECTION, "Upgrade"))
+        .insert_header((header::UPGRADE, "websocket"))
+        .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+        .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+    // The accept key for the sample nonce of RFC 6455
+    assert_eq!(
+        resp.headers().get(header::SEC_WEBSOCKET_ACCEPT).unwrap(),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+}
+
+#[actix_web::test]
+async fn websocket_requires_an_upgrade() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::get().uri("/ws").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // The failed handshake leaves no client behind to count toward max_clients
+    assert_eq!(server.state.read().await.websocket_clients(), 0);
+    let req = test::TestRequest::get().uri("/clients").to_request();
+    let clients: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(clients, json!([]));
+}
+
+#[actix_web::test]
+async fn command_with_a_line_break_is_rejected() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    for command in ["say hi\nstop", "say hi\rstop", "say hi\r\nstop"] {
+        let req = test::TestRequest::post()
+            .uri("/command")
+            .set_json(json!({ "command": command }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{:?}", command);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "invalid_command");
+    }
+
+    // Had the stop been smuggled through, the server would no longer answer
+    let req = test::TestRequest::post()
+        .uri("/command")
+        .set_json(json!({ "command": "list" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn commands_and_stop_go_through_the_server_process() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    let mut console = server.start_mock().await;
+    let replies = tokio::spawn(async move {
+        let mut commands = Vec::new();
+        while let Some(command) = console.next_command().await {
+            match command.as_str() {
+                "list" => console.print("There are 0 of a max of 20 players online:"),
+                "save-all flush" => console.print("Saved the game"),
+                _ => {}
+            }
+            commands.push(command);
+        }
+        commands
+    });
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["running"], true);
+
+    let req = test::TestRequest::post()
+        .uri("/command")
+        .set_json(json!({ "command": "list" }))
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body["output"],
+        json!(["There are 0 of a max of 20 players online:"])
+    );
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["running"], false);
+
+    // Dropping the mock closed its console
+    assert_eq!(replies.await.unwrap(), ["list", "save-all flush"]);
+}
+
+#[actix_web::test]
+async fn metrics_in_the_exposition_format() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert!(lines.contains(&"# TYPE minecraft_server_running gauge"));
+    assert!(lines.contains(&"minecraft_server_running 0"));
+    assert!(lines.contains(&"minecraft_console_server_running{server=\"default\"} 0"));
+    // Every sample follows the HELP and TYPE lines of its metric
+    for sample in lines.iter().filter(|line| !line.starts_with('#')) {
+        let name = sample.split(['{', ' ']).next().unwrap();
+        let help = format!("# HELP {} ", name);
+        let kind = format!("# TYPE {} ", name);
+        let help = lines
+            .iter()
+            .position(|line| line.starts_with(&help))
+            .unwrap();
+        let kind = lines
+            .iter()
+            .position(|line| line.starts_with(&kind))
+            .unwrap();
+        let sample = lines.iter().position(|line| line == sample).unwrap();
+        assert!(help < kind && kind < sample, "{}", name);
+    }
+}
+
+#[actix_web::test]
+async fn panic_while_holding_the_state_lock_does_not_wedge_requests() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    // Like a handler panicking halfway through changing the state
+    let state = server.state.clone();
+    let panicked = tokio::spawn(async move {
+        let mut app_state = state.write().await;
+        let _ = app_state.server_mut(None);
+        panic!("handler panicked while holding the state lock");
+    })
+    .await;
+    assert!(panicked.unwrap_err().is_panic());
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let resp = tokio::time::timeout(
+        std::time::Duration::from_secs(1),
+        test::call_service(&app, req),
+    )
+    .await
+    .expect("the state lock was left held");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn command_after_the_console_closed_is_stdin_unavailable() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    // The server closes its console input but keeps running
+    drop(server.start_mock().await);
+
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/command")
+            .set_json(json!({ "command": "list" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "stdin_unavailable");
+    }
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["running"], true);
+    assert_eq!(body["console"], "unavailable");
+}
+
+#[actix_web::test]
+async fn stop_twice_is_a_conflict_the_second_time() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "not_running");
+
+    // Stopped for good, it can be started again
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn stop_cancels_a_pending_automatic_restart() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    {
+        let mut state = server.state.write().await;
+        let auto_restart = &mut state.server_mut(None).unwrap().auto_restart;
+        auto_restart.set_enabled(true);
+        auto_restart.on_exit(Some(1));
+        assert!(auto_restart.is_pending());
+    }
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        test::read_body(resp).await,
+        "Cancelled the pending automatic restart."
+    );
+    assert!(!server
+        .state
+        .read()
+        .await
+        .default_server()
+        .auto_restart
+        .is_pending());
+
+    // Nothing is left to stop
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::CONFLICT
+    );
+}
+
+#[actix_web::test]
+async fn status_is_answered_while_the_server_stops() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    let mut console = server.start_mock().await;
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    let stop = test::call_service(&app, req);
+    tokio::pin!(stop);
+    // Run the stop until it waits for the world to be saved
+    tokio::select! {
+        _ = &mut stop => panic!("stopped before the world was saved"),
+        command = console.next_command() => assert_eq!(command.as_deref(), Some("save-all flush")),
+    }
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let status: Value = tokio::time::timeout(
+        std::time::Duration::from_secs(1),
+        test::call_and_read_body_json(&app, req),
+    )
+    .await
+    .expect("the stop holds the state lock");
+    assert_eq!(status["running"], false);
+    assert_eq!(status["stopping"], true);
+
+    // Neither a start nor a second stop can get in before the stop is done
+    for uri in ["/start", "/stop"] {
+        let req = test::TestRequest::post().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "stopping");
+    }
+
+    console.print("[Server thread/INFO]: Saved the game");
+    assert_eq!(stop.await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["stopping"], false);
+}
+
+#[actix_web::test]
+async fn a_command_waiting_for_its_response_does_not_block_another() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    let mut console = server.start_mock().await;
+
+    let req = test::TestRequest::post()
+        .uri("/command")
+        .set_json(json!({ "command": "say first" }))
+        .to_request();
+    let first = test::call_service(&app, req);
+    tokio::pin!(first);
+    // Run the first request until its command is written; it then waits for a
+    // response the server doesn't print yet
+    tokio::select! {
+        _ = &mut first => panic!("answered before the server responded"),
+        command = console.next_command() => assert_eq!(command.as_deref(), Some("say first")),
+    }
+
+    // The first request is left suspended without holding the state lock
+    drop(
+        tokio::time::timeout(std::time::Duration::from_secs(1), server.state.write())
+            .await
+            .expect("the first command holds the state lock"),
+    );
+    let req = test::TestRequest::post()
+        .uri("/command")
+        .set_json(json!({ "command": "list" }))
+        .to_request();
+    let (resp, _) = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        futures_util::future::join(test::call_service(&app, req), async {
+            assert_eq!(console.next_command().await.as_deref(), Some("list"));
+            console.print("There are 0 of a max of 20 players online:");
+        }),
+    )
+    .await
+    .expect("the second command waited for the first");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["output"],
+        json!(["There are 0 of a max of 20 players online:"])
+    );
+
+    console.print("[Server] first");
+    assert_eq!(first.await.status(), StatusCode::OK);
+}
@@ -0,0 +1,194 @@
+//! Starting a real process, a shell script standing in for the server.
+
+use super::TestServer;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde_json::{json, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Writes an executable script that runs `setup` and then answers the
+/// commands `/stop` sends like a server.
+fn script(dir: &Path, name: &str, setup: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(
+        &path,
+        format!(
+            "#!/bin/sh\n{}\nwhile read -r line; do\n  case \"$line\" in\n    save-all*) echo 'Saved the game' ;;\n    stop) exit 0 ;;\n  esac\ndone\n",
+            setup
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// Waits for a file the script writes, returning its contents.
+async fn read_when_written(path: &Path) -> String {
+    for _ in 0..50 {
+        if let Ok(contents) = std::fs::read_to_string(path) {
+            if contents.ends_with('\n') {
+                return contents;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("{} was not written", path.display());
+}
+
+impl TestServer {
+    /// Makes `/start` run processes instead of the simulated server.
+    async fn run_processes(&self) {
+        let mut state = self.state.write().await;
+        state.server_mut(None).unwrap().server_config.dry_run = false;
+    }
+}
+
+#[actix_web::test]
+async fn started_process_sees_the_requested_environment() {
+    let server = TestServer::new();
+    server.run_processes().await;
+    let app = server.app().await;
+    let path = script(
+        &server.dir,
+        "printenv.sh",
+        "printf '%s|%s|%s\\n' \"$MC_WORLD\" \"$JAVA_OPTS\" \"${HOME-unset}\" > env.txt",
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/start")
+        .set_json(json!({
+            "file_path": path,
+            "force": true,
+            "env": { "MC_WORLD": "flat world", "JAVA_OPTS": "-Dsecret=hunter2" },
+        }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let env = read_when_written(&server.dir.join("env.txt")).await;
+    let home = std::env::var("HOME").unwrap_or_else(|_| "unset".to_string());
+    assert_eq!(env, format!("flat world|-Dsecret=hunter2|{}\n", home));
+
+    // The values aren't echoed back
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(!String::from_utf8_lossy(&body).contains("hunter2"));
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn env_clear_leaves_only_the_requested_environment() {
+    let server = TestServer::new();
+    server.run_processes().await;
+    let app = server.app().await;
+    let path = script(
+        &server.dir,
+        "printenv.sh",
+        "printf '%s|%s\\n' \"$MC_WORLD\" \"${HOME-unset}\" > env.txt",
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/start")
+        .set_json(json!({
+            "file_path": path,
+            "force": true,
+            "env": { "MC_WORLD": "flat" },
+            "env_clear": true,
+        }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let env = read_when_written(&server.dir.join("env.txt")).await;
+    assert_eq!(env, "flat|unset\n");
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn start_runs_the_configured_path_by_default() {
+    let server = TestServer::new();
+    let path = script(&server.dir, "configured.sh", "echo configured > ran.txt");
+    {
+        let mut state = server.state.write().await;
+        let config = &mut state.server_mut(None).unwrap().server_config;
+        config.dry_run = false;
+        config.server_path = Some(path.display().to_string());
+    }
+    let app = server.app().await;
+
+    // Without a file_path; force only skips the port check
+    let req = test::TestRequest::post()
+        .uri("/start")
+        .set_json(json!({ "force": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(
+        read_when_written(&server.dir.join("ran.txt")).await,
+        "configured\n"
+    );
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn start_runs_the_requested_path_instead() {
+    let server = TestServer::new();
+    server.run_processes().await;
+    script(&server.dir, "configured.sh", "echo configured > ran.txt");
+    let path = script(&server.dir, "override.sh", "echo override > ran.txt");
+    {
+        let mut state = server.state.write().await;
+        let config = &mut state.server_mut(None).unwrap().server_config;
+        config.server_path = Some(server.dir.join("configured.sh").display().to_string());
+    }
+    let app = server.app().await;
+
+    let req = test::TestRequest::post()
+        .uri("/start")
+        .set_json(json!({ "file_path": path, "force": true }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(
+        read_when_written(&server.dir.join("ran.txt")).await,
+        "override\n"
+    );
+
+    let req = test::TestRequest::post().uri("/stop").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn start_rejects_a_path_that_cannot_run() {
+    let server = TestServer::new();
+    server.run_processes().await;
+    let app = server.app().await;
+    let not_executable = server.dir.join("not-executable.sh");
+    std::fs::write(&not_executable, "#!/bin/sh\n").unwrap();
+
+    for (path, reason) in [
+        (server.dir.join("missing.sh"), "No such file"),
+        (server.dir.clone(), "not a file"),
+        (not_executable, "not executable"),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/start")
+            .set_json(json!({ "file_path": path }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "invalid_path");
+        assert!(
+            body["error"].as_str().unwrap().contains(reason),
+            "{}",
+            body["error"]
+        );
+    }
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["running"], false);
+}
@@ -0,0 +1,160 @@
+//! A mock server process, put in place of the real one to drive the handlers
+//! without running anything.
+
+use super::TestServer;
+use crate::error::ServerError;
+use crate::metrics::Metrics;
+use crate::server::process::ConsoleInput;
+use crate::server::{
+    CommandSender, LaunchSpec, ServerConfig, ServerProcess, StopOutcome, StopStage,
+};
+use crate::state::LogSender;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
+use tokio::io::{duplex, AsyncBufReadExt, BufReader, DuplexStream, Lines};
+use tokio::sync::broadcast;
+
+/// Bytes the console pipe holds before a command write has to wait for the test.
+const PIPE_CAPACITY: usize = 4096;
+
+/// A running server that writes its commands to a pipe the test reads from,
+/// and prints whatever the test makes it print.
+pub struct MockServer {
+    commands: CommandSender,
+    output: broadcast::Sender<String>,
+    running: bool,
+    path: PathBuf,
+    working_dir: PathBuf,
+    /// The test's end of the console, until the test takes it.
+    console: Option<MockConsole>,
+}
+
+/// The test's end of a mock server's console.
+pub struct MockConsole {
+    commands: Lines<BufReader<DuplexStream>>,
+    output: broadcast::Sender<String>,
+}
+
+impl MockConsole {
+    /// Returns the next command written to the console, None once the server is gone.
+    pub async fn next_command(&mut self) -> Option<String> {
+        self.commands.next_line().await.ok().flatten()
+    }
+
+    /// Prints a line of console output.
+    pub fn print(&self, line: &str) {
+        let _ = self.output.send(line.to_string());
+    }
+}
+
+impl MockServer {
+    /// Takes the test's end of the console.
+    pub fn console(&mut self) -> MockConsole {
+        self.console.take().expect("the console was already taken")
+    }
+}
+
+impl ServerProcess for MockServer {
+    fn start(
+        launch: LaunchSpec,
+        log_sender: LogSender,
+        config: ServerConfig,
+        metrics: Arc<Metrics>,
+    ) -> BoxFuture<'static, Result<Self, ServerError>> {
+        async move {
+            let (stdin, console) = duplex(PIPE_CAPACITY);
+            let commands = CommandSender::spawn(
+                Box::new(stdin) as ConsoleInput,
+                &config,
+                metrics,
+                log_sender,
+            );
+            let (output, _) = broadcast::channel(config.output_buffer_lines);
+            Ok(MockServer {
+                commands,
+                output: output.clone(),
+                running: true,
+                path: launch.path,
+                working_dir: launch.working_dir,
+                console: Some(MockConsole {
+                    commands: BufReader::new(console).lines(),
+                    output,
+                }),
+            })
+        }
+        .boxed()
+    }
+
+    fn stop(
+        &mut self,
+        _stop_sent: bool,
+    ) -> BoxFuture<'_, Result<Option<StopOutcome>, ServerError>> {
+        let outcome = self.running.then_some(StopOutcome {
+            stage: StopStage::Command,
+            exit_code: Some(0),
+        });
+        self.running = false;
+        async move { Ok(outcome) }.boxed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.running
+    }
+
+    fn command_sender(&self) -> Result<CommandSender, ServerError> {
+        if !self.running {
+            return Err(ServerError::NotRunning);
+        }
+        Ok(self.commands.clone())
+    }
+
+    fn try_wait(&mut self) -> Option<ExitStatus> {
+        None
+    }
+
+    fn console_available(&self) -> bool {
+        self.running && !self.commands.is_closed()
+    }
+
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+
+    fn subscribe_output(&self) -> broadcast::Receiver<String> {
+        self.output.subscribe()
+    }
+}
+
+impl TestServer {
+    /// Makes a mock the running default server, as if `/start` had started it.
+    ///
+    /// # Returns
+    /// * `MockConsole` - The test's end of the mock's console
+    pub async fn start_mock(&self) -> MockConsole {
+        let mut state = self.state.write().await;
+        let server = state.server_mut(None).expect("the default server");
+        let launch = LaunchSpec::from_config(None, &server.server_config).expect("a launch spec");
+        let mut mock = MockServer::start(
+            launch,
+            server.log_sender.clone(),
+            server.server_config.clone(),
+            Arc::default(),
+        )
+        .await
+        .expect("start the mock");
+        let console = mock.console();
+        server.minecraft_server = Some(Box::new(mock));
+        console
+    }
+}
@@ -0,0 +1,98 @@
+//! Tests of the HTTP and WebSocket API.
+//!
+//! Each test builds the app the way `main` does, with the server in dry-run
+//! mode so `/start` runs the simulated server instead of a Minecraft binary.
+//! The server directory is a fresh temporary directory, and the audit log and
+//! every other file the backend would write are turned off.
+
+use crate::auth::{self, Auth};
+use crate::config::Config;
+use crate::health::Health;
+use crate::routes;
+use crate::server::ServerId;
+use crate::state::{AppState, Broadcast};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::middleware::from_fn;
+use actix_web::{test, web, App};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::RwLock;
+
+mod health;
+mod http;
+#[cfg(unix)]
+mod launch;
+mod mock;
+mod mods;
+mod websocket;
+mod worlds;
+
+/// Number of test servers created so far, making their directories unique.
+static SERVERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The state behind a test app, removing its server directory when dropped.
+struct TestServer {
+    state: Arc<RwLock<AppState>>,
+    /// Directory the simulated server runs in.
+    dir: PathBuf,
+    /// What `/health` and `/ready` report about the backend's own tasks.
+    health: Arc<Health>,
+    /// Kept open so the output readers don't stop for lack of a receiver.
+    _log_receiver: UnboundedReceiver<(ServerId, Broadcast)>,
+}
+
+impl TestServer {
+    /// Creates the state of a backend with a dry-run default server.
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "minecraft-console-test-{}-{}",
+            std::process::id(),
+            SERVERS.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).expect("create the server directory");
+
+        let mut config = Config::default();
+        config.server.dry_run = true;
+        config.server.server_path = Some(dir.join("server.jar").display().to_string());
+        config.server.working_dir = Some(dir.clone());
+        config.audit.enabled = false;
+        config.log_file.enabled = false;
+
+        let (log_sender, log_receiver) = unbounded_channel();
+        TestServer {
+            state: Arc::new(RwLock::new(AppState::new(log_sender, config))),
+            dir,
+            health: Health::new(),
+            _log_receiver: log_receiver,
+        }
+    }
+
+    /// Builds the app with the API routes and authentication, as in `main`.
+    async fn app(
+        &self,
+    ) -> impl Service<
+        actix_http::Request,
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+    > {
+        let auth: Arc<Auth> = Auth::new(&Config::default().auth);
+        test::init_service(
+            App::new()
+                .wrap(from_fn(auth::authenticate))
+                .app_data(web::Data::new(self.state.clone()))
+                .app_data(web::Data::new(self.health.clone()))
+                .app_data(web::Data::new(auth))
+                .configure(routes::init_routes),
+        )
+        .await
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
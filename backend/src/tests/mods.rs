@@ -0,0 +1,290 @@
+//! Listing, enabling, disabling and uploading mods.
+
+use super::TestServer;
+use actix_http::Request;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde_json::Value;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Boundary between the parts of an upload.
+const BOUNDARY: &str = "mod-upload-boundary";
+
+/// Builds a jar holding the given files.
+fn jar(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut jar = ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, contents) in files {
+        jar.start_file(*path, SimpleFileOptions::default()).unwrap();
+        jar.write_all(contents.as_bytes()).unwrap();
+    }
+    jar.finish().unwrap().into_inner()
+}
+
+/// Writes a jar holding the given files to the server's mods directory.
+fn write_jar(server: &TestServer, name: &str, files: &[(&str, &str)]) {
+    let dir = server.dir.join("mods");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(name), jar(files)).unwrap();
+}
+
+/// Builds a `POST /mods/upload` request with a file as its only part.
+fn upload(query: &str, name: &str, contents: &[u8]) -> Request {
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+         Content-Type: application/java-archive\r\n\r\n",
+        BOUNDARY, name
+    )
+    .into_bytes();
+    body.extend_from_slice(contents);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    test::TestRequest::post()
+        .uri(&format!("/mods/upload{}", query))
+        .insert_header((
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        ))
+        .set_payload(body)
+        .to_request()
+}
+
+/// Returns the listed mod with the given file name.
+fn find<'a>(mods: &'a Value, file: &str) -> &'a Value {
+    mods.as_array()
+        .and_then(|mods| mods.iter().find(|m| m["file"] == file))
+        .unwrap_or_else(|| panic!("{} is not listed in {}", file, mods))
+}
+
+#[actix_web::test]
+async fn lists_mods_of_a_server_never_started() {
+    let server = TestServer::new();
+    write_jar(&server, "create.jar", &[]);
+    let app = server.app().await;
+
+    let req = test::TestRequest::get().uri("/mods").to_request();
+    let mods: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(mods.as_array().map(Vec::len), Some(1));
+    assert_eq!(mods[0]["file"], "create.jar");
+}
+
+#[actix_web::test]
+async fn lists_mods_with_their_metadata() {
+    let server = TestServer::new();
+    write_jar(
+        &server,
+        "create.jar",
+        &[
+            (
+                "META-INF/neoforge.mods.toml",
+                "modLoader = \"javafml\"\n[[mods]]\nmodId = \"create\"\n\
+                 displayName = \"Create\"\nversion = \"${file.jarVersion}\"\n",
+            ),
+            (
+                "META-INF/MANIFEST.MF",
+                "Manifest-Version: 1.0\nImplementation-Version: 6.0.4\n",
+            ),
+        ],
+    );
+    write_jar(
+        &server,
+        "sodium.jar.disabled",
+        &[(
+            "fabric.mod.json",
+            r#"{"id": "sodium", "name": "Sodium", "version": "0.6.0"}"#,
+        )],
+    );
+    write_jar(&server, "library.jar", &[("com/example/Lib.class", "")]);
+    std::fs::write(server.dir.join("mods/broken.jar"), "not a zip").unwrap();
+    std::fs::write(server.dir.join("mods/readme.txt"), "not a mod").unwrap();
+    let app = server.app().await;
+
+    let req = test::TestRequest::get().uri("/mods").to_request();
+    let mods: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(mods.as_array().map(Vec::len), Some(4));
+
+    let create = find(&mods, "create.jar");
+    assert_eq!(create["enabled"], true);
+    assert_eq!(create["loader"], "neoforge");
+    assert_eq!(create["mod_id"], "create");
+    assert_eq!(create["name"], "Create");
+    assert_eq!(create["version"], "6.0.4");
+
+    let sodium = find(&mods, "sodium.jar.disabled");
+    assert_eq!(sodium["enabled"], false);
+    assert_eq!(sodium["loader"], "fabric");
+    assert_eq!(sodium["version"], "0.6.0");
+
+    for file in ["library.jar", "broken.jar"] {
+        let jar = find(&mods, file);
+        assert!(jar.get("mod_id").is_none());
+        assert_eq!(jar["sha256"].as_str().map(str::len), Some(64));
+    }
+    let broken = find(&mods, "broken.jar");
+    assert_eq!(broken["size"], 9);
+    assert_eq!(
+        broken["sha256"],
+        "a3989126344744ef800dbc88bf7e744853f3f2eac75c9ab3301f4e845ef22078"
+    );
+}
+
+#[actix_web::test]
+async fn disable_and_enable_rename_the_jar() {
+    let server = TestServer::new();
+    write_jar(&server, "create.jar", &[]);
+    let mods = server.dir.join("mods");
+    let app = server.app().await;
+
+    let req = test::TestRequest::post()
+        .uri("/mods/create.jar/disable")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["file"], "create.jar.disabled");
+    assert_eq!(body["enabled"], false);
+    assert_eq!(body["changed"], true);
+    assert!(mods.join("create.jar.disabled").is_file());
+    assert!(!mods.join("create.jar").exists());
+
+    // Disabling again changes nothing
+    let req = test::TestRequest::post()
+        .uri("/mods/create.jar/disable")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["changed"], false);
+
+    let req = test::TestRequest::post()
+        .uri("/mods/create.jar.disabled/enable")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["file"], "create.jar");
+    assert_eq!(body["changed"], true);
+    assert!(mods.join("create.jar").is_file());
+}
+
+#[actix_web::test]
+async fn toggle_only_renames_jars_in_the_mods_directory() {
+    let server = TestServer::new();
+    write_jar(&server, "create.jar", &[]);
+    write_jar(&server, "../outside.jar", &[]);
+    let app = server.app().await;
+
+    for (uri, status) in [
+        ("/mods/readme.txt/disable", StatusCode::BAD_REQUEST),
+        ("/mods/..%5Coutside.jar/disable", StatusCode::BAD_REQUEST),
+        ("/mods/missing.jar/disable", StatusCode::NOT_FOUND),
+    ] {
+        let req = test::TestRequest::post().uri(uri).to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            status,
+            "{}",
+            uri
+        );
+    }
+    // An encoded slash doesn't match the route at all
+    let req = test::TestRequest::post()
+        .uri("/mods/..%2Foutside.jar/disable")
+        .to_request();
+    assert!(test::call_service(&app, req)
+        .await
+        .status()
+        .is_client_error());
+    assert!(server.dir.join("outside.jar").is_file());
+}
+
+#[actix_web::test]
+async fn toggle_refuses_a_running_server_unless_forced() {
+    let server = TestServer::new();
+    write_jar(&server, "create.jar", &[]);
+    let app = server.app().await;
+
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post()
+        .uri("/mods/create.jar/disable")
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::CONFLICT
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/mods/create.jar/disable?force=true")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["enabled"], false);
+}
+
+#[actix_web::test]
+async fn upload_installs_the_jar_and_returns_its_metadata() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    let sodium = jar(&[(
+        "fabric.mod.json",
+        r#"{"id": "sodium", "name": "Sodium", "version": "0.6.0"}"#,
+    )]);
+
+    let resp = test::call_service(&app, upload("", "sodium.jar", &sodium)).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["file"], "sodium.jar");
+    assert_eq!(body["mod_id"], "sodium");
+    assert_eq!(body["size"], sodium.len());
+    assert_eq!(
+        std::fs::read(server.dir.join("mods/sodium.jar")).unwrap(),
+        sodium
+    );
+
+    // A taken name is refused, or numbered on request
+    let resp = test::call_service(&app, upload("", "sodium.jar", &sodium)).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let req = upload("?on_duplicate=rename", "sodium.jar", &sodium);
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["file"], "sodium-1.jar");
+
+    // Only the installed jars are left, no temporary files
+    let mut files: Vec<String> = std::fs::read_dir(server.dir.join("mods"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["sodium-1.jar", "sodium.jar"]);
+}
+
+#[actix_web::test]
+async fn upload_refuses_invalid_jars() {
+    let server = TestServer::new();
+    server.state.write().await.mods_config.max_upload_mb = 1;
+    let app = server.app().await;
+
+    for (name, contents, status) in [
+        ("readme.txt", jar(&[]), StatusCode::BAD_REQUEST),
+        ("../outside.jar", jar(&[]), StatusCode::BAD_REQUEST),
+        ("broken.jar", b"not a zip".to_vec(), StatusCode::BAD_REQUEST),
+        (
+            "huge.jar",
+            vec![0; 1024 * 1024 + 1],
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+    ] {
+        let resp = test::call_service(&app, upload("", name, &contents)).await;
+        assert_eq!(resp.status(), status, "{}", name);
+    }
+    let mods = std::fs::read_dir(server.dir.join("mods")).unwrap();
+    assert_eq!(mods.count(), 0);
+    assert!(!server.dir.join("outside.jar").exists());
+}
+
+#[actix_web::test]
+async fn upload_refuses_a_running_server_unless_allowed() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    let req = test::TestRequest::post().uri("/start").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let resp = test::call_service(&app, upload("", "create.jar", &jar(&[]))).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let resp = test::call_service(&app, upload("?allow_hot=true", "create.jar", &jar(&[]))).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+}
@@ -0,0 +1,165 @@
+//! Commands sent over `/ws`, through a client that speaks the WebSocket
+//! protocol to the app directly instead of over a socket.
+
+use super::TestServer;
+use actix_codec::{Decoder, Encoder};
+use actix_http::ws::{Codec, Frame, Message};
+use actix_http::{BoxedPayloadStream, Payload};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::Service;
+use actix_web::error::PayloadError;
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use actix_web::web::{Bytes, BytesMut};
+use serde_json::{json, Value};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+/// How long a test waits for the frame it expects.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The client end of a WebSocket connection to the test app.
+struct WsClient {
+    /// Frames the client sends, fed to the app as the request body.
+    outgoing: UnboundedSender<Bytes>,
+    /// The upgrade response's body, carrying the frames the app sends.
+    incoming: BoxBody,
+    /// Received bytes not yet decoded into a frame.
+    buffer: BytesMut,
+    codec: Codec,
+}
+
+impl WsClient {
+    /// Opens a connection with an upgrade request to `uri`.
+    async fn connect<S, B>(app: &S, uri: &str) -> Self
+    where
+        S: Service<
+            actix_http::Request,
+            Response = actix_web::dev::ServiceResponse<B>,
+            Error = actix_web::Error,
+        >,
+        B: MessageBody + 'static,
+    {
+        let (outgoing, mut frames) = unbounded_channel::<Bytes>();
+        // The body stays open until the client is dropped, like a socket would
+        let body = futures_util::stream::poll_fn(move |cx| {
+            frames
+                .poll_recv(cx)
+                .map(|frame| frame.map(Ok::<_, PayloadError>))
+        });
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header((header::CONNECTION, "Upgrade"))
+            .insert_header((header::UPGRADE, "websocket"))
+            .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+            .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+            .to_request();
+        let payload: BoxedPayloadStream = Box::pin(body);
+        let (req, _) = req.replace_payload(Payload::Stream { payload });
+        let resp = test::call_service(app, req).await;
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        WsClient {
+            outgoing,
+            incoming: resp.into_body().boxed(),
+            buffer: BytesMut::new(),
+            codec: Codec::new().client_mode(),
+        }
+    }
+
+    /// Sends a text frame.
+    fn send(&mut self, text: &str) {
+        let mut frame = BytesMut::new();
+        self.codec
+            .encode(Message::Text(text.to_string().into()), &mut frame)
+            .expect("encode the frame");
+        self.outgoing
+            .send(frame.freeze())
+            .expect("the app reads the connection");
+    }
+
+    /// Returns the next text frame, None once the app closed the connection.
+    async fn next_text(&mut self) -> Option<String> {
+        loop {
+            if let Some(frame) = self.codec.decode(&mut self.buffer).expect("a valid frame") {
+                match frame {
+                    Frame::Text(text) => {
+                        return Some(String::from_utf8(text.to_vec()).expect("UTF-8 text"))
+                    }
+                    Frame::Close(_) => return None,
+                    _ => continue,
+                }
+            }
+            let chunk = poll_fn(|cx| Pin::new(&mut self.incoming).poll_next(cx)).await?;
+            self.buffer
+                .extend_from_slice(&chunk.expect("read the response body"));
+        }
+    }
+
+    /// Returns the next JSON frame whose `type` is `kind`, skipping the others.
+    async fn next_of_type(&mut self, kind: &str) -> Value {
+        let wait = async {
+            loop {
+                let text = self.next_text().await.expect("the connection is open");
+                if let Ok(frame) = serde_json::from_str::<Value>(&text) {
+                    if frame["type"] == kind {
+                        return frame;
+                    }
+                }
+            }
+        };
+        tokio::time::timeout(FRAME_TIMEOUT, wait)
+            .await
+            .unwrap_or_else(|_| panic!("no {} frame", kind))
+    }
+}
+
+#[actix_web::test]
+async fn command_while_stopped_is_an_error_frame() {
+    let server = TestServer::new();
+    let app = server.app().await;
+
+    let mut client = WsClient::connect(&app, "/ws?format=json").await;
+    let welcome = client.next_of_type("welcome").await;
+    assert_eq!(welcome["running"], false);
+
+    client.send(&json!({ "type": "command", "command": "list" }).to_string());
+    let error = client.next_of_type("error").await;
+    assert_eq!(error["code"], "not_running");
+    assert!(error["message"].as_str().is_some_and(|m| !m.is_empty()));
+    assert!(error.get("id").is_none());
+
+    // A plain text command gets the same answer
+    client.send("list");
+    let error = client.next_of_type("error").await;
+    assert_eq!(error["code"], "not_running");
+}
+
+#[actix_web::test]
+async fn viewer_commands_are_refused() {
+    let server = TestServer::new();
+    let app = server.app().await;
+    let mut console = server.start_mock().await;
+
+    let mut viewer = WsClient::connect(&app, "/ws?format=json&mode=viewer").await;
+    let welcome = viewer.next_of_type("welcome").await;
+    assert_eq!(welcome["read_only"], true);
+
+    viewer.send(&json!({ "type": "command", "id": "1", "command": "op viewer" }).to_string());
+    let error = viewer.next_of_type("error").await;
+    assert_eq!(error["code"], "read_only");
+    assert_eq!(error["id"], "1");
+    viewer.send("op viewer");
+    let error = viewer.next_of_type("error").await;
+    assert_eq!(error["code"], "read_only");
+
+    // Neither reached the console: the first command it sees is an operator's
+    let mut operator = WsClient::connect(&app, "/ws?format=json").await;
+    operator.send(&json!({ "type": "command", "command": "list" }).to_string());
+    assert_eq!(
+        operator.next_of_type("command_ack").await["command"],
+        "list"
+    );
+    assert_eq!(console.next_command().await.as_deref(), Some("list"));
+}
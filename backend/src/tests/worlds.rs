@@ -0,0 +1,67 @@
+//! Reading world info from `level.dat`.
+
+use super::TestServer;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use serde_json::Value;
+
+/// A gzip'd `level.dat` as written by 1.21.1.
+const LEVEL_DAT: &[u8] = include_bytes!("fixtures/level.dat");
+
+/// Writes a world with the given `level.dat` to the server directory.
+fn write_world(server: &TestServer, name: &str, level_dat: &[u8]) {
+    let dir = server.dir.join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("level.dat"), level_dat).unwrap();
+}
+
+#[actix_web::test]
+async fn world_info_from_the_level_dat_fixture() {
+    let server = TestServer::new();
+    write_world(&server, "world", LEVEL_DAT);
+    let app = server.app().await;
+
+    let req = test::TestRequest::get()
+        .uri("/worlds/world/info")
+        .to_request();
+    let info: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(info["name"], "world");
+    assert_eq!(info["level_name"], "Fixture World");
+    assert_eq!(info["seed"], "-4172144997902289642");
+    assert_eq!(info["version"], "1.21.1");
+    assert_eq!(info["game_mode"], "creative");
+    assert_eq!(info["difficulty"], "normal");
+    assert_eq!(info["hardcore"], false);
+    assert_eq!(info["spawn"], serde_json::json!([64, 72, -128]));
+    let last_played = info["last_played"].as_str().unwrap();
+    assert_eq!(
+        chrono::DateTime::parse_from_rfc3339(last_played)
+            .unwrap()
+            .timestamp_millis(),
+        1760000000000
+    );
+}
+
+#[actix_web::test]
+async fn world_info_errors() {
+    let server = TestServer::new();
+    write_world(&server, "truncated", &LEVEL_DAT[..LEVEL_DAT.len() / 2]);
+    write_world(&server, "garbage", b"not nbt at all");
+    let app = server.app().await;
+
+    for name in ["truncated", "garbage"] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/worlds/{}/info", name))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR, "{}", name);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "invalid_level_dat", "{}", name);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/worlds/missing/info")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
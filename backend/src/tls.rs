@@ -0,0 +1,109 @@
+//! TLS for the HTTP server.
+//!
+//! When `tls_cert` and `tls_key` are set in `[http]`, the certificate chain
+//! and private key are loaded from their PEM files at startup and the server
+//! is bound with rustls, so the API is served over HTTPS and consoles connect
+//! with `wss://`.
+//!
+//! The files are read again on SIGHUP or `POST /admin/reload-tls`, so a
+//! renewed certificate (e.g. from Let's Encrypt) is picked up by new
+//! connections without a restart. A reload that fails keeps the current
+//! certificate.
+
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+
+/// The certificate served to clients, reloadable while running.
+#[derive(Debug)]
+pub struct Certificates {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl Certificates {
+    /// Loads the certificate chain and private key.
+    ///
+    /// # Arguments
+    /// * `cert` - PEM file with the certificate chain, leaf certificate first
+    /// * `key` - PEM file with the private key (PKCS#8, PKCS#1 or SEC1)
+    ///
+    /// # Returns
+    /// * `Result<Arc<Certificates>, String>` - The certificates, or why the files couldn't be used
+    pub fn load(cert: &Path, key: &Path) -> Result<Arc<Self>, String> {
+        let current = load_certified_key(cert, key)?;
+        Ok(Arc::new(Certificates {
+            cert: cert.to_path_buf(),
+            key: key.to_path_buf(),
+            current: RwLock::new(Arc::new(current)),
+        }))
+    }
+
+    /// Reads the certificate and key files again, keeping the current ones
+    /// if the new files can't be used.
+    pub fn reload(&self) -> Result<(), String> {
+        let reloaded = load_certified_key(&self.cert, &self.key)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(reloaded);
+        info!("Reloaded the certificate from {}", self.cert.display());
+        Ok(())
+    }
+
+    /// Builds the rustls configuration serving these certificates.
+    pub fn server_config(self: &Arc<Self>) -> ServerConfig {
+        ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone())
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        )
+    }
+}
+
+/// Reads a certificate chain and its private key, checking that they match.
+fn load_certified_key(cert: &Path, key: &Path) -> Result<CertifiedKey, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Could not read certificates from {}: {}", cert.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("Could not read private key from {}: {}", key.display(), e))?;
+
+    CertifiedKey::from_der(certs, key, &ring::default_provider())
+        .map_err(|e| format!("Invalid certificate or key: {}", e))
+}
+
+/// Reloads the certificates whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub async fn reload_on_sighup(certificates: Arc<Certificates>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("Could not listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        if let Err(e) = certificates.reload() {
+            warn!("Keeping the current certificate: {}", e);
+        }
+    }
+}
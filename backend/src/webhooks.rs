@@ -0,0 +1,312 @@
+//! Outbound webhooks for server events.
+//!
+//! Every [`ServerEvent`] broadcast to console clients is also posted as JSON
+//! to each `[[webhooks]]` target whose event filter matches:
+//!
+//! ```json
+//! {"event":"crashed","timestamp":"2024-05-01T03:00:00Z","data":{"server":"default","exit_code":1}}
+//! ```
+//!
+//! With a secret configured, the `X-Signature-256` header carries
+//! `sha256=` followed by the hex HMAC-SHA256 of the body, so the receiver can
+//! check the request came from us. Failed deliveries are retried with
+//! exponential backoff; those that still fail are appended to a dead-letter
+//! file with their payload. Recent deliveries are listed at `GET /webhooks/deliveries`.
+
+use crate::server::ServerId;
+use crate::state::ServerEvent;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// Event names a target can filter on, as in the `type` of the WebSocket events.
+pub const EVENT_NAMES: &[&str] = &[
+    "player_join",
+    "player_leave",
+    "started",
+    "ready",
+    "stopping",
+    "stopped",
+    "crashed",
+    "eula_required",
+    "console_unavailable",
+    "version",
+    "overloaded",
+    "restarting",
+    "restart_abandoned",
+    "restart_warning",
+];
+/// File failed deliveries are appended to, one JSON object per line.
+pub const DEAD_LETTER_FILE: &str = "webhook-dead-letters.log";
+/// How long a single request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attempts per delivery before it is dead-lettered.
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled after each further failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Number of deliveries kept for `GET /webhooks/deliveries`.
+const DELIVERY_HISTORY: usize = 100;
+
+/// A receiver of webhook requests, from a `[[webhooks]]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookTarget {
+    /// URL requests are posted to.
+    pub url: String,
+    /// Key for the `X-Signature-256` header; requests aren't signed without one.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Events sent to this target; empty sends all of them.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+impl WebhookTarget {
+    /// Checks the URL and event names.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.url.starts_with("https://") || self.url.starts_with("http://")) {
+            return Err(format!(
+                "webhook url '{}' must start with http:// or https://",
+                self.url
+            ));
+        }
+        if let Some(event) = self
+            .events
+            .iter()
+            .find(|event| !EVENT_NAMES.contains(&event.as_str()))
+        {
+            return Err(format!(
+                "webhook {} has an unknown event '{}'",
+                self.url, event
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns true if the target wants this event.
+    fn accepts(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|name| name == event)
+    }
+}
+
+/// Where a delivery stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Still being attempted.
+    Pending,
+    /// The receiver answered with a 2xx status.
+    Delivered,
+    /// Every attempt failed; the payload was dead-lettered.
+    Failed,
+}
+
+/// A delivery of one event to one target, as listed by `GET /webhooks/deliveries`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    /// Also sent in the `X-Webhook-Delivery` header.
+    pub id: u64,
+    pub url: String,
+    pub event: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// HTTP status of the last response, absent if no response arrived.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_status: Option<u16>,
+    /// Why the last attempt failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Handle for sending events to the webhook targets, cheap to clone.
+#[derive(Clone)]
+pub struct Webhooks {
+    targets: Arc<Vec<WebhookTarget>>,
+    client: reqwest::Client,
+    /// Recent deliveries, oldest first. Only held briefly, never across an await.
+    deliveries: Arc<Mutex<VecDeque<Delivery>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Webhooks {
+    /// Creates the handle for the configured targets.
+    pub fn new(targets: Vec<WebhookTarget>) -> Self {
+        Webhooks {
+            targets: Arc::new(targets),
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            deliveries: Arc::new(Mutex::new(VecDeque::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Posts an event to every target that wants it, each from a task of its
+    /// own so the caller never waits for a receiver. Must be called within the
+    /// Tokio runtime.
+    pub fn dispatch(&self, server_id: &ServerId, event: &ServerEvent) {
+        if self.targets.is_empty() {
+            return;
+        }
+        let Ok(Value::Object(mut data)) = serde_json::to_value(event) else {
+            return;
+        };
+        let Some(Value::String(name)) = data.remove("type") else {
+            return;
+        };
+        data.insert("server".to_string(), json!(server_id));
+        let now = Utc::now();
+        let body = json!({ "event": name, "timestamp": now, "data": data }).to_string();
+
+        for target in self.targets.iter().filter(|target| target.accepts(&name)) {
+            let delivery = Delivery {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                url: target.url.clone(),
+                event: name.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                response_status: None,
+                error: None,
+                created_at: now,
+                updated_at: now,
+            };
+            self.record(delivery.clone());
+            let webhooks = self.clone();
+            let target = target.clone();
+            let body = body.clone();
+            tokio::spawn(async move { webhooks.deliver(delivery, &target, body).await });
+        }
+    }
+
+    /// Returns the recent deliveries, newest first.
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        let deliveries = self
+            .deliveries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        deliveries.iter().rev().cloned().collect()
+    }
+
+    /// Attempts a delivery until it succeeds or runs out of attempts.
+    async fn deliver(&self, mut delivery: Delivery, target: &WebhookTarget, body: String) {
+        let signature = target.secret.as_deref().map(|secret| sign(secret, &body));
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            delivery.attempts += 1;
+            let mut request = self
+                .client
+                .post(&target.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Webhook-Event", &delivery.event)
+                .header("X-Webhook-Delivery", delivery.id.to_string())
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header("X-Signature-256", signature);
+            }
+
+            let retryable = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    delivery.response_status = Some(status.as_u16());
+                    if status.is_success() {
+                        delivery.status = DeliveryStatus::Delivered;
+                        delivery.error = None;
+                        false
+                    } else {
+                        delivery.error = Some(format!("receiver answered {}", status));
+                        // Other client errors won't go away by asking again
+                        status.is_server_error()
+                            || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                            || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                    }
+                }
+                Err(e) => {
+                    delivery.response_status = None;
+                    delivery.error = Some(e.to_string());
+                    true
+                }
+            };
+            if delivery.status == DeliveryStatus::Pending
+                && (!retryable || delivery.attempts == MAX_ATTEMPTS)
+            {
+                delivery.status = DeliveryStatus::Failed;
+            }
+            delivery.updated_at = Utc::now();
+            self.record(delivery.clone());
+
+            match delivery.status {
+                DeliveryStatus::Pending => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                DeliveryStatus::Delivered => return,
+                DeliveryStatus::Failed => {
+                    dead_letter(&delivery, &body);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Adds a delivery to the history, or replaces the entry with its id.
+    fn record(&self, delivery: Delivery) {
+        let mut deliveries = self
+            .deliveries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(existing) = deliveries.iter_mut().find(|d| d.id == delivery.id) {
+            *existing = delivery;
+            return;
+        }
+        while deliveries.len() >= DELIVERY_HISTORY {
+            deliveries.pop_front();
+        }
+        deliveries.push_back(delivery);
+    }
+}
+
+/// Computes the `X-Signature-256` header value for a body.
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+/// Appends a delivery that ultimately failed, with its payload, to the dead-letter file.
+fn dead_letter(delivery: &Delivery, body: &str) {
+    warn!(
+        "Delivery {} of '{}' to {} failed after {} attempts: {}",
+        delivery.id,
+        delivery.event,
+        delivery.url,
+        delivery.attempts,
+        delivery.error.as_deref().unwrap_or_default()
+    );
+    let entry = json!({
+        "delivery": delivery,
+        "payload": serde_json::from_str::<Value>(body).unwrap_or(Value::Null),
+    });
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(DEAD_LETTER_FILE)
+        .and_then(|mut file| writeln!(file, "{}", entry));
+    if let Err(e) = result {
+        error!("Could not write {}: {}", DEAD_LETTER_FILE, e);
+    }
+}
@@ -7,19 +7,185 @@
 //! - Command processing from clients to the server
 
 use actix::prelude::*;
-use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
 use std::{
-    sync::{Arc, Mutex},
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
     time::{Duration, Instant},
 };
+use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, info, info_span, trace, warn, Instrument, Span};
 
-use crate::state::AppState;
+use crate::audit::{AuditAction, Caller};
+use crate::auth::{Principal, Scope};
+use crate::broadcaster::{Broadcaster, ClientEncoding, ClientReceiver};
+use crate::config;
+use crate::error::ServerError;
+use crate::history::{CommandRecord, CommandStatus};
+use crate::macros::MacroRun;
+use crate::server::log_entry::LogFormat;
+use crate::server::ServerId;
+use crate::state::{self, AppState};
+use crate::websocket::rate_limit::TokenBucket;
 
-/// Heartbeat interval for pings
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-/// Client timeout duration.
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Settings for WebSocket connections.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// Interval between heartbeat pings.
+    #[serde(rename = "heartbeat_interval_secs", deserialize_with = "config::secs")]
+    pub heartbeat_interval: Duration,
+    /// How long a client may go without answering before it is disconnected.
+    #[serde(rename = "client_timeout_secs", deserialize_with = "config::secs")]
+    pub client_timeout: Duration,
+    /// Number of recent log lines and events replayed to a client when it connects.
+    pub replay_lines: usize,
+    /// Most WebSocket clients connected at once, across all servers; 0 for no limit.
+    pub max_clients: usize,
+    /// Messages buffered for a WebSocket or SSE client that isn't keeping up.
+    pub client_buffer: usize,
+    /// What happens once a client's buffer is full.
+    pub overflow: OverflowPolicy,
+    /// Commands a client may send per second on average; 0 for no limit.
+    pub command_rate: f64,
+    /// Commands a client may send in a burst before the rate applies.
+    pub command_burst: u32,
+    /// Commands refused in a row for the rate limit after which the client is
+    /// disconnected; 0 never disconnects.
+    pub command_disconnect_after: u32,
+    /// Line sent to text clients when they connect, with `{server}`,
+    /// `{client_id}` and `{timestamp}` filled in; empty sends none.
+    pub banner: String,
+    /// Whether clients also get hints for debugging the connection, e.g. about
+    /// duplicate connections from the same frontend.
+    pub debug: bool,
+    /// Whether commands sent by clients are announced to every client of the
+    /// server as `<client N> ran: <command>`, so operators see who ran what.
+    pub audit_commands: bool,
+}
+
+/// What to do with a client whose buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered messages. The channel rounds the buffer up to
+    /// a power of two, so a little more may be kept.
+    DropOldest,
+    /// Disconnect the client.
+    Disconnect,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Banner sent to text clients unless `[websocket] banner` is set.
+const DEFAULT_BANNER: &str = "--- Connected to the '{server}' console as client {client_id} ---";
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            heartbeat_interval: Duration::from_secs(5),
+            client_timeout: Duration::from_secs(10),
+            replay_lines: 200,
+            max_clients: 100,
+            client_buffer: 1024,
+            overflow: OverflowPolicy::DropOldest,
+            command_rate: 5.0,
+            command_burst: 10,
+            command_disconnect_after: 50,
+            banner: DEFAULT_BANNER.to_string(),
+            debug: false,
+            audit_commands: false,
+        }
+    }
+}
+
+impl WebSocketConfig {
+    /// Applies environment variable overrides.
+    ///
+    /// * `MC_WS_HEARTBEAT_SECS` - seconds between pings
+    /// * `MC_WS_CLIENT_TIMEOUT_SECS` - seconds without a pong before disconnecting
+    /// * `MC_WS_REPLAY_LINES` - lines and events replayed to new clients
+    /// * `MC_WS_MAX_CLIENTS` - most clients connected at once
+    /// * `MC_WS_CLIENT_BUFFER` - messages buffered per client
+    /// * `MC_WS_OVERFLOW` - `drop_oldest` or `disconnect`
+    /// * `MC_WS_COMMAND_RATE` - commands per second per client
+    /// * `MC_WS_COMMAND_BURST` - commands per client in a burst
+    /// * `MC_WS_COMMAND_DISCONNECT_AFTER` - refused commands in a row before disconnecting
+    /// * `MC_WS_BANNER` - line sent to text clients when they connect
+    /// * `MC_WS_DEBUG` - `true` to send connection debugging hints to clients
+    /// * `MC_WS_AUDIT_COMMANDS` - `true` to announce client commands to all clients
+    pub fn apply_env(&mut self) -> Result<(), String> {
+        if let Some(interval) = config::env_secs("MC_WS_HEARTBEAT_SECS")? {
+            self.heartbeat_interval = interval;
+        }
+        if let Some(timeout) = config::env_secs("MC_WS_CLIENT_TIMEOUT_SECS")? {
+            self.client_timeout = timeout;
+        }
+        if let Some(lines) = config::env_parse("MC_WS_REPLAY_LINES")? {
+            self.replay_lines = lines;
+        }
+        if let Some(max_clients) = config::env_parse("MC_WS_MAX_CLIENTS")? {
+            self.max_clients = max_clients;
+        }
+        if let Some(buffer) = config::env_parse("MC_WS_CLIENT_BUFFER")? {
+            self.client_buffer = buffer;
+        }
+        if let Some(overflow) = config::env_parse("MC_WS_OVERFLOW")? {
+            self.overflow = overflow;
+        }
+        if let Some(rate) = config::env_parse("MC_WS_COMMAND_RATE")? {
+            self.command_rate = rate;
+        }
+        if let Some(burst) = config::env_parse("MC_WS_COMMAND_BURST")? {
+            self.command_burst = burst;
+        }
+        if let Some(after) = config::env_parse("MC_WS_COMMAND_DISCONNECT_AFTER")? {
+            self.command_disconnect_after = after;
+        }
+        if let Some(banner) = config::env_string("MC_WS_BANNER") {
+            self.banner = banner;
+        }
+        if let Some(debug) = config::env_parse("MC_WS_DEBUG")? {
+            self.debug = debug;
+        }
+        if let Some(audit) = config::env_parse("MC_WS_AUDIT_COMMANDS")? {
+            self.audit_commands = audit;
+        }
+        if self.heartbeat_interval.is_zero() {
+            return Err("WebSocket heartbeat interval must be greater than zero".to_string());
+        }
+        if self.client_timeout <= self.heartbeat_interval {
+            return Err(
+                "WebSocket client timeout must be longer than the heartbeat interval".to_string(),
+            );
+        }
+        if self.client_buffer == 0 {
+            return Err("WebSocket client buffer must be greater than zero".to_string());
+        }
+        if !self.command_rate.is_finite() || self.command_rate < 0.0 {
+            return Err("WebSocket command rate must be zero or a positive number".to_string());
+        }
+        if self.command_rate > 0.0 && self.command_burst == 0 {
+            return Err("WebSocket command burst must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
 
 /// WebSocket actor for the Minecraft server console.
 ///
@@ -32,28 +198,223 @@ pub struct ConsoleWebSocket {
     /// The last time the heartbeat was received.
     last_heartbeat: Instant,
     /// Shared application state
-    app_state: web::Data<Arc<Mutex<AppState>>>,
-    /// Client ID assigned by AppState
+    app_state: web::Data<Arc<RwLock<AppState>>>,
+    /// Server whose console this client is attached to
+    server_id: ServerId,
+    /// Client ID assigned by the server's broadcaster
     client_id: usize,
-    /// Channel for receiving log messages
-    log_rx: Option<tokio::sync::mpsc::UnboundedReceiver<String>>,
+    /// Broadcaster of the server, to unregister from without the state lock.
+    broadcaster: Arc<Broadcaster>,
+    /// Client ID and remote address, recorded with the client's commands in the audit log.
+    caller: Caller,
+    /// User the client logged in as, None when logins are disabled.
+    principal: Option<Principal>,
+    /// Log messages for this client, taken when the actor starts.
+    log_rx: Option<ClientReceiver>,
+    /// Set when the client limit had been reached; the connection is closed
+    /// as soon as it opens, and the client was never registered.
+    rejected: bool,
+    /// Heartbeat settings.
+    config: WebSocketConfig,
+    /// Limits how fast this client may send commands.
+    rate_limit: TokenBucket,
+    /// Commands refused for the rate limit since the last one that was let through.
+    refused_commands: u32,
+    /// Sent when the connection opens: a `welcome` frame for JSON clients, the
+    /// configured banner for text clients, None if the banner is disabled.
+    welcome: Option<String>,
+    /// Whether the client wants the banner and debugging hints, false for `?banner=false`.
+    banner: bool,
+    /// Connected with `?mode=viewer`, so commands are refused even if the user may send them.
+    mode: ClientMode,
+    /// Queue of the client's commands, run one after another by a task
+    /// started with the actor; None until then.
+    command_lane: Option<mpsc::UnboundedSender<CommandTask>>,
+    /// Span of this connection, the parent of everything logged for it.
+    span: Span,
+}
+
+/// Checking, sending and answering one of a client's commands.
+type CommandTask = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Query parameters for `/ws`.
+#[derive(Clone, Copy, Deserialize)]
+pub struct WsQuery {
+    /// `text` (the default) for plain log lines or `json` for structured entries.
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Sequence number of the last frame a reconnecting client saw; only
+    /// later frames are replayed, after a `gap` frame if some are gone.
+    pub since: Option<u64>,
+    /// `false` to skip the text banner and debugging hints, e.g. for scripts
+    /// reading the console. JSON clients still get the `welcome` frame.
+    #[serde(default = "default_true")]
+    pub banner: bool,
+    /// `viewer` to only watch the console; commands the client sends are refused.
+    #[serde(default)]
+    pub mode: ClientMode,
+}
+
+/// Whether a WebSocket client may send commands, from `?mode=`.
+///
+/// A user with the viewer scope is read-only whatever mode it asks for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientMode {
+    /// Watch the console and send commands.
+    #[default]
+    Operator,
+    /// Only watch the console.
+    Viewer,
+}
+
+/// `?banner=` is on unless a client turns it off.
+fn default_true() -> bool {
+    true
+}
+
+/// Fills in the placeholders of a banner template.
+///
+/// # Arguments
+/// * `template` - Banner with `{server}`, `{client_id}` and `{timestamp}` placeholders
+/// * `server_id` - Server the client connected to
+/// * `client_id` - Id the client was assigned
+fn render_banner(template: &str, server_id: &str, client_id: usize) -> String {
+    template
+        .replace("{server}", server_id)
+        .replace("{client_id}", &client_id.to_string())
+        .replace(
+            "{timestamp}",
+            &Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        )
 }
 
 impl ConsoleWebSocket {
-    /// Creates a new instance of the ConsoleWebSocket actor.
+    /// Registers a client with a server and creates its ConsoleWebSocket actor.
+    ///
+    /// Registering happens here rather than when the actor starts, because
+    /// the state lock can only be taken in async code. Once `max_clients`
+    /// clients are connected, the client isn't registered and its connection
+    /// is closed when the actor starts.
     ///
     /// # Arguments
     /// * `app_state` - Shared application state
+    /// * `server_id` - Server whose console the client attaches to, or None for the default server
+    /// * `query` - Format, resume point, banner and mode the client asked for
+    /// * `remote_ip` - Address the connection came from
+    /// * `principal` - User the client logged in as, if logins are enabled
     ///
     /// # Returns
-    /// * New ConsoleWebSocket instance
-    pub fn new(app_state: web::Data<Arc<Mutex<AppState>>>) -> Self {
-        Self {
+    /// * New ConsoleWebSocket instance, or UnknownServer
+    pub async fn connect(
+        app_state: web::Data<Arc<RwLock<AppState>>>,
+        server_id: Option<&str>,
+        query: &WsQuery,
+        remote_ip: Option<String>,
+        principal: Option<Principal>,
+    ) -> Result<Self, ServerError> {
+        let WsQuery {
+            format: log_format,
+            since,
+            banner,
+            mode,
+        } = *query;
+        let read_only = mode == ClientMode::Viewer
+            || principal
+                .as_ref()
+                .is_some_and(|principal| principal.scope == Scope::Viewer);
+        let (server_id, broadcaster, registration, config, running, replayed, recent_commands) = {
+            // The write lock keeps other clients from registering between the
+            // limit check and this client's registration
+            let state = app_state.write().await;
+            let config = state.websocket_config.clone();
+            let full = config.max_clients != 0 && state.websocket_clients() >= config.max_clients;
+            let server = state.server(server_id)?;
+            let broadcaster = server.broadcaster();
+            let (running, replayed) = (server.is_running(), broadcaster.replay_len(since));
+            // Read-only clients can't send commands, so they don't get any to recall
+            let recent_commands = if read_only {
+                Vec::new()
+            } else {
+                state
+                    .command_history
+                    .recent_unique(default_history_limit(), |record| {
+                        record.server == server.id() && record.status == CommandStatus::Executed
+                    })
+                    .into_iter()
+                    .map(|record| record.command)
+                    .collect()
+            };
+            let registration = (!full).then(|| {
+                broadcaster.register_client(
+                    ClientEncoding::WebSocket(log_format),
+                    since,
+                    remote_ip.clone(),
+                    principal.as_ref(),
+                    read_only,
+                )
+            });
+            (
+                server.id().to_string(),
+                broadcaster,
+                registration,
+                config,
+                running,
+                replayed,
+                recent_commands,
+            )
+        };
+        let rejected = registration.is_none();
+        if rejected {
+            warn!(
+                server = %server_id,
+                "Rejected a client, {} clients are connected",
+                config.max_clients
+            );
+        }
+        let (client_id, log_rx) = registration.unzip();
+        let client_id = client_id.unwrap_or_default();
+        let welcome = match log_format {
+            LogFormat::Json => Some(
+                ServerMessage::Welcome {
+                    client_id,
+                    server: server_id.clone(),
+                    running,
+                    protocol_version: PROTOCOL_VERSION,
+                    replayed,
+                    read_only,
+                    recent_commands,
+                }
+                .to_json(),
+            ),
+            LogFormat::Text => (banner && !config.banner.is_empty())
+                .then(|| render_banner(&config.banner, &server_id, client_id)),
+        };
+        let span = info_span!("websocket", client_id, server = %server_id);
+        Ok(Self {
+            span,
             last_heartbeat: Instant::now(),
             app_state,
-            client_id: 0,
-            log_rx: None,
-        }
+            server_id,
+            client_id,
+            broadcaster,
+            caller: Caller {
+                client_id: Some(client_id),
+                remote_ip,
+                user: principal.as_ref().map(|principal| principal.name.clone()),
+                scope: principal.as_ref().map(|principal| principal.scope),
+            },
+            principal,
+            log_rx,
+            rejected,
+            rate_limit: TokenBucket::new(config.command_rate, config.command_burst),
+            refused_commands: 0,
+            welcome,
+            banner,
+            mode,
+            command_lane: None,
+            config,
+        })
     }
 
     /// Schedules heartbeat pings to ensure the client stays connected.
@@ -64,16 +425,517 @@ impl ConsoleWebSocket {
     /// # Arguments
     /// * `ctx` - WebSocket context
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |actor, ctx| {
+        ctx.run_interval(self.config.heartbeat_interval, |actor, ctx| {
             // Check if the client has timed out.
-            if Instant::now().duration_since(actor.last_heartbeat) > CLIENT_TIMEOUT {
-                println!("Websocket client heartbeat failed, disconnecting!");
+            if Instant::now().duration_since(actor.last_heartbeat) > actor.config.client_timeout {
+                actor
+                    .span
+                    .in_scope(|| info!("Heartbeat failed, disconnecting"));
+                // Tell the client why, in case only its pongs are getting lost
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Away,
+                    description: Some("Heartbeat timeout".to_string()),
+                }));
                 ctx.stop();
                 return;
             }
             ctx.ping(b"");
         });
     }
+
+    /// Closes the connection once the client's token expires, with code 4001
+    /// so the client knows to log in again and reconnect.
+    ///
+    /// # Arguments
+    /// * `ctx` - WebSocket context
+    fn close_on_expiry(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(principal) = &self.principal else {
+            return;
+        };
+        ctx.run_later(principal.expires_in(), |actor, ctx| {
+            actor
+                .span
+                .in_scope(|| info!("Token expired, disconnecting"));
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Other(TOKEN_EXPIRED),
+                description: Some("Token expired".to_string()),
+            }));
+            ctx.stop();
+        });
+    }
+
+    /// Checks that the client may send commands, i.e. isn't a viewer.
+    ///
+    /// A command from a user with the viewer scope is answered with a
+    /// `forbidden` error frame naming the operator scope, and one from a
+    /// `?mode=viewer` connection with a `read_only` error frame. Whether an
+    /// operator may send a particular command is checked with the command
+    /// policy.
+    ///
+    /// # Arguments
+    /// * `id` - Id of the command, if it has one
+    /// * `ctx` - WebSocket context
+    ///
+    /// # Returns
+    /// * `bool` - True if the client may send commands
+    fn may_command(&self, id: Option<String>, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        let viewer = self
+            .principal
+            .as_ref()
+            .is_some_and(|principal| principal.scope == Scope::Viewer);
+        let error = if viewer {
+            ServerError::Forbidden {
+                required: Scope::Operator,
+            }
+        } else if self.mode == ClientMode::Viewer {
+            ServerError::ReadOnly
+        } else {
+            return true;
+        };
+        self.span
+            .in_scope(|| debug!("Refused a command from a read-only client"));
+        ctx.text(ServerMessage::error(id, &error).to_json());
+        false
+    }
+
+    /// Checks a command against the client's rate limit.
+    ///
+    /// A refused command is answered with a `rate_limited` frame, and once
+    /// `command_disconnect_after` commands were refused in a row the client is
+    /// disconnected.
+    ///
+    /// # Arguments
+    /// * `id` - Id of the command, if it has one
+    /// * `ctx` - WebSocket context
+    ///
+    /// # Returns
+    /// * `bool` - True if the command may be sent
+    fn allow_command(&mut self, id: Option<String>, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        let retry_after = match self.rate_limit.try_take(Instant::now()) {
+            Ok(()) => {
+                self.refused_commands = 0;
+                return true;
+            }
+            Err(retry_after) => retry_after,
+        };
+        self.refused_commands += 1;
+        let limit = self.config.command_disconnect_after;
+        if limit != 0 && self.refused_commands >= limit {
+            self.span
+                .in_scope(|| warn!("Kept sending commands past the rate limit, disconnecting"));
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Policy,
+                description: Some("Too many commands".to_string()),
+            }));
+            ctx.stop();
+            return false;
+        }
+        ctx.text(
+            ServerMessage::RateLimited {
+                id,
+                retry_after_ms: retry_after.as_millis().max(1) as u64,
+            }
+            .to_json(),
+        );
+        false
+    }
+
+    /// Sends a plain console command and acknowledges it with a `command_ack` frame once
+    /// it has been written to the console.
+    ///
+    /// If the command can't be sent, the client gets an `error` frame without an
+    /// id instead of the acknowledgement.
+    ///
+    /// # Arguments
+    /// * `command` - The command to send to the server
+    /// * `ctx` - WebSocket context
+    fn run_command(&self, command: String, ctx: &mut ws::WebsocketContext<Self>) {
+        // Only log commands, not debug every received message
+        if !command.trim().is_empty() {
+            self.span
+                .in_scope(|| debug!("Command received: {}", command));
+        }
+
+        // Clone what we need to move into the future
+        let app_state = self.app_state.clone();
+        let server_id = self.server_id.clone();
+        let client_id = self.client_id;
+        let caller = self.caller.clone();
+        let addr = ctx.address();
+
+        // Spawn the async operation to send command to the server
+        let task = async move {
+            let result =
+                match state::check_command(&app_state, Some(&server_id), &caller, &command).await {
+                    Ok(()) => state::send_command(&app_state, Some(&server_id), &command).await,
+                    Err(e) => Err(e),
+                };
+            let server = Some(server_id.as_str());
+            state::audit(
+                &app_state,
+                &caller,
+                server,
+                AuditAction::Command,
+                Some(&command),
+                &result,
+            )
+            .await;
+            let reply = match result {
+                Ok(()) => {
+                    state::record_command(&app_state, Some(&server_id), Some(client_id), &command)
+                        .await;
+                    state::announce_command(&app_state, Some(&server_id), client_id, &command)
+                        .await;
+                    ServerMessage::CommandAck { command }
+                }
+                Err(e) => {
+                    warn!("Error sending command: {}", e);
+                    ServerMessage::error(None, &e)
+                }
+            };
+            addr.do_send(ForwardLog(reply.to_json()));
+        };
+        self.queue_command(task);
+    }
+
+    /// Sends a console command and replies with the output that follows it.
+    ///
+    /// The output subscription is created before the command is written so no
+    /// response line can be missed. Lines are collected until the console goes
+    /// quiet; if nothing arrives within the response timeout the client gets an
+    /// `error` frame with the code `timeout`.
+    ///
+    /// With `want_response` the command goes through RCON when the server has it
+    /// enabled, and the reply is always a `command_result`, even an empty one.
+    ///
+    /// # Arguments
+    /// * `id` - Client-chosen id echoed back in the reply
+    /// * `command` - The command to send to the server
+    /// * `want_response` - Whether to prefer RCON for the response
+    /// * `timeout` - How long to wait for output, or None for the server's response timeout
+    /// * `ctx` - WebSocket context
+    fn run_correlated_command(
+        &self,
+        id: String,
+        command: String,
+        want_response: bool,
+        timeout: Option<Duration>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.span
+            .in_scope(|| debug!(id, "Command received: {}", command));
+
+        let app_state = self.app_state.clone();
+        let server_id = self.server_id.clone();
+        let client_id = self.client_id;
+        let caller = self.caller.clone();
+        let addr = ctx.address();
+
+        let task = async move {
+            let result = if let Err(e) =
+                state::check_command(&app_state, Some(&server_id), &caller, &command).await
+            {
+                Err(e)
+            } else if want_response {
+                state::command_response(&app_state, Some(&server_id), &command, timeout)
+                    .await
+                    .map(|response| response.lines().map(str::to_string).collect())
+            } else {
+                state::command_output(&app_state, Some(&server_id), &command, timeout).await
+            };
+            let server = Some(server_id.as_str());
+            state::audit(
+                &app_state,
+                &caller,
+                server,
+                AuditAction::Command,
+                Some(&command),
+                &result,
+            )
+            .await;
+            if result.is_ok() {
+                state::record_command(&app_state, Some(&server_id), Some(client_id), &command)
+                    .await;
+                state::announce_command(&app_state, Some(&server_id), client_id, &command).await;
+            }
+            let reply = match result {
+                Ok(output) => ServerMessage::CommandResult { id, output },
+                Err(e) => {
+                    warn!(id, "Error sending command: {}", e);
+                    ServerMessage::error(Some(id), &e)
+                }
+            };
+            addr.do_send(ForwardLog(reply.to_json()));
+        };
+        self.queue_command(task);
+    }
+
+    /// Runs a macro and replies with a `macro_result` once its last command
+    /// was sent, or an `error` frame if it couldn't be started.
+    ///
+    /// # Arguments
+    /// * `id` - Client-chosen id echoed back in the reply
+    /// * `name` - Name of the macro
+    /// * `continue_on_error` - Whether to go on after a command failed
+    /// * `ctx` - WebSocket context
+    fn run_macro(
+        &self,
+        id: Option<String>,
+        name: String,
+        continue_on_error: bool,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.span.in_scope(|| debug!("Macro received: {}", name));
+
+        let app_state = self.app_state.clone();
+        let server_id = self.server_id.clone();
+        let caller = self.caller.clone();
+        let addr = ctx.address();
+
+        let task = async move {
+            let result = state::run_macro(
+                &app_state,
+                Some(&server_id),
+                &caller,
+                &name,
+                continue_on_error,
+            )
+            .await;
+            let reply = match result {
+                Ok(run) => ServerMessage::MacroResult { id, run },
+                Err(e) => {
+                    warn!("Error running macro {}: {}", name, e);
+                    ServerMessage::error(id, &e)
+                }
+            };
+            addr.do_send(ForwardLog(reply.to_json()));
+        };
+        self.queue_command(task);
+    }
+
+    /// Runs a command's task once the client's earlier commands are done.
+    ///
+    /// Checking a command against the policy takes the state lock, so tasks
+    /// spawned side by side could reach the server's queue in either order.
+    /// Running them one after another keeps a client's commands, and the
+    /// replies to them, in the order the client sent them; a correlated
+    /// command's output also can't mix with the next command's.
+    fn queue_command(&self, task: impl Future<Output = ()> + 'static) {
+        if let Some(lane) = &self.command_lane {
+            // The lane only ends with the actor
+            let _ = lane.send(Box::pin(task.instrument(self.span.clone())));
+        }
+    }
+
+    /// Replies with the state of this client's server.
+    ///
+    /// # Arguments
+    /// * `ctx` - WebSocket context
+    fn send_status(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let app_state = self.app_state.clone();
+        let server_id = self.server_id.clone();
+        let addr = ctx.address();
+
+        actix::spawn(async move {
+            let reply = match app_state.read().await.server(Some(&server_id)) {
+                Ok(server) => ServerMessage::Status {
+                    running: server.is_running(),
+                    uptime_seconds: server.uptime_seconds(),
+                    ready: server.is_ready(),
+                    players_online: server.online_players().len(),
+                },
+                Err(e) => ServerMessage::error(None, &e),
+            };
+            addr.do_send(ForwardLog(reply.to_json()));
+        });
+    }
+
+    /// Replies with the commands this client sent to its server, newest first.
+    ///
+    /// # Arguments
+    /// * `limit` - Most commands to return
+    /// * `ctx` - WebSocket context
+    fn send_history(&self, limit: usize, ctx: &mut ws::WebsocketContext<Self>) {
+        let app_state = self.app_state.clone();
+        let server_id = self.server_id.clone();
+        let client_id = self.client_id;
+        let addr = ctx.address();
+
+        actix::spawn(async move {
+            let commands = app_state
+                .read()
+                .await
+                .command_history
+                .recent(limit, |record| {
+                    record.server == server_id && record.client_id == Some(client_id)
+                });
+            addr.do_send(ForwardLog(ServerMessage::History { commands }.to_json()));
+        });
+    }
+}
+
+/// Messages a client can send as JSON text frames.
+///
+/// Text frames that don't parse as one of these are sent to the console as
+/// is, unless their `type` names one of them. A command that happens to be
+/// valid JSON can be sent as the `command` of a `command` message.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// A console command. With an `id`, the console output that follows is
+    /// collected and returned to this client as a `command_result`. With
+    /// `want_response` as well, the command runs over RCON if the server has it
+    /// enabled, so the result holds exactly the command's response.
+    /// `timeout_secs` overrides how long to wait for the output.
+    Command {
+        #[serde(default)]
+        id: Option<String>,
+        command: String,
+        #[serde(default)]
+        want_response: bool,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    /// Asks for the state of this client's server, answered with a `status` message.
+    Status,
+    /// Asks for this client's recent commands on this server, newest first.
+    History {
+        #[serde(default = "default_history_limit")]
+        limit: usize,
+    },
+    /// Runs a macro's commands in order, answered with a `macro_result` once
+    /// the last one was sent. Counts as one command for the rate limit.
+    Macro {
+        #[serde(default)]
+        id: Option<String>,
+        name: String,
+        #[serde(default)]
+        continue_on_error: bool,
+    },
+}
+
+/// Values of `type` that name a [`ClientMessage`].
+const CONTROL_TYPES: &[&str] = &["command", "status", "history", "macro"];
+
+/// What a text frame from a client turned out to be.
+enum TextFrame {
+    /// A control message.
+    Message(ClientMessage),
+    /// JSON whose `type` names a control message but that doesn't parse as
+    /// one; answered with an `invalid_message` error rather than sent to the
+    /// console.
+    Invalid(String),
+    /// Anything else, sent to the console as is.
+    Console,
+}
+
+impl TextFrame {
+    /// Works out what a text frame is.
+    fn parse(text: &str) -> Self {
+        let error = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => return TextFrame::Message(message),
+            Err(e) => e,
+        };
+        let names_control = serde_json::from_str::<serde_json::Value>(text)
+            .ok()
+            .and_then(|value| {
+                let kind = value.get("type")?.as_str()?;
+                Some(CONTROL_TYPES.contains(&kind))
+            })
+            .unwrap_or(false);
+        if names_control {
+            TextFrame::Invalid(format!("Invalid message: {}", error))
+        } else {
+            TextFrame::Console
+        }
+    }
+}
+
+/// Commands returned for a `history` message without a `limit`.
+fn default_history_limit() -> usize {
+    20
+}
+
+/// Close code sent when the client's login token expires.
+const TOKEN_EXPIRED: u16 = 4001;
+
+/// Version of the JSON frames exchanged with clients, sent in the `welcome`
+/// frame so clients can tell which messages the backend understands.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Structured replies sent to a single client as JSON text frames.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    /// First frame sent to a client with `format=json`.
+    Welcome {
+        client_id: usize,
+        /// Server whose console the client is attached to.
+        server: ServerId,
+        running: bool,
+        protocol_version: u32,
+        /// Recent log entries and events that follow before live output.
+        replayed: usize,
+        /// Whether commands from this client are refused.
+        read_only: bool,
+        /// The commands that last ran on the server from any client, newest
+        /// first, for up-arrow recall; empty for read-only clients.
+        recent_commands: Vec<String>,
+    },
+    /// A plain command was written to the console.
+    CommandAck { command: String },
+    /// Console output captured after a correlated command.
+    CommandResult { id: String, output: Vec<String> },
+    /// The state of the client's server, answering a `status` message.
+    Status {
+        running: bool,
+        /// Absent while the server is stopped.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        uptime_seconds: Option<u64>,
+        /// Whether the server has finished starting and accepts players.
+        ready: bool,
+        players_online: usize,
+    },
+    /// The client's recent commands, newest first, answering a `history` message.
+    History { commands: Vec<CommandRecord> },
+    /// What came of each of a macro's commands, answering a `macro` message.
+    MacroResult {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(flatten)]
+        run: MacroRun,
+    },
+    /// A command was refused because the client sent too many; it may be sent
+    /// again after `retry_after_ms`. `id` is absent for commands sent without one.
+    RateLimited {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        retry_after_ms: u64,
+    },
+    /// A command could not be sent. `id` is absent for commands sent without one.
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        /// Same identifier as the `code` of HTTP error bodies, e.g. `not_running`.
+        code: &'static str,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    /// Builds an error frame for a failed command.
+    fn error(id: Option<String>, error: &ServerError) -> Self {
+        ServerMessage::Error {
+            id,
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Serializes the message into a JSON text frame.
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
 }
 
 /// Message type for internal actor communication to forward logs
@@ -81,20 +943,39 @@ impl ConsoleWebSocket {
 #[rtype(result = "()")]
 pub struct ForwardLog(String);
 
+/// Closes the connection after the client was unregistered by the server,
+/// e.g. for falling too far behind or on an admin's request.
+#[derive(Message)]
+#[rtype(result = "()")]
+struct Unsubscribed {
+    /// True if an admin disconnected the client.
+    kicked: bool,
+}
+
+impl Handler<Unsubscribed> for ConsoleWebSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unsubscribed, ctx: &mut Self::Context) {
+        let description = if msg.kicked {
+            "Disconnected by admin"
+        } else {
+            "Disconnected by the server"
+        };
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(description.to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
 /// Handler for ForwardLog messages
 impl Handler<ForwardLog> for ConsoleWebSocket {
     type Result = ();
 
     fn handle(&mut self, msg: ForwardLog, ctx: &mut Self::Context) {
         // Send log message to the WebSocket client
-        let log = msg.0;
-
-        // To See if the log is being sent to specific client
-        // println!(
-        //     "Client {}: Sending log via WebSocket: {}",
-        //     self.client_id, &log
-        // );
-        ctx.text(log);
+        ctx.text(msg.0);
     }
 }
 
@@ -105,56 +986,80 @@ impl Actor for ConsoleWebSocket {
     /// Called when the actor is started.
     /// Sets up heartbeat checks and log streaming.
     fn started(&mut self, ctx: &mut Self::Context) {
+        if self.rejected {
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Again,
+                description: Some(format!(
+                    "Too many clients, at most {} may be connected",
+                    self.config.max_clients
+                )),
+            }));
+            ctx.stop();
+            return;
+        }
+
+        let (lane, mut tasks) = mpsc::unbounded_channel::<CommandTask>();
+        self.command_lane = Some(lane);
+        actix::spawn(async move {
+            while let Some(task) = tasks.recv().await {
+                task.await;
+            }
+        });
+
         // Start heartbeat monitoring
         self.hb(ctx);
+        self.close_on_expiry(ctx);
 
-        // Register this client and set up log streaming
-        if let Ok(mut app_state) = self.app_state.lock() {
-            let (client_id, log_rx) = app_state.register_client();
-            self.client_id = client_id;
+        // Set up log streaming for the client registered in `connect`
+        if let Some(mut log_rx) = self.log_rx.take() {
+            let client_id = self.client_id;
 
             // Get address of self
             let addr = ctx.address();
 
-            // Format a welcome message with timestamp to help identify separate connections
-            let timestamp = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-
-            ctx.text(format!(
-                "--- Connected to Minecraft console WebSocket (client ID: {}, timestamp: {}) ---",
-                client_id, timestamp
-            ));
-
-            // Send instruction to help debug multiple connections
-            ctx.text("If you see multiple connection messages, check your application for duplicate WebSocket connections");
+            // Greet the client before the forwarder starts sending the replayed frames
+            if let Some(welcome) = self.welcome.take() {
+                ctx.text(welcome);
+            }
+            if self.config.debug && self.banner {
+                ctx.text(format!(
+                    "[Debug]: Connected as client {}. If you see multiple connection messages, check your application for duplicate WebSocket connections",
+                    client_id
+                ));
+            }
 
             // Spawn a task to forward logs to this WebSocket client
-            let mut log_rx = log_rx;
-            actix::spawn(async move {
-                println!("[Log Receiver]: Started (Client {})", client_id);
+            let task = async move {
+                debug!("Log receiver started");
                 while let Some(log) = log_rx.recv().await {
-                    println!("[Log Receiver]: Fowarded (Client {}): {}", client_id, &log);
+                    // Off by default, printing every line slows the whole
+                    // backend down under heavy output
+                    trace!("Forwarded: {}", &log);
 
-                    // Send the log message to the WebSocket actor
-                    addr.do_send(ForwardLog(log));
+                    // Wait for the actor to take it, so a slow client fills its
+                    // own buffer instead of the actor's mailbox
+                    if addr.send(ForwardLog(log)).await.is_err() {
+                        break;
+                    }
                 }
-                println!("[Log Receiver]: Terminated (Client {})", client_id);
-            });
-        } else {
-            ctx.text("[Log Receiver]:  Could not access server state");
-            ctx.stop();
+                debug!("Log receiver terminated");
+                // Does nothing if the actor has already stopped
+                addr.do_send(Unsubscribed {
+                    kicked: log_rx.was_kicked(),
+                });
+            };
+            actix::spawn(task.instrument(self.span.clone()));
         }
     }
 
     /// Called when the actor is stopping.
-    /// Unregisters the client from the application state.
+    /// Unregisters the client from the server's broadcaster.
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        // Unregister this client when the WebSocket is closing
-        if let Ok(mut app_state) = self.app_state.lock() {
-            app_state.unregister_client(self.client_id);
+        if self.rejected {
+            return Running::Stop;
         }
+        // Unregister this client when the WebSocket is closing
+        self.broadcaster.unregister_client(self.client_id);
         Running::Stop
     }
 }
@@ -173,35 +1078,64 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ConsoleWebSocket
                 self.last_heartbeat = Instant::now();
             }
             Ok(ws::Message::Text(text)) => {
-                // Only log commands, not debug every received message
-                if !text.trim().is_empty() {
-                    println!("Client {}: Command received: {}", self.client_id, text);
-                }
-
-                // Clone what we need to move into the future
-                let text_clone = text.clone();
-                let app_state = self.app_state.clone();
-                let client_id = self.client_id;
-
-                // Immediately acknowledge receipt of the command
-                ctx.text(format!("Command received: {}", text));
-
-                // Spawn the async operation to send command to the server
-                actix::spawn(async move {
-                    if let Ok(mut state) = app_state.lock() {
-                        match state.send_command(&text_clone).await {
-                            Ok(_) => {
-                                // Command was sent successfully - no need to log
-                            }
-                            Err(e) => {
-                                // Only log errors
-                                println!("Client {}: Error sending command: {}", client_id, e);
-                            }
+                // JSON control messages are handled first; any other text is a console command
+                let frame = TextFrame::parse(&text);
+                let command = matches!(
+                    frame,
+                    TextFrame::Console
+                        | TextFrame::Message(ClientMessage::Command { .. })
+                        | TextFrame::Message(ClientMessage::Macro { .. })
+                );
+                self.broadcaster.record_activity(self.client_id, command);
+                match frame {
+                    TextFrame::Message(ClientMessage::Command {
+                        id: Some(id),
+                        command,
+                        want_response,
+                        timeout_secs,
+                    }) => {
+                        if self.may_command(Some(id.clone()), ctx)
+                            && self.allow_command(Some(id.clone()), ctx)
+                        {
+                            let timeout = timeout_secs.map(Duration::from_secs);
+                            self.run_correlated_command(id, command, want_response, timeout, ctx)
                         }
-                    } else {
-                        println!("Client {}: Error: Could not access server state", client_id);
                     }
-                });
+                    TextFrame::Message(ClientMessage::Command {
+                        id: None, command, ..
+                    }) => {
+                        if self.may_command(None, ctx) && self.allow_command(None, ctx) {
+                            self.run_command(command, ctx)
+                        }
+                    }
+                    TextFrame::Message(ClientMessage::Status) => self.send_status(ctx),
+                    TextFrame::Message(ClientMessage::History { limit }) => {
+                        self.send_history(limit, ctx)
+                    }
+                    TextFrame::Message(ClientMessage::Macro {
+                        id,
+                        name,
+                        continue_on_error,
+                    }) => {
+                        if self.may_command(id.clone(), ctx) && self.allow_command(id.clone(), ctx)
+                        {
+                            self.run_macro(id, name, continue_on_error, ctx)
+                        }
+                    }
+                    TextFrame::Invalid(message) => ctx.text(
+                        ServerMessage::Error {
+                            id: None,
+                            code: "invalid_message",
+                            message,
+                        }
+                        .to_json(),
+                    ),
+                    TextFrame::Console => {
+                        if self.may_command(None, ctx) && self.allow_command(None, ctx) {
+                            self.run_command(text.to_string(), ctx)
+                        }
+                    }
+                }
             }
             Ok(ws::Message::Binary(bin)) => ctx.binary(bin),
             Ok(ws::Message::Close(reason)) => {
@@ -216,17 +1150,43 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ConsoleWebSocket
 
 /// HTTP handler to upgrade incoming requests to WebSocket connections.
 ///
+/// `/servers/{id}/ws` attaches to that server's console, `/ws` to the default server's.
+///
 /// # Arguments
 /// * `req` - HTTP request
 /// * `stream` - Payload stream
 /// * `app_state` - Shared application state
+/// * `query` - `?format=json` to receive structured log entries instead of text
+///   lines, `?since=<seq>` to only replay what a reconnecting client missed, and
+///   `?banner=false` to skip the banner
 ///
 /// # Returns
-/// * HTTP response or error
+/// * HTTP response or error, not found if there is no server with the id in the path
 pub async fn ws_index(
     req: HttpRequest,
     stream: web::Payload,
-    app_state: web::Data<Arc<Mutex<AppState>>>,
+    app_state: web::Data<Arc<RwLock<AppState>>>,
+    query: web::Query<WsQuery>,
 ) -> Result<HttpResponse, Error> {
-    ws::start(ConsoleWebSocket::new(app_state), &req, stream)
+    let remote_ip = req.peer_addr().map(|addr| addr.ip().to_string());
+    let principal = req.extensions().get::<Principal>().cloned();
+    let socket = ConsoleWebSocket::connect(
+        app_state,
+        req.match_info().get("id"),
+        &query,
+        remote_ip,
+        principal,
+    )
+    .await?;
+    let (broadcaster, client_id, registered) = (
+        socket.broadcaster.clone(),
+        socket.client_id,
+        !socket.rejected,
+    );
+    ws::start(socket, &req, stream).inspect_err(|_| {
+        // The actor never starts, so `stopping` won't unregister the client
+        if registered {
+            broadcaster.unregister_client(client_id);
+        }
+    })
 }
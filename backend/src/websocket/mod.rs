@@ -4,5 +4,6 @@
 //! real-time console access and bidirectional communication.
 
 mod console_socket;
+mod rate_limit;
 
-pub use console_socket::ws_index;
+pub use console_socket::{ws_index, OverflowPolicy, WebSocketConfig};
@@ -0,0 +1,135 @@
+//! Token bucket limiting how fast a WebSocket client may send commands.
+//!
+//! Each connection has its own bucket holding up to `burst` tokens, refilled
+//! at `rate` tokens per second. A command takes one token; without one it is
+//! refused and the client is told how long until the next token.
+
+use std::time::{Duration, Instant};
+
+/// Rate limiter for one client's commands.
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// Tokens added per second; 0 disables the limit.
+    rate: f64,
+    /// Most tokens the bucket holds.
+    burst: f64,
+    tokens: f64,
+    /// When the tokens were last refilled.
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    ///
+    /// # Arguments
+    /// * `rate` - Tokens added per second, 0 for no limit
+    /// * `burst` - Most tokens the bucket holds
+    pub fn new(rate: f64, burst: u32) -> Self {
+        TokenBucket {
+            rate,
+            burst: f64::from(burst),
+            tokens: f64::from(burst),
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Takes a token for a command.
+    ///
+    /// # Arguments
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    /// * `Result<(), Duration>` - How long until a token is available if there is none
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        if self.rate <= 0.0 {
+            return Ok(());
+        }
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Takes tokens until one is refused, returning how many were taken.
+    fn drain(bucket: &mut TokenBucket, now: Instant) -> usize {
+        let mut taken = 0;
+        while bucket.try_take(now).is_ok() {
+            taken += 1;
+            assert!(taken <= 1000, "the bucket never ran dry");
+        }
+        taken
+    }
+
+    #[test]
+    fn a_full_bucket_allows_a_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(5.0, 10);
+        assert_eq!(drain(&mut bucket, start), 10);
+    }
+
+    #[test]
+    fn an_empty_bucket_refuses_with_the_wait_for_the_next_token() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(5.0, 10);
+        drain(&mut bucket, start);
+
+        // At 5 tokens per second the next one takes 200ms
+        let wait = bucket.try_take(start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(200));
+        // Half way there, half the wait is left
+        let wait = bucket
+            .try_take(start + Duration::from_millis(100))
+            .unwrap_err();
+        assert_eq!(wait.as_millis(), 100);
+    }
+
+    #[test]
+    fn tokens_refill_at_the_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(5.0, 10);
+        drain(&mut bucket, start);
+
+        assert!(bucket.try_take(start + Duration::from_millis(199)).is_err());
+        assert!(bucket.try_take(start + Duration::from_millis(200)).is_ok());
+        // One second adds 5 tokens
+        assert_eq!(drain(&mut bucket, start + Duration::from_millis(1200)), 5);
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(5.0, 10);
+        drain(&mut bucket, start);
+
+        // An hour idle still only refills the burst
+        assert_eq!(drain(&mut bucket, start + Duration::from_secs(3600)), 10);
+    }
+
+    #[test]
+    fn a_clock_going_backwards_adds_nothing() {
+        let start = Instant::now() + Duration::from_secs(1);
+        let mut bucket = TokenBucket::new(5.0, 1);
+        bucket.refilled = start;
+        assert!(bucket.try_take(start).is_ok());
+        assert!(bucket.try_take(start - Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn rate_zero_disables_the_limit() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(0.0, 0);
+        for _ in 0..100 {
+            assert!(bucket.try_take(now).is_ok());
+        }
+    }
+}
@@ -30,10 +30,15 @@ const Console: React.FC = () => {
 
 	// More aggressive filtering of connection messages
 	const filteredMessages = messages.reduce((acc: string[], message: string, index: number) => {
-		// Skip all "Connected to Minecraft console WebSocket" messages except the first one
-		if (message.includes("Connected to Minecraft console WebSocket")) {
+		// Command acknowledgements aren't console output
+		if (message.startsWith('{"type":"command_ack"')) {
+			return acc;
+		}
+
+		// Skip all connection banners except the first one
+		if (message.startsWith("--- Connected to the ")) {
 			// Only keep the first connection message
-			const previousConnections = acc.filter(m => m.includes("Connected to Minecraft console WebSocket"));
+			const previousConnections = acc.filter(m => m.startsWith("--- Connected to the "));
 			if (previousConnections.length > 0) {
 				return acc;
 			}
@@ -46,7 +51,7 @@ const Console: React.FC = () => {
 			}
 			if (message === "Disconnected from server console" &&
 				acc.length > 0 &&
-				acc[acc.length - 1].includes("Connected to Minecraft console WebSocket")) {
+				acc[acc.length - 1].startsWith("--- Connected to the ")) {
 				return acc;
 			}
 		}
@@ -1,9 +1,18 @@
 // Use dynamic URL that works on any machine - frontend and backend run on same host
-const BASE_URL = `http://${window.location.hostname}:8080`;
+const BASE_URL = `${window.location.protocol === 'https:' ? 'https:' : 'http:'}//${window.location.hostname}:8080`;
 
 async function handleResponse(response: Response): Promise<string> {
 	if (!response.ok) {
-		const errorText = await response.text();
+		let errorText = await response.text();
+		// Some errors come back as JSON with the message in `error`
+		try {
+			const body = JSON.parse(errorText);
+			if (typeof body?.error === 'string') {
+				errorText = body.error;
+			}
+		} catch {
+			// Plain text error
+		}
 		throw new Error(errorText || `HTTP error! status: ${response.status}`);
 	}
 	return response.text();
@@ -33,11 +42,17 @@ export async function stopServer(): Promise<string> {
 	}
 }
 
+export interface ServerStatus {
+	running: boolean;
+	uptime_seconds?: number;
+	restart_count: number;
+}
+
 export async function fetchStatus(): Promise<string> {
 	try {
 		const response = await fetch(`${BASE_URL}/status`);
-		const statusText = await handleResponse(response);
-		return statusText;
+		const status: ServerStatus = JSON.parse(await handleResponse(response));
+		return status.running ? "Minecraft server is running." : "Minecraft server is not running.";
 	} catch (error) {
 		throw new Error(`Failed to fetch status: ${error instanceof Error ? error.message : 'Network error'}`);
 	}